@@ -109,7 +109,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
             _,
         > = UnmappedMemoryRegion::new(ut, slots).unwrap();
         let stack_mem =
-            root_vspace.map_region(stack_mem, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+            root_vspace.map_region(stack_mem, CapRights::RW, memory_attributes::Cached)?;
 
         let mut hello_process = StandardProcess::new::<hello_printer::ProcParams, _>(
             &mut hello_vspace,
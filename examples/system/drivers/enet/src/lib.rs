@@ -2,7 +2,7 @@
 
 use ferros::cap::{role, CNodeRole};
 use ferros::userland::{Consumer1, Producer, RetypeForSetup};
-use ferros::vspace::{shared_status, MappedMemoryRegion};
+use ferros::vspace::{memory_attributes, shared_status, MappedMemoryRegion};
 use imx6_hal::pac::{
     enet::{self, ENET},
     typenum::{op, U1, U16},
@@ -31,10 +31,14 @@ pub struct ProcParams<Role: CNodeRole> {
     /// Producer of Ethernet frames received from the ENET ingress
     pub producer: Producer<Role, IpcEthernetFrame>,
 
-    /// DMA-able memory for use by the Ethernet Rx/Tx descriptors and packets.
-    ///
-    /// NOTE: currently expects to be mapped *not* cacheable
-    pub dma_mem: MappedMemoryRegion<EthDmaMemSizeInBits, shared_status::Exclusive>,
+    /// DMA-able memory for use by the Ethernet Rx/Tx descriptors and packets,
+    /// mapped uncached so that it needs no cache maintenance.
+    pub dma_mem: MappedMemoryRegion<
+        EthDmaMemSizeInBits,
+        shared_status::Exclusive,
+        role::Local,
+        memory_attributes::Uncached,
+    >,
 
     /// Hardware MAC address
     pub mac_addr: EthernetAddress,
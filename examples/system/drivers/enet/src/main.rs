@@ -23,7 +23,6 @@ pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
     log::debug!("[enet-driver] Process started");
 
     let dma_mem = params.dma_mem;
-    dma_mem.flush().unwrap();
 
     // Downgrade to something more easily managed by the HAL
     let mut dma_mem = unsafe {
@@ -162,7 +162,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let iomuxc_mem = iomux_vspace.map_region(
             UnmappedMemoryRegion::new_device(iomuxc_ut, slots)?,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT & !arch::vm_attributes::PAGE_CACHEABLE,
+            memory_attributes::Device,
         )?;
         let params = iomux::ProcParams {
             iomuxc: unsafe { IOMUXC::from_vaddr(iomuxc_mem.vaddr() as _) },
@@ -171,7 +171,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let stack_mem: UnmappedMemoryRegion<<resources::Iomux as ElfProc>::StackSizeBits, _> =
             UnmappedMemoryRegion::new(ut, slots).unwrap();
         let stack_mem =
-            root_vspace.map_region(stack_mem, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+            root_vspace.map_region(stack_mem, CapRights::RW, memory_attributes::Cached)?;
         let mut iomux_process = StandardProcess::new::<iomux::ProcParams<_>, _>(
             &mut iomux_vspace,
             iomux_cnode,
@@ -314,7 +314,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let socket_buffer_mem = tcpip_vspace.map_region_and_move(
             socket_buffer_mem_unmapped,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT,
+            memory_attributes::Cached,
             &root_cnode,
             mem_slots,
         )?;
@@ -328,7 +328,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let gpt_mem = tcpip_vspace.map_region(
             UnmappedMemoryRegion::new_device(gpt_ut, slots)?,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT & !arch::vm_attributes::PAGE_CACHEABLE,
+            memory_attributes::Device,
         )?;
         let params = tcpip::ProcParams {
             gpt: unsafe { GPT::from_vaddr(gpt_mem.vaddr() as _) },
@@ -342,7 +342,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let stack_mem: UnmappedMemoryRegion<<resources::TcpIp as ElfProc>::StackSizeBits, _> =
             UnmappedMemoryRegion::new(ut, slots).unwrap();
         let stack_mem =
-            root_vspace.map_region(stack_mem, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+            root_vspace.map_region(stack_mem, CapRights::RW, memory_attributes::Cached)?;
         let mut tcpip_process = StandardProcess::new::<tcpip::ProcParams<_>, _>(
             &mut tcpip_vspace,
             tcpip_cnode,
@@ -371,7 +371,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let enet_mem = enet_vspace.map_region(
             UnmappedMemoryRegion::new_device(enet_ut, slots)?,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT & !arch::vm_attributes::PAGE_CACHEABLE,
+            memory_attributes::Device,
         )?;
         let dma_mem_unmapped: UnmappedMemoryRegion<enet::EthDmaMemSizeInBits, _> =
             UnmappedMemoryRegion::new(ut, slots)?;
@@ -379,8 +379,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let dma_mem = enet_vspace.map_region_and_move(
             dma_mem_unmapped,
             CapRights::RW,
-            // NOTE: driver expects uncached DMA memory for the time being
-            arch::vm_attributes::DEFAULT & !arch::vm_attributes::PAGE_CACHEABLE,
+            memory_attributes::Uncached,
             &root_cnode,
             mem_slots,
         )?;
@@ -394,7 +393,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let stack_mem: UnmappedMemoryRegion<<resources::Enet as ElfProc>::StackSizeBits, _> =
             UnmappedMemoryRegion::new(ut, slots).unwrap();
         let stack_mem =
-            root_vspace.map_region(stack_mem, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+            root_vspace.map_region(stack_mem, CapRights::RW, memory_attributes::Cached)?;
         let mut enet_process = StandardProcess::new::<enet::ProcParams<_>, _>(
             &mut enet_vspace,
             enet_cnode,
@@ -443,7 +442,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let storage_buffer = pstorage_vspace.map_region_and_move(
             storage_buffer_unmapped,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT,
+            memory_attributes::Cached,
             &root_cnode,
             mem_slots,
         )?;
@@ -455,7 +454,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let scratchpad_buffer = pstorage_vspace.map_region_and_move(
             scratchpad_buffer_unmapped,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT,
+            memory_attributes::Cached,
             &root_cnode,
             mem_slots,
         )?;
@@ -469,7 +468,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let spi1_mem = pstorage_vspace.map_region(
             UnmappedMemoryRegion::new_device(spi1_ut, slots)?,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT & !arch::vm_attributes::PAGE_CACHEABLE,
+            memory_attributes::Device,
         )?;
         let gpio3_ut = dev_allocator
             .get_untyped_by_address_range_slot_infallible(
@@ -481,7 +480,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let gpio3_mem = pstorage_vspace.map_region(
             UnmappedMemoryRegion::new_device(gpio3_ut, slots)?,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT & !arch::vm_attributes::PAGE_CACHEABLE,
+            memory_attributes::Device,
         )?;
         let params = persistent_storage::ProcParams {
             spi: unsafe { ECSPI1::from_vaddr(spi1_mem.vaddr() as _) },
@@ -496,7 +495,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
             _,
         > = UnmappedMemoryRegion::new(ut, slots).unwrap();
         let stack_mem =
-            root_vspace.map_region(stack_mem, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+            root_vspace.map_region(stack_mem, CapRights::RW, memory_attributes::Cached)?;
         let mut pstorage_process = StandardProcess::new::<persistent_storage::ProcParams<_>, _>(
             &mut pstorage_vspace,
             pstorage_cnode,
@@ -556,7 +555,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let uart1_mem = console_vspace.map_region(
             UnmappedMemoryRegion::new_device(uart1_ut, slots)?,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT & !arch::vm_attributes::PAGE_CACHEABLE,
+            memory_attributes::Device,
         )?;
         let console_buffer_unmapped: UnmappedMemoryRegion<console::ConsoleBufferSizeBits, _> =
             UnmappedMemoryRegion::new(ut, slots)?;
@@ -564,7 +563,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let console_buffer = console_vspace.map_region_and_move(
             console_buffer_unmapped,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT,
+            memory_attributes::Cached,
             &root_cnode,
             mem_slots,
         )?;
@@ -578,7 +577,7 @@ fn run(raw_bootinfo: &'static selfe_sys::seL4_BootInfo) -> Result<(), TopLevelEr
         let stack_mem: UnmappedMemoryRegion<<resources::Console as ElfProc>::StackSizeBits, _> =
             UnmappedMemoryRegion::new(ut, slots).unwrap();
         let stack_mem =
-            root_vspace.map_region(stack_mem, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+            root_vspace.map_region(stack_mem, CapRights::RW, memory_attributes::Cached)?;
         let mut console_process = StandardProcess::new::<console::ProcParams<_>, _>(
             &mut console_vspace,
             console_cnode,
@@ -0,0 +1,54 @@
+#![no_std]
+#![feature(panic_info_message)]
+//! A `#[panic_handler]` for ferros child processes, which reports a panic
+//! to the parent's `ExitWatcher` whether or not the kernel can print.
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use ferros::cap::role;
+use ferros::debug_println;
+use ferros::userland::{yield_forever, ExitReporter, PanicReport};
+
+static mut REPORTER: Option<ExitReporter<role::Local>> = None;
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Make `reporter` the destination for this process' panics. Call it as
+/// early as possible, with the `ExitReporter` handed down by the parent.
+///
+/// Returns a reference to the installed reporter, so that it can still be
+/// used for an orderly `exit`. Panics if called more than once.
+pub fn install(reporter: ExitReporter<role::Local>) -> &'static ExitReporter<role::Local> {
+    if INSTALLED.swap(true, Ordering::SeqCst) {
+        panic!("A panic reporter has already been installed");
+    }
+    unsafe {
+        REPORTER = Some(reporter);
+        REPORTER.as_ref().unwrap()
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    // A panic while reporting a panic has nowhere useful left to go
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        yield_forever()
+    }
+
+    let (file, line, column) = match info.location() {
+        Some(l) => (l.file(), l.line(), l.column()),
+        None => ("<unknown>", 0, 0),
+    };
+    let report = match (info.message(), info.payload().downcast_ref::<&str>()) {
+        (Some(message), _) => PanicReport::new(file, line, column, *message),
+        (None, Some(payload)) => PanicReport::new(file, line, column, format_args!("{}", payload)),
+        (None, None) => PanicReport::new(file, line, column, format_args!("Box<Any>")),
+    };
+
+    debug_println!("{}", report);
+
+    if let Some(reporter) = unsafe { REPORTER.as_ref() } {
+        reporter.panicked(&report)
+    }
+    yield_forever()
+}
@@ -15,6 +15,8 @@ pub mod alloc {
 
     pub struct UTBuddy<T: Unsigned>(PhantomData<T>);
 
+    pub struct DeviceBuddy;
+
     impl<T: Unsigned> UTBuddy<T> {
         pub fn alloc<BitSize: Unsigned>(
             self,
@@ -36,6 +38,7 @@ pub mod userland {
 }
 pub mod vspace {
     use core::marker::PhantomData;
+    pub struct VSpace;
     pub struct ScratchRegion<'a, 'b, T = ()>(pub PhantomData<&'a T>, pub PhantomData<&'b T>);
     pub struct MappedMemoryRegion<T, SS: SharedStatus>(PhantomData<T>, PhantomData<SS>);
     pub trait SharedStatus {}
@@ -142,6 +142,8 @@ fn local_allocations<G: IdGenerator>(
     let untyped = Ident::new("untyped", Span::call_site());
     let asid_pool = Ident::new("asid_pool", Span::call_site());
     let scratch = Ident::new("scratch", Span::call_site());
+    let vspace = Ident::new("vspace", Span::call_site());
+    let device_buddy = Ident::new("device_buddy", Span::call_site());
     let local_cnode = Ident::new("local_cnode", Span::call_site());
     let thread_authority = Ident::new("thread_authority", Span::call_site());
     let vspace_paging_root = Ident::new("vspace_paging_root", Span::call_site());
@@ -199,6 +201,8 @@ fn local_allocations<G: IdGenerator>(
             }
             ParamKind::IRQControl => (parse_quote!({}), irq_control.clone()),
             ParamKind::VSpaceScratch => (parse_quote!({}), scratch.clone()),
+            ParamKind::VSpace => (parse_quote!({}), vspace.clone()),
+            ParamKind::DeviceBuddy => (parse_quote!({}), device_buddy.clone()),
             ParamKind::MappedMemoryRegion => {
                 // TODO - be sure that split/alloc prevents making too-small of regions
                 // such that page alignment would be violated
@@ -266,6 +270,7 @@ fn run_test_decl() -> FnDecl {
             ferros::cap::LocalCap<ferros::cap::ASIDPool<ferros::test_support::MaxTestASIDPoolSize>>
     ));
     run_test_inputs.push(parse_quote!(scratch: &mut ferros::vspace::ScratchRegion));
+    run_test_inputs.push(parse_quote!(vspace: &mut ferros::vspace::VSpace));
     run_test_inputs.push(parse_quote!(
         mapped_memory_region:
             ferros::vspace::MappedMemoryRegion<
@@ -288,6 +293,7 @@ fn run_test_decl() -> FnDecl {
     run_test_inputs.push(parse_quote!(
         irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>
     ));
+    run_test_inputs.push(parse_quote!(device_buddy: &mut ferros::alloc::DeviceBuddy));
     FnDecl {
         fn_token: syn::token::Fn::default(),
         generics: syn::Generics::default(),
@@ -349,13 +355,15 @@ mod tests {
                 asid_pool: ferros::cap::LocalCap<
                     ferros::cap::ASIDPool<ferros::test_support::MaxTestASIDPoolSize>>,
                 scratch: &mut ferros::vspace::ScratchRegion,
+                vspace: &mut ferros::vspace::VSpace,
                 mapped_memory_region: ferros::vspace::MappedMemoryRegion<
                     ferros::test_support::MaxMappedMemoryRegionBitSize, ferros::vspace::shared_status::Exclusive,>,
                 local_cnode: &ferros::cap::LocalCap<ferros::cap::LocalCNode>,
                 thread_authority: &ferros::cap::LocalCap<ferros::cap::ThreadPriorityAuthority>,
                 vspace_paging_root: &ferros::cap::LocalCap<ferros::arch::PagingRoot>,
                 user_image: &ferros::bootstrap::UserImage<ferros::cap::role::Local>,
-                irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>
+                irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>,
+                device_buddy: &mut ferros::alloc::DeviceBuddy
             ) -> (&'static str, ferros::test_support::TestOutcome) {
                 fn under_test() {
                     assert!(true);
@@ -412,13 +420,15 @@ mod tests {
                 asid_pool: ferros::cap::LocalCap<
                     ferros::cap::ASIDPool<ferros::test_support::MaxTestASIDPoolSize>>,
                 scratch: &mut ferros::vspace::ScratchRegion,
+                vspace: &mut ferros::vspace::VSpace,
                 mapped_memory_region: ferros::vspace::MappedMemoryRegion<
                     ferros::test_support::MaxMappedMemoryRegionBitSize, ferros::vspace::shared_status::Exclusive,>,
                 local_cnode: &ferros::cap::LocalCap<ferros::cap::LocalCNode>,
                 thread_authority: &ferros::cap::LocalCap<ferros::cap::ThreadPriorityAuthority>,
                 vspace_paging_root: &ferros::cap::LocalCap<ferros::arch::PagingRoot>,
                 user_image: &ferros::bootstrap::UserImage<ferros::cap::role::Local>,
-                irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>
+                irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>,
+                device_buddy: &mut ferros::alloc::DeviceBuddy
             ) -> (&'static str, ferros::test_support::TestOutcome) {
                 fn under_test(ut: LocalCap<Untyped<U5>>, sl: LocalCNodeSlots<U4>) -> Result<(), SeL4Error> {
                     let r = ut.split(sl);
@@ -479,13 +489,15 @@ mod tests {
                 asid_pool: ferros::cap::LocalCap<
                     ferros::cap::ASIDPool<ferros::test_support::MaxTestASIDPoolSize>>,
                 scratch: &mut ferros::vspace::ScratchRegion,
+                vspace: &mut ferros::vspace::VSpace,
                 mapped_memory_region: ferros::vspace::MappedMemoryRegion<
                     ferros::test_support::MaxMappedMemoryRegionBitSize, ferros::vspace::shared_status::Exclusive,>,
                 local_cnode: &ferros::cap::LocalCap<ferros::cap::LocalCNode>,
                 thread_authority: &ferros::cap::LocalCap<ferros::cap::ThreadPriorityAuthority>,
                 vspace_paging_root: &ferros::cap::LocalCap<ferros::arch::PagingRoot>,
                 user_image: &ferros::bootstrap::UserImage<ferros::cap::role::Local>,
-                irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>
+                irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>,
+                device_buddy: &mut ferros::alloc::DeviceBuddy
             ) -> (&'static str, ferros::test_support::TestOutcome) {
                 fn under_test(mem: MappedMemoryRegion<U12, shared_status::Exclusive>) -> Result<(), SeL4Error> {
                     Ok(())
@@ -41,6 +41,8 @@ pub(crate) enum ParamKind {
     ASIDPool { count: usize },
     MappedMemoryRegion,
     VSpaceScratch,
+    VSpace,
+    DeviceBuddy,
     CNode,
     ThreadPriorityAuthority,
     UserImage,
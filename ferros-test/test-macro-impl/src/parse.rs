@@ -70,6 +70,8 @@ fn extract_expected_resources<'a>(
 
 fn validate_param_collection(params: &[Param]) -> Result<(), ParseError> {
     let mut scratch_count = 0;
+    let mut vspace_count = 0;
+    let mut device_buddy_count = 0;
     let mut irq_control_count = 0;
     for p in params {
         match p.kind {
@@ -82,6 +84,24 @@ fn validate_param_collection(params: &[Param]) -> Result<(), ParseError> {
                     });
                 }
             }
+            ParamKind::VSpace => {
+                vspace_count += 1;
+                if vspace_count > 1 {
+                    return Err(ParseError::ArgumentConstraint {
+                        msg: "Only a single VSpace argument may be specified.",
+                        span: p.original_ident.span(),
+                    });
+                }
+            }
+            ParamKind::DeviceBuddy => {
+                device_buddy_count += 1;
+                if device_buddy_count > 1 {
+                    return Err(ParseError::ArgumentConstraint {
+                        msg: "Only a single DeviceBuddy argument may be specified.",
+                        span: p.original_ident.span(),
+                    });
+                }
+            }
             ParamKind::IRQControl => {
                 irq_control_count += 1;
                 if irq_control_count > 1 {
@@ -201,6 +221,28 @@ impl ParamKind {
                 // TODO - More detailed lifetime and ScratchRegion number of pages as type param matching
                 ParamKind::VSpaceScratch
             }
+            "VSpace" => {
+                if arg_kind == ArgKind::RefMut {
+                    ParamKind::VSpace
+                } else {
+                    return Err(ParseError::InvalidArgumentType {
+                        msg: "The only supported test function argument for VSpace is &mut VSpace"
+                            .to_string(),
+                        span: segment.span(),
+                    });
+                }
+            }
+            "DeviceBuddy" => {
+                if arg_kind == ArgKind::RefMut {
+                    ParamKind::DeviceBuddy
+                } else {
+                    return Err(ParseError::InvalidArgumentType {
+                        msg: "The only supported test function argument for DeviceBuddy is &mut DeviceBuddy"
+                            .to_string(),
+                        span: segment.span(),
+                    });
+                }
+            }
             "CNodeSlots" => ParamKind::CNodeSlots {
                 count: extract_first_argument_as_unsigned(&segment.arguments)?,
             },
@@ -495,4 +537,47 @@ mod tests {
             panic!("Should have produced an ArgumentConstraint error")
         }
     }
+
+    #[test]
+    fn parse_model_accepts_mutable_vspace_param() {
+        let user_fn = quote! {
+            fn user_fn(local_vspace: &mut VSpace) {
+            }
+        };
+
+        let content = SynContent::parse(quote!(), user_fn).expect("SynContent not parsed");
+        let model = TestModel::parse(content).expect("TestModel not parsed");
+        assert_eq!(1, model.resources.len());
+        assert_eq!(ParamKind::VSpace, model.resources[0].kind);
+    }
+
+    #[test]
+    fn parse_model_rejects_shared_vspace_param() {
+        let user_fn = quote! {
+            fn user_fn(local_vspace: &VSpace) {
+            }
+        };
+
+        let content = SynContent::parse(quote!(), user_fn).expect("SynContent not parsed");
+        if let ParseError::InvalidArgumentType { .. } =
+            TestModel::parse(content).expect_err("TestModel parse should have failed")
+        {
+            // Cool
+        } else {
+            panic!("Should have produced an InvalidArgumentType error")
+        }
+    }
+
+    #[test]
+    fn parse_model_accepts_mutable_device_buddy_param() {
+        let user_fn = quote! {
+            fn user_fn(devices: &mut DeviceBuddy) {
+            }
+        };
+
+        let content = SynContent::parse(quote!(), user_fn).expect("SynContent not parsed");
+        let model = TestModel::parse(content).expect("TestModel not parsed");
+        assert_eq!(1, model.resources.len());
+        assert_eq!(ParamKind::DeviceBuddy, model.resources[0].kind);
+    }
 }
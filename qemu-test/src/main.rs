@@ -153,6 +153,36 @@ fn run_qemu_test<F>(
     };
 }
 
+fn run_build_failure_test(test_case: &str, error_line: Regex, test_platform: TestPlatform) {
+    let mut build_command = Command::new("selfe");
+    (&mut build_command)
+        .arg("build")
+        .arg("--sel4_arch")
+        .arg(test_platform.sel4_arch())
+        .arg("--platform")
+        .arg(test_platform.platform())
+        .arg("-v")
+        .current_dir("test-project")
+        .env("TEST_CASE", test_case);
+
+    println!(r#"running: TEST_CASE={} {:?}"#, test_case, build_command);
+    let build_result = build_command.output().expect("Couldn't run `selfe build`");
+    io::stdout().write_all(&build_result.stdout).unwrap();
+    io::stderr().write_all(&build_result.stderr).unwrap();
+    assert!(!build_result.status.success());
+
+    let stdout = String::from_utf8_lossy(&build_result.stdout);
+    let stderr = String::from_utf8_lossy(&build_result.stderr);
+    assert!(
+        stdout
+            .lines()
+            .chain(stderr.lines())
+            .any(|line| error_line.is_match(line)),
+        "No line of the build output matched {}",
+        error_line
+    );
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -161,7 +191,7 @@ mod tests {
         fn unified_tests_sabre() {
             run_qemu_test::<fn()>(
                 "unified_tests",
-                Regex::new(".*test result: ok\\. 23 passed;.*").unwrap(),
+                Regex::new(".*test result: ok\\. 47 passed;.*").unwrap(),
                 Regex::new(".*Root task should never return from main.*").unwrap(),
                 None,
                 None,
@@ -174,7 +204,33 @@ mod tests {
         fn unified_tests_virt() {
             run_qemu_test::<fn()>(
                 "unified_tests",
-                Regex::new(".*test result: ok\\. 23 passed;.*").unwrap(),
+                Regex::new(".*test result: ok\\. 47 passed;.*").unwrap(),
+                Regex::new(".*Root task should never return from main.*").unwrap(),
+                None,
+                None,
+                TestPlatform::VirtTx1Aarch64,
+            );
+        }
+    }
+
+    sequential_test! {
+        fn const_generics_sabre() {
+            run_qemu_test::<fn()>(
+                "const_generics",
+                Regex::new(".*test result: ok\\. 1 passed;.*").unwrap(),
+                Regex::new(".*Root task should never return from main.*").unwrap(),
+                None,
+                None,
+                TestPlatform::SabreAarch32,
+            );
+        }
+    }
+
+    sequential_test! {
+        fn const_generics_virt() {
+            run_qemu_test::<fn()>(
+                "const_generics",
+                Regex::new(".*test result: ok\\. 1 passed;.*").unwrap(),
                 Regex::new(".*Root task should never return from main.*").unwrap(),
                 None,
                 None,
@@ -183,6 +239,42 @@ mod tests {
         }
     }
 
+    sequential_test! {
+        fn memory_server_ipc_sabre() {
+            run_qemu_test::<fn()>(
+                "memory_server_ipc",
+                Regex::new(".*memory server client: ok.*").unwrap(),
+                Regex::new(".*(memory server client: failed|Root task should never return from main).*").unwrap(),
+                None,
+                None,
+                TestPlatform::SabreAarch32,
+            );
+        }
+    }
+
+    sequential_test! {
+        fn memory_server_ipc_virt() {
+            run_qemu_test::<fn()>(
+                "memory_server_ipc",
+                Regex::new(".*memory server client: ok.*").unwrap(),
+                Regex::new(".*(memory server client: failed|Root task should never return from main).*").unwrap(),
+                None,
+                None,
+                TestPlatform::VirtTx1Aarch64,
+            );
+        }
+    }
+
+    sequential_test! {
+        fn slot_shortfall_sabre() {
+            run_build_failure_test(
+                "slot_shortfall",
+                Regex::new(".*smart_alloc needs 5 slots, only 4 provided.*").unwrap(),
+                TestPlatform::SabreAarch32,
+            );
+        }
+    }
+
     sequential_test! {
         fn uart_sabre() {
             use std::net::TcpStream;
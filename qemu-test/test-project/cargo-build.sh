@@ -14,5 +14,15 @@ fi
 echo "======================= building elf-process ======================"
 cargo xbuild -p elf-process $@;
 
+echo "=================== building panicking-process ===================="
+cargo xbuild -p panicking-process $@;
+
+# The const generics test case needs an incomplete compiler feature, which
+# the rest of the suite is kept clear of.
+root_task_features=""
+if [ "${TEST_CASE}" = "const_generics" ]; then
+    root_task_features="--features const_generics"
+fi
+
 echo "======================== building root-task ======================="
-cargo xbuild -p root-task $@;
+cargo xbuild -p root-task $root_task_features $@;
@@ -1,5 +1,8 @@
 #![no_std]
 #![no_main]
+#![feature(thread_local)]
+
+use core::cell::Cell;
 
 use ferros::*;
 use ferros::cap::*;
@@ -9,6 +12,11 @@ use elf_process::ProcParams;
 
 static mut MUT_GLOBAL: u32 = 0;
 
+// Initialized, so that it lands in .tdata and has to be copied from the
+// PT_TLS segment into the thread's TLS area.
+#[thread_local]
+static TLS_VALUE: Cell<u32> = Cell::new(42);
+
 #[no_mangle]
 pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
     // try to set the mut global, to see that BSS was mapped
@@ -16,9 +24,12 @@ pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
         MUT_GLOBAL = 42;
     }
 
+    // and that the initial thread got its own TLS area, set up from PT_TLS
+    TLS_VALUE.set(TLS_VALUE.get() + 1);
+
     params
         .outcome_sender
-        .blocking_send(&(params.value == 42))
+        .blocking_send(&(params.value == 42 && TLS_VALUE.get() == 43))
         .expect("Found value does not match expectations");
 
     unsafe {
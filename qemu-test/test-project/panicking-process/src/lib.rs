@@ -0,0 +1,13 @@
+#![no_std]
+
+use ferros::cap::*;
+use ferros::userland::{ExitReporter, RetypeForSetup};
+
+pub struct ProcParams<Role: CNodeRole> {
+    pub items: usize,
+    pub exit_reporter: ExitReporter<Role>,
+}
+
+impl RetypeForSetup for ProcParams<role::Local> {
+    type Output = ProcParams<role::Child>;
+}
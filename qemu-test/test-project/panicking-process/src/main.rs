@@ -0,0 +1,19 @@
+#![no_std]
+#![no_main]
+
+use ferros::cap::*;
+extern crate ferros_panic;
+extern crate selfe_runtime;
+
+use panicking_process::ProcParams;
+
+#[no_mangle]
+pub extern "C" fn _start(params: ProcParams<role::Local>) -> ! {
+    let exit = ferros_panic::install(params.exit_reporter);
+
+    if params.items > 0 {
+        panic!("no more work, {} items left", params.items);
+    }
+
+    exit.exit(0)
+}
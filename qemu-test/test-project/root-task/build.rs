@@ -23,5 +23,15 @@ fn main() {
         stack_size_bits: None,
     };
 
-    embed_resources(&resources, vec![&elf_proc as &dyn Resource]);
+    let panicking_proc = ElfResource {
+        path: bin_dir.join("panicking-process"),
+        image_name: "panicking-process".to_owned(),
+        type_name: "PanickingProcess".to_owned(),
+        stack_size_bits: None,
+    };
+
+    embed_resources(
+        &resources,
+        vec![&elf_proc as &dyn Resource, &panicking_proc as &dyn Resource],
+    );
 }
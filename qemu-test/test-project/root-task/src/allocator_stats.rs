@@ -0,0 +1,80 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::stats::{Usage, UsageLedger};
+use ferros::alloc::ut_buddy::weak_ut_buddy;
+use ferros::cap::*;
+
+#[ferros_test::ferros_test]
+pub fn allocator_stats(
+    local_slots: LocalCNodeSlots<U16>,
+    local_ut: LocalCap<Untyped<U14>>,
+    root_cnode: &LocalCap<LocalCNode>,
+) -> Result<(), TopLevelError> {
+    let mut uts = weak_ut_buddy(local_ut.weaken());
+    let mut slots = local_slots.weaken();
+
+    let fresh = uts.stats();
+    if fresh.free_count(14) != 1
+        || fresh.free_bytes() != 1 << 14
+        || fresh.largest_free_size_bits() != Some(14)
+        || fresh.allocated_bytes != 0
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "A fresh buddy should hold just the untyped it wraps",
+        ));
+    }
+
+    let mut ledger = UsageLedger::new();
+    let before = Usage::measure(&uts, &slots);
+    let ut12 = uts.alloc(&mut slots, 12)?;
+    ledger
+        .charge("worker", before, Usage::measure(&uts, &slots))
+        .map_err(|_| TopLevelError::TestAssertionFailure("The ledger should have room"))?;
+
+    // Two splits, leaving a U13 and a U12 free.
+    let split = uts.stats();
+    if split.free_count(13) != 1
+        || split.free_count(12) != 1
+        || split.free_bytes() != (1 << 14) - (1 << 12)
+        || split.allocated_bytes != 1 << 12
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Splitting should show in the size classes",
+        ));
+    }
+    let slot_stats = slots.stats();
+    if slot_stats.used != 4 || slot_stats.remaining != 12 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Each split should use two slots",
+        ));
+    }
+    match ledger.usage_of("worker") {
+        Some(usage) if usage.untyped_bytes == 1 << 12 && usage.slots == 4 => (),
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "The worker should be charged with what it took",
+            ))
+        }
+    }
+
+    let before = Usage::measure(&uts, &slots);
+    uts.free(ut12, root_cnode)?;
+    ledger
+        .credit("worker", before, Usage::measure(&uts, &slots))
+        .map_err(|_| TopLevelError::TestAssertionFailure("The ledger should have room"))?;
+
+    let freed = uts.stats();
+    if freed.allocated_bytes != 0 || freed.high_water_bytes != 1 << 12 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "The high-water mark should outlast what was freed",
+        ));
+    }
+    if ledger.outstanding().count() != 0 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Nothing should be left charged once freed",
+        ));
+    }
+    Ok(())
+}
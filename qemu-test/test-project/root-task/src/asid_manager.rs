@@ -0,0 +1,116 @@
+use typenum::*;
+
+use ferros::alloc::asid_manager::{ASIDManager, ASIDManagerError};
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn asid_manager(
+    local_slots: LocalCNodeSlots<U8192>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+    let mut asids = ASIDManager::from_pool(asid_pool);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let failed_root = retype(ut, slots)?;
+        let failed_slots: LocalCNodeSlots<U1024> = slots;
+        let failed_ut: LocalCap<Untyped<U4>> = ut;
+        let first_root = retype(ut, slots)?;
+        let first_slots: LocalCNodeSlots<U1024> = slots;
+        let first_ut: LocalCap<Untyped<U15>> = ut;
+        let second_root = retype(ut, slots)?;
+        let second_slots: LocalCNodeSlots<U1024> = slots;
+        let second_ut: LocalCap<Untyped<U15>> = ut;
+        let third_root = retype(ut, slots)?;
+        let third_slots: LocalCNodeSlots<U1024> = slots;
+        let third_ut: LocalCap<Untyped<U15>> = ut;
+    });
+
+    // The kernel assigns this ASID, but with too little untyped for even
+    // one page table the address space can't be finished, and is torn
+    // down again, freeing the ASID.
+    let failed = asids.assign(|asid| {
+        VSpace::new(
+            failed_root,
+            asid,
+            failed_slots.weaken(),
+            failed_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )
+    })?;
+    if failed.is_ok() || asids.free_count() != 2 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "a failed VSpace's ASID wasn't left free",
+        ));
+    }
+
+    // Both ASIDs are still free in the kernel's eyes too, or the second
+    // of these would find the pool full.
+    let first_vspace = asids.assign(|asid| {
+        VSpace::new(
+            first_root,
+            asid,
+            first_slots.weaken(),
+            first_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )
+    })??;
+    let _second_vspace = asids.assign(|asid| {
+        VSpace::new(
+            second_root,
+            asid,
+            second_slots.weaken(),
+            second_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )
+    })??;
+
+    match asids.assign(|_| Ok::<(), TopLevelError>(())) {
+        Err(ASIDManagerError::NoPoolsLeft) => (),
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "a spent pool handed out another ASID",
+            ))
+        }
+    }
+
+    // Destroying an address space frees its ASID for the next.
+    asids.release(first_vspace.destroy(root_cnode)?)?;
+    if asids.free_count() != 1 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "a released ASID wasn't taken back",
+        ));
+    }
+
+    let _third_vspace = asids.assign(|asid| {
+        VSpace::new(
+            third_root,
+            asid,
+            third_slots.weaken(),
+            third_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )
+    })??;
+    if asids.free_count() != 0 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "a reused ASID wasn't counted as assigned",
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::cap::*;
+use ferros::userland::CapRights;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Source {
+    Irq,
+    Queue,
+    Peer,
+}
+
+#[ferros_test::ferros_test]
+pub fn badge_bits(
+    local_slots: LocalCNodeSlots<U4>,
+    notification_ut: LocalCap<Untyped<U4>>,
+) -> Result<(), TopLevelError> {
+    let (slot, local_slots) = local_slots.alloc();
+    let notification: LocalCap<Notification> = notification_ut.retype(slot)?;
+
+    let bits = BadgeBits::new();
+    let (irq, bits) = bits.alloc();
+    let (queue, bits) = bits.alloc();
+    let (peer, bits) = bits.alloc();
+    assert_eq!(bits.remaining(), BadgeBitCount::USIZE - 3);
+
+    let sources = [
+        (Source::Irq, irq.badge()),
+        (Source::Queue, queue.badge()),
+        (Source::Peer, peer.badge()),
+    ];
+
+    let (slot, local_slots) = local_slots.alloc();
+    let queue_sender = notification.mint_inside_cnode(slot, CapRights::RWG, queue.badge())?;
+    let (slot, _local_slots) = local_slots.alloc();
+    let peer_sender = notification.mint_inside_cnode(slot, CapRights::RWG, peer.badge())?;
+
+    queue_sender.signal();
+    peer_sender.signal();
+
+    let wakeup = notification.wait();
+    assert!(!irq.is_set_in(wakeup));
+    assert!(queue.is_set_in(wakeup));
+    assert!(peer.is_set_in(wakeup));
+
+    let mut decoded = wakeup.decode(&sources);
+    assert_eq!(decoded.next(), Some(Source::Queue));
+    assert_eq!(decoded.next(), Some(Source::Peer));
+    assert_eq!(decoded.next(), None);
+    Ok(())
+}
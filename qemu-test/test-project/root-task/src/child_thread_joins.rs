@@ -0,0 +1,65 @@
+use super::TopLevelError;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::cap::*;
+use ferros::userland::{RetypeForSetup, Thread};
+use ferros::vspace::*;
+
+#[ferros_test::ferros_test]
+pub fn child_thread_joins(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    ipc_buffer_region: MappedMemoryRegion<U12, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+    local_vspace: &mut VSpace,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let stack_region: UnmappedMemoryRegion<U17, _> = UnmappedMemoryRegion::new(ut, slots)?;
+        let (exit_notification_slot, _child_slots) = child_slots.alloc();
+
+        let thread = Thread::new_joinable(
+            local_vspace,
+            child_cnode,
+            stack_region,
+            add_them_up,
+            ProcParams {
+                values: [1, 2, 3, 4],
+            },
+            ipc_buffer_region,
+            ut,
+            ut,
+            root_cnode,
+            slots,
+            exit_notification_slot,
+            tpa,
+            None, // fault
+            None, // tls
+        )?;
+    });
+
+    let handle = thread.start()?;
+    if handle.join()? != 10 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Joined thread should have returned the sum of its parameters",
+        ));
+    }
+    Ok(())
+}
+
+pub struct ProcParams {
+    pub values: [usize; 4],
+}
+
+impl RetypeForSetup for ProcParams {
+    type Output = ProcParams;
+}
+
+fn add_them_up(params: ProcParams) -> usize {
+    params.values.iter().sum()
+}
@@ -13,17 +13,17 @@ pub fn child_thread_runs(
     local_slots: LocalCNodeSlots<U32768>,
     local_ut: LocalCap<Untyped<U20>>,
     asid_pool: LocalCap<ASIDPool<U1>>,
-    stack_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
     ipc_buffer_region: MappedMemoryRegion<U12, shared_status::Exclusive>,
     root_cnode: &LocalCap<LocalCNode>,
     user_image: &UserImage<role::Local>,
     tpa: &LocalCap<ThreadPriorityAuthority>,
-    vspace_paging_root: &LocalCap<ferros::arch::PagingRoot>,
+    local_vspace: &mut VSpace,
 ) -> Result<(), TopLevelError> {
     let uts = ut_buddy(local_ut);
 
     smart_alloc!(|slots: local_slots, ut: uts| {
         let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let stack_region: UnmappedMemoryRegion<U17, _> = UnmappedMemoryRegion::new(ut, slots)?;
         let (child_fault_source_slot, _child_slots) = child_slots.alloc();
         let (fault_source, outcome_sender, handler) =
             fault_or_message_channel(&root_cnode, ut, slots, child_fault_source_slot, slots)?;
@@ -33,9 +33,9 @@ pub fn child_thread_runs(
         };
 
         let child_process = Thread::new(
-            vspace_paging_root,
+            local_vspace,
             child_cnode,
-            stack_mapped_region,
+            stack_region,
             proc_main,
             params,
             ipc_buffer_region,
@@ -43,6 +43,7 @@ pub fn child_thread_runs(
             slots,
             tpa,
             None, // fault
+            None, // tls
         )?;
     });
 
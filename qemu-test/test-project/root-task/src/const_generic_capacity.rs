@@ -0,0 +1,59 @@
+use typenum::*;
+
+use ferros::alloc::const_ut_buddy::{const_ut_buddy, give_back, splits};
+use ferros::arch::MaxUntypedSize;
+use ferros::cap::{Endpoint, LocalCNode, LocalCNodeSlots, LocalCap, Untyped};
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn const_generic_capacity(
+    ut: LocalCap<Untyped<U16>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    slots: LocalCNodeSlots<U16>,
+) -> Result<(), TopLevelError> {
+    let slots = slots.to_const();
+    let uts = const_ut_buddy(ut.to_const());
+
+    // Splitting 2^16 down to 2^12 takes four splits, two slots each, and
+    // leaves a 2^12 behind, so the second takes none.
+    let (split_slots, slots) = slots.alloc::<8>();
+    let (first, uts) = uts.alloc::<12>(split_slots)?;
+    let (no_slots, slots) = slots.alloc::<0>();
+    let (second, uts) = uts.alloc::<12>(no_slots)?;
+
+    let (endpoint_slot, slots) = slots.alloc::<1>();
+    let _endpoint: LocalCap<Endpoint> = first.retype(endpoint_slot)?;
+
+    let (halves_slots, slots) = slots.alloc::<2>();
+    let (_left, _right) = second.split(halves_slots)?;
+
+    // The 2^13 left behind is handed out whole, then given back.
+    let (no_slots, slots) = slots.alloc::<0>();
+    let (third, uts) = uts.alloc::<13>(no_slots)?;
+    let uts = uts.free(third, root_cnode)?;
+
+    let _rest: LocalCNodeSlots<U5> = slots.to_typenum();
+
+    // The largest untyped a pool can be made of can be held and handed
+    // out like any other.
+    let largest = give_back(0, MaxUntypedSize::USIZE);
+    if splits(largest, MaxUntypedSize::USIZE) != 0
+        || splits(largest, 12) != MaxUntypedSize::USIZE - 12
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "the largest untyped size isn't held",
+        ));
+    }
+
+    let stats = uts.weaken().stats();
+    for size_bits in 12..=16 {
+        let expected = if (13..=15).contains(&size_bits) { 1 } else { 0 };
+        if stats.free_count(size_bits) != expected {
+            return Err(TopLevelError::TestAssertionFailure(
+                "unexpected free untypeds left after const allocation",
+            ));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::arch::fault::VMFault;
+use typenum::*;
+
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::CapRights;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn copy_on_write(
+    local_slots: LocalCNodeSlots<U4096>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    local_vspace: &mut VSpace,
+    local_vspace_scratch: &mut ScratchRegion,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (first_asid, asid_pool) = asid_pool.alloc();
+        let first_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let first_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut first_vspace = VSpace::new(
+            retype(ut, slots)?,
+            first_asid,
+            first_vspace_slots.weaken(),
+            first_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (second_asid, _asid_pool) = asid_pool.alloc();
+        let second_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let second_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut second_vspace = VSpace::new(
+            retype(ut, slots)?,
+            second_asid,
+            second_vspace_slots.weaken(),
+            second_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let unmapped_region: UnmappedMemoryRegion<U14, _> = UnmappedMemoryRegion::new(ut, slots)?;
+        let first_slots: LocalCNodeSlots<U4> = slots;
+        let second_slots: LocalCNodeSlots<U4> = slots;
+        let write_page_ut: LocalCap<Untyped<U12>> = ut;
+        let write_page_slot: LocalCNodeSlot = slots;
+    });
+
+    // Private copies are made from the original as mapped here, so the
+    // original has to live in the local address space. It stays read-only
+    // there: writes to it aren't copied on.
+    let mut region =
+        local_vspace.map_region(unmapped_region, CapRights::RW, memory_attributes::Cached)?;
+    for (i, byte) in region.as_mut_slice().iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let original_vaddr = region.vaddr();
+    let mut table = CowRegion::new(region, local_vspace)?;
+    if local_vspace.mapping_at(original_vaddr).map(|m| m.rights) != Some(CapRights::R) {
+        return Err(TopLevelError::TestAssertionFailure(
+            "The original should be made read-only in its owner",
+        ));
+    }
+
+    let first_vaddr = table.map_into(&mut first_vspace, first_slots, root_cnode)?;
+    let second_vaddr = table.map_into(&mut second_vspace, second_slots, root_cnode)?;
+    for (vspace, vaddr) in [(&first_vspace, first_vaddr), (&second_vspace, second_vaddr)].iter() {
+        match vspace.mapping_at(*vaddr) {
+            Some(m) if m.rights == CapRights::R && m.shared && m.size == 1 << 14 => (),
+            _ => {
+                return Err(TopLevelError::TestAssertionFailure(
+                    "Copy-on-write mappings should be shared and read-only",
+                ))
+            }
+        }
+    }
+
+    // A permission fault on reading isn't a copy-on-write fault, and is
+    // turned down before any resources are spent on it.
+    let read_fault = VMFault {
+        sender: Badge::from(0),
+        program_counter: 0,
+        address: first_vaddr,
+        is_instruction_fault: false,
+        fault_status_register: PERMISSION_FAULT,
+    };
+    if table.claims(&read_fault, &first_vspace) {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Only writes to copy-on-write pages should be handled",
+        ));
+    }
+
+    // A write to the second page gets a private copy of just that page.
+    let page_bytes = 1 << 12;
+    let write_vaddr = first_vaddr + page_bytes;
+    let write_fault = VMFault {
+        sender: Badge::from(0),
+        program_counter: 0,
+        address: write_vaddr + 8,
+        is_instruction_fault: false,
+        fault_status_register: PERMISSION_FAULT | WRITE_NOT_READ,
+    };
+    if !table.claims(&write_fault, &first_vspace) || table.claims(&write_fault, &second_vspace) {
+        return Err(TopLevelError::TestAssertionFailure(
+            "A write should only be claimed in the address space it faulted in",
+        ));
+    }
+    let private_page = table.handle_write_fault(
+        &write_fault,
+        &mut first_vspace,
+        write_page_ut,
+        write_page_slot,
+        local_vspace_scratch,
+    )?;
+    let layout_ok = match (
+        first_vspace.mapping_at(first_vaddr),
+        first_vspace.mapping_at(write_vaddr),
+        first_vspace.mapping_at(write_vaddr + page_bytes),
+    ) {
+        (Some(below), Some(private), Some(above)) => {
+            below.rights == CapRights::R
+                && below.size == page_bytes
+                && private.rights == CapRights::RW
+                && !private.shared
+                && private.size == page_bytes
+                && above.start == write_vaddr + page_bytes
+                && above.size == 2 * page_bytes
+        }
+        _ => false,
+    };
+    if !layout_ok || private_page.vaddr() != write_vaddr {
+        return Err(TopLevelError::TestAssertionFailure(
+            "The private copy should replace just its page in the listed mappings",
+        ));
+    }
+
+    // Unmapping the region leaves only the private copy behind, and makes
+    // room for the region to be mapped again.
+    let first_slots = table.unmap_from(&mut first_vspace, root_cnode)?;
+    if first_vspace.mapping_at(first_vaddr).is_some()
+        || first_vspace.mapping_at(write_vaddr + page_bytes).is_some()
+        || first_vspace.mapping_at(write_vaddr).map(|m| m.rights) != Some(CapRights::RW)
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Only the private copy should stay mapped",
+        ));
+    }
+    match table.unmap_from(&mut first_vspace, root_cnode) {
+        Err(CowError::NotMapped) => (),
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "A region should only be unmapped from where it is mapped",
+            ))
+        }
+    }
+    let remapped_vaddr = table.map_into(&mut first_vspace, first_slots, root_cnode)?;
+    if first_vspace.mapping_at(remapped_vaddr).map(|m| m.rights) != Some(CapRights::R) {
+        return Err(TopLevelError::TestAssertionFailure(
+            "The region should map again with the slots it handed back",
+        ));
+    }
+
+    // The private copy starts out with its page of the original contents.
+    let mut private_page = first_vspace.unmap_region(private_page)?;
+    let copied = local_vspace_scratch.temporarily_map_region(&mut private_page, |page| {
+        page.as_slice()
+            .iter()
+            .enumerate()
+            .all(|(i, byte)| *byte == (page_bytes + i) as u8)
+    })?;
+    if !copied {
+        return Err(TopLevelError::TestAssertionFailure(
+            "The private copy should hold the original's contents",
+        ));
+    }
+    Ok(())
+}
+
+/// A permission fault on a page, in both the short- and long-descriptor
+/// status formats.
+const PERMISSION_FAULT: usize = 0b1111;
+
+/// Marks a data abort as caused by a write: bit 11 in the aarch32
+/// short-descriptor format, bit 6 in the long-descriptor one aarch64 uses.
+/// Neither format reads the other's bit as part of the fault status.
+const WRITE_NOT_READ: usize = (1 << 11) | (1 << 6);
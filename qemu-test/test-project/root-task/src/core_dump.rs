@@ -0,0 +1,130 @@
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::arch::fault::Fault;
+use ferros::bootstrap::UserImage;
+use ferros::cap::{
+    retype, retype_cnode, role, ASIDPool, CNodeRole, LocalCNode, LocalCNodeSlots, LocalCap,
+    ThreadPriorityAuthority, Untyped,
+};
+use ferros::userland::{
+    exit_channel, CapRights, CoreDumpRegion, ExitReporter, ProcessOutcome, RetypeForSetup,
+    SliceSink, StandardProcess,
+};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn core_dump(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (child_fault_source_slot, _child_slots) = child_slots.alloc();
+        let (source, reporter, watcher) =
+            exit_channel(&root_cnode, ut, slots, child_fault_source_slot, slots)?;
+        let params = ProcParams { reporter };
+
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+
+        let child_root = retype(ut, slots)?;
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+
+        let mut child_vspace = VSpace::new(
+            child_root,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let mut child_process = StandardProcess::new(
+            &mut child_vspace,
+            child_cnode,
+            local_mapped_region,
+            root_cnode,
+            proc_main as extern "C" fn(_) -> (),
+            params,
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(source),
+        )?;
+    });
+    child_process.start()?;
+
+    let fault = match watcher.wait()? {
+        ProcessOutcome::Faulted(fault @ Fault::VMFault(_)) => fault,
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "Child process should have hit a VM fault",
+            ))
+        }
+    };
+    child_process.stop()?;
+
+    let region_data = [0xa5u8; 100];
+    let regions = [CoreDumpRegion::new(
+        0x1000_0000,
+        &region_data,
+        CapRights::RW,
+    )];
+    let mut sink = SliceSink::new(unsafe { &mut CORE_BUFFER });
+    child_process
+        .write_core_dump(Some(&fault), &regions, &mut sink)
+        .map_err(|_| TopLevelError::TestAssertionFailure("Could not write core dump"))?;
+    let core = sink.written();
+
+    if &core[..4] != b"\x7fELF" || core[16] != 4 || core[17] != 0 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Core dump should start with an ELF header for a core file",
+        ));
+    }
+    let page_size = 4096;
+    if core.len() != 2 * page_size {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Core dump should hold a page of headers and one page of region data",
+        ));
+    }
+    if core[page_size..page_size + region_data.len()] != region_data[..]
+        || core[page_size + region_data.len()..]
+            .iter()
+            .any(|b| *b != 0)
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Core dump region contents were not written at the expected offset",
+        ));
+    }
+    Ok(())
+}
+
+static mut CORE_BUFFER: [u8; 3 * 4096] = [0; 3 * 4096];
+
+pub struct ProcParams<Role: CNodeRole> {
+    pub reporter: ExitReporter<Role>,
+}
+
+impl RetypeForSetup for ProcParams<role::Local> {
+    type Output = ProcParams<role::Child>;
+}
+
+pub extern "C" fn proc_main(params: ProcParams<role::Local>) {
+    unsafe {
+        let x: *const usize = 0x88888888usize as _;
+        debug_println!("Value from arbitrary memory is: {}", *x);
+    }
+    params.reporter.exit(0)
+}
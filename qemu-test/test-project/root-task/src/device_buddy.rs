@@ -0,0 +1,64 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::DeviceBuddy;
+use ferros::arch::PageBits;
+use ferros::cap::*;
+
+#[ferros_test::ferros_test]
+pub fn device_buddy(
+    local_slots: LocalCNodeSlots<U64>,
+    devices: &mut DeviceBuddy,
+    root_cnode: &LocalCap<LocalCNode>,
+) -> Result<(), TopLevelError> {
+    let mut slots = local_slots.weaken();
+
+    // The smallest free untyped needs the fewest splits to get a page
+    // out of.
+    let before = devices.available().count();
+    let (paddr, size_bits) = devices
+        .available()
+        .min_by_key(|&(_, size_bits)| size_bits)
+        .ok_or(TopLevelError::TestAssertionFailure(
+            "The platform should have device untypeds",
+        ))?;
+
+    let page = devices.alloc(&mut slots, paddr, PageBits::U8)?;
+    if page.paddr() != paddr || page.size_bits() != PageBits::U8 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "The page should start where its untyped did",
+        ));
+    }
+
+    // Each split leaves its other half free.
+    let splits = usize::from(size_bits - PageBits::U8);
+    if devices.available().count() != before - 1 + splits
+        || devices.available().any(|(p, _)| p == paddr)
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Splitting should leave the rest of the untyped free",
+        ));
+    }
+
+    // Freeing the page merges every half back into the original.
+    devices.free(page, root_cnode)?;
+    if devices.available().count() != before
+        || !devices.available().any(|entry| entry == (paddr, size_bits))
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Freeing the page should merge the untyped back together",
+        ));
+    }
+
+    // Splitting it again reuses the slots the merges emptied.
+    let slots_left = slots.stats().remaining;
+    let page = devices.alloc(&mut slots, paddr, PageBits::U8)?;
+    if slots.stats().remaining != slots_left {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Splits should reuse the slots freed by merging",
+        ));
+    }
+    devices.free(page, root_cnode)?;
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use typenum::*;
+
+use ferros::arch::PageBytes;
+use ferros::vspace::dma::{direction, DmaBuffer};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn dma_buffer_ownership(
+    local_mapped_region: MappedMemoryRegion<U14, shared_status::Exclusive>,
+) -> Result<(), TopLevelError> {
+    let expected_paddr = local_mapped_region.paddr()?;
+    let mut buffer = DmaBuffer::from_region(local_mapped_region)?;
+    if buffer.paddr() != expected_paddr || buffer.paddr() % PageBytes::USIZE != 0 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "A DMA buffer should report the page-aligned physical address of its region",
+        ));
+    }
+
+    for (i, b) in buffer.as_mut_slice().iter_mut().enumerate() {
+        *b = i as u8;
+    }
+
+    let buffer = buffer
+        .into_device::<direction::ToDevice>()
+        .map_err(|(e, _)| e)?;
+    let buffer = buffer.into_cpu().map_err(|(e, _)| e)?;
+
+    // Neither a clean nor an invalidate after a clean may lose what the
+    // CPU wrote
+    let buffer = buffer
+        .into_device::<direction::Bidirectional>()
+        .map_err(|(e, _)| e)?;
+    let buffer = buffer.into_cpu().map_err(|(e, _)| e)?;
+    if buffer
+        .as_slice()
+        .iter()
+        .enumerate()
+        .any(|(i, b)| *b != i as u8)
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Cleaned DMA buffer contents should survive handing the buffer back",
+        ));
+    }
+
+    let buffer = buffer
+        .into_device::<direction::FromDevice>()
+        .map_err(|(e, _)| e)?;
+    let buffer = buffer.into_cpu().map_err(|(e, _)| e)?;
+    let _region = buffer.into_region();
+    Ok(())
+}
@@ -1,14 +1,15 @@
 use typenum::*;
 
 use ferros::alloc::{smart_alloc, ut_buddy};
-use ferros::arch::{self, CodePageCount, CodePageTableCount, PageBytes};
+use ferros::arch::{CodePageCount, CodePageTableCount, PageBytes};
 use ferros::bootstrap::UserImage;
 use ferros::cap::*;
 use ferros::userland::{
     fault_or_message_channel, CapRights, FaultOrMessage, RetypeForSetup, Sender, StandardProcess,
 };
 use ferros::vspace::{
-    shared_status, MappedMemoryRegion, ProcessCodeImageConfig, UnmappedMemoryRegion, VSpace,
+    memory_attributes, shared_status, MappedMemoryRegion, ProcessCodeImageConfig,
+    UnmappedMemoryRegion, VSpace,
 };
 
 use super::TopLevelError;
@@ -69,7 +70,7 @@ pub fn grandkid_process_runs(
             let child_mapped_region = child_vspace.map_region_and_move(
                 child_unmapped_region,
                 CapRights::RW,
-                arch::vm_attributes::DEFAULT,
+                memory_attributes::Cached,
                 cnode,
                 slots_c,
             )?;
@@ -0,0 +1,132 @@
+use ferros::alloc::ut_buddy::weak_ut_buddy;
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::arch::PageBytes;
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::CapRights;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn guard_pages(
+    local_slots: LocalCNodeSlots<U2048>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let packed_region: UnmappedMemoryRegion<U12, _> = UnmappedMemoryRegion::new(ut, slots)?;
+        let guarded_region: UnmappedMemoryRegion<U14, _> = UnmappedMemoryRegion::new(ut, slots)?;
+        let stack_region: UnmappedMemoryRegion<U14, _> = UnmappedMemoryRegion::new(ut, slots)?;
+        let spare_ut: LocalCap<Untyped<U16>> = ut;
+        let spare_slots: LocalCNodeSlots<U64> = slots;
+    });
+
+    // By default, regions are packed back to back
+    let packed =
+        child_vspace.map_region(packed_region, CapRights::RW, memory_attributes::Cached)?;
+    if !child_vspace.guards().is_empty() {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Regions should get no guards by default",
+        ));
+    }
+
+    child_vspace.set_guard_spans(GuardSpans {
+        stack_pages: 2,
+        region_pages: 1,
+    });
+    let guarded =
+        child_vspace.map_region(guarded_region, CapRights::RW, memory_attributes::Cached)?;
+    let packed_end = packed.vaddr() + packed.size_bytes();
+    if guarded.vaddr() - packed_end < PageBytes::USIZE {
+        return Err(TopLevelError::TestAssertionFailure(
+            "A guarded region should not be mapped right after the previous one",
+        ));
+    }
+    child_vspace.name_region(guarded.vaddr(), "rx ring");
+    let guarded_end = guarded.vaddr() + guarded.size_bytes();
+    let overrun = child_vspace.guard_at(guarded_end).map_or(false, |guard| {
+        guard.kind == GuardKind::Region
+            && guard.side == GuardSide::Above
+            && guard.region_start == guarded.vaddr()
+            && guard.name() == Some("rx ring")
+    });
+    if !overrun {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Running off the end of a region should land in its named guard",
+        ));
+    }
+
+    let stack = child_vspace.map_stack_region(stack_region, memory_attributes::Cached)?;
+    if stack.vaddr() - guarded_end < 3 * PageBytes::USIZE {
+        return Err(TopLevelError::TestAssertionFailure(
+            "A stack should be preceded by its own guard, beyond the region's",
+        ));
+    }
+    let overflow = child_vspace
+        .guard_at(stack.vaddr() - 1)
+        .map_or(false, |guard| {
+            guard.kind == GuardKind::Stack
+                && guard.side == GuardSide::Below
+                && guard.name().is_none()
+        });
+    if !overflow {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Running off the bottom of a stack should land in its guard",
+        ));
+    }
+    if child_vspace.guard_at(stack.vaddr()).is_some() || child_vspace.guards().len() != 4 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Only the spans either side of guarded regions should be guards",
+        ));
+    }
+
+    // Once the table of guards is full, regions still get their guards,
+    // which just go unlisted.
+    let mut spare_ut = weak_ut_buddy(spare_ut.weaken());
+    let mut spare_slots = spare_slots.weaken();
+    let mut previous_end = stack.vaddr() + stack.size_bytes();
+    let extra_regions = MAX_GUARDS / 2 + 1;
+    for _ in 0..extra_regions {
+        let ut = spare_ut.alloc(&mut spare_slots, 12)?;
+        let region = WeakMemoryRegion::new(ut, &mut spare_slots)?;
+        let region = child_vspace.weak_map_region(
+            region,
+            CapRights::RW,
+            memory_attributes::Cached.vm_attributes(),
+        )?;
+        if region.vaddr() - previous_end < PageBytes::USIZE {
+            return Err(TopLevelError::TestAssertionFailure(
+                "A guarded region should be kept apart from the previous one",
+            ));
+        }
+        previous_end = region.vaddr() + region.size_bytes();
+    }
+    if child_vspace.guards().len() != MAX_GUARDS
+        || child_vspace.untracked_guards() != 4 + 2 * extra_regions - MAX_GUARDS
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Guards beyond the table's capacity should be counted as untracked",
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::arch::{self, PageBits};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::CapRights;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+type U33768 = Sum<U32768, U1000>;
+
+#[ferros_test::ferros_test]
+pub fn large_frame_mapping(
+    local_slots: LocalCNodeSlots<U33768>,
+    local_ut: LocalCap<Untyped<U22>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let region_ut: LocalCap<Untyped<U21>> = ut;
+        let region_slots: LocalCNodeSlots<U16> = slots;
+    });
+
+    let region =
+        WeakMemoryRegion::new_with_large_frames(region_ut.weaken(), &mut region_slots.weaken())?;
+    if region.granule_bits() <= PageBits::U8 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "A 2MB region should be backed by large frames",
+        ));
+    }
+    let granule_bytes = 1 << region.granule_bits();
+
+    let mapped =
+        child_vspace.weak_map_region(region, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+    if mapped.vaddr() % granule_bytes != 0 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Large frames should be mapped at an address aligned to their size",
+        ));
+    }
+    let vaddr = mapped.vaddr();
+    let region = child_vspace.weak_unmap_region(mapped)?;
+
+    // Large frames can't be mapped part-way into a frame-sized chunk of
+    // address space
+    let region = match child_vspace.weak_map_region_at_addr(
+        region,
+        vaddr + arch::PageBytes::USIZE,
+        CapRights::RW,
+        arch::vm_attributes::DEFAULT,
+    ) {
+        Err((VSpaceError::MappingError(MappingError::AddrNotPageAligned), region)) => region,
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "Mapping a large frame at a misaligned address should fail",
+            ))
+        }
+    };
+
+    let mapped = child_vspace
+        .weak_map_region_at_addr(region, vaddr, CapRights::RW, arch::vm_attributes::DEFAULT)
+        .map_err(|(e, _)| e)?;
+    let _ = child_vspace.weak_unmap_region(mapped)?;
+    Ok(())
+}
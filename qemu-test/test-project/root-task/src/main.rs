@@ -12,30 +12,59 @@ extern crate selfe_sys;
 #[macro_use]
 extern crate typenum;
 
+mod allocator_stats;
+mod asid_manager;
+mod badge_bits;
 mod call_and_response_loop;
 mod child_process_cap_management;
 mod child_process_runs;
+mod child_thread_joins;
 mod child_thread_runs;
+#[cfg(test_case = "const_generics")]
+mod const_generic_capacity;
+mod copy_on_write;
+mod core_dump;
+mod device_buddy;
+mod dma_buffer_ownership;
 mod dont_tread_on_me;
 mod double_door_backpressure;
+mod elf_process_panics;
 mod elf_process_runs;
 mod fault_or_message_handler;
 mod fault_pair;
 mod grandkid_process_runs;
+mod guard_pages;
 mod irq_control_manipulation;
+mod large_frame_mapping;
+mod memory_attributes_mapping;
 mod memory_read_protection;
+mod memory_server;
+mod memory_server_ipc;
 mod memory_write_protection;
+mod object_factory;
 mod over_register_size_params;
 mod polling_consumer;
+mod process_exit_codes;
+mod region_protection;
 mod reuse_slots;
 mod reuse_untyped;
 mod root_task_runs;
 mod self_hosted_mem_mgmt;
+mod shared_elf_segments;
 mod shared_page_queue;
+#[cfg(test_case = "slot_shortfall")]
+mod slot_shortfall;
+mod stack_guard_spans;
 mod stack_setup;
+mod sync_contention;
+mod sync_primitives;
+mod two_level_cspace;
 mod uart;
+mod vm_fault_decoding;
+mod vspace_layout;
 mod weak_elf;
 mod wutbuddy;
+mod wutbuddy_free;
 
 mod resources {
     include! {concat!(env!("OUT_DIR"), "/resources.rs")}
@@ -46,46 +75,86 @@ extern "C" {
     static _selfe_arc_data_end: usize;
 }
 
+use ferros::alloc::asid_manager::ASIDManagerError;
+use ferros::alloc::device_buddy::DeviceBuddyError;
 use ferros::alloc::micro_alloc::Error as AllocError;
+use ferros::alloc::object_factory::ObjectFactoryError;
 use ferros::alloc::ut_buddy::UTBuddyError;
+use ferros::cap::CSpaceError;
 use ferros::cap::IRQError;
 use ferros::cap::RetypeError;
 use ferros::error::SeL4Error;
 use ferros::userland::{
     FaultManagementError, IPCError, MultiConsumerError, ProcessSetupError, ThreadSetupError,
 };
-use ferros::vspace::VSpaceError;
+use ferros::vspace::{CowError, VSpaceError};
 
-#[cfg(not(test_case = "uart"))]
+#[cfg(not(any(
+    test_case = "uart",
+    test_case = "memory_server_ipc",
+    test_case = "slot_shortfall"
+)))]
 use ferros_test::ferros_test_main;
 
-#[cfg(not(test_case = "uart"))]
+#[cfg(not(any(
+    test_case = "uart",
+    test_case = "memory_server_ipc",
+    test_case = "const_generics",
+    test_case = "slot_shortfall"
+)))]
 ferros_test_main!(&[
+    &allocator_stats::allocator_stats,
+    &asid_manager::asid_manager,
+    &badge_bits::badge_bits,
     &call_and_response_loop::call_and_response_loop,
     &child_process_cap_management::child_process_cap_management,
     &child_process_runs::child_process_runs,
+    &child_thread_joins::child_thread_joins,
     &child_thread_runs::child_thread_runs,
+    &copy_on_write::copy_on_write,
+    &core_dump::core_dump,
+    &device_buddy::device_buddy,
+    &dma_buffer_ownership::dma_buffer_ownership,
     &dont_tread_on_me::dont_tread_on_me,
     &double_door_backpressure::double_door_backpressure,
+    &elf_process_panics::elf_process_panics,
     &elf_process_runs::elf_process_runs,
     &fault_or_message_handler::fault_or_message_handler,
     &fault_pair::fault_pair,
     &grandkid_process_runs::grandkid_process_runs,
+    &guard_pages::guard_pages,
     &irq_control_manipulation::irq_control_manipulation,
+    &large_frame_mapping::large_frame_mapping,
+    &memory_attributes_mapping::memory_attributes_mapping,
     &memory_read_protection::memory_read_protection,
+    &memory_server::memory_server,
     &memory_write_protection::memory_write_protection,
+    &object_factory::object_factory,
     &over_register_size_params::over_register_size_params,
     &polling_consumer::polling_consumer,
+    &process_exit_codes::process_exit_codes,
+    &region_protection::region_protection,
     &reuse_slots::reuse_slots,
     &reuse_untyped::reuse_untyped,
     &root_task_runs::root_task_runs,
     &self_hosted_mem_mgmt::self_hosted_mem_mgmt,
+    &shared_elf_segments::shared_elf_segments,
     &shared_page_queue::shared_page_queue,
+    &stack_guard_spans::stack_guard_spans,
     &stack_setup::stack_setup,
+    &sync_contention::sync_contention,
+    &sync_primitives::sync_primitives,
+    &two_level_cspace::two_level_cspace,
+    &vm_fault_decoding::vm_fault_decoding,
+    &vspace_layout::vspace_layout,
     &wutbuddy::wutbuddy,
+    &wutbuddy_free::wutbuddy_free,
     &weak_elf::weak_elf_process_runs,
 ]);
 
+#[cfg(test_case = "const_generics")]
+ferros_test_main!(&[&const_generic_capacity::const_generic_capacity]);
+
 #[cfg(test_case = "uart")]
 fn main() {
     debug_println!("Starting the test!");
@@ -103,6 +172,19 @@ pub fn run(raw_boot_info: &'static selfe_sys::seL4_BootInfo) {
     }
 }
 
+#[cfg(test_case = "memory_server_ipc")]
+fn main() {
+    debug_println!("Starting the test!");
+    let bootinfo = unsafe { &*sel4_start::BOOTINFO };
+    memory_server_ipc::run(bootinfo).expect("run");
+}
+
+#[cfg(test_case = "slot_shortfall")]
+fn main() {
+    let bootinfo = unsafe { &*sel4_start::BOOTINFO };
+    slot_shortfall::run(bootinfo);
+}
+
 #[derive(Debug)]
 pub enum TopLevelError {
     AllocError(AllocError),
@@ -116,6 +198,11 @@ pub enum TopLevelError {
     ThreadSetupError(ThreadSetupError),
     UTBuddyError(UTBuddyError),
     RetypeError(RetypeError),
+    CowError(CowError),
+    CSpaceError(CSpaceError),
+    ASIDManagerError(ASIDManagerError),
+    ObjectFactoryError(ObjectFactoryError),
+    DeviceBuddyError(DeviceBuddyError),
     TestAssertionFailure(&'static str),
 }
 
@@ -184,3 +271,32 @@ impl From<RetypeError> for TopLevelError {
         TopLevelError::RetypeError(e)
     }
 }
+impl From<CowError> for TopLevelError {
+    fn from(e: CowError) -> Self {
+        TopLevelError::CowError(e)
+    }
+}
+
+impl From<CSpaceError> for TopLevelError {
+    fn from(e: CSpaceError) -> Self {
+        TopLevelError::CSpaceError(e)
+    }
+}
+
+impl From<ASIDManagerError> for TopLevelError {
+    fn from(e: ASIDManagerError) -> Self {
+        TopLevelError::ASIDManagerError(e)
+    }
+}
+
+impl From<ObjectFactoryError> for TopLevelError {
+    fn from(e: ObjectFactoryError) -> Self {
+        TopLevelError::ObjectFactoryError(e)
+    }
+}
+
+impl From<DeviceBuddyError> for TopLevelError {
+    fn from(e: DeviceBuddyError) -> Self {
+        TopLevelError::DeviceBuddyError(e)
+    }
+}
@@ -0,0 +1,76 @@
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::arch::vm_attributes;
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::CapRights;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn memory_attributes_mapping(
+    local_slots: LocalCNodeSlots<U2048>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let uncached_region: UnmappedMemoryRegion<U14, _> = UnmappedMemoryRegion::new(ut, slots)?;
+        let no_exec_region: UnmappedMemoryRegion<U14, _> = UnmappedMemoryRegion::new(ut, slots)?;
+    });
+
+    if memory_attributes::Uncached.vm_attributes() & vm_attributes::PAGE_CACHEABLE != 0 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Uncached mappings should not be cacheable",
+        ));
+    }
+    let no_exec = memory_attributes::ExecuteNever(memory_attributes::Cached);
+    if no_exec.vm_attributes()
+        != memory_attributes::Cached.vm_attributes() | vm_attributes::EXECUTE_NEVER
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "ExecuteNever should only add the execute-never attribute",
+        ));
+    }
+
+    let uncached: MappedMemoryRegion<_, _, role::Local, memory_attributes::Uncached> =
+        child_vspace.map_region(uncached_region, CapRights::RW, memory_attributes::Uncached)?;
+    if uncached.rights() != CapRights::RW {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Mapping with attributes should keep the requested rights",
+        ));
+    }
+
+    // Only cacheable mappings offer cache maintenance
+    let no_exec = child_vspace.map_region(no_exec_region, CapRights::RW, no_exec)?;
+    no_exec.flush()?;
+    no_exec.flush_range(no_exec.vaddr(), 1)?;
+
+    let uncached_region = child_vspace.unmap_region(uncached)?;
+    let _ = child_vspace.unmap_region(no_exec)?;
+
+    // An unmapped region can be mapped again with different attributes
+    let cached =
+        child_vspace.map_region(uncached_region, CapRights::R, memory_attributes::Cached)?;
+    cached.flush()?;
+    let _ = child_vspace.unmap_region(cached)?;
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use ferros::alloc::ut_buddy::weak_ut_buddy;
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::arch::PageBytes;
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{CapRights, MemoryRefusal, MemoryRequest, MemoryResponse, MemoryServer};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn memory_server(
+    local_slots: LocalCNodeSlots<U2048>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+        let (_child_cnode, child_slots) = retype_cnode::<U4>(ut, slots)?;
+
+        let probe_ut: LocalCap<Untyped<U13>> = ut;
+        let probe_slots: LocalCNodeSlots<U2> = slots;
+        let server_ut: LocalCap<Untyped<U14>> = ut;
+        let server_slots: LocalCNodeSlots<U32> = slots;
+    });
+
+    // Find two free pages in the client's address space for its heap.
+    let probe = WeakMemoryRegion::new(probe_ut.weaken(), &mut probe_slots.weaken())?;
+    let probe = child_vspace.weak_map_region(
+        probe,
+        CapRights::RW,
+        memory_attributes::Cached.vm_attributes(),
+    )?;
+    let heap = probe.vaddr();
+    let _ = child_vspace.weak_unmap_region(probe)?;
+
+    let client = Badge::from(1);
+    let mut server = MemoryServer::new(
+        weak_ut_buddy(server_ut.weaken()),
+        server_slots.weaken(),
+        root_cnode,
+    );
+    server
+        .add_client(
+            client,
+            2 * PageBytes::USIZE,
+            child_vspace,
+            child_slots.weaken(),
+        )
+        .map_err(|_| TopLevelError::TestAssertionFailure("The client should be added"))?;
+
+    if server.handle(Badge::from(2), MemoryRequest::Quota)
+        != MemoryResponse::Refused(MemoryRefusal::UnknownClient)
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Requests with an unknown badge should be refused",
+        ));
+    }
+    if server.handle(
+        client,
+        MemoryRequest::Pages {
+            count: 1,
+            vaddr: heap + 1,
+        },
+    ) != MemoryResponse::Refused(MemoryRefusal::InvalidRequest)
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Pages should only be mapped at aligned addresses",
+        ));
+    }
+
+    let first_page = match server.handle(
+        client,
+        MemoryRequest::Pages {
+            count: 1,
+            vaddr: heap,
+        },
+    ) {
+        MemoryResponse::Pages { grant } => grant,
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "A page within the quota should be granted",
+            ))
+        }
+    };
+    match server.handle(client, MemoryRequest::Untyped { size_bits: 12 }) {
+        MemoryResponse::Untyped { .. } => (),
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "An untyped within the quota should be granted",
+            ))
+        }
+    }
+
+    let second_page = MemoryRequest::Pages {
+        count: 1,
+        vaddr: heap + PageBytes::USIZE,
+    };
+    if server.handle(client, second_page) != MemoryResponse::Refused(MemoryRefusal::QuotaExceeded) {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Requests beyond the quota should be refused",
+        ));
+    }
+    if server.handle(client, MemoryRequest::Quota)
+        != (MemoryResponse::Quota {
+            used: 2 * PageBytes::USIZE,
+            limit: 2 * PageBytes::USIZE,
+        })
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Both grants should count against the quota",
+        ));
+    }
+
+    if server.handle(client, MemoryRequest::Return { grant: first_page })
+        != MemoryResponse::Returned
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "A granted page should be taken back",
+        ));
+    }
+    if server.handle(client, MemoryRequest::Return { grant: first_page })
+        != MemoryResponse::Refused(MemoryRefusal::UnknownGrant)
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "A grant should only be taken back once",
+        ));
+    }
+    match server.handle(client, second_page) {
+        MemoryResponse::Pages { .. } => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Returning a grant should make room in the quota",
+        )),
+    }
+}
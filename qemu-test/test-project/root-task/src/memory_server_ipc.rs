@@ -0,0 +1,218 @@
+use selfe_sys::*;
+
+use typenum::*;
+
+use ferros::alloc::ut_buddy::weak_ut_buddy;
+use ferros::alloc::{self, micro_alloc, smart_alloc};
+use ferros::arch::PageBytes;
+use ferros::bootstrap::{root_cnode, BootInfo};
+use ferros::cap::*;
+use ferros::userland::{
+    call_channel, Caller, CapRights, DefaultStackBitSize, MemoryRefusal, MemoryRequest,
+    MemoryResponse, MemoryServer, RetypeForSetup, StandardProcess,
+};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+/// Serve memory from the root task, as a memory server is meant to be
+/// run, to a badged client process which reports how its requests went.
+/// Serving never returns.
+pub fn run(raw_boot_info: &'static seL4_BootInfo) -> Result<(), TopLevelError> {
+    let (mut allocator, _device_allocator) = micro_alloc::bootstrap_allocators(&raw_boot_info)?;
+    let (root_cnode, local_slots) = root_cnode(&raw_boot_info);
+    let (root_vspace_slots, local_slots): (LocalCNodeSlots<U100>, _) = local_slots.alloc();
+    let BootInfo {
+        mut root_vspace,
+        asid_control,
+        user_image,
+        root_tcb,
+        ..
+    } = BootInfo::wrap(
+        &raw_boot_info,
+        allocator
+            .get_untyped::<U13>()
+            .expect("Initial untyped retrieval failure"),
+        root_vspace_slots,
+    );
+    let uts = alloc::ut_buddy(
+        allocator
+            .get_untyped::<U21>()
+            .expect("initial alloc failure"),
+    );
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (asid_pool, _asid_control) = asid_control.allocate_asid_pool(ut, slots)?;
+        let (client_asid, _asid_pool) = asid_pool.alloc();
+
+        let client_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let client_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut client_vspace = VSpace::new(
+            retype(ut, slots)?,
+            client_asid,
+            client_vspace_slots.weaken(),
+            client_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            &user_image,
+            &root_cnode,
+        )?;
+        let (client_cnode, client_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let (ipc_setup, responder) = call_channel(ut, &root_cnode, slots, slots)?;
+        let (caller_slot, client_slots) = client_slots.alloc();
+        let caller = ipc_setup.create_badged_caller(caller_slot, Badge::from(CLIENT_BADGE))?;
+        let (grant_slots, _client_slots): (LocalCap<CNodeSlotsData<U2, role::Child>>, _) =
+            client_slots.alloc();
+
+        let probe_ut: LocalCap<Untyped<U13>> = ut;
+        let probe_slots: LocalCNodeSlots<U2> = slots;
+        let server_ut: LocalCap<Untyped<U14>> = ut;
+        let server_slots: LocalCNodeSlots<U8> = slots;
+
+        let unmapped_region: UnmappedMemoryRegion<DefaultStackBitSize, _> =
+            UnmappedMemoryRegion::new(ut, slots)?;
+        let mapped_region =
+            root_vspace.map_region(unmapped_region, CapRights::RW, memory_attributes::Cached)?;
+
+        // Hold on to two free pages for the client's heap while its stack
+        // and IPC buffer are mapped.
+        let probe = WeakMemoryRegion::new(probe_ut.weaken(), &mut probe_slots.weaken())?;
+        let probe = client_vspace.weak_map_region(
+            probe,
+            CapRights::RW,
+            memory_attributes::Cached.vm_attributes(),
+        )?;
+        let heap = probe.vaddr();
+
+        let mut client_process = StandardProcess::new(
+            &mut client_vspace,
+            client_cnode,
+            mapped_region,
+            &root_cnode,
+            client_main as extern "C" fn(_) -> (),
+            ClientParams { caller, heap },
+            ut,
+            ut,
+            slots,
+            root_tcb.as_ref(),
+            None, // fault
+        )?;
+    });
+    let _ = client_vspace.weak_unmap_region(probe)?;
+
+    let mut server = MemoryServer::new(
+        weak_ut_buddy(server_ut.weaken()),
+        server_slots.weaken(),
+        &root_cnode,
+    );
+    server
+        .add_client(
+            Badge::from(CLIENT_BADGE),
+            2 * PageBytes::USIZE,
+            client_vspace,
+            grant_slots.weaken(),
+        )
+        .map_err(|_| TopLevelError::TestAssertionFailure("The client should be added"))?;
+
+    client_process.start()?;
+    server.serve(responder)?;
+    Ok(())
+}
+
+const CLIENT_BADGE: usize = 0b101;
+
+pub struct ClientParams<Role: CNodeRole> {
+    pub caller: Caller<MemoryRequest, MemoryResponse, Role>,
+    pub heap: usize,
+}
+
+impl RetypeForSetup for ClientParams<role::Local> {
+    type Output = ClientParams<role::Child>;
+}
+
+pub extern "C" fn client_main(params: ClientParams<role::Local>) {
+    match exercise_server(&params.caller, params.heap) {
+        Ok(()) => debug_println!("memory server client: ok"),
+        Err(reason) => debug_println!("memory server client: failed: {}", reason),
+    }
+}
+
+fn exercise_server(
+    caller: &Caller<MemoryRequest, MemoryResponse, role::Local>,
+    heap: usize,
+) -> Result<(), &'static str> {
+    let call = |request| {
+        caller
+            .blocking_call(&request)
+            .map_err(|_| "The call to the server failed")
+    };
+
+    let first_page = match call(MemoryRequest::Pages {
+        count: 1,
+        vaddr: heap,
+    })? {
+        MemoryResponse::Pages { grant } => grant,
+        _ => return Err("A page within the quota should be granted"),
+    };
+    write_and_check(heap, 0xdead_beef)?;
+
+    let untyped = match call(MemoryRequest::Untyped { size_bits: 12 })? {
+        MemoryResponse::Untyped { grant, .. } => grant,
+        _ => return Err("An untyped within the quota should be granted"),
+    };
+    let second_page = MemoryRequest::Pages {
+        count: 1,
+        vaddr: heap + PageBytes::USIZE,
+    };
+    if call(second_page)? != MemoryResponse::Refused(MemoryRefusal::QuotaExceeded) {
+        return Err("Requests beyond the quota should be refused");
+    }
+
+    for grant in [untyped, first_page].iter() {
+        if call(MemoryRequest::Return { grant: *grant })? != MemoryResponse::Returned {
+            return Err("Grants should be taken back");
+        }
+    }
+    if call(MemoryRequest::Quota)?
+        != (MemoryResponse::Quota {
+            used: 0,
+            limit: 2 * PageBytes::USIZE,
+        })
+    {
+        return Err("Returned grants should no longer count against the quota");
+    }
+
+    // Neither the server nor the client has the slots for all of these
+    // grants, unless those of returned grants are reused.
+    for _ in 0..8 {
+        let page = match call(second_page)? {
+            MemoryResponse::Pages { grant } => grant,
+            _ => return Err("A page should be granted again once returned"),
+        };
+        write_and_check(heap + PageBytes::USIZE, 0x1234_5678)?;
+        match call(MemoryRequest::Untyped { size_bits: 12 })? {
+            MemoryResponse::Untyped { grant, .. } => {
+                if call(MemoryRequest::Return { grant })? != MemoryResponse::Returned {
+                    return Err("An untyped should be taken back");
+                }
+            }
+            _ => return Err("An untyped should be granted again once returned"),
+        }
+        if call(MemoryRequest::Return { grant: page })? != MemoryResponse::Returned {
+            return Err("A page should be taken back");
+        }
+    }
+    Ok(())
+}
+
+fn write_and_check(vaddr: usize, value: usize) -> Result<(), &'static str> {
+    let ptr = vaddr as *mut usize;
+    let read = unsafe {
+        core::ptr::write_volatile(ptr, value);
+        core::ptr::read_volatile(ptr)
+    };
+    if read != value {
+        return Err("A granted page should be writable");
+    }
+    Ok(())
+}
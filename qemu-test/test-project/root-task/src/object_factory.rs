@@ -0,0 +1,74 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::object_factory::ObjectFactoryError;
+use ferros::alloc::ut_buddy::weak_ut_buddy;
+use ferros::alloc::ObjectFactory;
+use ferros::cap::*;
+
+#[ferros_test::ferros_test]
+pub fn object_factory(
+    local_slots: LocalCNodeSlots<U64>,
+    local_ut: LocalCap<Untyped<U14>>,
+    root_cnode: &LocalCap<LocalCNode>,
+) -> Result<(), TopLevelError> {
+    let mut factory = ObjectFactory::new(
+        weak_ut_buddy(local_ut.weaken()),
+        local_slots.weaken(),
+        root_cnode,
+    );
+
+    let endpoint: LocalCap<Endpoint> = factory.make()?;
+    let endpoint_cptr = endpoint.cptr;
+    let notifications = factory.make_many::<Notification>(3)?;
+    notifications.for_each(|notification| {
+        notification.signal();
+        notification.wait();
+        Ok::<_, TopLevelError>(())
+    })?;
+    match factory.make_region(11) {
+        Err(ObjectFactoryError::RegionSmallerThanPage(11)) => (),
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "Regions smaller than a page should be refused",
+            ))
+        }
+    }
+    let region = factory.make_region(12)?;
+    if factory.stats().allocated_bytes == 0 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Made objects should be backed by untyped memory",
+        ));
+    }
+
+    factory.delete_many(notifications)?;
+    factory.delete_region(region)?;
+    factory.delete(endpoint)?;
+    let emptied = factory.stats();
+    if emptied.allocated_bytes != 0 || emptied.free_count(14) != 1 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Deleting everything should merge the untyped back together",
+        ));
+    }
+
+    let endpoint: LocalCap<Endpoint> = factory.make()?;
+    if endpoint.cptr != endpoint_cptr {
+        return Err(TopLevelError::TestAssertionFailure(
+            "A new object should reuse the slot of the last one deleted",
+        ));
+    }
+    factory.delete(endpoint)?;
+
+    // Together these rounds need more than the 64 slots, unless those of
+    // deleted objects, and of merged splits, are reused.
+    for _ in 0..16 {
+        let endpoint: LocalCap<Endpoint> = factory.make()?;
+        let notifications = factory.make_many::<Notification>(3)?;
+        let region = factory.make_region(12)?;
+        factory.delete_region(region)?;
+        factory.delete_many(notifications)?;
+        factory.delete(endpoint)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use selfe_sys::{seL4_MessageInfo_new, seL4_Send};
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::*;
+use ferros::cap::*;
+use ferros::test_support::*;
+use ferros::userland::*;
+use ferros::vspace::*;
+
+use typenum::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn process_exit_codes(
+    mut outer_slots: LocalCNodeSlots<U32768>,
+    mut outer_ut: LocalCap<Untyped<U21>>,
+    mut asid_pool: LocalCap<ASIDPool<U512>>,
+    mut irq_control: LocalCap<IRQControl>,
+    mut local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    for c in [Command::Exit(7), Command::Panic, Command::ThrowFault].iter() {
+        with_temporary_resources(
+            &mut outer_slots,
+            &mut outer_ut,
+            &mut asid_pool,
+            &mut local_mapped_region,
+            &mut irq_control,
+            |inner_slots,
+             inner_ut,
+             inner_asid_pool,
+             mapped_region,
+             _inner_irq_control|
+             -> Result<(), TopLevelError> {
+                let uts = ut_buddy(inner_ut);
+                smart_alloc!(|slots: inner_slots, ut: uts| {
+                    let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+                    let (child_fault_source_slot, _child_slots) = child_slots.alloc();
+                    let (source, reporter, watcher) =
+                        exit_channel(&root_cnode, ut, slots, child_fault_source_slot, slots)?;
+                    let params = ProcParams {
+                        command: *c,
+                        reporter,
+                    };
+
+                    let (child_asid, _asid_pool) = inner_asid_pool.alloc();
+
+                    let child_root = retype(ut, slots)?;
+                    let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+                    let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+
+                    let mut child_vspace = VSpace::new(
+                        child_root,
+                        child_asid,
+                        child_vspace_slots.weaken(),
+                        child_vspace_ut.weaken(),
+                        ProcessCodeImageConfig::ReadOnly,
+                        user_image,
+                        root_cnode,
+                    )?;
+
+                    let mut child_process = StandardProcess::new(
+                        &mut child_vspace,
+                        child_cnode,
+                        mapped_region,
+                        root_cnode,
+                        proc_main as extern "C" fn(_) -> (),
+                        params,
+                        ut,
+                        ut,
+                        slots,
+                        tpa,
+                        Some(source),
+                    )?;
+                });
+                child_process.start()?;
+
+                let outcome = watcher.wait()?;
+                child_process.terminate(root_cnode)?;
+
+                match (c, outcome) {
+                    (Command::Exit(expected), ProcessOutcome::Exited(code)) => {
+                        if *expected != code {
+                            return Err(TopLevelError::TestAssertionFailure(
+                                "Child process reported the wrong exit code",
+                            ));
+                        }
+                    }
+                    (Command::Panic, ProcessOutcome::Panicked(report)) => {
+                        if report.message() != "no more work, 42 items left" {
+                            return Err(TopLevelError::TestAssertionFailure(
+                                "Child process panic message was not captured",
+                            ));
+                        }
+                        if report.line() == 0 || !report.file().ends_with("process_exit_codes.rs") {
+                            return Err(TopLevelError::TestAssertionFailure(
+                                "Child process panic location was not captured",
+                            ));
+                        }
+                    }
+                    (Command::ThrowFault, ProcessOutcome::Faulted(_)) => {}
+                    _ => {
+                        return Err(TopLevelError::TestAssertionFailure(
+                            "Child process ended in an unexpected way",
+                        ))
+                    }
+                }
+                Ok(())
+            },
+        )??;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub enum Command {
+    Exit(i32),
+    Panic,
+    ThrowFault,
+}
+
+pub struct ProcParams<Role: CNodeRole> {
+    pub command: Command,
+    pub reporter: ExitReporter<Role>,
+}
+
+impl RetypeForSetup for ProcParams<role::Local> {
+    type Output = ProcParams<role::Child>;
+}
+
+pub extern "C" fn proc_main(params: ProcParams<role::Local>) {
+    let ProcParams { command, reporter } = params;
+    match command {
+        Command::Exit(code) => reporter.exit(code),
+        Command::Panic => reporter.panicked(&PanicReport::new(
+            file!(),
+            line!(),
+            column!(),
+            format_args!("no more work, {} items left", 42),
+        )),
+        Command::ThrowFault => unsafe {
+            seL4_Send(
+                314159, // bogus cptr to nonexistent endpoint
+                seL4_MessageInfo_new(0, 0, 0, 0),
+            );
+        },
+    }
+}
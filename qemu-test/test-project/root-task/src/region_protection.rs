@@ -0,0 +1,84 @@
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::CapRights;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn region_protection(
+    local_slots: LocalCNodeSlots<U4096>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (other_asid, _asid_pool) = asid_pool.alloc();
+        let other_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let other_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut other_vspace = VSpace::new(
+            retype(ut, slots)?,
+            other_asid,
+            other_vspace_slots.weaken(),
+            other_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let unmapped_region: UnmappedMemoryRegion<U14, _> = UnmappedMemoryRegion::new(ut, slots)?;
+    });
+
+    let region =
+        child_vspace.map_region(unmapped_region, CapRights::RW, memory_attributes::Cached)?;
+    let vaddr = region.vaddr();
+
+    let sealed: MappedMemoryRegion<_, _, role::Local, _, mapped_rights::R> = child_vspace
+        .protect_region(region, mapped_rights::R {})
+        .map_err(|(e, _)| e)?;
+    if sealed.rights() != CapRights::R || sealed.vaddr() != vaddr {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Protecting a region should change its rights in place",
+        ));
+    }
+
+    // Only the address space a region is mapped in can protect it
+    let sealed = match other_vspace.protect_region(sealed, mapped_rights::RW {}) {
+        Err((VSpaceError::ASIDMismatch, Some(region))) => region,
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "Protecting a region mapped in another address space should fail",
+            ))
+        }
+    };
+
+    let unsealed = child_vspace
+        .protect_region(sealed, mapped_rights::RW {})
+        .map_err(|(e, _)| e)?;
+    if unsealed.rights() != CapRights::RW {
+        return Err(TopLevelError::TestAssertionFailure(
+            "A region should be able to regain the rights of its caps",
+        ));
+    }
+    let _ = child_vspace.unmap_region(unsealed)?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use super::TopLevelError;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::CapRights;
+use ferros::vspace::*;
+use selfe_arc;
+
+#[ferros_test::ferros_test]
+pub fn shared_elf_segments(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    mut local_vspace_scratch: &mut ScratchRegion,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    let archive_slice: &[u8] = unsafe {
+        core::slice::from_raw_parts(
+            &crate::_selfe_arc_data_start,
+            &crate::_selfe_arc_data_end as *const _ as usize
+                - &crate::_selfe_arc_data_start as *const _ as usize,
+        )
+    };
+
+    let archive = selfe_arc::read::Archive::from_slice(archive_slice);
+    let elf_data = archive
+        .file(crate::resources::ElfProcess::IMAGE_NAME)
+        .expect("find elf-process in arc");
+
+    // Resolved once, used for both instances
+    let segments = SharedElfSegments::new(elf_data, user_image)?;
+    if segments.page_count() == 0 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "An ELF image should have read-only segments to share",
+        ));
+    }
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (first_asid, asid_pool) = asid_pool.alloc();
+        let first_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let first_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let first_vspace = VSpace::new_from_shared_elf::<crate::resources::ElfProcess>(
+            retype(ut, slots)?,
+            first_asid,
+            first_vspace_slots.weaken(),
+            first_vspace_ut.weaken(),
+            &segments,
+            slots, // page_slots
+            ut,    // elf_writable_mem
+            &root_cnode,
+            &mut local_vspace_scratch,
+        )?;
+
+        let (second_asid, _asid_pool) = asid_pool.alloc();
+        let second_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let second_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let second_vspace = VSpace::new_from_shared_elf::<crate::resources::ElfProcess>(
+            retype(ut, slots)?,
+            second_asid,
+            second_vspace_slots.weaken(),
+            second_vspace_ut.weaken(),
+            &segments,
+            slots, // page_slots
+            ut,    // elf_writable_mem
+            &root_cnode,
+            &mut local_vspace_scratch,
+        )?;
+    });
+
+    let shared_bytes = |vspace: &VSpace| -> usize {
+        vspace
+            .mappings()
+            .iter()
+            .filter(|m| m.source == MappingSource::Image && m.rights == CapRights::R && m.shared)
+            .map(|m| m.size)
+            .sum()
+    };
+    if shared_bytes(&first_vspace) != segments.size_bytes()
+        || shared_bytes(&second_vspace) != segments.size_bytes()
+    {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Each instance should map all of the shared read-only segments",
+        ));
+    }
+    if first_vspace.mappings() != second_vspace.mappings() {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Instances of one image should be laid out identically",
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use selfe_sys::*;
+
+use typenum::*;
+
+use ferros::alloc::smart_alloc;
+use ferros::bootstrap::root_cnode;
+use ferros::cap::*;
+
+/// Ask a `smart_alloc!` block for more slots than it is given. This
+/// case must fail to build, with the block's summary as the error.
+pub fn run(raw_boot_info: &'static seL4_BootInfo) {
+    let (_root_cnode, local_slots) = root_cnode(&raw_boot_info);
+    let (local_slots, _): (LocalCNodeSlots<U4>, _) = local_slots.alloc();
+
+    smart_alloc!(|slots: local_slots| {
+        let child_slots: LocalCNodeSlots<U3> = slots;
+        let reply_slot: LocalCNodeSlot = slots;
+        let spare_slot: LocalCNodeSlot = slots;
+    });
+}
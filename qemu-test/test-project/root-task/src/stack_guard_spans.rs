@@ -0,0 +1,118 @@
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::arch::fault::Fault;
+use ferros::bootstrap::*;
+use ferros::cap::*;
+use ferros::userland::*;
+use ferros::vspace::*;
+
+use typenum::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn stack_guard_spans(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (child_fault_source_slot, _child_slots) = child_slots.alloc();
+        let (source, reporter, watcher) =
+            exit_channel(&root_cnode, ut, slots, child_fault_source_slot, slots)?;
+
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        // With region guards switched on as well, the stack must still
+        // only be surrounded by its own guards.
+        child_vspace.set_guard_spans(GuardSpans {
+            stack_pages: 1,
+            region_pages: 1,
+        });
+
+        let mut child_process = StandardProcess::new(
+            &mut child_vspace,
+            child_cnode,
+            local_mapped_region,
+            root_cnode,
+            proc_main as extern "C" fn(_) -> (),
+            ProcParams { reporter },
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(source),
+        )?;
+    });
+    child_process.start()?;
+
+    let fault = match watcher.wait()? {
+        ProcessOutcome::Faulted(Fault::VMFault(fault)) => fault,
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "Child process should have hit a VM fault",
+            ))
+        }
+    };
+    child_process.terminate(root_cnode)?;
+
+    let overflow = child_vspace.guard_at(fault.address).map_or(false, |guard| {
+        guard.kind == GuardKind::Stack && guard.side == GuardSide::Below
+    });
+    if !overflow {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Overflowing the stack should land in the stack's own guard",
+        ));
+    }
+
+    // The stack's two guards, and the IPC buffer's two region guards
+    let stack_guards = child_vspace
+        .guards()
+        .iter()
+        .filter(|guard| guard.kind == GuardKind::Stack)
+        .count();
+    if stack_guards != 2 || child_vspace.guards().len() != 4 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "The stack should not have picked up region guards",
+        ));
+    }
+    Ok(())
+}
+
+pub struct ProcParams<Role: CNodeRole> {
+    pub reporter: ExitReporter<Role>,
+}
+
+impl RetypeForSetup for ProcParams<role::Local> {
+    type Output = ProcParams<role::Child>;
+}
+
+pub extern "C" fn proc_main(params: ProcParams<role::Local>) {
+    debug_println!("Sum: {}", recurse(0));
+    params.reporter.exit(0)
+}
+
+fn recurse(depth: usize) -> usize {
+    if depth == usize::MAX {
+        return 0;
+    }
+    let frame = [depth; 64];
+    unsafe { core::ptr::read_volatile(&frame[63]) + recurse(depth + 1) }
+}
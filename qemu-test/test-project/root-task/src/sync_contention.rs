@@ -0,0 +1,111 @@
+use super::TopLevelError;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::cap::*;
+use ferros::sync::*;
+use ferros::userland::{RetypeForSetup, Thread};
+use ferros::vspace::*;
+
+pub struct Progress {
+    count: usize,
+    done: bool,
+}
+
+static PROGRESS: MutexState<Progress> = MutexState::new(Progress {
+    count: 0,
+    done: false,
+});
+static FINISHED: CondvarState = CondvarState::new();
+static STARTED: SemaphoreState = SemaphoreState::new(0);
+
+#[ferros_test::ferros_test]
+pub fn sync_contention(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    ipc_buffer_region: MappedMemoryRegion<U12, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+    local_vspace: &mut VSpace,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let stack_region: UnmappedMemoryRegion<U17, _> = UnmappedMemoryRegion::new(ut, slots)?;
+
+        let progress_setup = SyncSetup::new(&PROGRESS, ut, slots)?;
+        let progress: Mutex<Progress, role::Local> =
+            progress_setup.participant(root_cnode, slots)?;
+        let (slot, child_slots) = child_slots.alloc();
+        let child_progress = progress_setup.participant(root_cnode, slot)?;
+
+        let finished_setup = SyncSetup::new(&FINISHED, ut, slots)?;
+        let finished: Condvar<role::Local> = finished_setup.participant(root_cnode, slots)?;
+        let (slot, child_slots) = child_slots.alloc();
+        let child_finished = finished_setup.participant(root_cnode, slot)?;
+
+        let started_setup = SyncSetup::new(&STARTED, ut, slots)?;
+        let started: Semaphore<role::Local> = started_setup.participant(root_cnode, slots)?;
+        let (slot, _child_slots) = child_slots.alloc();
+        let child_started = started_setup.participant(root_cnode, slot)?;
+
+        let thread = Thread::new(
+            local_vspace,
+            child_cnode,
+            stack_region,
+            contender_main,
+            ContenderParams {
+                progress: child_progress,
+                finished: child_finished,
+                started: child_started,
+            },
+            ipc_buffer_region,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+            None, // tls
+        )?;
+    });
+
+    // Hold the lock while the thread starts, so that it has to wait for it.
+    let mut guard = progress.lock();
+    thread.start()?;
+
+    // Block until the thread is running; it then goes on to block on the
+    // lock we hold.
+    started.acquire();
+    guard.count += 1;
+
+    // Waiting lets go of the lock, which wakes the thread.
+    while !guard.done {
+        guard = finished.wait(guard);
+    }
+
+    if guard.count != 2 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Both threads should have counted while holding the lock",
+        ));
+    }
+    Ok(())
+}
+
+pub struct ContenderParams<Role: CNodeRole> {
+    pub progress: Mutex<Progress, Role>,
+    pub finished: Condvar<Role>,
+    pub started: Semaphore<Role>,
+}
+
+impl RetypeForSetup for ContenderParams<role::Local> {
+    type Output = ContenderParams<role::Child>;
+}
+
+pub extern "C" fn contender_main(params: ContenderParams<role::Local>) {
+    params.started.release();
+    let mut guard = params.progress.lock();
+    guard.count += 1;
+    guard.done = true;
+    params.finished.notify_one();
+}
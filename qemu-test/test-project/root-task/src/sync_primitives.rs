@@ -0,0 +1,54 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::cap::*;
+use ferros::sync::*;
+
+static COUNTER: MutexState<usize> = MutexState::new(0);
+static PERMITS: SemaphoreState = SemaphoreState::new(2);
+static ALONE: BarrierState = BarrierState::new(1);
+
+#[ferros_test::ferros_test]
+pub fn sync_primitives(
+    local_slots: LocalCNodeSlots<U6>,
+    mutex_ut: LocalCap<Untyped<U4>>,
+    semaphore_ut: LocalCap<Untyped<U4>>,
+    barrier_ut: LocalCap<Untyped<U4>>,
+    root_cnode: &LocalCap<LocalCNode>,
+) -> Result<(), TopLevelError> {
+    let (slot, local_slots) = local_slots.alloc();
+    let setup = SyncSetup::new(&COUNTER, mutex_ut, slot)?;
+    let (slot, local_slots) = local_slots.alloc();
+    let counter: Mutex<usize, role::Local> = setup.participant(root_cnode, slot)?;
+
+    {
+        let mut guard = counter.lock();
+        *guard += 1;
+        if counter.try_lock().is_some() {
+            return Err(TopLevelError::TestAssertionFailure(
+                "Mutex should not be lockable twice",
+            ));
+        }
+    }
+    assert_eq!(*counter.lock(), 1);
+
+    let (slot, local_slots) = local_slots.alloc();
+    let setup = SyncSetup::new(&PERMITS, semaphore_ut, slot)?;
+    let (slot, local_slots) = local_slots.alloc();
+    let permits: Semaphore<role::Local> = setup.participant(root_cnode, slot)?;
+    permits.acquire();
+    assert!(permits.try_acquire());
+    assert!(!permits.try_acquire());
+    permits.release();
+    assert!(permits.try_acquire());
+
+    let (slot, local_slots) = local_slots.alloc();
+    let setup = SyncSetup::new(&ALONE, barrier_ut, slot)?;
+    let (slot, _local_slots) = local_slots.alloc();
+    let barrier: Barrier<role::Local> = setup.participant(root_cnode, slot)?;
+    assert!(barrier.wait());
+    assert!(barrier.wait());
+
+    Ok(())
+}
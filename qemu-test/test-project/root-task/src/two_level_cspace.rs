@@ -0,0 +1,100 @@
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::arch::TCBBits;
+use ferros::cap::*;
+use ferros::userland::{CapRights, RetypeForSetup, Thread};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn two_level_cspace(
+    local_slots: LocalCNodeSlots<U128>,
+    local_ut: LocalCap<Untyped<U20>>,
+    ipc_buffer_region: MappedMemoryRegion<U12, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+    local_vspace: &mut VSpace,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (cspace, root_slots) = retype_two_level_cnode::<U4, U12>(ut, 0, slots)?;
+        let (first_root_slot, root_slots) = root_slots.alloc::<U1>();
+        let (second_root_slot, _root_slots) = root_slots.alloc::<U1>();
+        let first_sub_slots = cspace.add_sub_cnode(ut, first_root_slot, slots, root_cnode)?;
+        let second_sub_slots = cspace.add_sub_cnode(ut, second_root_slot, slots, root_cnode)?;
+        let local_endpoint: LocalCap<Endpoint> = retype(ut, slots)?;
+        let local_notification: LocalCap<Notification> = retype(ut, slots)?;
+        let endpoint_ut: LocalCap<Untyped<U4>> = ut;
+        let stack_region: UnmappedMemoryRegion<U17, _> = UnmappedMemoryRegion::new(ut, slots)?;
+        let tcb_ut: LocalCap<Untyped<TCBBits>> = ut;
+        let tcb_slots: LocalCNodeSlots<U1> = slots;
+    });
+
+    // Between them, the two sub-CNodes hold more slots than a
+    // single-level child CNode's 4096.
+    let sub_bits = core::mem::size_of::<usize>() * 8 - 4;
+
+    let (_skipped, last_slots) = first_sub_slots.alloc::<U4094>();
+    let (notification_slot, last_slot) = last_slots.alloc::<U1>();
+    let copied = local_endpoint.copy_to_path(root_cnode, last_slot, CapRights::RWG)?;
+    if copied.cptr != (1 << sub_bits) | 4095 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "copied endpoint has the wrong path",
+        ));
+    }
+
+    let (first_slot, _rest) = second_sub_slots.alloc::<U1>();
+    let retyped: ChildCap<Endpoint> = endpoint_ut.retype_to_path(first_slot)?;
+    if retyped.cptr != 2 << sub_bits {
+        return Err(TopLevelError::TestAssertionFailure(
+            "retyped endpoint has the wrong path",
+        ));
+    }
+
+    // A signal only gets through if the child's CSpace resolves the path
+    // the way it was built, guards included.
+    let notification = local_notification.mint_to_path(
+        root_cnode,
+        notification_slot,
+        CapRights::RWG,
+        Badge::from(CHILD_BADGE),
+    )?;
+    let child = Thread::new(
+        local_vspace,
+        cspace.into_cspace_root(),
+        stack_region,
+        child_main,
+        ChildParams { notification },
+        ipc_buffer_region,
+        tcb_ut,
+        tcb_slots,
+        tpa,
+        None, // fault
+        None, // tls
+    )?;
+    child.start()?;
+
+    if local_notification.wait() != Badge::from(CHILD_BADGE) {
+        return Err(TopLevelError::TestAssertionFailure(
+            "the child should signal through its path-addressed notification",
+        ));
+    }
+    Ok(())
+}
+
+const CHILD_BADGE: usize = 0b11;
+
+pub struct ChildParams<Role: CNodeRole> {
+    pub notification: Cap<Notification, Role>,
+}
+
+impl RetypeForSetup for ChildParams<role::Local> {
+    type Output = ChildParams<role::Child>;
+}
+
+pub extern "C" fn child_main(params: ChildParams<role::Local>) {
+    params.notification.signal();
+}
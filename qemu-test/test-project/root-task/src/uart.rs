@@ -113,7 +113,7 @@ pub fn run(raw_boot_info: &'static seL4_BootInfo) -> Result<(), TopLevelError> {
         let uart1_page_1 = uart1_vspace.map_region(
             unmapped_uart1_page1,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT,
+            memory_attributes::Cached,
         )?;
         assert!(uart1_page_1.paddr().unwrap() == UART1_PADDR);
 
@@ -125,7 +125,7 @@ pub fn run(raw_boot_info: &'static seL4_BootInfo) -> Result<(), TopLevelError> {
         let unmapped_region: UnmappedMemoryRegion<DefaultStackBitSize, _> =
             UnmappedMemoryRegion::new(ut, slots)?;
         let mapped_region =
-            root_vspace.map_region(unmapped_region, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+            root_vspace.map_region(unmapped_region, CapRights::RW, memory_attributes::Cached)?;
         let mut uart1_process = StandardProcess::new(
             &mut uart1_vspace,
             uart1_cnode,
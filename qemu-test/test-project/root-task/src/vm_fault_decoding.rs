@@ -0,0 +1,154 @@
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::arch::fault::Fault;
+use ferros::bootstrap::*;
+use ferros::cap::*;
+use ferros::test_support::*;
+use ferros::userland::*;
+use ferros::vspace::*;
+
+use typenum::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn vm_fault_decoding(
+    mut outer_slots: LocalCNodeSlots<U32768>,
+    mut outer_ut: LocalCap<Untyped<U21>>,
+    mut asid_pool: LocalCap<ASIDPool<U512>>,
+    mut irq_control: LocalCap<IRQControl>,
+    mut local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    for c in [Command::ReadUnmapped, Command::OverflowStack].iter() {
+        with_temporary_resources(
+            &mut outer_slots,
+            &mut outer_ut,
+            &mut asid_pool,
+            &mut local_mapped_region,
+            &mut irq_control,
+            |inner_slots,
+             inner_ut,
+             inner_asid_pool,
+             mapped_region,
+             _inner_irq_control|
+             -> Result<(), TopLevelError> {
+                let uts = ut_buddy(inner_ut);
+                smart_alloc!(|slots: inner_slots, ut: uts| {
+                    let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+                    let (child_fault_source_slot, _child_slots) = child_slots.alloc();
+                    let (source, reporter, watcher) =
+                        exit_channel(&root_cnode, ut, slots, child_fault_source_slot, slots)?;
+                    let params = ProcParams {
+                        command: *c,
+                        reporter,
+                    };
+
+                    let (child_asid, _asid_pool) = inner_asid_pool.alloc();
+
+                    let child_root = retype(ut, slots)?;
+                    let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+                    let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+
+                    let mut child_vspace = VSpace::new(
+                        child_root,
+                        child_asid,
+                        child_vspace_slots.weaken(),
+                        child_vspace_ut.weaken(),
+                        ProcessCodeImageConfig::ReadOnly,
+                        user_image,
+                        root_cnode,
+                    )?;
+
+                    let mut child_process = StandardProcess::new(
+                        &mut child_vspace,
+                        child_cnode,
+                        mapped_region,
+                        root_cnode,
+                        proc_main as extern "C" fn(_) -> (),
+                        params,
+                        ut,
+                        ut,
+                        slots,
+                        tpa,
+                        Some(source),
+                    )?;
+                });
+                child_process.start()?;
+
+                let fault = match watcher.wait()? {
+                    ProcessOutcome::Faulted(Fault::VMFault(fault)) => fault,
+                    _ => {
+                        return Err(TopLevelError::TestAssertionFailure(
+                            "Child process should have hit a VM fault",
+                        ))
+                    }
+                };
+                let report = fault.explain(&child_process.known_regions());
+                child_process.terminate(root_cnode)?;
+                debug_println!("{}", report);
+
+                match c {
+                    Command::ReadUnmapped => {
+                        if report.details.cause != VMFaultCause::Translation
+                            || report.details.access != VMFaultAccess::Read
+                            || report.region.is_some()
+                        {
+                            return Err(TopLevelError::TestAssertionFailure(
+                                "Expected a read of an unmapped address",
+                            ));
+                        }
+                    }
+                    Command::OverflowStack => {
+                        if report.region.map(|r| r.kind) != Some(RegionKind::StackGuard) {
+                            return Err(TopLevelError::TestAssertionFailure(
+                                "Expected the stack guard page to be hit",
+                            ));
+                        }
+                    }
+                }
+                Ok(())
+            },
+        )??;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub enum Command {
+    ReadUnmapped,
+    OverflowStack,
+}
+
+pub struct ProcParams<Role: CNodeRole> {
+    pub command: Command,
+    pub reporter: ExitReporter<Role>,
+}
+
+impl RetypeForSetup for ProcParams<role::Local> {
+    type Output = ProcParams<role::Child>;
+}
+
+pub extern "C" fn proc_main(params: ProcParams<role::Local>) {
+    let ProcParams { command, reporter } = params;
+    match command {
+        Command::ReadUnmapped => unsafe {
+            let x: *const usize = 0x88888888usize as _;
+            debug_println!("Value from arbitrary memory is: {}", *x);
+        },
+        Command::OverflowStack => {
+            debug_println!("Sum: {}", recurse(0));
+        }
+    }
+    reporter.exit(0)
+}
+
+fn recurse(depth: usize) -> usize {
+    if depth == usize::MAX {
+        return 0;
+    }
+    let frame = [depth; 64];
+    unsafe { core::ptr::read_volatile(&frame[63]) + recurse(depth + 1) }
+}
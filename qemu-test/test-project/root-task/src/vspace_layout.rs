@@ -0,0 +1,118 @@
+use core::fmt::{self, Write};
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::CapRights;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+/// Counts the lines written to it, rather than keeping them.
+struct LineCounter(usize);
+
+impl Write for LineCounter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.matches('\n').count();
+        Ok(())
+    }
+}
+
+#[ferros_test::ferros_test]
+pub fn vspace_layout(
+    local_slots: LocalCNodeSlots<U2048>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let exclusive_region: UnmappedMemoryRegion<U14, _> = UnmappedMemoryRegion::new(ut, slots)?;
+        let shared_region: UnmappedMemoryRegion<U12, _> = UnmappedMemoryRegion::new(ut, slots)?;
+    });
+
+    // The process image shows up as a single read-only span
+    let image_mappings = child_vspace
+        .mappings()
+        .iter()
+        .filter(|m| m.source == MappingSource::Image)
+        .count();
+    if image_mappings != 1 {
+        return Err(TopLevelError::TestAssertionFailure(
+            "The image should be listed as one coalesced mapping",
+        ));
+    }
+
+    let exclusive =
+        child_vspace.map_region(exclusive_region, CapRights::RW, memory_attributes::Cached)?;
+    let shared = child_vspace.map_shared_region_and_consume(
+        shared_region.to_shared(),
+        CapRights::R,
+        memory_attributes::Cached,
+    )?;
+
+    let listed = child_vspace
+        .mapping_at(exclusive.vaddr() + 1)
+        .map_or(false, |m| {
+            m.start == exclusive.vaddr()
+                && m.size == exclusive.size_bytes()
+                && m.rights == CapRights::RW
+                && !m.shared
+        });
+    if !listed {
+        return Err(TopLevelError::TestAssertionFailure(
+            "An exclusive region should be listed where it was mapped",
+        ));
+    }
+    if child_vspace.mapping_at(shared.vaddr()).map(|m| m.shared) != Some(true) {
+        return Err(TopLevelError::TestAssertionFailure(
+            "A shared region should be listed as shared",
+        ));
+    }
+
+    let exclusive = child_vspace
+        .protect_region(exclusive, mapped_rights::R {})
+        .map_err(|(e, _)| e)?;
+    if child_vspace.mapping_at(exclusive.vaddr()).map(|m| m.rights) != Some(CapRights::R) {
+        return Err(TopLevelError::TestAssertionFailure(
+            "Protecting a region should update its listed rights",
+        ));
+    }
+
+    // One line per mapping, plus the available range
+    let mut lines = LineCounter(0);
+    write!(lines, "{}", child_vspace.layout())
+        .map_err(|_| TopLevelError::TestAssertionFailure("Printing the layout should succeed"))?;
+    if lines.0 != child_vspace.mappings().len() {
+        return Err(TopLevelError::TestAssertionFailure(
+            "The layout should print a line per mapping",
+        ));
+    }
+
+    let exclusive_vaddr = exclusive.vaddr();
+    let _ = child_vspace.unmap_region(exclusive)?;
+    if child_vspace.mapping_at(exclusive_vaddr).is_some() {
+        return Err(TopLevelError::TestAssertionFailure(
+            "An unmapped region should no longer be listed",
+        ));
+    }
+    let _ = child_vspace.unmap_region(shared)?;
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::ut_buddy::{ut_buddy, weak_ut_buddy};
+use ferros::cap::*;
+
+#[ferros_test::ferros_test]
+pub fn wutbuddy_free(
+    local_slots: LocalCNodeSlots<U64>,
+    local_ut: LocalCap<Untyped<U14>>,
+    root_cnode: &LocalCap<LocalCNode>,
+) -> Result<(), TopLevelError> {
+    let (split_slots, local_slots) = local_slots.alloc();
+    let (weak_ut, strong_ut) = local_ut.split(split_slots)?;
+    let (merged_split_slots, local_slots) = local_slots.alloc();
+    let (strong_slots, local_slots) = local_slots.alloc();
+    let (no_slots, local_slots) = local_slots.alloc();
+    let (more_no_slots, local_slots) = local_slots.alloc();
+    let (empty_slots, local_slots): (LocalCNodeSlots<U0>, _) = local_slots.alloc();
+    let mut empty_slots = empty_slots.weaken();
+    let mut weak_slots = local_slots.weaken();
+
+    // Splitting the U13 in two and freeing the half in use merges them
+    // back, so the whole U13 can be had again.
+    let mut wut = weak_ut_buddy(weak_ut.weaken());
+    let weak_12 = wut.alloc(&mut weak_slots, 12)?;
+    wut.free(weak_12, root_cnode)?;
+
+    // The slots the merged halves lived in are used to split it again.
+    let again_12 = wut.alloc(&mut empty_slots, 12)?;
+    wut.free(again_12, root_cnode)?;
+    let weak_13 = wut.alloc(&mut weak_slots, 13)?;
+    assert_eq!(weak_13.size_bits(), 13);
+
+    // Merging revoked the halves, so none of it is in use and it can be
+    // split in two.
+    weak_13
+        .split(merged_split_slots)
+        .map_err(|_| TopLevelError::TestAssertionFailure("A freed untyped should be unused"))?;
+
+    // The strong buddy takes a freed untyped back as it is, and its pool
+    // sizes say so: the second U12 comes without splitting.
+    let uts = ut_buddy(strong_ut);
+    let (ut12, uts) = uts.alloc::<U12, _>(strong_slots)?;
+    let uts = uts.free(ut12, root_cnode)?;
+    let (_first, uts) = uts.alloc::<U12, U0>(no_slots)?;
+    let (_second, _uts) = uts.alloc::<U12, U0>(more_no_slots)?;
+    Ok(())
+}
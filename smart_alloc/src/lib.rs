@@ -1,22 +1,27 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
 use proc_macro2::Span;
-use quote::TokenStreamExt;
+use quote::{quote, quote_spanned, TokenStreamExt};
 use std::fmt::{Display, Formatter};
 use syn::export::TokenStream2;
 use syn::fold::Fold;
+use syn::parse::Parser;
 use syn::parse_macro_input::parse as syn_parse;
 use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
 use syn::token::Comma;
 use syn::{
-    parse_quote, ArgCaptured, Block, Error as SynError, Expr, ExprClosure, FnArg, GenericArgument,
-    Ident, Item as SynItem, ItemMacro, Pat, PathArguments, ReturnType, Stmt, Type,
+    parse_quote, ArgCaptured, Block, Error as SynError, Expr, ExprClosure, ExprPath, FnArg,
+    GenericArgument, Ident, Item as SynItem, ItemMacro, LitStr, Pat, PathArguments, ReturnType,
+    Stmt, Type,
 };
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 const RESOURCE_TYPE_HINT_CSLOTS: &str = "CNodeSlots";
 const RESOURCE_TYPE_HINT_UNTYPED: &str = "UntypedBuddy";
+const RESOURCE_TYPE_HINT_WEAK_CSLOTS: &str = "WCNodeSlots";
+const RESOURCE_TYPE_HINT_WEAK_UNTYPED: &str = "WUTBuddy";
+const RESOURCE_TYPE_HINT_ASID_POOL: &str = "ASIDPool";
 
 const EXPECTED_LAYOUT_MESSAGE: &str = r"smart_alloc expects to be invoked like:
 smart_alloc!(  |cs: cslots, ut: untypeds| {
@@ -30,7 +35,9 @@ it should be in one of the following forms:
 * `request_id: resource_id`
 * `request_id: resource_id<ResourceKind>`
 
-where ResourceKind is one of CNodeSlots or UntypedBuddy.";
+where ResourceKind is one of CNodeSlots, UntypedBuddy, WCNodeSlots, WUTBuddy
+or ASIDPool. Resources without a kind are taken to be slots, then untyped
+memory, as strong or weak as the other resources declared.";
 
 #[proc_macro]
 pub fn smart_alloc(tokens: TokenStream) -> TokenStream {
@@ -66,6 +73,9 @@ fn smart_alloc_structured(tokens: TokenStream2) -> Result<Vec<Stmt>, Error> {
     // and construct an allocation plan for each site for later code generation
     let mut id_tracker = IdTracker::from(&header);
     block = id_tracker.fold_block(block);
+    if let Some(e) = id_tracker.errors.into_iter().next() {
+        return Err(e);
+    }
 
     let resource_ids = ResolvedResourceIds::resolve(&header, &id_tracker.planned_allocs).unwrap();
     let mut output_stmts = materialize_alloc_statements(&id_tracker.planned_allocs, resource_ids);
@@ -81,28 +91,115 @@ fn materialize_alloc_statements(
     let ResolvedResourceIds {
         cslots_resource,
         untyped_resource,
+        asid_pool_resource,
     } = resource_ids;
+    // Slots are taken through a tally, which checks that they all fit
+    // once their sizes have been inferred, rather than one at a time.
+    let tally = gensym("tally");
+    let takes_slots = planned_allocs
+        .iter()
+        .any(|p| !matches!(p, PlannedAlloc::Asid { .. }));
     let mut output_stmts = Vec::new();
+    if takes_slots {
+        let start_tally: Stmt = parse_quote! {
+            let #tally = #cslots_resource.tally();
+        };
+        output_stmts.push(start_tally);
+    }
+    let mut statements = Vec::new();
     for plan in planned_allocs {
         match plan {
-            PlannedAlloc::CSlot(id) => {
-                let alloc_cslot: Stmt = parse_quote! {
-                    let (#id, #cslots_resource) = #cslots_resource.alloc();
-                };
-                output_stmts.push(alloc_cslot);
+            PlannedAlloc::CSlot {
+                id,
+                statement,
+                span,
+            } => {
+                output_stmts.extend(spanned_stmts(quote_spanned! {*span=>
+                    let (#id, #tally) = #tally.alloc();
+                }));
+                statements.push(LitStr::new(statement, Span::call_site()));
             }
-            PlannedAlloc::Untyped { ut, cslot } => {
-                let alloc_both: Block = parse_quote! { {
-                    let (#cslot, #cslots_resource) = #cslots_resource.alloc();
+            PlannedAlloc::Untyped {
+                ut,
+                cslot,
+                statement,
+                span,
+            } => {
+                output_stmts.extend(spanned_stmts(quote_spanned! {*span=>
+                    let (#cslot, #tally) = #tally.alloc();
                     let (#ut, #untyped_resource) = #untyped_resource.alloc(#cslot)?;
-                }};
-                output_stmts.extend(alloc_both.stmts);
+                    let #tally = #tally.untyped(&#ut);
+                }));
+                statements.push(LitStr::new(statement, Span::call_site()));
+            }
+            PlannedAlloc::Asid { id, span } => {
+                output_stmts.extend(spanned_stmts(quote_spanned! {*span=>
+                    let (#id, #asid_pool_resource) = #asid_pool_resource.alloc();
+                }));
             }
         }
     }
+    if takes_slots {
+        let statements_ty = gensym("statements");
+        let finish_tally: Block = parse_quote! { {
+            #[allow(non_camel_case_types)]
+            struct #statements_ty;
+            impl ::ferros::alloc::capacity::Statements for #statements_ty {
+                const STATEMENTS: &'static [&'static str] = &[#(#statements),*];
+            }
+            let #cslots_resource = #tally.finish::<#statements_ty>();
+        }};
+        output_stmts.extend(finish_tally.stmts);
+    }
     output_stmts
 }
 
+/// Parse generated statements, keeping the spans they were given.
+fn spanned_stmts(tokens: TokenStream2) -> Vec<Stmt> {
+    Block::parse_within
+        .parse2(tokens)
+        .expect("generated statements should parse")
+}
+
+/// A short, single-line rendering of a statement, for summaries of what
+/// each statement in a block allocates.
+fn describe_statement(stmt: &Stmt) -> String {
+    const MAX_DESCRIPTION_CHARS: usize = 72;
+    let mut described = String::new();
+    // How many `<`s opening generic arguments are yet to be closed.
+    let mut generics_depth = 0;
+    for token in quote!(#stmt).to_string().split_whitespace() {
+        let follows_name =
+            described.ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '!');
+        let opens_generics = token == "<" && (follows_name || described.ends_with("::"));
+        let closes_generics = token.starts_with('>')
+            && token.chars().all(|c| c == '>')
+            && generics_depth >= token.len();
+        let joins_previous = described.is_empty()
+            || token.starts_with(|c| ",;?.)]:".contains(c))
+            || (token.starts_with('(') && follows_name)
+            || opens_generics
+            || closes_generics
+            || described.ends_with(|c| "(.[&!".contains(c))
+            || (described.ends_with('<') && generics_depth > 0)
+            || described.ends_with("::");
+        if opens_generics {
+            generics_depth += 1;
+        } else if closes_generics {
+            generics_depth -= token.len();
+        }
+        if !joins_previous {
+            described.push(' ');
+        }
+        described.push_str(token);
+    }
+    if described.chars().count() > MAX_DESCRIPTION_CHARS {
+        described = described.chars().take(MAX_DESCRIPTION_CHARS - 3).collect();
+        described.push_str("...");
+    }
+    described
+}
+
 #[derive(Debug)]
 enum Error {
     NoReturnTypeAllowed { span: Span },
@@ -115,6 +212,9 @@ enum Error {
     InvalidRequestId { id: String, span: Span },
     MissingResourceId { request_id: String, span: Span },
     TooManyResources { span: Span },
+    AmbiguousResourceKind { kind: String, span: Span },
+    MixedResourceStrength { span: Span },
+    StrongResourceInLoop { id: String, span: Span },
     SynParse(SynError),
 }
 impl Error {
@@ -133,6 +233,9 @@ impl Error {
                 span,
             } => *span,
             Error::TooManyResources { span } => *span,
+            Error::AmbiguousResourceKind { kind: _, span } => *span,
+            Error::MixedResourceStrength { span } => *span,
+            Error::StrongResourceInLoop { id: _, span } => *span,
             Error::SynParse(e) => e.span(),
         }
     }
@@ -184,6 +287,26 @@ impl Display for Error {
                 "{}\nbut more than two resources without explicit resource kinds were requested",
                 EXPECTED_LAYOUT_MESSAGE
             ),
+            Error::AmbiguousResourceKind { kind, .. } => format!(
+                "{}\nbut more than one resource of the kind {} was declared",
+                EXPECTED_LAYOUT_MESSAGE, kind
+            ),
+            Error::MixedResourceStrength { .. } => format!(
+                "{}\nbut strong and weak resources were mixed; use {} with {}, or {} with {}",
+                EXPECTED_LAYOUT_MESSAGE,
+                RESOURCE_TYPE_HINT_CSLOTS,
+                RESOURCE_TYPE_HINT_UNTYPED,
+                RESOURCE_TYPE_HINT_WEAK_CSLOTS,
+                RESOURCE_TYPE_HINT_WEAK_UNTYPED
+            ),
+            Error::StrongResourceInLoop { id, .. } => format!(
+                "{}\nbut {} was used inside a loop, where the number of allocations isn't known \
+                 up front; only {} and {} resources can be allocated from at runtime",
+                EXPECTED_LAYOUT_MESSAGE,
+                id,
+                RESOURCE_TYPE_HINT_WEAK_CSLOTS,
+                RESOURCE_TYPE_HINT_WEAK_UNTYPED
+            ),
             Error::SynParse(se) => se.to_compile_error().to_string(),
         };
         f.write_str(&s)
@@ -318,17 +441,102 @@ fn header_from_intermediate_resources(
     resources: &[IntermediateResource],
     all_resources_span: Span,
 ) -> Result<Header, Error> {
-    match resources.len() {
-        0 => Err(Error::MissingRequiredResourceKind {
-            msg: RESOURCE_TYPE_HINT_CSLOTS.to_string(),
-            span: all_resources_span,
-        }),
-        1 => Header::from_single_resource(&resources[0]),
-        2 => Header::from_resource_pair(&resources[0], &resources[1]),
-        _ => Err(Error::TooManyResources {
+    if resources.len() > 3 {
+        return Err(Error::TooManyResources {
             span: all_resources_span,
-        }),
+        });
+    }
+    for (i, first) in resources.iter().enumerate() {
+        for second in &resources[i + 1..] {
+            if first.resource_id == second.resource_id {
+                return Err(Error::AmbiguousResourceId {
+                    id: first.resource_id.to_string(),
+                    span: first.resource_id.span(),
+                });
+            }
+            if first.request_id == second.request_id {
+                return Err(Error::AmbiguousRequestId {
+                    id: first.request_id.to_string(),
+                    span: first.request_id.span(),
+                });
+            }
+        }
+    }
+
+    // Place the resources with explicit kinds first...
+    let mut cnode_slots: Option<(&IntermediateResource, Option<Strength>)> = None;
+    let mut untypeds: Option<(&IntermediateResource, Option<Strength>)> = None;
+    let mut asid_pool: Option<&IntermediateResource> = None;
+    for resource in resources {
+        let (place, strength, hint) = match resource.kind {
+            None => continue,
+            Some(ResKind::CNodeSlots) => (
+                &mut cnode_slots,
+                Strength::Strong,
+                RESOURCE_TYPE_HINT_CSLOTS,
+            ),
+            Some(ResKind::WeakCNodeSlots) => {
+                (&mut cnode_slots, Strength::Weak, RESOURCE_TYPE_HINT_CSLOTS)
+            }
+            Some(ResKind::Untyped) => (&mut untypeds, Strength::Strong, RESOURCE_TYPE_HINT_UNTYPED),
+            Some(ResKind::WeakUntyped) => {
+                (&mut untypeds, Strength::Weak, RESOURCE_TYPE_HINT_UNTYPED)
+            }
+            Some(ResKind::ASIDPool) => {
+                if asid_pool.is_some() {
+                    return Err(Error::AmbiguousResourceKind {
+                        kind: RESOURCE_TYPE_HINT_ASID_POOL.to_string(),
+                        span: resource.request_id.span(),
+                    });
+                }
+                asid_pool = Some(resource);
+                continue;
+            }
+        };
+        if place.is_some() {
+            return Err(Error::AmbiguousResourceKind {
+                kind: hint.to_string(),
+                span: resource.request_id.span(),
+            });
+        }
+        *place = Some((resource, Some(strength)));
     }
+    // ...then fill in slots, then untyped memory, with those without.
+    for resource in resources.iter().filter(|r| r.kind.is_none()) {
+        if cnode_slots.is_none() {
+            cnode_slots = Some((resource, None));
+        } else if untypeds.is_none() {
+            untypeds = Some((resource, None));
+        } else {
+            return Err(Error::TooManyResources {
+                span: all_resources_span,
+            });
+        }
+    }
+
+    let (cnode_slots, slots_strength) =
+        cnode_slots.ok_or_else(|| Error::MissingRequiredResourceKind {
+            msg: RESOURCE_TYPE_HINT_CSLOTS.to_string(),
+            span: all_resources_span,
+        })?;
+    let untyped_strength = untypeds.and_then(|(_, strength)| strength);
+    // Resources without a kind take on the strength of those with one.
+    let strength = match (slots_strength, untyped_strength) {
+        (Some(a), Some(b)) if a != b => {
+            return Err(Error::MixedResourceStrength {
+                span: all_resources_span,
+            })
+        }
+        (Some(s), _) | (None, Some(s)) => s,
+        (None, None) => Strength::Strong,
+    };
+
+    Ok(Header {
+        strength,
+        cnode_slots: ResourceRequest::from(cnode_slots),
+        untypeds: untypeds.map(|(resource, _)| ResourceRequest::from(resource)),
+        asid_pool: asid_pool.map(ResourceRequest::from),
+    })
 }
 
 #[derive(PartialEq)]
@@ -352,6 +560,9 @@ fn parse_resource_kind(ident: Ident) -> Result<ResKind, Error> {
     match raw_kind.as_ref() {
         RESOURCE_TYPE_HINT_CSLOTS => Ok(ResKind::CNodeSlots),
         RESOURCE_TYPE_HINT_UNTYPED => Ok(ResKind::Untyped),
+        RESOURCE_TYPE_HINT_WEAK_CSLOTS => Ok(ResKind::WeakCNodeSlots),
+        RESOURCE_TYPE_HINT_WEAK_UNTYPED => Ok(ResKind::WeakUntyped),
+        RESOURCE_TYPE_HINT_ASID_POOL => Ok(ResKind::ASIDPool),
         _ => Err(Error::InvalidResourceKind {
             found: raw_kind,
             span: ident.span(),
@@ -369,17 +580,32 @@ struct IntermediateResource {
 enum ResKind {
     CNodeSlots,
     Untyped,
+    WeakCNodeSlots,
+    WeakUntyped,
+    ASIDPool,
+}
+
+/// Strong resources are split up front, with sizes worked out at compile
+/// time. Weak ones are allocated from where they are used, and checked at
+/// runtime, so they may be used in loops.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Strength {
+    Strong,
+    Weak,
 }
 
 #[derive(Debug, PartialEq)]
 struct Header {
+    pub(crate) strength: Strength,
     pub(crate) cnode_slots: ResourceRequest,
     pub(crate) untypeds: Option<ResourceRequest>,
+    pub(crate) asid_pool: Option<ResourceRequest>,
 }
 
 struct ResolvedResourceIds {
     cslots_resource: Ident,
     untyped_resource: Ident,
+    asid_pool_resource: Ident,
 }
 
 impl ResolvedResourceIds {
@@ -405,99 +631,15 @@ impl ResolvedResourceIds {
             .unwrap_or_else(|| {
                 Ident::new("untyped_buddy_not_provided_to_macro", Span::call_site())
             });
+        let asid_pool_resource = header
+            .asid_pool
+            .as_ref()
+            .map(|rr| Ident::new(&rr.resource_id.to_string(), rr.resource_id.span()))
+            .unwrap_or_else(|| Ident::new("asid_pool_not_provided_to_macro", Span::call_site()));
         Ok(ResolvedResourceIds {
             cslots_resource,
             untyped_resource,
-        })
-    }
-}
-
-impl Header {
-    fn from_single_resource(first: &IntermediateResource) -> Result<Header, Error> {
-        // There is only one resource defined, so it better be a cnode slots resource
-        match first.kind {
-            None => (),
-            Some(ResKind::CNodeSlots) => (),
-            _ => {
-                return Err(Error::MissingRequiredResourceKind {
-                    msg: format!(
-                        "The only resource declared was not the required kind, {}",
-                        RESOURCE_TYPE_HINT_CSLOTS
-                    ),
-                    span: first.request_id.span(),
-                })
-            }
-        };
-        Ok(Header {
-            cnode_slots: ResourceRequest {
-                resource_id: first.resource_id.clone(),
-                request_id: first.request_id.clone(),
-            },
-            untypeds: None,
-        })
-    }
-
-    fn from_resource_pair(
-        first: &IntermediateResource,
-        second: &IntermediateResource,
-    ) -> Result<Header, Error> {
-        match (first.kind.as_ref(), second.kind.as_ref()) {
-            (None, None) => Header::from_known_kinds_resource_pair(first, second),
-            (Some(fk), None) => match fk {
-                ResKind::CNodeSlots => Header::from_known_kinds_resource_pair(first, second),
-                ResKind::Untyped => Header::from_known_kinds_resource_pair(second, first),
-            },
-            (None, Some(sk)) => match sk {
-                ResKind::CNodeSlots => Header::from_known_kinds_resource_pair(second, first),
-                ResKind::Untyped => Header::from_known_kinds_resource_pair(first, second),
-            },
-            (Some(fk), Some(_sk)) => match fk {
-                ResKind::CNodeSlots => Header::from_known_kinds_resource_pair(first, second),
-                ResKind::Untyped => Header::from_known_kinds_resource_pair(second, first),
-            },
-        }
-    }
-
-    fn from_known_kinds_resource_pair(
-        cnode_slots: &IntermediateResource,
-        untypeds: &IntermediateResource,
-    ) -> Result<Header, Error> {
-        // Check for duplicates
-        if cnode_slots.resource_id == untypeds.resource_id {
-            return Err(Error::AmbiguousResourceId {
-                id: cnode_slots.resource_id.to_string(),
-                span: cnode_slots.resource_id.span(),
-            });
-        }
-        if cnode_slots.request_id == untypeds.request_id {
-            return Err(Error::AmbiguousRequestId {
-                id: cnode_slots.request_id.to_string(),
-                span: cnode_slots.request_id.span(),
-            });
-        }
-
-        // Confirm assumptions about resource kinds
-        if !(cnode_slots.kind == None || cnode_slots.kind == Some(ResKind::CNodeSlots)) {
-            return Err(Error::MissingRequiredResourceKind {
-                msg: RESOURCE_TYPE_HINT_CSLOTS.to_string(),
-                span: cnode_slots.request_id.span(),
-            });
-        };
-        if !(untypeds.kind == None || untypeds.kind == Some(ResKind::Untyped)) {
-            return Err(Error::MissingRequiredResourceKind {
-                msg: RESOURCE_TYPE_HINT_UNTYPED.to_string(),
-                span: untypeds.request_id.span(),
-            });
-        };
-        Ok(Header {
-            cnode_slots: ResourceRequest {
-                resource_id: cnode_slots.resource_id.clone(),
-                request_id: cnode_slots.request_id.clone(),
-            },
-            untypeds: Some(ResourceRequest {
-                resource_id: untypeds.resource_id.clone(),
-                request_id: untypeds.request_id.clone(),
-            }),
+            asid_pool_resource,
         })
     }
 }
@@ -508,34 +650,98 @@ struct ResourceRequest {
     pub(crate) request_id: syn::Ident,
 }
 
+impl From<&IntermediateResource> for ResourceRequest {
+    fn from(r: &IntermediateResource) -> Self {
+        ResourceRequest {
+            resource_id: r.resource_id.clone(),
+            request_id: r.request_id.clone(),
+        }
+    }
+}
+
 struct IdTracker {
+    strength: Strength,
     cslot_request_id: Ident,
     untyped_request_id: Option<Ident>,
+    asid_request_id: Option<Ident>,
+    cslots_resource: Ident,
+    untyped_resource: Option<Ident>,
     planned_allocs: Vec<PlannedAlloc>,
+    /// The statement of the block being folded that requests are
+    /// currently being found in.
+    statement: String,
+    /// How many blocks deep the fold currently is.
+    block_depth: usize,
+    /// How many loops deep the fold currently is.
+    loop_depth: usize,
+    errors: Vec<Error>,
 }
 
+/// An allocation to make before the block, for a request at `span` in
+/// `statement`.
 enum PlannedAlloc {
-    CSlot(Ident),
-    Untyped { ut: Ident, cslot: Ident },
+    CSlot {
+        id: Ident,
+        statement: String,
+        span: Span,
+    },
+    Untyped {
+        ut: Ident,
+        cslot: Ident,
+        statement: String,
+        span: Span,
+    },
+    Asid {
+        id: Ident,
+        span: Span,
+    },
 }
 
 impl From<&Header> for IdTracker {
     fn from(h: &Header) -> Self {
-        IdTracker::new(
-            Ident::new(&h.cnode_slots.request_id.to_string(), Span::call_site()),
-            h.untypeds
-                .as_ref()
-                .map(|rr| Ident::new(&rr.request_id.to_string(), Span::call_site())),
-        )
+        let call_site = |id: &Ident| Ident::new(&id.to_string(), Span::call_site());
+        IdTracker {
+            strength: h.strength,
+            cslot_request_id: call_site(&h.cnode_slots.request_id),
+            untyped_request_id: h.untypeds.as_ref().map(|rr| call_site(&rr.request_id)),
+            asid_request_id: h.asid_pool.as_ref().map(|rr| call_site(&rr.request_id)),
+            cslots_resource: h.cnode_slots.resource_id.clone(),
+            untyped_resource: h.untypeds.as_ref().map(|rr| rr.resource_id.clone()),
+            planned_allocs: vec![],
+            statement: String::new(),
+            block_depth: 0,
+            loop_depth: 0,
+            errors: vec![],
+        }
     }
 }
 
 impl IdTracker {
-    fn new(cslot_request_id: Ident, untyped_request_id: Option<Ident>) -> Self {
-        IdTracker {
-            cslot_request_id,
-            untyped_request_id,
-            planned_allocs: vec![],
+    /// The runtime allocation to put in place of `id`, if it requests a
+    /// weak resource.
+    fn weak_alloc_expr(&self, id: &Ident) -> Option<Expr> {
+        if self.strength != Strength::Weak {
+            return None;
+        }
+        let cslots_resource = &self.cslots_resource;
+        if *id == self.cslot_request_id {
+            return Some(parse_quote! { #cslots_resource.alloc_strong()? });
+        }
+        match (&self.untyped_request_id, &self.untyped_resource) {
+            (Some(ut_request_id), Some(untyped_resource)) if id == ut_request_id => {
+                Some(parse_quote! { #untyped_resource.alloc_strong(&mut #cslots_resource)? })
+            }
+            _ => None,
+        }
+    }
+
+    /// Note a strong request, which can't be satisfied from inside a loop.
+    fn check_not_in_loop(&mut self, node: &Ident) {
+        if self.loop_depth > 0 {
+            self.errors.push(Error::StrongResourceInLoop {
+                id: node.to_string(),
+                span: node.span(),
+            });
         }
     }
 }
@@ -554,7 +760,11 @@ impl Fold for IdTracker {
     fn fold_block(&mut self, i: Block) -> Block {
         let brace_token = i.brace_token;
         let visitor = self;
-        let expand = |st: Stmt| -> Vec<Stmt> {
+        visitor.block_depth += 1;
+        let mut expand = |st: Stmt| -> Vec<Stmt> {
+            if visitor.block_depth == 1 {
+                visitor.statement = describe_statement(&st);
+            }
             match st {
                 s @ Stmt::Local(_) => vec![visitor.fold_stmt(s)],
                 s @ Stmt::Expr(_) => vec![visitor.fold_stmt(s)],
@@ -569,9 +779,18 @@ impl Fold for IdTracker {
                         if macro_name_matches(&item_macro, "smart_alloc") {
                             // TODO - consider passing down pre-existing header-defined request ids
                             // in order to detect and error out in the case of accidental shadowing
-                            let nested_stmts = smart_alloc_structured(item_macro.mac.tts).unwrap();
+                            let nested_stmts = match smart_alloc_structured(item_macro.mac.tts) {
+                                Ok(nested_stmts) => nested_stmts,
+                                Err(e) => {
+                                    visitor.errors.push(e);
+                                    return vec![];
+                                }
+                            };
                             let mut out_stmts = Vec::new();
                             for stmt in nested_stmts {
+                                if visitor.block_depth == 1 {
+                                    visitor.statement = describe_statement(&stmt);
+                                }
                                 out_stmts.push(visitor.fold_stmt(stmt));
                             }
                             out_stmts
@@ -584,24 +803,70 @@ impl Fold for IdTracker {
                 }
             }
         };
-        let stmts = i.stmts.into_iter().map(expand).flatten().collect();
+        let stmts = i.stmts.into_iter().map(&mut expand).flatten().collect();
+        visitor.block_depth -= 1;
         Block { brace_token, stmts }
     }
 
+    fn fold_expr(&mut self, e: Expr) -> Expr {
+        match e {
+            Expr::Path(ExprPath {
+                qself: None,
+                ref path,
+                ..
+            }) if path.leading_colon.is_none() && path.segments.len() == 1 => {
+                let id = &path.segments[0].ident;
+                match self.weak_alloc_expr(id) {
+                    Some(alloc) => alloc,
+                    None => syn::fold::fold_expr(self, e),
+                }
+            }
+            Expr::ForLoop(_) | Expr::While(_) | Expr::Loop(_) => {
+                self.loop_depth += 1;
+                let folded = syn::fold::fold_expr(self, e);
+                self.loop_depth -= 1;
+                folded
+            }
+            _ => syn::fold::fold_expr(self, e),
+        }
+    }
+
     fn fold_ident(&mut self, node: Ident) -> Ident {
+        if let Some(asid_request_id) = &self.asid_request_id {
+            if node == *asid_request_id {
+                self.check_not_in_loop(&node);
+                let fresh_id = gensym("asid");
+                self.planned_allocs.push(PlannedAlloc::Asid {
+                    id: fresh_id.clone(),
+                    span: node.span(),
+                });
+                return fresh_id;
+            }
+        }
+        // Weak requests are replaced as whole expressions, in `fold_expr`.
+        if self.strength == Strength::Weak {
+            return node;
+        }
         if node == self.cslot_request_id {
+            self.check_not_in_loop(&node);
             let fresh_id = gensym("cslots");
-            self.planned_allocs
-                .push(PlannedAlloc::CSlot(fresh_id.clone()));
+            self.planned_allocs.push(PlannedAlloc::CSlot {
+                id: fresh_id.clone(),
+                statement: self.statement.clone(),
+                span: node.span(),
+            });
             return fresh_id;
         }
 
         if let Some(ut_request_id) = &self.untyped_request_id {
             if node == *ut_request_id {
+                self.check_not_in_loop(&node);
                 let fresh_id = gensym("untyped");
                 self.planned_allocs.push(PlannedAlloc::Untyped {
                     ut: fresh_id.clone(),
                     cslot: gensym("cslots_for_untyped"),
+                    statement: self.statement.clone(),
+                    span: node.span(),
                 });
                 return fresh_id;
             }
@@ -2,22 +2,67 @@
 
 use smart_alloc::smart_alloc;
 
+// Generated code names the traits it implements by their path in ferros.
+extern crate self as ferros;
+
+mod alloc {
+    pub mod capacity {
+        pub trait Statements {
+            const STATEMENTS: &'static [&'static str];
+        }
+    }
+}
+
+use crate::alloc::capacity::Statements;
+
 struct CNodeSlots {
     capacity: usize,
+    /// The statements of the last block allocated from these slots.
+    statements: &'static [&'static str],
 }
 
 impl CNodeSlots {
-    fn alloc(self) -> (CNodeSlots, CNodeSlots) {
+    fn tally(self) -> SlotTally {
+        SlotTally {
+            capacity: self.capacity,
+            taken: 0,
+        }
+    }
+
+    fn new(capacity: usize) -> Self {
+        CNodeSlots {
+            capacity,
+            statements: &[],
+        }
+    }
+}
+
+struct SlotTally {
+    capacity: usize,
+    taken: usize,
+}
+
+impl SlotTally {
+    fn alloc(self) -> (CNodeSlots, SlotTally) {
         (
-            CNodeSlots { capacity: 1 },
-            CNodeSlots {
-                capacity: self.capacity - 1,
+            CNodeSlots::new(1),
+            SlotTally {
+                taken: self.taken + 1,
+                ..self
             },
         )
     }
 
-    fn new(capacity: usize) -> Self {
-        CNodeSlots { capacity }
+    fn untyped(self, _untyped: &UntypedBuddy) -> SlotTally {
+        self
+    }
+
+    fn finish<S: Statements>(self) -> CNodeSlots {
+        assert!(self.taken <= self.capacity);
+        CNodeSlots {
+            capacity: self.capacity - self.taken,
+            statements: S::STATEMENTS,
+        }
     }
 }
 
@@ -41,6 +86,68 @@ impl UntypedBuddy {
     }
 }
 
+#[derive(Debug, PartialEq)]
+enum WeakAllocError {
+    OutOfSlots,
+    OutOfMemory,
+}
+
+struct WCNodeSlots {
+    capacity: usize,
+}
+
+impl WCNodeSlots {
+    fn alloc_strong(&mut self) -> Result<CNodeSlots, WeakAllocError> {
+        if self.capacity == 0 {
+            return Err(WeakAllocError::OutOfSlots);
+        }
+        self.capacity -= 1;
+        Ok(CNodeSlots::new(1))
+    }
+
+    fn new(capacity: usize) -> Self {
+        WCNodeSlots { capacity }
+    }
+}
+
+struct WUTBuddy {
+    capacity: usize,
+}
+
+impl WUTBuddy {
+    fn alloc_strong(&mut self, slots: &mut WCNodeSlots) -> Result<UntypedBuddy, WeakAllocError> {
+        if self.capacity == 0 {
+            return Err(WeakAllocError::OutOfMemory);
+        }
+        let _ = slots.alloc_strong()?;
+        self.capacity -= 1;
+        Ok(UntypedBuddy { capacity: 1 })
+    }
+
+    fn new(capacity: usize) -> Self {
+        WUTBuddy { capacity }
+    }
+}
+
+struct ASIDPool {
+    capacity: usize,
+}
+
+impl ASIDPool {
+    fn alloc(self) -> (usize, ASIDPool) {
+        (
+            self.capacity,
+            ASIDPool {
+                capacity: self.capacity - 1,
+            },
+        )
+    }
+
+    fn new(capacity: usize) -> Self {
+        ASIDPool { capacity }
+    }
+}
+
 #[test]
 fn single_resource() -> Result<(), ()> {
     let cslots = CNodeSlots::new(10);
@@ -245,6 +352,180 @@ fn single_resources_deeply_nested() -> Result<(), ()> {
     Ok(())
 }
 
+#[test]
+fn weak_resources() -> Result<(), WeakAllocError> {
+    let mut cslots = WCNodeSlots::new(5);
+    let mut untypeds = WUTBuddy::new(5);
+
+    smart_alloc!(|c: cslots<WCNodeSlots>, u: untypeds<WUTBuddy>| {
+        let gamma = consume_slot(c);
+        let eta = consume_untyped(u);
+    });
+
+    assert_eq!(1, gamma);
+    assert_eq!(1, eta);
+    // One slot for the request, and one for splitting the untyped
+    assert_eq!(3, cslots.capacity);
+    assert_eq!(4, untypeds.capacity);
+    Ok(())
+}
+
+#[test]
+fn weak_resources_kind_inferred() -> Result<(), WeakAllocError> {
+    let mut cslots = WCNodeSlots::new(5);
+    let mut untypeds = WUTBuddy::new(5);
+
+    smart_alloc!(|u: untypeds<WUTBuddy>, c: cslots| {
+        let gamma = consume_slot(c);
+        let eta = consume_untyped(u);
+    });
+
+    assert_eq!(1, gamma);
+    assert_eq!(1, eta);
+    assert_eq!(3, cslots.capacity);
+    assert_eq!(4, untypeds.capacity);
+    Ok(())
+}
+
+#[test]
+fn weak_resources_in_loops_and_conditionals() -> Result<(), WeakAllocError> {
+    let mut cslots = WCNodeSlots::new(10);
+    let mut untypeds = WUTBuddy::new(5);
+
+    smart_alloc!(|c: cslots<WCNodeSlots>, u: untypeds| {
+        let mut total = 0;
+        for i in 0..3 {
+            total += consume_slot(c);
+            if i % 2 == 0 {
+                total += consume_untyped(u);
+            }
+        }
+        let mut n = 0;
+        while n < 2 {
+            total += consume_slot(c);
+            n += 1;
+        }
+    });
+
+    assert_eq!(7, total);
+    // Five slots requested, and two used to split untypeds
+    assert_eq!(3, cslots.capacity);
+    assert_eq!(3, untypeds.capacity);
+    Ok(())
+}
+
+#[test]
+fn weak_resources_run_out_at_runtime() {
+    let mut cslots = WCNodeSlots::new(2);
+
+    let result = (|| -> Result<(), WeakAllocError> {
+        smart_alloc!(|c: cslots<WCNodeSlots>| {
+            for _ in 0..3 {
+                consume_slot(c);
+            }
+        });
+        Ok(())
+    })();
+
+    assert_eq!(Err(WeakAllocError::OutOfSlots), result);
+    assert_eq!(0, cslots.capacity);
+}
+
+#[test]
+fn asid_pool_resource() -> Result<(), ()> {
+    let cslots = CNodeSlots::new(5);
+    let untypeds = UntypedBuddy::new(5);
+    let asids = ASIDPool::new(4);
+
+    smart_alloc!(|c: cslots, a: asids<ASIDPool>, u: untypeds| {
+        let first = a;
+        let second = a;
+        let gamma = consume_slot(c);
+        let eta = consume_untyped(u);
+    });
+
+    assert_eq!(4, first);
+    assert_eq!(3, second);
+    assert_eq!(2, asids.capacity);
+    assert_eq!(1, gamma);
+    assert_eq!(1, eta);
+    assert_eq!(3, cslots.capacity);
+    assert_eq!(4, untypeds.capacity);
+    Ok(())
+}
+
+#[test]
+fn slot_requests_listed_by_statement() -> Result<(), ()> {
+    let cslots = CNodeSlots::new(5);
+    let untypeds = UntypedBuddy::new(5);
+
+    smart_alloc!(|c: cslots, u: untypeds| {
+        let gamma = consume_slot(c);
+        let alpha = 3;
+        let eta = consume_both(c, u);
+        if alpha > 2 {
+            consume_slot(c);
+        }
+        let delta: Vec<Option<usize>> = Vec::from([Some(consume_slot(c))]);
+    });
+
+    assert_eq!(1, gamma);
+    assert_eq!(2, eta);
+    assert_eq!(vec![Some(1)], delta);
+    assert_eq!(0, cslots.capacity);
+    assert_eq!(4, untypeds.capacity);
+    assert_eq!(
+        &[
+            "let gamma = consume_slot(c);",
+            "let eta = consume_both(c, u);",
+            "let eta = consume_both(c, u);",
+            "if alpha > 2 { consume_slot(c); }",
+            "let delta: Vec<Option<usize>> = Vec::from([Some(consume_slot(c))]);",
+        ],
+        cslots.statements
+    );
+    Ok(())
+}
+
+#[test]
+fn slot_requests_listed_per_block() -> Result<(), ()> {
+    let cslots = CNodeSlots::new(5);
+
+    smart_alloc!(|outer: cslots| {
+        let cslots_inner = CNodeSlots::new(5);
+        smart_alloc! {|inner: cslots_inner| {
+            let gamma = consume_slot(inner);
+            let eta = consume_slot(outer);
+        }};
+    });
+
+    assert_eq!(1, gamma);
+    assert_eq!(1, eta);
+    assert_eq!(&["let eta = consume_slot(outer);"], cslots.statements);
+    assert_eq!(
+        &["let gamma = consume_slot(inner);"],
+        cslots_inner.statements
+    );
+    Ok(())
+}
+
+#[test]
+fn long_statements_shortened_when_listed() -> Result<(), ()> {
+    let cslots = CNodeSlots::new(5);
+
+    smart_alloc!(|c: cslots| {
+        let gamma =
+            consume_slot(c) + consume_slot(CNodeSlots::new(1)) + consume_slot(CNodeSlots::new(2));
+    });
+
+    assert_eq!(4, gamma);
+    assert_eq!(
+        &["let gamma = consume_slot(c) + consume_slot(CNodeSlots::new(1)) + cons..."],
+        cslots.statements
+    );
+    Ok(())
+}
+
 fn consume_slot(cslots: CNodeSlots) -> usize {
     cslots.capacity
 }
@@ -252,3 +533,7 @@ fn consume_slot(cslots: CNodeSlots) -> usize {
 fn consume_untyped(ut: UntypedBuddy) -> usize {
     ut.capacity
 }
+
+fn consume_both(cslots: CNodeSlots, ut: UntypedBuddy) -> usize {
+    cslots.capacity + ut.capacity
+}
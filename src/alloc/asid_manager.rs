@@ -0,0 +1,241 @@
+//! ASIDs handed out at runtime, from pools made as they are needed, and
+//! taken back once their address spaces are destroyed.
+use core::marker::PhantomData;
+
+use arrayvec::ArrayVec;
+use typenum::*;
+
+use super::ut_buddy::UTBuddyError;
+use super::WUTBuddy;
+use crate::arch;
+use crate::cap::{
+    ASIDControlError, ASIDPool, Cap, InternalASID, LocalCap, ReleasedASID, UnassignedASID,
+    WASIDControl, WCNodeSlots,
+};
+use crate::error::SeL4Error;
+
+/// The most pools an `ASIDManager` hands out ASIDs from.
+pub const MAX_MANAGED_POOLS: usize = 8;
+
+const ASID_WORDS: usize = arch::ASIDPoolSize::USIZE / 64;
+
+#[derive(Debug)]
+pub enum ASIDManagerError {
+    /// The manager holds as many pools as it can keep track of.
+    TooManyPools,
+    /// The kernel has no more pools to give out, or the manager wasn't
+    /// given the means to make them.
+    NoPoolsLeft,
+    NotEnoughSlots,
+    /// The ASID wasn't handed out by this manager, or has already been
+    /// released.
+    UnknownASID,
+    UTBuddyError(UTBuddyError),
+    SeL4Error(SeL4Error),
+}
+
+impl From<UTBuddyError> for ASIDManagerError {
+    fn from(e: UTBuddyError) -> Self {
+        ASIDManagerError::UTBuddyError(e)
+    }
+}
+
+impl From<SeL4Error> for ASIDManagerError {
+    fn from(e: SeL4Error) -> Self {
+        ASIDManagerError::SeL4Error(e)
+    }
+}
+
+impl From<ASIDControlError> for ASIDManagerError {
+    fn from(e: ASIDControlError) -> Self {
+        match e {
+            ASIDControlError::NoPoolsLeft => ASIDManagerError::NoPoolsLeft,
+            ASIDControlError::SeL4Error(e) => ASIDManagerError::SeL4Error(e),
+        }
+    }
+}
+
+/// A pool, and which of its ASIDs are assigned.
+struct ManagedPool {
+    cptr: usize,
+    id: usize,
+    assigned: [u64; ASID_WORDS],
+}
+
+impl ManagedPool {
+    /// Track a pool, counting the ASIDs outside the ones it has left as
+    /// assigned: they have been handed out already, or belong to the other
+    /// half of a split.
+    fn new<FreeSlots: Unsigned>(pool: LocalCap<ASIDPool<FreeSlots>>) -> Self {
+        let mut managed = ManagedPool {
+            cptr: pool.cptr,
+            id: pool.cap_data.id,
+            assigned: [0; ASID_WORDS],
+        };
+        let free = pool.cap_data.next_free_slot..pool.cap_data.next_free_slot + FreeSlots::USIZE;
+        for index in 0..arch::ASIDPoolSize::USIZE {
+            if !free.contains(&index) {
+                managed.set_assigned(index, true);
+            }
+        }
+        // ASID 0 is never assigned by the kernel, so it is never free.
+        if managed.id == 0 {
+            managed.set_assigned(0, true);
+        }
+        managed
+    }
+
+    fn first_free(&self) -> Option<usize> {
+        self.assigned
+            .iter()
+            .position(|word| *word != u64::MAX)
+            .map(|i| i * 64 + (!self.assigned[i]).trailing_zeros() as usize)
+    }
+
+    fn is_assigned(&self, index: usize) -> bool {
+        self.assigned[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn set_assigned(&mut self, index: usize, assigned: bool) {
+        if assigned {
+            self.assigned[index / 64] |= 1 << (index % 64);
+        } else {
+            self.assigned[index / 64] &= !(1 << (index % 64));
+        }
+    }
+}
+
+/// What a manager makes new pools with.
+struct PoolMaker {
+    control: LocalCap<WASIDControl>,
+    untyped: WUTBuddy,
+    slots: WCNodeSlots,
+}
+
+/// Hands out ASIDs at runtime, and takes them back. Unlike `ASIDPool`,
+/// which counts what it has left in its type, this suits a system that
+/// starts and stops processes for as long as it runs.
+pub struct ASIDManager {
+    maker: Option<PoolMaker>,
+    pools: ArrayVec<[ManagedPool; MAX_MANAGED_POOLS]>,
+}
+
+impl ASIDManager {
+    /// Make pools with `control` as they are needed, from `untyped`,
+    /// placing them in `slots`, which also provide for the splits the
+    /// buddy makes along the way.
+    pub fn new(control: LocalCap<WASIDControl>, untyped: WUTBuddy, slots: WCNodeSlots) -> Self {
+        ASIDManager {
+            maker: Some(PoolMaker {
+                control,
+                untyped,
+                slots,
+            }),
+            pools: ArrayVec::new(),
+        }
+    }
+
+    /// Hand out the ASIDs `pool` has left, and no others.
+    pub fn from_pool<FreeSlots: Unsigned>(pool: LocalCap<ASIDPool<FreeSlots>>) -> Self {
+        let mut pools = ArrayVec::new();
+        pools.push(ManagedPool::new(pool));
+        ASIDManager { maker: None, pools }
+    }
+
+    /// Hand out the ASIDs a pool that's already been made has left, before
+    /// making any more.
+    pub fn adopt<FreeSlots: Unsigned>(
+        &mut self,
+        pool: LocalCap<ASIDPool<FreeSlots>>,
+    ) -> Result<(), ASIDManagerError> {
+        self.pools
+            .try_push(ManagedPool::new(pool))
+            .map_err(|_| ASIDManagerError::TooManyPools)
+    }
+
+    /// Hand the first free ASID, making a new pool if there are none, to
+    /// `make`, which is expected to assign it by making a `VSpace` with
+    /// it. The ASID only counts as assigned once `make` succeeds: the
+    /// `VSpace` constructors leave their ASID unassigned when they fail,
+    /// so the kernel will pick the same one again next time.
+    pub fn assign<T, E>(
+        &mut self,
+        make: impl FnOnce(LocalCap<UnassignedASID>) -> Result<T, E>,
+    ) -> Result<Result<T, E>, ASIDManagerError> {
+        let found = self
+            .pools
+            .iter()
+            .enumerate()
+            .find_map(|(i, pool)| pool.first_free().map(|index| (i, index)));
+        let (pool_index, index) = match found {
+            Some(found) => found,
+            None => {
+                self.make_pool()?;
+                (
+                    self.pools.len() - 1,
+                    self.pools[self.pools.len() - 1].first_free().unwrap(),
+                )
+            }
+        };
+
+        let pool = &mut self.pools[pool_index];
+        let made = make(Cap {
+            cptr: pool.cptr,
+            _role: PhantomData,
+            cap_data: UnassignedASID {
+                asid: InternalASID {
+                    asid: (pool.id << arch::ASIDLowBits::USIZE) | index,
+                },
+            },
+        });
+        if made.is_ok() {
+            pool.set_assigned(index, true);
+        }
+        Ok(made)
+    }
+
+    /// Take back the ASID of a destroyed address space, to be handed out
+    /// again.
+    pub fn release(&mut self, released: ReleasedASID) -> Result<(), ASIDManagerError> {
+        let id = released.asid.asid >> arch::ASIDLowBits::USIZE;
+        let index = released.asid.asid & (arch::ASIDPoolSize::USIZE - 1);
+        match self.pools.iter_mut().find(|pool| pool.id == id) {
+            Some(pool) if pool.is_assigned(index) => {
+                pool.set_assigned(index, false);
+                Ok(())
+            }
+            _ => Err(ASIDManagerError::UnknownASID),
+        }
+    }
+
+    /// How many ASIDs are left to hand out before another pool is made.
+    pub fn free_count(&self) -> usize {
+        self.pools
+            .iter()
+            .flat_map(|pool| pool.assigned.iter())
+            .map(|word| word.count_zeros() as usize)
+            .sum()
+    }
+
+    pub fn pool_count(&self) -> usize {
+        self.pools.len()
+    }
+
+    fn make_pool(&mut self) -> Result<(), ASIDManagerError> {
+        let maker = match &mut self.maker {
+            Some(maker) if maker.control.free_pools() > 0 => maker,
+            _ => return Err(ASIDManagerError::NoPoolsLeft),
+        };
+        if self.pools.is_full() {
+            return Err(ASIDManagerError::TooManyPools);
+        }
+        let slot = maker
+            .slots
+            .alloc_strong::<U1>()
+            .map_err(|_| ASIDManagerError::NotEnoughSlots)?;
+        let ut12 = maker.untyped.alloc_strong::<U12>(&mut maker.slots)?;
+        let pool = maker.control.allocate_asid_pool(ut12, slot)?;
+        self.pools.push(ManagedPool::new(pool));
+        Ok(())
+    }
+}
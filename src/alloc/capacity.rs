@@ -0,0 +1,285 @@
+//! The compile-time slot count behind `smart_alloc!`, which fails the
+//! build with a summary of the block when its requests don't fit.
+use core::marker::PhantomData;
+use core::ops::{Add, Sub};
+
+use typenum::*;
+
+use crate::cap::{memory_kind, CNodeRole, CNodeSlotsData, Cap, LocalCap, Untyped};
+
+/// The most requests a summary lists. Those beyond it still count toward
+/// the total.
+pub const MAX_LISTED_REQUESTS: usize = 32;
+
+/// The longest a summary gets, in bytes, before it is cut short.
+pub const MAX_SUMMARY_BYTES: usize = 4096;
+
+/// The statements of a block, one for each request made in it, in order.
+/// `smart_alloc!` writes these out for each block it expands.
+pub trait Statements {
+    const STATEMENTS: &'static [&'static str];
+}
+
+/// A request for slots alone.
+pub struct SlotRequest<Count>(PhantomData<Count>);
+
+/// A request for an untyped, along with the slots the untyped allocator
+/// takes to split it off.
+pub struct UntypedRequest<BitSize, Count>(PhantomData<(BitSize, Count)>);
+
+pub trait Request {
+    type Slots: Unsigned;
+    /// The size of the untyped requested, or 0 for slots alone.
+    const UNTYPED_BITS: usize;
+}
+
+impl<Count: Unsigned> Request for SlotRequest<Count> {
+    type Slots = Count;
+    const UNTYPED_BITS: usize = 0;
+}
+
+impl<BitSize: Unsigned, Count: Unsigned> Request for UntypedRequest<BitSize, Count> {
+    type Slots = Count;
+    const UNTYPED_BITS: usize = BitSize::USIZE;
+}
+
+/// The end of a list of requests.
+pub struct NoRequests;
+
+/// A list of requests, latest first.
+pub struct Then<Latest, Earlier>(PhantomData<(Latest, Earlier)>);
+
+pub trait Requests {
+    type Slots: Unsigned;
+    const COUNT: usize;
+    /// The slots and untyped size of each request, in order.
+    const LISTED: [(usize, usize); MAX_LISTED_REQUESTS];
+}
+
+impl Requests for NoRequests {
+    type Slots = U0;
+    const COUNT: usize = 0;
+    const LISTED: [(usize, usize); MAX_LISTED_REQUESTS] = [(0, 0); MAX_LISTED_REQUESTS];
+}
+
+impl<Latest: Request, Earlier: Requests> Requests for Then<Latest, Earlier>
+where
+    Earlier::Slots: Add<Latest::Slots>,
+    Sum<Earlier::Slots, Latest::Slots>: Unsigned,
+{
+    type Slots = Sum<Earlier::Slots, Latest::Slots>;
+    const COUNT: usize = Earlier::COUNT + 1;
+    const LISTED: [(usize, usize); MAX_LISTED_REQUESTS] = list(
+        Earlier::LISTED,
+        Earlier::COUNT,
+        (Latest::Slots::USIZE, Latest::UNTYPED_BITS),
+    );
+}
+
+/// Slots that hand out the requests of a `smart_alloc!` block, keeping
+/// track of what each took. The slots a request takes are only known once
+/// the compiler has inferred its type, after the macro has run, so their
+/// total is checked in a constant rather than by the macro.
+pub struct SlotTally<Size: Unsigned, Role: CNodeRole, CapRole: CNodeRole, Taken> {
+    cptr: usize,
+    offset: usize,
+    _slots: PhantomData<(Size, Role, CapRole, Taken)>,
+}
+
+impl<Size: Unsigned, CapRole: CNodeRole, Role: CNodeRole> Cap<CNodeSlotsData<Size, Role>, CapRole> {
+    /// Start a tally of the requests made of these slots.
+    pub fn tally(self) -> SlotTally<Size, Role, CapRole, NoRequests> {
+        let (cptr, offset, _) = self.elim();
+        SlotTally {
+            cptr,
+            offset,
+            _slots: PhantomData,
+        }
+    }
+}
+
+impl<Size: Unsigned, Role: CNodeRole, CapRole: CNodeRole, Taken>
+    SlotTally<Size, Role, CapRole, Taken>
+{
+    /// Take `Count` slots. Unlike `CNodeSlots::alloc`, this doesn't ask
+    /// that there are enough left: that is checked by `finish`.
+    pub fn alloc<Count: Unsigned>(
+        self,
+    ) -> (
+        Cap<CNodeSlotsData<Count, Role>, CapRole>,
+        SlotTally<Size, Role, CapRole, Then<SlotRequest<Count>, Taken>>,
+    ) {
+        (
+            Cap::internal_new(self.cptr, self.offset),
+            SlotTally {
+                cptr: self.cptr,
+                offset: self.offset + Count::USIZE,
+                _slots: PhantomData,
+            },
+        )
+    }
+
+    /// Check that the requests taken fit in the slots, failing the build
+    /// with a summary of `S` if they don't, and give back what's left.
+    pub fn finish<S: Statements>(
+        self,
+    ) -> Cap<CNodeSlotsData<Diff<Size, Minimum<Size, Taken::Slots>>, Role>, CapRole>
+    where
+        Taken: Requests,
+        Size: Min<Taken::Slots>,
+        Size: Sub<Minimum<Size, Taken::Slots>>,
+        Diff<Size, Minimum<Size, Taken::Slots>>: Unsigned,
+    {
+        #[allow(clippy::let_unit_value)]
+        let () = Fits::<Size, Taken, S>::CHECK;
+        Cap::internal_new(self.cptr, self.offset)
+    }
+}
+
+impl<Size: Unsigned, Role: CNodeRole, CapRole: CNodeRole, Count: Unsigned, Earlier>
+    SlotTally<Size, Role, CapRole, Then<SlotRequest<Count>, Earlier>>
+{
+    /// Note that the slots last taken went to splitting off `untyped`.
+    pub fn untyped<BitSize: Unsigned>(
+        self,
+        _untyped: &LocalCap<Untyped<BitSize, memory_kind::General>>,
+    ) -> SlotTally<Size, Role, CapRole, Then<UntypedRequest<BitSize, Count>, Earlier>> {
+        SlotTally {
+            cptr: self.cptr,
+            offset: self.offset,
+            _slots: PhantomData,
+        }
+    }
+}
+
+struct Fits<Size, Taken, S>(PhantomData<(Size, Taken, S)>);
+
+impl<Size: Unsigned, Taken: Requests, S: Statements> Fits<Size, Taken, S> {
+    const SUMMARY: Summary = summarize(
+        Size::USIZE,
+        Taken::Slots::USIZE,
+        Taken::COUNT,
+        &Taken::LISTED,
+        S::STATEMENTS,
+    );
+
+    const CHECK: () = if Taken::Slots::USIZE > Size::USIZE {
+        panic!("{}", Self::SUMMARY.written())
+    };
+}
+
+const fn list(
+    mut listed: [(usize, usize); MAX_LISTED_REQUESTS],
+    index: usize,
+    request: (usize, usize),
+) -> [(usize, usize); MAX_LISTED_REQUESTS] {
+    if index < MAX_LISTED_REQUESTS {
+        listed[index] = request;
+    }
+    listed
+}
+
+/// A message built up at compile time.
+struct Summary {
+    bytes: [u8; MAX_SUMMARY_BYTES],
+    len: usize,
+}
+
+impl Summary {
+    /// The part of the message written so far. A slice can't be cut to
+    /// length by indexing in a constant, so this puts it together from
+    /// its pointer and length, as `core::slice::from_raw_parts` does.
+    const fn written(&'static self) -> &'static str {
+        #[derive(Clone, Copy)]
+        #[repr(C)]
+        struct Parts {
+            ptr: *const u8,
+            len: usize,
+        }
+        union Text {
+            parts: Parts,
+            text: &'static str,
+        }
+        // Only ASCII is ever written to a summary.
+        unsafe {
+            Text {
+                parts: Parts {
+                    ptr: self.bytes.as_ptr(),
+                    len: self.len,
+                },
+            }
+            .text
+        }
+    }
+}
+
+const fn summarize(
+    provided: usize,
+    needed: usize,
+    count: usize,
+    listed: &[(usize, usize); MAX_LISTED_REQUESTS],
+    statements: &[&str],
+) -> Summary {
+    let mut s = Summary {
+        bytes: [0; MAX_SUMMARY_BYTES],
+        len: 0,
+    };
+    s = write_str(s, "smart_alloc needs ");
+    s = write_usize(s, needed);
+    s = write_str(s, " slots, only ");
+    s = write_usize(s, provided);
+    s = write_str(s, " provided");
+
+    let mut i = 0;
+    while i < count && i < MAX_LISTED_REQUESTS {
+        let (slots, untyped_bits) = listed[i];
+        s = write_str(s, "\n  `");
+        s = write_str(
+            s,
+            if i < statements.len() {
+                statements[i]
+            } else {
+                "?"
+            },
+        );
+        s = write_str(s, "` takes ");
+        if untyped_bits > 0 {
+            s = write_str(s, "a 2^");
+            s = write_usize(s, untyped_bits);
+            s = write_str(s, " byte untyped and ");
+        }
+        s = write_usize(s, slots);
+        s = write_str(s, if slots == 1 { " slot" } else { " slots" });
+        i += 1;
+    }
+    if count > MAX_LISTED_REQUESTS {
+        s = write_str(s, "\n  and ");
+        s = write_usize(s, count - MAX_LISTED_REQUESTS);
+        s = write_str(s, " more requests");
+    }
+    s
+}
+
+const fn write_str(mut s: Summary, text: &str) -> Summary {
+    let text = text.as_bytes();
+    let mut i = 0;
+    while i < text.len() && s.len < MAX_SUMMARY_BYTES {
+        s.bytes[s.len] = if text[i] < 0x80 { text[i] } else { b'?' };
+        s.len += 1;
+        i += 1;
+    }
+    s
+}
+
+const fn write_usize(mut s: Summary, n: usize) -> Summary {
+    let mut divisor = 1;
+    while n / divisor >= 10 {
+        divisor *= 10;
+    }
+    while divisor > 0 && s.len < MAX_SUMMARY_BYTES {
+        s.bytes[s.len] = b'0' + (n / divisor % 10) as u8;
+        s.len += 1;
+        divisor /= 10;
+    }
+    s
+}
@@ -0,0 +1,176 @@
+//! A buddy allocator like `UTBuddy`, with the untypeds it holds tracked
+//! by a const generic rather than a typenum list.
+use core::marker::PhantomData;
+
+use arrayvec::ArrayVec;
+use typenum::*;
+
+use super::ut_buddy::{alloc, make_pool, revoke, ULCons, ULNull, UList, UTPoolSlotsPerSize};
+use super::{UTBuddy, UTBuddyError, WUTBuddy};
+use crate::arch::{MaxUntypedSize, MinUntypedSize};
+use crate::cap::{Cap, ConstUntyped, LocalCNode, LocalCNodeSlot, LocalCap, LocalConstCNodeSlots};
+use crate::error::SeL4Error;
+
+/// `FREE` has a bit set for each size, from `MinUntypedSize` up, that the
+/// pool holds an untyped of. Splitting only ever leaves one behind at each
+/// size, so unlike `UTBuddy`, `free` doesn't compile for a size the pool
+/// already holds.
+pub struct ConstUTBuddy<const FREE: usize> {
+    pool: [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE],
+}
+
+/// Make a new ConstUTBuddy by wrapping an untyped.
+pub fn const_ut_buddy<const BITS: usize>(
+    ut: LocalCap<ConstUntyped<BITS>>,
+) -> ConstUTBuddy<{ give_back(0, BITS) }>
+where
+    [(); give_back(0, BITS)]: ,
+{
+    let mut pool = make_pool();
+    pool[BITS - MinUntypedSize::USIZE].push(ut.cptr);
+    ConstUTBuddy { pool }
+}
+
+impl<const FREE: usize> ConstUTBuddy<FREE> {
+    pub fn alloc<const BITS: usize>(
+        mut self,
+        slots: LocalConstCNodeSlots<{ splits(FREE, BITS) * 2 }>,
+    ) -> Result<
+        (
+            LocalCap<ConstUntyped<BITS>>,
+            ConstUTBuddy<{ take(FREE, BITS) }>,
+        ),
+        SeL4Error,
+    >
+    where
+        [(); splits(FREE, BITS) * 2]: ,
+        [(); take(FREE, BITS)]: ,
+    {
+        let (cptr, offset) = (slots.cptr, slots.cap_data.offset);
+        let slots_iter = (0..splits(FREE, BITS) * 2)
+            .map(move |n| LocalCNodeSlot::internal_new(cptr, offset + n));
+        let weak_ut = alloc(
+            &mut self.pool,
+            None,
+            slots_iter,
+            BITS as u8,
+            splits(FREE, BITS) as u8,
+        )?;
+        Ok((
+            Cap::wrap_cptr(weak_ut.cptr),
+            ConstUTBuddy { pool: self.pool },
+        ))
+    }
+
+    /// Return an untyped allocated from this pool, first revoking
+    /// everything derived from it so that it can be allocated afresh.
+    pub fn free<const BITS: usize>(
+        mut self,
+        ut: LocalCap<ConstUntyped<BITS>>,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<ConstUTBuddy<{ give_back(FREE, BITS) }>, UTBuddyError>
+    where
+        [(); give_back(FREE, BITS)]: ,
+    {
+        revoke(cnode, ut.cptr)?;
+        self.pool[BITS - MinUntypedSize::USIZE].push(ut.cptr);
+        Ok(ConstUTBuddy { pool: self.pool })
+    }
+
+    /// Erase the sizes tracked in the type, for allocation checked at
+    /// runtime instead.
+    pub fn weaken(self) -> WUTBuddy {
+        WUTBuddy::from_pool(self.pool)
+    }
+
+    /// The same pool, tracked by typenum. `PoolSizes` must hold the
+    /// sizes `FREE` does.
+    pub fn to_typenum<PoolSizes: FreeSizes>(self) -> UTBuddy<PoolSizes> {
+        #[allow(clippy::let_unit_value)]
+        let () = SameSizes::<PoolSizes, FREE>::CHECK;
+        UTBuddy::from_pool(self.pool)
+    }
+}
+
+impl<PoolSizes: FreeSizes> UTBuddy<PoolSizes> {
+    /// The same pool, tracked by a const generic. It must hold no more
+    /// than one untyped of each size.
+    pub fn to_const(self) -> ConstUTBuddy<{ PoolSizes::FREE }>
+    where
+        [(); PoolSizes::FREE]: ,
+    {
+        ConstUTBuddy {
+            pool: self.into_pool(),
+        }
+    }
+}
+
+/// The sizes a typenum pool holds, as a `ConstUTBuddy` tracks them.
+pub trait FreeSizes: UList {
+    const FREE: usize;
+}
+
+impl FreeSizes for ULNull {
+    const FREE: usize = 0;
+}
+
+impl<Head: Unsigned, Tail: FreeSizes> FreeSizes for ULCons<Head, Tail>
+where
+    ULCons<Head, Tail>: UList,
+{
+    const FREE: usize = held(Head::USIZE) | (Tail::FREE << 1);
+}
+
+/// How many times an untyped of `1 << bits` bytes must be split off a
+/// larger one: the distance from `bits` up to the nearest size held.
+pub const fn splits(free: usize, bits: usize) -> usize {
+    if bits < MinUntypedSize::USIZE || bits > MaxUntypedSize::USIZE {
+        panic!("untypeds that size can't be allocated")
+    }
+    let index = bits - MinUntypedSize::USIZE;
+    let mut i = index;
+    while i <= MaxUntypedSize::USIZE - MinUntypedSize::USIZE {
+        if free & (1 << i) != 0 {
+            return i - index;
+        }
+        i += 1;
+    }
+    panic!("no untyped that big is left in the pool")
+}
+
+/// The sizes held after taking an untyped of `1 << bits` bytes: the one
+/// split down is gone, and a half is left at each size on the way.
+pub const fn take(free: usize, bits: usize) -> usize {
+    let index = bits - MinUntypedSize::USIZE;
+    let from = index + splits(free, bits);
+    let left_behind = (1 << from) - (1 << index);
+    (free & !(1 << from)) | left_behind
+}
+
+/// The sizes held after an untyped of `1 << bits` bytes is given back.
+pub const fn give_back(free: usize, bits: usize) -> usize {
+    if bits < MinUntypedSize::USIZE || bits > MaxUntypedSize::USIZE {
+        panic!("untypeds that size can't be held")
+    }
+    let bit = 1 << (bits - MinUntypedSize::USIZE);
+    if free & bit != 0 {
+        panic!("the pool already holds an untyped that size")
+    }
+    free | bit
+}
+
+const fn held(count: usize) -> usize {
+    if count > 1 {
+        panic!("a ConstUTBuddy holds no more than one untyped of each size")
+    }
+    count
+}
+
+/// Fails to compile unless `PoolSizes` holds what `FREE` does.
+struct SameSizes<PoolSizes, const FREE: usize>(PhantomData<PoolSizes>);
+
+impl<PoolSizes: FreeSizes, const FREE: usize> SameSizes<PoolSizes, FREE> {
+    const CHECK: () = if PoolSizes::FREE != FREE {
+        panic!("a ConstUTBuddy was converted to a UTBuddy holding different sizes")
+    };
+}
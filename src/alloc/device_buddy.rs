@@ -0,0 +1,283 @@
+//! A buddy allocator for device memory, which hands out untypeds by the
+//! physical address range they cover.
+use core::cmp;
+use core::marker::PhantomData;
+
+use arrayvec::ArrayVec;
+use typenum::*;
+
+use super::micro_alloc::{DeviceAllocator, MAX_INIT_UNTYPED_ITEMS};
+use super::ut_buddy::{revoke, Split, MAX_TRACKED_SPLITS};
+use crate::arch::{MaxUntypedSize, PageBits};
+use crate::cap::{
+    memory_kind, Cap, LocalCNode, LocalCNodeSlots, LocalCap, Untyped, WCNodeSlots, WUntyped,
+    WUntypedSplitError,
+};
+use crate::error::SeL4Error;
+
+/// The most free untypeds a `DeviceBuddy` can hold at once.
+pub const MAX_DEVICE_BUDDY_UNTYPEDS: usize = MAX_INIT_UNTYPED_ITEMS;
+
+#[derive(Debug)]
+pub enum DeviceBuddyError {
+    /// Device untypeds are handed out from a page up to the largest
+    /// untyped size for this architecture.
+    SizeOutOfRange(u8),
+    /// The requested address is not aligned to the requested size.
+    AddressNotAligned,
+    /// No free untyped managed by this allocator covers the range.
+    RangeNotAvailable,
+    /// There are not enough CNode slots to do the requisite splitting.
+    NotEnoughSlots,
+    TooManyUntypeds,
+    /// The untyped being freed, at this cptr, is already free, or isn't
+    /// the size this allocator split it off as.
+    NotFromThisPool(usize),
+    SplitError(WUntypedSplitError),
+    SeL4Error(SeL4Error),
+}
+
+impl From<WUntypedSplitError> for DeviceBuddyError {
+    fn from(e: WUntypedSplitError) -> Self {
+        DeviceBuddyError::SplitError(e)
+    }
+}
+
+impl From<SeL4Error> for DeviceBuddyError {
+    fn from(e: SeL4Error) -> Self {
+        DeviceBuddyError::SeL4Error(e)
+    }
+}
+
+/// Device untypeds, split on demand and merged again as they are freed.
+/// Unlike `DeviceAllocator`, this takes ranges back, so a peripheral
+/// window can be carved up between several drivers.
+pub struct DeviceBuddy {
+    /// The free untypeds, kept sorted by physical address.
+    untypeds: ArrayVec<[LocalCap<WUntyped<memory_kind::Device>>; MAX_DEVICE_BUDDY_UNTYPEDS]>,
+    splits: ArrayVec<[Split; MAX_TRACKED_SPLITS]>,
+    /// Pairs of slots emptied by merging halves, for later splits to use.
+    spare_slots: ArrayVec<[LocalCNodeSlots<U2>; MAX_TRACKED_SPLITS]>,
+}
+
+impl DeviceBuddy {
+    pub fn new() -> Self {
+        DeviceBuddy {
+            untypeds: ArrayVec::new(),
+            splits: ArrayVec::new(),
+            spare_slots: ArrayVec::new(),
+        }
+    }
+
+    /// Hand another device untyped over to the allocator.
+    pub fn add(
+        &mut self,
+        ut: LocalCap<WUntyped<memory_kind::Device>>,
+    ) -> Result<(), DeviceBuddyError> {
+        if self.untypeds.is_full() {
+            return Err(DeviceBuddyError::TooManyUntypeds);
+        }
+        self.insert_sorted(ut);
+        Ok(())
+    }
+
+    /// The physical address and size in bits of each free untyped, in
+    /// address order.
+    pub fn available(&self) -> impl Iterator<Item = (usize, u8)> + '_ {
+        self.untypeds.iter().map(|ut| (ut.paddr(), ut.size_bits()))
+    }
+
+    /// Take the `1 << size_bits` bytes of device memory starting at
+    /// `paddr`, splitting the free untyped covering them as needed. Each
+    /// split consumes two slots, taken from those left by past merges
+    /// before `slots`.
+    pub fn alloc(
+        &mut self,
+        slots: &mut WCNodeSlots,
+        paddr: usize,
+        size_bits: u8,
+    ) -> Result<LocalCap<WUntyped<memory_kind::Device>>, DeviceBuddyError> {
+        if size_bits < PageBits::U8 || size_bits > MaxUntypedSize::U8 {
+            return Err(DeviceBuddyError::SizeOutOfRange(size_bits));
+        }
+        if paddr & ((1 << size_bits) - 1) != 0 {
+            return Err(DeviceBuddyError::AddressNotAligned);
+        }
+
+        // Untypeds are aligned to their size, so one containing `paddr`
+        // which is at least as large covers the whole range.
+        let position = self
+            .untypeds
+            .iter()
+            .position(|ut| ut.paddr() <= paddr && paddr - ut.paddr() < ut.size_bytes())
+            .ok_or(DeviceBuddyError::RangeNotAvailable)?;
+        let found_size_bits = self.untypeds[position].size_bits();
+        if found_size_bits < size_bits {
+            return Err(DeviceBuddyError::RangeNotAvailable);
+        }
+
+        // Check everything splitting needs up front, so that running out
+        // part way doesn't leave halves unaccounted for.
+        let split_count = usize::from(found_size_bits - size_bits);
+        let spare_count = cmp::min(split_count, self.spare_slots.len());
+        if (split_count - spare_count) * 2 > slots.size() {
+            return Err(DeviceBuddyError::NotEnoughSlots);
+        }
+        if self.untypeds.len() - 1 + split_count > self.untypeds.capacity() {
+            return Err(DeviceBuddyError::TooManyUntypeds);
+        }
+
+        let mut ut = self.untypeds.remove(position);
+        while ut.size_bits() > size_bits {
+            let parent = ut.cptr;
+            let slot_pair = match self.spare_slots.pop() {
+                Some(pair) => pair,
+                None => slots
+                    .alloc_strong::<U2>()
+                    .map_err(|_| DeviceBuddyError::NotEnoughSlots)?,
+            };
+            let (lower, upper) = ut.split(slot_pair)?;
+            let _ = self.splits.try_push(Split {
+                parent,
+                first_half: lower.cptr,
+                half_size_bits: lower.size_bits(),
+            });
+            let (wanted, spare) = if paddr < upper.paddr() {
+                (lower, upper)
+            } else {
+                (upper, lower)
+            };
+            self.insert_sorted(spare);
+            ut = wanted;
+        }
+        Ok(ut)
+    }
+
+    /// Take the `Size` bits of device memory starting at `paddr`, as a
+    /// strong untyped.
+    pub fn alloc_strong<Size: Unsigned>(
+        &mut self,
+        slots: &mut WCNodeSlots,
+        paddr: usize,
+    ) -> Result<LocalCap<Untyped<Size, memory_kind::Device>>, DeviceBuddyError> {
+        let weak_ut = self.alloc(slots, paddr, Size::U8)?;
+        Ok(Cap {
+            cptr: weak_ut.cptr,
+            cap_data: Untyped {
+                kind: weak_ut.cap_data.kind,
+                _bit_size: PhantomData,
+            },
+            _role: PhantomData,
+        })
+    }
+
+    /// Return an untyped allocated from this allocator, first revoking
+    /// everything derived from it, e.g. the frames a driver mapped. While
+    /// its buddy is free too, the two are merged back into the untyped
+    /// they were split from, and the slots the halves occupied are kept
+    /// for later splits.
+    ///
+    /// Untyped that overlap memory which is already free, or that are
+    /// split, or that don't match the size they were split off as, are
+    /// refused.
+    pub fn free(
+        &mut self,
+        ut: LocalCap<WUntyped<memory_kind::Device>>,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<(), DeviceBuddyError> {
+        let size_bits = ut.size_bits();
+        if size_bits < PageBits::U8 || size_bits > MaxUntypedSize::U8 {
+            return Err(DeviceBuddyError::SizeOutOfRange(size_bits));
+        }
+        let recorded_size_bits = self
+            .splits
+            .iter()
+            .find(|s| s.buddy_of(ut.cptr).is_some())
+            .map(|s| s.half_size_bits);
+        let overlaps_free = self.untypeds.iter().any(|u| {
+            u.cptr == ut.cptr
+                || (u.paddr() < ut.paddr() + ut.size_bytes()
+                    && ut.paddr() < u.paddr() + u.size_bytes())
+        });
+        if overlaps_free
+            || self.splits.iter().any(|s| s.parent == ut.cptr)
+            || recorded_size_bits.map_or(false, |bits| bits != size_bits)
+        {
+            return Err(DeviceBuddyError::NotFromThisPool(ut.cptr));
+        }
+
+        // Merging never adds to the free untypeds, only returning it
+        // unmerged does.
+        if self.untypeds.is_full() && self.free_buddy(&ut).is_none() {
+            return Err(DeviceBuddyError::TooManyUntypeds);
+        }
+
+        revoke(cnode, ut.cptr)?;
+        let mut ut = ut;
+        while let Some((buddy_position, split_position)) = self.free_buddy(&ut) {
+            let buddy = self.untypeds.remove(buddy_position);
+            let split = self.splits.remove(split_position);
+            // Deletes both halves, leaving the parent whole again.
+            revoke(cnode, split.parent)?;
+            let _ = self
+                .spare_slots
+                .try_push(LocalCNodeSlots::internal_new(cnode.cptr, split.first_half));
+            ut = Cap {
+                cptr: split.parent,
+                cap_data: WUntyped {
+                    size_bits: ut.size_bits() + 1,
+                    kind: memory_kind::Device {
+                        paddr: cmp::min(ut.paddr(), buddy.paddr()),
+                    },
+                },
+                _role: PhantomData,
+            };
+        }
+        self.insert_sorted(ut);
+        Ok(())
+    }
+
+    /// Where the buddy of `ut` is among the free untypeds, and where the
+    /// split it came from is recorded, if its buddy is free.
+    fn free_buddy(&self, ut: &LocalCap<WUntyped<memory_kind::Device>>) -> Option<(usize, usize)> {
+        let split_position = self
+            .splits
+            .iter()
+            .position(|s| s.buddy_of(ut.cptr).is_some())?;
+        let buddy = self.splits[split_position].buddy_of(ut.cptr)?;
+        let buddy_position = self.untypeds.iter().position(|u| u.cptr == buddy)?;
+        Some((buddy_position, split_position))
+    }
+
+    /// Room must have been checked for.
+    fn insert_sorted(&mut self, ut: LocalCap<WUntyped<memory_kind::Device>>) {
+        let position = self
+            .untypeds
+            .iter()
+            .position(|u| u.paddr() > ut.paddr())
+            .unwrap_or_else(|| self.untypeds.len());
+        self.untypeds.insert(position, ut);
+    }
+}
+
+impl Default for DeviceBuddy {
+    fn default() -> Self {
+        DeviceBuddy::new()
+    }
+}
+
+impl From<DeviceAllocator> for DeviceBuddy {
+    fn from(alloc: DeviceAllocator) -> Self {
+        // Both hold at most MAX_INIT_UNTYPED_ITEMS, already sorted by
+        // physical address.
+        let mut untypeds = ArrayVec::new();
+        for ut in alloc.untypeds {
+            untypeds.push(ut);
+        }
+        DeviceBuddy {
+            untypeds,
+            splits: ArrayVec::new(),
+            spare_slots: ArrayVec::new(),
+        }
+    }
+}
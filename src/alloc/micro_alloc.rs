@@ -14,6 +14,8 @@ use crate::cap::{
     WUntyped, WUntypedSplitError,
 };
 use crate::pow::Pow;
+
+use super::stats::{AllocatedBytes, UntypedStats};
 use arrayvec::ArrayVec;
 use core::convert::{TryFrom, TryInto};
 use typenum::*;
@@ -73,7 +75,10 @@ pub fn bootstrap_allocators(
     // initial insertion
     pdqsort::sort_by_key(&mut device_uts, |wut| wut.cap_data.kind.paddr);
     Ok((
-        Allocator { items: general_uts },
+        Allocator {
+            items: general_uts,
+            allocated: AllocatedBytes::default(),
+        },
         DeviceAllocator {
             untypeds: device_uts,
         },
@@ -83,6 +88,7 @@ pub fn bootstrap_allocators(
 /// An allocator for general purpose memory.
 pub struct Allocator {
     pub(super) items: ArrayVec<[LocalCap<WUntyped<memory_kind::General>>; MAX_INIT_UNTYPED_ITEMS]>,
+    allocated: AllocatedBytes,
 }
 
 impl Debug for Allocator {
@@ -117,8 +123,17 @@ impl Allocator {
             _role: PhantomData,
         };
         self.items.remove(position);
+        self.allocated.take(1 << BitSize::USIZE);
         Some(ut)
     }
+
+    pub fn stats(&self) -> UntypedStats {
+        let mut stats = UntypedStats::new(self.allocated);
+        for ut in self.items.iter() {
+            stats.count_free(ut.size_bits(), 1);
+        }
+        stats
+    }
 }
 
 // TODO(dan@auxon.io): I have no idea what to put here.
@@ -128,7 +143,7 @@ const MAX_DEVICE_UTS: usize = MAX_INIT_UNTYPED_ITEMS;
 
 /// An allocator for memory in use by devices.
 pub struct DeviceAllocator {
-    untypeds: ArrayVec<[LocalCap<WUntyped<memory_kind::Device>>; MAX_DEVICE_UTS]>,
+    pub(super) untypeds: ArrayVec<[LocalCap<WUntyped<memory_kind::Device>>; MAX_DEVICE_UTS]>,
 }
 
 impl Debug for DeviceAllocator {
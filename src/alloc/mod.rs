@@ -1,5 +1,15 @@
+pub mod asid_manager;
+pub mod capacity;
+#[cfg(feature = "const_generics")]
+pub mod const_ut_buddy;
+pub mod device_buddy;
 pub mod micro_alloc;
+pub mod object_factory;
+pub mod stats;
 pub mod ut_buddy;
 
+pub use self::asid_manager::ASIDManager;
+pub use self::device_buddy::DeviceBuddy;
+pub use self::object_factory::ObjectFactory;
 pub use self::ut_buddy::{ut_buddy, UTBuddy, WUTBuddy};
 pub use crate::smart_alloc::smart_alloc;
@@ -0,0 +1,262 @@
+//! Kernel objects made on demand, from a pool of untyped memory and a
+//! range of slots, and recycled once deleted.
+use core::cmp;
+use core::marker::PhantomData;
+
+use arrayvec::ArrayVec;
+use typenum::*;
+
+use super::stats::UntypedStats;
+use super::ut_buddy::UTBuddyError;
+use super::WUTBuddy;
+use crate::arch::{MinUntypedSize, PageBits};
+use crate::cap::{
+    memory_kind, role, Cap, CapType, DirectRetype, LocalCNode, LocalCap, PhantomCap, RecycledSlots,
+    RetypeError, WCNodeSlots, WUntyped, WeakCapRange,
+};
+use crate::vspace::{shared_status, WeakMemoryRegion, WeakUnmappedMemoryRegion};
+
+/// The most objects, batches and regions an `ObjectFactory` keeps alive
+/// at once.
+pub const MAX_FACTORY_OBJECTS: usize = 64;
+
+#[derive(Debug)]
+pub enum ObjectFactoryError {
+    /// Batches must hold at least one object.
+    EmptyBatch,
+    /// The object wasn't made by this factory, or has been deleted.
+    UnknownObject,
+    TooManyObjects,
+    NotEnoughSlots,
+    /// Regions are backed by pages, so are at least a page in size.
+    RegionSmallerThanPage(u8),
+    UTBuddyError(UTBuddyError),
+    RetypeError(RetypeError),
+}
+
+impl From<UTBuddyError> for ObjectFactoryError {
+    fn from(e: UTBuddyError) -> Self {
+        ObjectFactoryError::UTBuddyError(e)
+    }
+}
+
+impl From<RetypeError> for ObjectFactoryError {
+    fn from(e: RetypeError) -> Self {
+        ObjectFactoryError::RetypeError(e)
+    }
+}
+
+/// The untyped an object, batch or region was made from.
+struct Backing {
+    untyped: usize,
+    size_bits: u8,
+    first_cptr: usize,
+    count: usize,
+}
+
+/// Makes kernel objects at runtime. Each object, batch or region gets an
+/// untyped of its own from a `WUTBuddy`, which deleting it revokes and
+/// gives back to be merged with its neighbours.
+pub struct ObjectFactory<'a> {
+    untyped: WUTBuddy,
+    slots: WCNodeSlots,
+    cnode: &'a LocalCap<LocalCNode>,
+    recycled_slots: RecycledSlots<role::Local>,
+    live: ArrayVec<[Backing; MAX_FACTORY_OBJECTS]>,
+}
+
+impl<'a> ObjectFactory<'a> {
+    /// Make objects from `untyped`, placing them in `slots`, which also
+    /// provide for the splits the buddy makes along the way.
+    pub fn new(untyped: WUTBuddy, slots: WCNodeSlots, cnode: &'a LocalCap<LocalCNode>) -> Self {
+        ObjectFactory {
+            untyped,
+            slots,
+            cnode,
+            recycled_slots: RecycledSlots::new(),
+            live: ArrayVec::new(),
+        }
+    }
+
+    /// Make a single object, reusing the slot of a deleted one if there
+    /// is any.
+    pub fn make<D: CapType + PhantomCap + DirectRetype>(
+        &mut self,
+    ) -> Result<LocalCap<D>, ObjectFactoryError> {
+        self.check_room()?;
+        let size_bits = cmp::max(D::SizeBits::U8, MinUntypedSize::U8);
+        let ut = self.untyped.alloc(&mut self.slots, size_bits)?;
+        let ut_cptr = ut.cptr;
+
+        let mut slot = self.object_slots(ut_cptr, size_bits, 1)?;
+        let offset = slot.cap_data.offset;
+        match ut.retype::<D>(&mut slot) {
+            Ok(object) => {
+                self.live.push(Backing {
+                    untyped: ut_cptr,
+                    size_bits,
+                    first_cptr: object.cptr,
+                    count: 1,
+                });
+                Ok(object)
+            }
+            Err(e) => {
+                self.recycled_slots.put_back(offset, 1);
+                self.give_back(ut_cptr, size_bits)?;
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Make `count` objects at once, in consecutive slots, reusing those of
+    /// deleted objects if enough of them are. They share an untyped, and
+    /// so are deleted together.
+    pub fn make_many<D: CapType + PhantomCap + DirectRetype>(
+        &mut self,
+        count: usize,
+    ) -> Result<WeakCapRange<D, role::Local>, ObjectFactoryError> {
+        if count == 0 {
+            return Err(ObjectFactoryError::EmptyBatch);
+        }
+        self.check_room()?;
+        let size_bits = cmp::max(
+            D::SizeBits::U8 + count.next_power_of_two().trailing_zeros() as u8,
+            MinUntypedSize::U8,
+        );
+        let ut = self.untyped.alloc(&mut self.slots, size_bits)?;
+        let ut_cptr = ut.cptr;
+        let mut object_slots = self.object_slots(ut_cptr, size_bits, count)?;
+        let offset = object_slots.cap_data.offset;
+
+        match ut.retype_many::<D>(count, &mut object_slots) {
+            Ok(objects) => {
+                self.live.push(Backing {
+                    untyped: ut_cptr,
+                    size_bits,
+                    first_cptr: objects.start_cptr,
+                    count,
+                });
+                Ok(objects)
+            }
+            Err(e) => {
+                self.recycled_slots.put_back(offset, count);
+                self.give_back(ut_cptr, size_bits)?;
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Make a region of `1 << size_bits` bytes, backed by pages, reusing
+    /// the slots of deleted objects if enough of them are.
+    pub fn make_region(
+        &mut self,
+        size_bits: u8,
+    ) -> Result<WeakUnmappedMemoryRegion<shared_status::Exclusive>, ObjectFactoryError> {
+        if size_bits < PageBits::U8 {
+            return Err(ObjectFactoryError::RegionSmallerThanPage(size_bits));
+        }
+        self.check_room()?;
+        let ut = self.untyped.alloc(&mut self.slots, size_bits)?;
+        let ut_cptr = ut.cptr;
+        let count = 1 << (size_bits - PageBits::U8);
+        let mut page_slots = self.object_slots(ut_cptr, size_bits, count)?;
+        let offset = page_slots.cap_data.offset;
+
+        match WeakMemoryRegion::new(ut, &mut page_slots) {
+            Ok(region) => {
+                self.live.push(Backing {
+                    untyped: ut_cptr,
+                    size_bits,
+                    first_cptr: region.caps.start_cptr,
+                    count,
+                });
+                Ok(region)
+            }
+            Err(e) => {
+                self.recycled_slots.put_back(offset, count);
+                self.give_back(ut_cptr, size_bits)?;
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Delete an object made with `make`.
+    pub fn delete<D: CapType>(&mut self, object: LocalCap<D>) -> Result<(), ObjectFactoryError> {
+        self.recycle(object.cptr)
+    }
+
+    /// Delete a batch of objects made with `make_many`.
+    pub fn delete_many<D: CapType>(
+        &mut self,
+        objects: WeakCapRange<D, role::Local>,
+    ) -> Result<(), ObjectFactoryError> {
+        self.recycle(objects.start_cptr)
+    }
+
+    /// Delete a region made with `make_region`. Unmap it first: deleting
+    /// its pages would unmap them anyway, but behind the back of the
+    /// `VSpace` they were mapped in.
+    pub fn delete_region(
+        &mut self,
+        region: WeakUnmappedMemoryRegion<shared_status::Exclusive>,
+    ) -> Result<(), ObjectFactoryError> {
+        self.recycle(region.caps.start_cptr)
+    }
+
+    pub fn stats(&self) -> UntypedStats {
+        self.untyped.stats()
+    }
+
+    fn check_room(&self) -> Result<(), ObjectFactoryError> {
+        if self.live.is_full() {
+            return Err(ObjectFactoryError::TooManyObjects);
+        }
+        Ok(())
+    }
+
+    /// `count` slots for the objects to be made from the untyped at
+    /// `ut_cptr`, which is given back should there be none.
+    fn object_slots(
+        &mut self,
+        ut_cptr: usize,
+        size_bits: u8,
+        count: usize,
+    ) -> Result<WCNodeSlots, ObjectFactoryError> {
+        match self.recycled_slots.alloc(&mut self.slots, count) {
+            Ok(slots) => Ok(slots),
+            Err(_) => {
+                self.give_back(ut_cptr, size_bits)?;
+                Err(ObjectFactoryError::NotEnoughSlots)
+            }
+        }
+    }
+
+    /// Revoke the untyped behind the objects starting at `first_cptr`,
+    /// which deletes them, and put it and their slots up for reuse. Should
+    /// the untyped not be taken back, the objects are still tracked.
+    fn recycle(&mut self, first_cptr: usize) -> Result<(), ObjectFactoryError> {
+        let position = self
+            .live
+            .iter()
+            .position(|b| b.first_cptr == first_cptr)
+            .ok_or(ObjectFactoryError::UnknownObject)?;
+        let (untyped, size_bits) = (self.live[position].untyped, self.live[position].size_bits);
+        self.give_back(untyped, size_bits)?;
+        let backing = self.live.remove(position);
+        self.recycled_slots
+            .put_back(backing.first_cptr, backing.count);
+        Ok(())
+    }
+
+    fn give_back(&mut self, cptr: usize, size_bits: u8) -> Result<(), ObjectFactoryError> {
+        let ut: LocalCap<WUntyped<memory_kind::General>> = Cap {
+            cptr,
+            cap_data: WUntyped {
+                size_bits,
+                kind: memory_kind::General,
+            },
+            _role: PhantomData,
+        };
+        Ok(self.untyped.free(ut, self.cnode)?)
+    }
+}
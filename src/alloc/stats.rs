@@ -0,0 +1,250 @@
+//! How much an allocator has handed out and how much it has left, and who
+//! took it.
+use core::fmt;
+
+use arrayvec::ArrayVec;
+use typenum::*;
+
+use super::WUTBuddy;
+use crate::arch::{MaxUntypedSize, MinUntypedSize};
+use crate::cap::{CNodeRole, WCNodeSlots};
+
+/// The most consumers a `UsageLedger` keeps track of.
+pub const MAX_LEDGER_CONSUMERS: usize = 16;
+
+/// The free untypeds an allocator holds, by size, and how much it has
+/// handed out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UntypedStats {
+    /// The number of free untypeds of each size, from `MinUntypedSize` up.
+    free_counts: [usize; MaxUntypedSize::USIZE],
+    /// Bytes currently handed out.
+    pub allocated_bytes: usize,
+    /// The most bytes ever handed out at once.
+    pub high_water_bytes: usize,
+}
+
+impl UntypedStats {
+    pub(super) fn new(allocated: AllocatedBytes) -> Self {
+        UntypedStats {
+            free_counts: [0; MaxUntypedSize::USIZE],
+            allocated_bytes: allocated.current,
+            high_water_bytes: allocated.high_water,
+        }
+    }
+
+    pub(super) fn count_free(&mut self, size_bits: u8, count: usize) {
+        if let Some(c) = self
+            .free_counts
+            .get_mut(usize::from(size_bits).wrapping_sub(MinUntypedSize::USIZE))
+        {
+            *c += count;
+        }
+    }
+
+    /// The number of free untypeds of `1 << size_bits` bytes.
+    pub fn free_count(&self, size_bits: u8) -> usize {
+        self.free_counts
+            .get(usize::from(size_bits).wrapping_sub(MinUntypedSize::USIZE))
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Each size, in bits, of which there are free untypeds, and how many.
+    pub fn size_classes(&self) -> impl Iterator<Item = (u8, usize)> + '_ {
+        self.free_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(i, count)| (i as u8 + MinUntypedSize::U8, *count))
+    }
+
+    pub fn free_bytes(&self) -> usize {
+        self.size_classes()
+            .map(|(size_bits, count)| count << size_bits)
+            .sum()
+    }
+
+    /// The size, in bits, of the largest free untyped, and so of the
+    /// largest single allocation possible.
+    pub fn largest_free_size_bits(&self) -> Option<u8> {
+        self.size_classes().map(|(size_bits, _)| size_bits).last()
+    }
+}
+
+impl fmt::Display for UntypedStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (size_bits, count) in self.size_classes() {
+            writeln!(f, "2^{}: {} free", size_bits, count)?;
+        }
+        write!(
+            f,
+            "{} bytes free, {} allocated, {} at most",
+            self.free_bytes(),
+            self.allocated_bytes,
+            self.high_water_bytes
+        )
+    }
+}
+
+/// Bytes handed out by an allocator, now and at most.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct AllocatedBytes {
+    current: usize,
+    high_water: usize,
+}
+
+impl AllocatedBytes {
+    pub(super) fn take(&mut self, bytes: usize) {
+        self.current += bytes;
+        if self.current > self.high_water {
+            self.high_water = self.current;
+        }
+    }
+
+    pub(super) fn give_back(&mut self, bytes: usize) {
+        self.current = self.current.saturating_sub(bytes);
+    }
+}
+
+/// How many of a set of slots have been handed out, and how many remain.
+/// Slots are never given back, so `used` is also their high-water mark.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlotStats {
+    pub used: usize,
+    pub remaining: usize,
+}
+
+impl SlotStats {
+    pub fn capacity(&self) -> usize {
+        self.used + self.remaining
+    }
+}
+
+impl fmt::Display for SlotStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} slots used, {} remaining",
+            self.used,
+            self.capacity(),
+            self.remaining
+        )
+    }
+}
+
+/// A snapshot of how much untyped memory and how many slots have been
+/// handed out, to be compared with another taken later.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Usage {
+    pub untyped_bytes: usize,
+    pub slots: usize,
+}
+
+impl Usage {
+    pub fn measure<Role: CNodeRole>(untyped: &WUTBuddy<Role>, slots: &WCNodeSlots) -> Usage {
+        Usage {
+            untyped_bytes: untyped.stats().allocated_bytes,
+            slots: slots.stats().used,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LedgerFull;
+
+/// What a named consumer has been charged with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Consumer {
+    pub name: &'static str,
+    pub usage: Usage,
+}
+
+/// Untyped memory and slots, attributed to the consumers they were
+/// handed out for: measure around setting up e.g. a child process, and
+/// charge the difference to it.
+#[derive(Default)]
+pub struct UsageLedger {
+    consumers: ArrayVec<[Consumer; MAX_LEDGER_CONSUMERS]>,
+}
+
+impl UsageLedger {
+    pub fn new() -> Self {
+        UsageLedger::default()
+    }
+
+    /// Charge `name` with what was handed out between the `before` and
+    /// `after` snapshots. Charges to the same name add up.
+    pub fn charge(
+        &mut self,
+        name: &'static str,
+        before: Usage,
+        after: Usage,
+    ) -> Result<(), LedgerFull> {
+        let consumer = self.consumer(name)?;
+        consumer.usage.untyped_bytes += after.untyped_bytes.saturating_sub(before.untyped_bytes);
+        consumer.usage.slots += after.slots.saturating_sub(before.slots);
+        Ok(())
+    }
+
+    /// Credit `name` with the untyped memory given back between the
+    /// `before` and `after` snapshots, e.g. by freeing what it used once
+    /// it has been torn down. Slots can't be given back.
+    pub fn credit(
+        &mut self,
+        name: &'static str,
+        before: Usage,
+        after: Usage,
+    ) -> Result<(), LedgerFull> {
+        let consumer = self.consumer(name)?;
+        let returned = before.untyped_bytes.saturating_sub(after.untyped_bytes);
+        consumer.usage.untyped_bytes = consumer.usage.untyped_bytes.saturating_sub(returned);
+        Ok(())
+    }
+
+    pub fn consumers(&self) -> &[Consumer] {
+        &self.consumers
+    }
+
+    pub fn usage_of(&self, name: &str) -> Option<Usage> {
+        self.consumers
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| c.usage)
+    }
+
+    /// Consumers which still hold untyped memory, e.g. to check for leaks
+    /// once they have all been torn down.
+    pub fn outstanding(&self) -> impl Iterator<Item = &Consumer> {
+        self.consumers.iter().filter(|c| c.usage.untyped_bytes > 0)
+    }
+
+    fn consumer(&mut self, name: &'static str) -> Result<&mut Consumer, LedgerFull> {
+        let position = match self.consumers.iter().position(|c| c.name == name) {
+            Some(position) => position,
+            None => {
+                self.consumers
+                    .try_push(Consumer {
+                        name,
+                        usage: Usage::default(),
+                    })
+                    .map_err(|_| LedgerFull)?;
+                self.consumers.len() - 1
+            }
+        };
+        Ok(&mut self.consumers[position])
+    }
+}
+
+impl fmt::Display for UsageLedger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for consumer in self.consumers.iter() {
+            writeln!(
+                f,
+                "{}: {} bytes, {} slots",
+                consumer.name, consumer.usage.untyped_bytes, consumer.usage.slots
+            )?;
+        }
+        Ok(())
+    }
+}
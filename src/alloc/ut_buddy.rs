@@ -16,7 +16,15 @@ use crate::cap::{
 };
 use crate::error::{ErrorExt, SeL4Error};
 
-type UTPoolSlotsPerSize = U4;
+use super::stats::{AllocatedBytes, UntypedStats};
+
+pub(super) type UTPoolSlotsPerSize = U4;
+
+/// The most splits a `WUTBuddy` remembers, in order to merge the halves
+/// again once both are freed. Halves of splits made beyond this are still
+/// freed, they just never merge. As many pairs of slots emptied by merging
+/// are kept for later splits.
+pub const MAX_TRACKED_SPLITS: usize = 32;
 
 /// A type-level linked list of typenum::Unsigned.
 pub trait UList {
@@ -118,9 +126,42 @@ type TakeUntyped_ResultPoolSizes<PoolSizes, Index> =
 #[allow(non_camel_case_types)]
 type TakeUntyped_NumSplits<PoolSizes, Index> = <PoolSizes as _TakeUntyped<Index>>::NumSplits;
 
+/// Type-level function to track the result of freeing an untyped
+pub trait _ReturnUntyped<Index> {
+    type ResultPoolSizes;
+}
+
+// Index is non-zero: recur with Index-1, and the remaining pools
+impl<IndexU: Unsigned, IndexB: Bit, Head: Unsigned, Tail: UList>
+    _ReturnUntyped<UInt<IndexU, IndexB>> for ULCons<Head, Tail>
+where
+    UInt<IndexU, IndexB>: Sub<U1>,
+    Diff<UInt<IndexU, IndexB>, U1>: Unsigned,
+
+    Tail: _ReturnUntyped<Diff<UInt<IndexU, IndexB>, U1>>,
+    ReturnUntyped_ResultPoolSizes<Tail, Diff<UInt<IndexU, IndexB>, U1>>: UList,
+{
+    type ResultPoolSizes =
+        ULCons<Head, ReturnUntyped_ResultPoolSizes<Tail, Diff<UInt<IndexU, IndexB>, U1>>>;
+}
+
+// Index is 0: add one to the head pool.
+impl<Head: Unsigned, Tail: UList> _ReturnUntyped<U0> for ULCons<Head, Tail>
+where
+    Head: Add<U1>,
+    Sum<Head, U1>: Unsigned,
+{
+    type ResultPoolSizes = ULCons<Sum<Head, U1>, Tail>;
+}
+
+#[allow(non_camel_case_types)]
+type ReturnUntyped_ResultPoolSizes<PoolSizes, Index> =
+    <PoolSizes as _ReturnUntyped<Index>>::ResultPoolSizes;
+
 /// Buddy alloc
 ///
-/// Presently restricted to provide memory_kind::General untyped
+/// Presently restricted to provide memory_kind::General untyped; see
+/// `DeviceBuddy` for device memory.
 pub struct UTBuddy<PoolSizes: UList> {
     _pool_sizes: PhantomData<PoolSizes>,
     pool: [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE],
@@ -145,6 +186,21 @@ where
 }
 
 impl<PoolSizes: UList> UTBuddy<PoolSizes> {
+    pub(super) fn from_pool(
+        pool: [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE],
+    ) -> Self {
+        UTBuddy {
+            _pool_sizes: PhantomData,
+            pool,
+        }
+    }
+
+    pub(super) fn into_pool(
+        self,
+    ) -> [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE] {
+        self.pool
+    }
+
     pub fn alloc<BitSize: Unsigned, NumSplits: Unsigned>(
         mut self,
         slots: LocalCNodeSlots<Prod<NumSplits, U2>>,
@@ -162,7 +218,13 @@ impl<PoolSizes: UList> UTBuddy<PoolSizes> {
         PoolSizes: _TakeUntyped<Diff<BitSize, MinUntypedSize>, NumSplits = NumSplits>,
         TakeUntyped_ResultPoolSizes<PoolSizes, Diff<BitSize, MinUntypedSize>>: UList,
     {
-        let weak_ut = alloc(&mut self.pool, slots.iter(), BitSize::U8, NumSplits::U8)?;
+        let weak_ut = alloc(
+            &mut self.pool,
+            None,
+            slots.iter(),
+            BitSize::U8,
+            NumSplits::U8,
+        )?;
         Ok((
             Cap::wrap_cptr(weak_ut.cptr),
             UTBuddy {
@@ -171,6 +233,42 @@ impl<PoolSizes: UList> UTBuddy<PoolSizes> {
             },
         ))
     }
+
+    /// Return an untyped allocated from this pool, first revoking
+    /// everything derived from it so that it can be allocated afresh.
+    ///
+    /// Unlike `WUTBuddy::free`, this never merges the untyped with its
+    /// buddy, since the pool sizes tracked in the type must stay exact.
+    pub fn free<BitSize: Unsigned>(
+        mut self,
+        ut: LocalCap<Untyped<BitSize, memory_kind::General>>,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<
+        UTBuddy<ReturnUntyped_ResultPoolSizes<PoolSizes, Diff<BitSize, MinUntypedSize>>>,
+        UTBuddyError,
+    >
+    where
+        BitSize: Sub<MinUntypedSize>,
+        PoolSizes: _ReturnUntyped<Diff<BitSize, MinUntypedSize>>,
+        ReturnUntyped_ResultPoolSizes<PoolSizes, Diff<BitSize, MinUntypedSize>>: UList,
+    {
+        if BitSize::U8 > MaxUntypedSize::U8 {
+            return Err(UTBuddyError::SizeOutOfRange(BitSize::U8));
+        }
+        let sub_pool = &mut self.pool[BitSize::USIZE - MinUntypedSize::USIZE];
+        if sub_pool.contains(&ut.cptr) {
+            return Err(UTBuddyError::NotFromThisPool(ut.cptr));
+        }
+        if sub_pool.is_full() {
+            return Err(UTBuddyError::PoolFull(BitSize::U8));
+        }
+        revoke(cnode, ut.cptr)?;
+        sub_pool.push(ut.cptr);
+        Ok(UTBuddy {
+            pool: self.pool,
+            _pool_sizes: PhantomData,
+        })
+    }
 }
 
 /// Make a weak ut buddy around a weak untyped.
@@ -179,10 +277,7 @@ pub fn weak_ut_buddy<Role: CNodeRole>(
 ) -> WUTBuddy<Role> {
     let mut pool = make_pool();
     pool[usize::from(ut.cap_data.size_bits) - MinUntypedSize::USIZE].push(ut.cptr);
-    WUTBuddy {
-        pool,
-        _role: PhantomData,
-    }
+    WUTBuddy::from_pool(pool)
 }
 
 /// The error returned when using the runtime-checked (weak)
@@ -198,6 +293,15 @@ pub enum UTBuddyError {
     /// The wrapped untyped lacks the sufficient size to do this
     /// allocation request.
     CannotAllocateRequestedSize(u8),
+    /// The untyped being freed is smaller or larger than any this
+    /// architecture's allocator deals in.
+    SizeOutOfRange(u8),
+    /// There is no room left in the pool for another untyped of this
+    /// size.
+    PoolFull(u8),
+    /// The untyped being freed, at this cptr, is already free, or isn't
+    /// the size this allocator split it off as.
+    NotFromThisPool(usize),
     /// We got an error from an seL4 syscall, namely the
     /// `seL4_Untyped_Retype` call.
     SeL4Error(SeL4Error),
@@ -212,12 +316,37 @@ impl From<SeL4Error> for UTBuddyError {
 /// A weakened implementation of a UTBuddy allocator where the state
 /// is checked at runtime rather than tracked in the types.
 ///
-/// Presently restricted to provide memory_kind::General untyped
+/// Presently restricted to provide memory_kind::General untyped; see
+/// `DeviceBuddy` for device memory.
 pub struct WUTBuddy<Role: CNodeRole = role::Local> {
     pool: [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE],
+    splits: ArrayVec<[Split; MAX_TRACKED_SPLITS]>,
+    /// Pairs of slots emptied by merging halves, for later splits to use.
+    spare_slots: ArrayVec<[LocalCNodeSlots<U2>; MAX_TRACKED_SPLITS]>,
+    allocated: AllocatedBytes,
     _role: PhantomData<Role>,
 }
 
+/// An untyped split into two halves, which live in adjacent slots.
+pub(super) struct Split {
+    pub(super) parent: usize,
+    pub(super) first_half: usize,
+    pub(super) half_size_bits: u8,
+}
+
+impl Split {
+    /// The other half, if `cptr` is one of this split's halves.
+    pub(super) fn buddy_of(&self, cptr: usize) -> Option<usize> {
+        if cptr == self.first_half {
+            Some(self.first_half + 1)
+        } else if cptr == self.first_half + 1 {
+            Some(self.first_half)
+        } else {
+            None
+        }
+    }
+}
+
 impl WUTBuddy<role::Local> {
     /// Allocate a strong untyped from the pool.
     pub fn alloc_strong<Size: Unsigned>(
@@ -264,7 +393,9 @@ impl WUTBuddy<role::Local> {
             return Err(UTBuddyError::CannotAllocateRequestedSize(size));
         }
 
-        let slot_count = usize::from(split_count) * 2;
+        // Splits use up the spare slots left by past merges first.
+        let spare_count = core::cmp::min(usize::from(split_count), self.spare_slots.len());
+        let slot_count = (usize::from(split_count) - spare_count) * 2;
         // We also need to confirm that we have enough slots.
         if slot_count > slots.cap_data.size {
             return Err(UTBuddyError::NotEnoughSlots);
@@ -275,6 +406,7 @@ impl WUTBuddy<role::Local> {
             cap_data: WCNodeSlotsData {
                 offset: slots.cap_data.offset,
                 size: slot_count,
+                start: slots.cap_data.offset,
                 _role: PhantomData,
             },
             _role: PhantomData,
@@ -285,15 +417,100 @@ impl WUTBuddy<role::Local> {
         slots.cap_data.offset += slot_count;
         slots.cap_data.size -= slot_count;
 
+        let spare_slot_count = self.spare_slots.len() - spare_count;
+        let spare_slots = self.spare_slots.drain(spare_slot_count..);
         let ut = alloc(
             &mut self.pool,
-            slots_for_alloc_to_consume.into_strong_iter(),
+            Some(&mut self.splits),
+            spare_slots
+                .flat_map(|pair| pair.iter())
+                .chain(slots_for_alloc_to_consume.into_strong_iter()),
             size,
             split_count,
         )?;
+        self.allocated.take(ut.size_bytes());
         Ok(ut)
     }
 
+    /// Return an untyped allocated from this pool, first revoking
+    /// everything derived from it so that it can be allocated afresh.
+    /// While its buddy, the other half of the untyped it was split from,
+    /// is free too, the two are merged back into that larger untyped.
+    ///
+    /// The slots the merged halves occupied are kept for later splits.
+    /// Untyped that are already free, or that are split, or that don't
+    /// match the size they were split off as, are refused; halves of splits
+    /// made beyond `MAX_TRACKED_SPLITS` can't be told apart, and are taken
+    /// back as they are.
+    pub fn free(
+        &mut self,
+        ut: LocalCap<WUntyped<memory_kind::General>>,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<(), UTBuddyError> {
+        let size_bits = ut.cap_data.size_bits;
+        if size_bits < MinUntypedSize::U8 || size_bits > MaxUntypedSize::U8 {
+            return Err(UTBuddyError::SizeOutOfRange(size_bits));
+        }
+        let recorded_size_bits = self
+            .splits
+            .iter()
+            .find(|s| s.buddy_of(ut.cptr).is_some())
+            .map(|s| s.half_size_bits);
+        if self.pool[usize::from(size_bits - MinUntypedSize::U8)].contains(&ut.cptr)
+            || self.splits.iter().any(|s| s.parent == ut.cptr)
+            || recorded_size_bits.map_or(false, |bits| bits != size_bits)
+        {
+            return Err(UTBuddyError::NotFromThisPool(ut.cptr));
+        }
+
+        // Work out how far the untyped merges before touching anything, so
+        // that a full pool leaves everything as it was.
+        let mut merged_cptr = ut.cptr;
+        let mut merged_size_bits = size_bits;
+        while let Some((parent, _)) = self.free_buddy(merged_cptr, merged_size_bits) {
+            merged_cptr = parent;
+            merged_size_bits += 1;
+        }
+        if self.pool[usize::from(merged_size_bits - MinUntypedSize::U8)].is_full() {
+            return Err(UTBuddyError::PoolFull(merged_size_bits));
+        }
+
+        revoke(cnode, ut.cptr)?;
+        self.allocated.give_back(ut.size_bytes());
+        let mut cptr = ut.cptr;
+        let mut size_bits = size_bits;
+        while let Some((parent, buddy)) = self.free_buddy(cptr, size_bits) {
+            let sub_pool = &mut self.pool[usize::from(size_bits - MinUntypedSize::U8)];
+            if let Some(index) = sub_pool.iter().position(|c| *c == buddy) {
+                sub_pool.remove(index);
+            }
+            // Deletes both halves, leaving the parent whole again.
+            revoke(cnode, parent)?;
+            if let Some(index) = self.splits.iter().position(|s| s.parent == parent) {
+                let split = self.splits.remove(index);
+                let _ = self
+                    .spare_slots
+                    .try_push(LocalCNodeSlots::internal_new(cnode.cptr, split.first_half));
+            }
+            cptr = parent;
+            size_bits += 1;
+        }
+        self.pool[usize::from(size_bits - MinUntypedSize::U8)].push(cptr);
+        Ok(())
+    }
+
+    /// The parent and buddy of the untyped at `cptr`, if its buddy is in
+    /// the pool.
+    fn free_buddy(&self, cptr: usize, size_bits: u8) -> Option<(usize, usize)> {
+        let split = self.splits.iter().find(|s| s.buddy_of(cptr).is_some())?;
+        let buddy = split.buddy_of(cptr)?;
+        if self.pool[usize::from(size_bits - MinUntypedSize::U8)].contains(&buddy) {
+            Some((split.parent, buddy))
+        } else {
+            None
+        }
+    }
+
     fn total_occupied_slots(&self) -> usize {
         self.pool.iter().map(|sub_pool| sub_pool.len()).sum()
     }
@@ -331,26 +548,56 @@ impl WUTBuddy<role::Local> {
                 child_bucket.push(child_wut.cptr);
             }
         }
+        // The halves of past splits were moved apart from their parents,
+        // which stay behind, so they can no longer be merged. The spare
+        // slots stay behind too.
         Ok(WUTBuddy {
             pool: child_pool,
+            splits: ArrayVec::new(),
+            spare_slots: ArrayVec::new(),
+            allocated: self.allocated,
             _role: PhantomData,
         })
     }
 }
 
 impl<Role: CNodeRole> WUTBuddy<Role> {
+    pub(super) fn from_pool(
+        pool: [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE],
+    ) -> Self {
+        WUTBuddy {
+            pool,
+            splits: ArrayVec::new(),
+            spare_slots: ArrayVec::new(),
+            allocated: AllocatedBytes::default(),
+            _role: PhantomData,
+        }
+    }
+
+    pub fn stats(&self) -> UntypedStats {
+        let mut stats = UntypedStats::new(self.allocated);
+        for (i, sub_pool) in self.pool.iter().enumerate() {
+            stats.count_free(i as u8 + MinUntypedSize::U8, sub_pool.len());
+        }
+        stats
+    }
+
     // This might be brought back to life later on
     #[allow(dead_code)]
     pub(crate) fn empty() -> WUTBuddy<Role> {
         WUTBuddy {
             pool: make_pool(),
+            splits: ArrayVec::new(),
+            spare_slots: ArrayVec::new(),
+            allocated: AllocatedBytes::default(),
             _role: PhantomData,
         }
     }
 }
 
-fn alloc(
+pub(super) fn alloc(
     pool: &mut [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE],
+    mut splits: Option<&mut ArrayVec<[Split; MAX_TRACKED_SPLITS]>>,
     slots_iter: impl Iterator<Item = LocalCNodeSlot>,
     size_bits: u8,
     split_count: u8,
@@ -386,6 +633,13 @@ fn alloc(
 
             pool[usize::from(i) - 1].push(slot_offset);
             pool[usize::from(i) - 1].push(slot_offset + 1);
+            if let Some(splits) = splits.as_mut() {
+                let _ = splits.try_push(Split {
+                    parent: cptr,
+                    first_half: slot_offset,
+                    half_size_bits: cptr_bitsize - 1,
+                });
+            }
         }
     }
 
@@ -412,12 +666,27 @@ impl From<super::micro_alloc::Allocator> for WUTBuddy<role::Local> {
 
         WUTBuddy {
             pool,
+            splits: ArrayVec::new(),
+            spare_slots: ArrayVec::new(),
+            allocated: AllocatedBytes::default(),
             _role: PhantomData,
         }
     }
 }
 
-fn make_pool() -> [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE] {
+pub(super) fn revoke(cnode: &LocalCap<LocalCNode>, cptr: usize) -> Result<(), SeL4Error> {
+    unsafe {
+        seL4_CNode_Revoke(
+            cnode.cptr,          // _service
+            cptr,                // index
+            seL4_WordBits as u8, // depth
+        )
+    }
+    .as_result()
+    .map_err(SeL4Error::CNodeRevoke)
+}
+
+pub(super) fn make_pool() -> [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE] {
     unsafe {
         let mut pool: [mem::MaybeUninit<ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>>;
             MaxUntypedSize::USIZE] = mem::MaybeUninit::uninit().assume_init();
@@ -7,7 +7,8 @@ use selfe_sys::*;
 
 use crate::arch;
 use crate::cap::{
-    memory_kind, ASIDControl, ASIDPool, CNodeRole, CNodeSlot, Cap, LocalCap, Untyped,
+    memory_kind, ASIDControl, ASIDPool, CNodeRole, CNodeSlot, Cap, LocalCNodeSlot, LocalCap,
+    Untyped, WASIDControl,
 };
 use crate::error::{ErrorExt, SeL4Error};
 
@@ -44,3 +45,33 @@ impl<FreePools: Unsigned> LocalCap<ASIDControl<FreePools>> {
         })
     }
 }
+
+impl LocalCap<WASIDControl> {
+    pub(crate) fn make_weak_asid_pool(
+        &mut self,
+        ut12: LocalCap<Untyped<U12, memory_kind::General>>,
+        dest_slot: LocalCNodeSlot,
+    ) -> Result<LocalCap<ASIDPool<arch::ASIDPoolSize>>, SeL4Error> {
+        let (dest_cptr, dest_offset, _) = dest_slot.elim();
+        unsafe {
+            seL4_ARM_ASIDControl_MakePool(
+                self.cptr,          // _service
+                ut12.cptr,          // untyped
+                dest_cptr,          // root
+                dest_offset,        // index
+                arch::WordSize::U8, // depth
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::ASIDControlMakePool)?;
+        Ok(Cap {
+            cptr: dest_offset,
+            cap_data: ASIDPool {
+                id: (arch::ASIDPoolCount::USIZE - self.cap_data.free_pools),
+                next_free_slot: 0,
+                _free_slots: PhantomData,
+            },
+            _role: PhantomData,
+        })
+    }
+}
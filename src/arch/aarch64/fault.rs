@@ -1,5 +1,6 @@
 use crate::cap::Badge;
-use crate::userland::MessageInfo;
+use crate::userland::vm_fault::decode_long_descriptor_status;
+use crate::userland::{MessageInfo, VMFaultDetails};
 use selfe_sys::*;
 
 #[derive(Debug)]
@@ -10,6 +11,14 @@ pub struct VMFault {
     pub is_instruction_fault: bool,
     pub fault_status_register: usize,
 }
+impl VMFault {
+    /// Decode the fault status code from the ESR into its cause, lookup
+    /// level and access type.
+    pub fn details(&self) -> VMFaultDetails {
+        decode_long_descriptor_status(self.fault_status_register, self.is_instruction_fault)
+    }
+}
+
 #[derive(Debug)]
 pub struct UnknownSyscall {
     pub sender: Badge,
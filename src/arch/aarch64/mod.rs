@@ -2,9 +2,11 @@ use core::marker::PhantomData;
 
 use typenum::*;
 
-use crate::cap::{page_state, Page, PageTable, PhantomCap};
-use crate::error::{ErrorExt, SeL4Error};
-use crate::vspace::{PagingRec, PagingTop};
+use crate::alloc::ut_buddy::WUTBuddy;
+use crate::cap::{page_state, LocalCap, Page, PageTable, PhantomCap, WCNodeSlots};
+use crate::error::{ErrorExt, KernelError, SeL4Error};
+use crate::userland::CapRights;
+use crate::vspace::{MappingError, PagingLayer, PagingRec, PagingTop};
 
 pub mod cap;
 pub mod fault;
@@ -85,6 +87,73 @@ impl AddressSpace {
             _item: PhantomData,
         }
     }
+
+    /// Map a frame larger than a page straight into the paging structure
+    /// whose entries span that much address space: a `PageDirectory` for
+    /// large pages and a `PageUpperDirectory` for huge pages. Missing
+    /// structures above it are created along the way.
+    pub(crate) fn map_large_frame(
+        &mut self,
+        frame: &LocalCap<Page<page_state::Unmapped>>,
+        frame_bits: u8,
+        addr: usize,
+        root: &mut LocalCap<PagingRoot>,
+        rights: CapRights,
+        vm_attributes: VMAttributes,
+        utb: &mut WUTBuddy,
+        mut slots: &mut WCNodeSlots,
+    ) -> Result<(), MappingError> {
+        if addr & ((1 << frame_bits) - 1) != 0 {
+            return Err(MappingError::AddrNotPageAligned);
+        }
+        match map_frame(frame, addr, root, rights, vm_attributes) {
+            Err(MappingError::Overflow) => {
+                if frame_bits == LargePageBits::U8 {
+                    let ut = utb.alloc(slots, PageDirectoryBits::U8)?;
+                    let dir = ut.retype::<cap::PageDirectory>(&mut slots)?;
+                    self.next.next.map_layer(
+                        &dir,
+                        addr,
+                        root,
+                        rights,
+                        vm_attributes,
+                        utb,
+                        slots,
+                    )?;
+                } else {
+                    let ut = utb.alloc(slots, PageUpperDirBits::U8)?;
+                    let dir = ut.retype::<cap::PageUpperDirectory>(&mut slots)?;
+                    self.next.next.next.map_layer(
+                        &dir,
+                        addr,
+                        root,
+                        rights,
+                        vm_attributes,
+                        utb,
+                        slots,
+                    )?;
+                }
+                map_frame(frame, addr, root, rights, vm_attributes)
+            }
+            res => res,
+        }
+    }
+}
+
+/// Map a frame of any size, signalling a missing paging structure above
+/// it with `MappingError::Overflow`.
+fn map_frame(
+    frame: &LocalCap<Page<page_state::Unmapped>>,
+    addr: usize,
+    root: &mut LocalCap<PagingRoot>,
+    rights: CapRights,
+    vm_attributes: VMAttributes,
+) -> Result<(), MappingError> {
+    match unsafe { frame.unchecked_page_map(addr, root, rights, vm_attributes) } {
+        Ok(_) => Ok(()),
+        Err(SeL4Error::PageMap(KernelError::FailedLookup)) => Err(MappingError::Overflow),
+        Err(e) => Err(MappingError::PageMapFailure(e)),
+    }
 }
 
 pub type ARMVCPUBits = U12;
@@ -150,9 +219,48 @@ pub mod vm_attributes {
 }
 
 pub(crate) unsafe fn flush_page(cptr: usize) -> Result<(), SeL4Error> {
-    selfe_sys::seL4_ARM_Page_CleanInvalidate_Data(cptr, 0x0000, PageBytes::USIZE)
+    flush_frame(cptr, PageBits::U8)
+}
+
+/// Clean and invalidate the data cache for a frame of `1 << frame_bits`
+/// bytes.
+pub(crate) unsafe fn flush_frame(cptr: usize, frame_bits: u8) -> Result<(), SeL4Error> {
+    clean_invalidate_frame_range(cptr, 0x0000, 1 << frame_bits)
+}
+
+/// Write back any dirty cache lines holding bytes `start..end` of a frame
+/// to memory.
+pub(crate) unsafe fn clean_frame_range(
+    cptr: usize,
+    start: usize,
+    end: usize,
+) -> Result<(), SeL4Error> {
+    selfe_sys::seL4_ARM_Page_Clean_Data(cptr, start, end)
+        .as_result()
+        .map_err(SeL4Error::PageCleanData)
+}
+
+/// Discard any cache lines holding bytes `start..end` of a frame, without
+/// writing them back. The kernel works in whole cache lines, so this also
+/// discards writes to bytes sharing a line with either end of the range.
+pub(crate) unsafe fn invalidate_frame_range(
+    cptr: usize,
+    start: usize,
+    end: usize,
+) -> Result<(), SeL4Error> {
+    selfe_sys::seL4_ARM_Page_Invalidate_Data(cptr, start, end)
         .as_result()
-        .map_err(SeL4Error::PageCleanInvalidateData)?;
+        .map_err(SeL4Error::PageInvalidateData)
+}
 
-    Ok(())
+/// Write back and then discard any cache lines holding bytes
+/// `start..end` of a frame.
+pub(crate) unsafe fn clean_invalidate_frame_range(
+    cptr: usize,
+    start: usize,
+    end: usize,
+) -> Result<(), SeL4Error> {
+    selfe_sys::seL4_ARM_Page_CleanInvalidate_Data(cptr, start, end)
+        .as_result()
+        .map_err(SeL4Error::PageCleanInvalidateData)
 }
@@ -0,0 +1,18 @@
+use selfe_sys::seL4_UserContext;
+
+/// `EM_AARCH64`
+pub(crate) const ELF_MACHINE: u16 = 183;
+
+/// The number of words in gdb's `elf_gregset_t` for aarch64.
+pub(crate) const GENERAL_REGISTER_COUNT: usize = 34;
+
+/// Lay out the registers in the order gdb expects them in an
+/// `NT_PRSTATUS` note: x0-x30, sp, pc, pstate.
+pub(crate) fn general_registers(regs: &seL4_UserContext) -> [usize; GENERAL_REGISTER_COUNT] {
+    [
+        regs.x0, regs.x1, regs.x2, regs.x3, regs.x4, regs.x5, regs.x6, regs.x7, regs.x8, regs.x9,
+        regs.x10, regs.x11, regs.x12, regs.x13, regs.x14, regs.x15, regs.x16, regs.x17, regs.x18,
+        regs.x19, regs.x20, regs.x21, regs.x22, regs.x23, regs.x24, regs.x25, regs.x26, regs.x27,
+        regs.x28, regs.x29, regs.x30, regs.sp, regs.pc, regs.spsr,
+    ]
+}
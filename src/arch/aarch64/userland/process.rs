@@ -90,6 +90,14 @@ pub(crate) fn set_thread_link_register(
     registers.x30 = (post_return_fn as *const fn() -> !) as usize;
 }
 
+/// Point the thread's thread pointer register (TPIDR_EL0) at its TLS area.
+pub(crate) fn set_thread_pointer(
+    registers: &mut selfe_sys::seL4_UserContext,
+    thread_pointer: usize,
+) {
+    registers.tpidr_el0 = thread_pointer;
+}
+
 #[doc(hidden)]
 #[allow(dead_code)]
 #[cfg(feature = "test_support")]
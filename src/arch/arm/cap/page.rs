@@ -5,6 +5,8 @@ use crate::error::{ErrorExt, SeL4Error};
 use crate::typenum::Unsigned;
 use crate::userland::CapRights;
 
+use super::super::{LargePageBits, PageBits, SectionBits, SuperSectionBits};
+
 impl<T: PageState> LocalCap<Page<T>> {
     pub(crate) fn paddr(&self) -> Result<usize, SeL4Error> {
         let res = unsafe { seL4_ARM_Page_GetAddress(self.cptr) };
@@ -36,19 +38,46 @@ impl LocalCap<Page<page_state::Unmapped>> {
 }
 
 impl LocalCap<Page<page_state::Mapped>> {
+    /// Change the rights and attributes of this frame's existing mapping
+    /// at `addr`. The kernel caps `rights` at those of the cap itself.
+    pub(crate) fn remap(
+        &mut self,
+        addr: usize,
+        root: &LocalCap<crate::arch::PagingRoot>,
+        rights: CapRights,
+        vm_attributes: seL4_ARM_VMAttributes,
+    ) -> Result<(), SeL4Error> {
+        unsafe {
+            seL4_ARM_Page_Map(
+                self.cptr,
+                root.cptr,
+                addr,
+                seL4_CapRights_t::from(rights),
+                vm_attributes,
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::PageMap)?;
+        self.cap_data.state.rights = rights;
+        Ok(())
+    }
+
     /// Keeping this non-public in order to restrict mapping operations to
     /// owners of a VSpace-related object
     pub(crate) fn unmap(self) -> Result<LocalCap<Page<page_state::Unmapped>>, SeL4Error> {
+        self.unmap_frame(super::super::PageBits::U8)
+    }
+
+    /// Unmap a frame of `1 << frame_bits` bytes, which may be larger than
+    /// a page if it backs a region built from large frames.
+    pub(crate) fn unmap_frame(
+        self,
+        frame_bits: u8,
+    ) -> Result<LocalCap<Page<page_state::Unmapped>>, SeL4Error> {
         if self.rights().is_writable() {
-            unsafe {
-                seL4_ARM_Page_CleanInvalidate_Data(
-                    self.cptr,
-                    0x0000,
-                    super::super::PageBytes::USIZE,
-                )
-            }
-            .as_result()
-            .map_err(SeL4Error::PageCleanInvalidateData)?;
+            unsafe { seL4_ARM_Page_CleanInvalidate_Data(self.cptr, 0x0000, 1 << frame_bits) }
+                .as_result()
+                .map_err(SeL4Error::PageCleanInvalidateData)?;
         }
 
         match unsafe { seL4_ARM_Page_Unmap(self.cptr) }.as_result() {
@@ -78,3 +107,19 @@ impl PhantomCap for Page<page_state::Unmapped> {
         }
     }
 }
+
+/// The frame sizes bigger than a page which a memory region can be built
+/// from, largest first.
+pub(crate) const LARGE_FRAME_BITS: [u8; 3] =
+    [SuperSectionBits::U8, SectionBits::U8, LargePageBits::U8];
+
+/// The seL4 object type of a frame of `1 << frame_bits` bytes.
+pub(crate) fn frame_object_type(frame_bits: u8) -> Option<usize> {
+    match frame_bits {
+        b if b == PageBits::U8 => Some(_object_seL4_ARM_SmallPageObject as usize),
+        b if b == SuperSectionBits::U8 => Some(_mode_object_seL4_ARM_SuperSectionObject as usize),
+        b if b == SectionBits::U8 => Some(_mode_object_seL4_ARM_SectionObject as usize),
+        b if b == LargePageBits::U8 => Some(_object_seL4_ARM_LargePageObject as usize),
+        _ => None,
+    }
+}
@@ -1,5 +1,7 @@
 use crate::cap::Badge;
-use crate::userland::MessageInfo;
+#[cfg(KernelArmHypervisorSupport)]
+use crate::userland::vm_fault::decode_long_descriptor_status;
+use crate::userland::{MessageInfo, VMFaultAccess, VMFaultCause, VMFaultDetails};
 use selfe_sys::*;
 
 #[derive(Debug)]
@@ -10,6 +12,51 @@ pub struct VMFault {
     pub is_instruction_fault: bool,
     pub fault_status_register: usize,
 }
+impl VMFault {
+    /// Decode the fault status register (DFSR or IFSR) into its cause,
+    /// lookup level and access type.
+    #[cfg(not(KernelArmHypervisorSupport))]
+    pub fn details(&self) -> VMFaultDetails {
+        // Short-descriptor format: FS is split across bits 10 and 3:0, and
+        // WnR (only meaningful for data aborts) is bit 11.
+        let fsr = self.fault_status_register;
+        let fault_status = (fsr & 0xf) | ((fsr >> 6) & 0x10);
+        let (cause, level) = match fault_status {
+            0b00001 => (VMFaultCause::Alignment, None),
+            0b00011 => (VMFaultCause::AccessFlag, Some(1)),
+            0b00110 => (VMFaultCause::AccessFlag, Some(2)),
+            0b00101 => (VMFaultCause::Translation, Some(1)),
+            0b00111 => (VMFaultCause::Translation, Some(2)),
+            0b01001 => (VMFaultCause::Domain, Some(1)),
+            0b01011 => (VMFaultCause::Domain, Some(2)),
+            0b01101 => (VMFaultCause::Permission, Some(1)),
+            0b01111 => (VMFaultCause::Permission, Some(2)),
+            0b01100 => (VMFaultCause::ExternalAbort, Some(1)),
+            0b01110 => (VMFaultCause::ExternalAbort, Some(2)),
+            0b01000 | 0b10110 => (VMFaultCause::ExternalAbort, None),
+            _ => (VMFaultCause::Other(fsr), None),
+        };
+        VMFaultDetails {
+            cause,
+            level,
+            access: if self.is_instruction_fault {
+                VMFaultAccess::Execute
+            } else if fsr & (1 << 11) != 0 {
+                VMFaultAccess::Write
+            } else {
+                VMFaultAccess::Read
+            },
+        }
+    }
+
+    /// Decode the fault status register (the HSR's ISS) into its cause,
+    /// lookup level and access type.
+    #[cfg(KernelArmHypervisorSupport)]
+    pub fn details(&self) -> VMFaultDetails {
+        decode_long_descriptor_status(self.fault_status_register, self.is_instruction_fault)
+    }
+}
+
 #[derive(Debug)]
 pub struct UnknownSyscall {
     pub sender: Badge,
@@ -43,12 +43,15 @@ mod hyp_dependent_constants {
 mod hyp_dependent_constants {
     use core::marker::PhantomData;
 
-    use crate::cap::{page_state, Page, PageTable};
-    use crate::vspace::{PagingRec, PagingTop};
+    use crate::alloc::ut_buddy::WUTBuddy;
+    use crate::cap::{page_state, DirectRetype, LocalCap, Page, PageTable, WCNodeSlots};
+    use crate::error::{KernelError, SeL4Error};
+    use crate::userland::CapRights;
+    use crate::vspace::{MappingError, PagingLayer, PagingRec, PagingTop};
 
     use typenum::*;
 
-    use super::cap;
+    use super::{cap, LargePageBits, VMAttributes};
 
     pub type PageTableBits = U10;
     pub type PageTableIndexBits = U8;
@@ -73,6 +76,53 @@ mod hyp_dependent_constants {
                 _item: PhantomData,
             }
         }
+
+        /// Map a frame larger than a page straight into the paging
+        /// structure whose entries span that much address space: a
+        /// `PageTable` for large pages and the `PageDirectory` for
+        /// sections and supersections. A missing page table is created
+        /// along the way.
+        pub(crate) fn map_large_frame(
+            &mut self,
+            frame: &LocalCap<Page<page_state::Unmapped>>,
+            frame_bits: u8,
+            addr: usize,
+            root: &mut LocalCap<PagingRoot>,
+            rights: CapRights,
+            vm_attributes: VMAttributes,
+            utb: &mut WUTBuddy,
+            mut slots: &mut WCNodeSlots,
+        ) -> Result<(), MappingError> {
+            if addr & ((1 << frame_bits) - 1) != 0 {
+                return Err(MappingError::AddrNotPageAligned);
+            }
+            match map_frame(frame, addr, root, rights, vm_attributes) {
+                Err(MappingError::Overflow) if frame_bits == LargePageBits::U8 => {
+                    let ut = utb.alloc(slots, <PageTable as DirectRetype>::SizeBits::U8)?;
+                    let table = ut.retype::<PageTable>(&mut slots)?;
+                    self.next
+                        .map_layer(&table, addr, root, rights, vm_attributes, utb, slots)?;
+                    map_frame(frame, addr, root, rights, vm_attributes)
+                }
+                res => res,
+            }
+        }
+    }
+
+    /// Map a frame of any size, signalling a missing paging structure above
+    /// it with `MappingError::Overflow`.
+    fn map_frame(
+        frame: &LocalCap<Page<page_state::Unmapped>>,
+        addr: usize,
+        root: &mut LocalCap<PagingRoot>,
+        rights: CapRights,
+        vm_attributes: VMAttributes,
+    ) -> Result<(), MappingError> {
+        match unsafe { frame.unchecked_page_map(addr, root, rights, vm_attributes) } {
+            Ok(_) => Ok(()),
+            Err(SeL4Error::PageMap(KernelError::FailedLookup)) => Err(MappingError::Overflow),
+            Err(e) => Err(MappingError::PageMapFailure(e)),
+        }
     }
 }
 
@@ -140,9 +190,48 @@ pub mod vm_attributes {
 }
 
 pub(crate) unsafe fn flush_page(cptr: usize) -> Result<(), SeL4Error> {
-    selfe_sys::seL4_ARM_Page_CleanInvalidate_Data(cptr, 0x0000, PageBytes::USIZE)
+    flush_frame(cptr, PageBits::U8)
+}
+
+/// Clean and invalidate the data cache for a frame of `1 << frame_bits`
+/// bytes.
+pub(crate) unsafe fn flush_frame(cptr: usize, frame_bits: u8) -> Result<(), SeL4Error> {
+    clean_invalidate_frame_range(cptr, 0x0000, 1 << frame_bits)
+}
+
+/// Write back any dirty cache lines holding bytes `start..end` of a frame
+/// to memory.
+pub(crate) unsafe fn clean_frame_range(
+    cptr: usize,
+    start: usize,
+    end: usize,
+) -> Result<(), SeL4Error> {
+    selfe_sys::seL4_ARM_Page_Clean_Data(cptr, start, end)
+        .as_result()
+        .map_err(SeL4Error::PageCleanData)
+}
+
+/// Discard any cache lines holding bytes `start..end` of a frame, without
+/// writing them back. The kernel works in whole cache lines, so this also
+/// discards writes to bytes sharing a line with either end of the range.
+pub(crate) unsafe fn invalidate_frame_range(
+    cptr: usize,
+    start: usize,
+    end: usize,
+) -> Result<(), SeL4Error> {
+    selfe_sys::seL4_ARM_Page_Invalidate_Data(cptr, start, end)
         .as_result()
-        .map_err(SeL4Error::PageCleanInvalidateData)?;
+        .map_err(SeL4Error::PageInvalidateData)
+}
 
-    Ok(())
+/// Write back and then discard any cache lines holding bytes
+/// `start..end` of a frame.
+pub(crate) unsafe fn clean_invalidate_frame_range(
+    cptr: usize,
+    start: usize,
+    end: usize,
+) -> Result<(), SeL4Error> {
+    selfe_sys::seL4_ARM_Page_CleanInvalidate_Data(cptr, start, end)
+        .as_result()
+        .map_err(SeL4Error::PageCleanInvalidateData)
 }
@@ -0,0 +1,16 @@
+use selfe_sys::seL4_UserContext;
+
+/// `EM_ARM`
+pub(crate) const ELF_MACHINE: u16 = 40;
+
+/// The number of words in gdb's `elf_gregset_t` for arm.
+pub(crate) const GENERAL_REGISTER_COUNT: usize = 18;
+
+/// Lay out the registers in the order gdb expects them in an
+/// `NT_PRSTATUS` note: r0-r15, cpsr, orig_r0.
+pub(crate) fn general_registers(regs: &seL4_UserContext) -> [usize; GENERAL_REGISTER_COUNT] {
+    [
+        regs.r0, regs.r1, regs.r2, regs.r3, regs.r4, regs.r5, regs.r6, regs.r7, regs.r8, regs.r9,
+        regs.r10, regs.r11, regs.r12, regs.sp, regs.r14, regs.pc, regs.cpsr, regs.r0,
+    ]
+}
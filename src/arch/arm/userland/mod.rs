@@ -1 +1,2 @@
+pub mod core_dump;
 pub mod process;
@@ -111,6 +111,15 @@ pub(crate) fn set_thread_link_register(
     registers.r14 = (post_return_fn as *const fn() -> !) as usize;
 }
 
+/// Point the thread's thread pointer register at its TLS area. This is
+/// TPIDRURO, which is where `__aeabi_read_tp` looks.
+pub(crate) fn set_thread_pointer(
+    registers: &mut selfe_sys::seL4_UserContext,
+    thread_pointer: usize,
+) {
+    registers.tpidruro = thread_pointer;
+}
+
 #[doc(hidden)]
 #[allow(dead_code)]
 #[cfg(feature = "test_support")]
@@ -38,6 +38,7 @@ pub fn root_cnode(
             _role: PhantomData,
             cap_data: CNode {
                 radix: 19,
+                guard_bits: (seL4_WordBits - 19) as u8,
                 _role: PhantomData,
             },
         },
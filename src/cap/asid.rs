@@ -14,3 +14,10 @@ pub struct AssignedASID {
 }
 
 impl CapType for AssignedASID {}
+
+/// The ASID of an address space that has been destroyed, which the kernel
+/// has freed, to be returned to the `ASIDManager` that handed it out.
+#[derive(Debug)]
+pub struct ReleasedASID {
+    pub(crate) asid: InternalASID,
+}
@@ -5,7 +5,9 @@ use core::ops::Sub;
 use typenum::*;
 
 use crate::arch;
-use crate::cap::{memory_kind, ASIDPool, CapType, LocalCNodeSlot, LocalCap, PhantomCap, Untyped};
+use crate::cap::{
+    memory_kind, ASIDPool, Cap, CapType, LocalCNodeSlot, LocalCap, PhantomCap, Untyped,
+};
 use crate::error::SeL4Error;
 
 #[derive(Debug)]
@@ -13,8 +15,17 @@ pub struct ASIDControl<FreePools: Unsigned> {
     _free_pools: PhantomData<FreePools>,
 }
 
+/// Can only represent an ASIDControl with the pools it has left counted
+/// at runtime.
+#[derive(Debug)]
+pub struct WASIDControl {
+    pub(crate) free_pools: usize,
+}
+
 impl<FreePools: Unsigned> CapType for ASIDControl<FreePools> {}
 
+impl CapType for WASIDControl {}
+
 impl<FreePools: Unsigned> PhantomCap for ASIDControl<FreePools> {
     fn phantom_instance() -> Self {
         Self {
@@ -42,4 +53,47 @@ impl<FreePools: Unsigned> LocalCap<ASIDControl<FreePools>> {
         let pool = self.make_asid_pool_without_consuming_control_pool(ut12, dest_slot)?;
         Ok((pool, unsafe { mem::transmute(self) }))
     }
+
+    /// weaken erases the count of pools left from the type, for pools
+    /// made as they are needed.
+    pub fn weaken(self) -> LocalCap<WASIDControl> {
+        Cap {
+            cptr: self.cptr,
+            cap_data: WASIDControl {
+                free_pools: FreePools::USIZE,
+            },
+            _role: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ASIDControlError {
+    NoPoolsLeft,
+    SeL4Error(SeL4Error),
+}
+
+impl From<SeL4Error> for ASIDControlError {
+    fn from(e: SeL4Error) -> Self {
+        ASIDControlError::SeL4Error(e)
+    }
+}
+
+impl LocalCap<WASIDControl> {
+    pub fn free_pools(&self) -> usize {
+        self.cap_data.free_pools
+    }
+
+    pub fn allocate_asid_pool(
+        &mut self,
+        ut12: LocalCap<Untyped<U12, memory_kind::General>>,
+        dest_slot: LocalCNodeSlot,
+    ) -> Result<LocalCap<ASIDPool<arch::ASIDPoolSize>>, ASIDControlError> {
+        if self.cap_data.free_pools == 0 {
+            return Err(ASIDControlError::NoPoolsLeft);
+        }
+        let pool = self.make_weak_asid_pool(ut12, dest_slot)?;
+        self.cap_data.free_pools -= 1;
+        Ok(pool)
+    }
 }
@@ -1,3 +1,15 @@
+use core::marker::PhantomData;
+use core::ops::{Add, BitOr, Sub};
+
+use typenum::operator_aliases::{Add1, Diff};
+use typenum::*;
+
+use crate::arch;
+
+/// The number of badge bits the kernel will actually deliver; see the note
+/// on `Badge` about the high 4 bits.
+pub type BadgeBitCount = Diff<arch::WordSize, U4>;
+
 /// Wrapper for an Endpoint or Notification badge.
 /// Note that the kernel will ignore any use of the high 4 bits
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
@@ -13,6 +25,19 @@ impl Badge {
         let overlap = self.inner & other.inner;
         overlap != 0
     }
+
+    /// Decode a (possibly accumulated) notification badge into the
+    /// names of the sources whose bits are set in it.
+    ///
+    /// `sources` is expected to be built out of the `BadgeBit`s handed
+    /// out by a single `BadgeBits` allocator, so that no two names share
+    /// a bit.
+    pub fn decode<'s, S: Copy>(self, sources: &'s [(S, Badge)]) -> impl Iterator<Item = S> + 's {
+        sources
+            .iter()
+            .filter(move |(_, b)| b.inner != 0 && (b.inner & self.inner) == b.inner)
+            .map(|(s, _)| *s)
+    }
 }
 
 impl From<usize> for Badge {
@@ -29,3 +54,87 @@ impl From<Badge> for usize {
         b.inner
     }
 }
+
+impl BitOr for Badge {
+    type Output = Badge;
+    fn bitor(self, rhs: Badge) -> Self::Output {
+        Badge {
+            inner: self.inner | rhs.inner,
+        }
+    }
+}
+
+/// The type-level record of which bits of a notification's badge space
+/// have not yet been handed out. Much like `CNodeSlots`, allocating from
+/// it consumes it and returns a successor with less capacity, so two
+/// sources sharing one notification can never be given overlapping bits.
+///
+/// Bits are handed out from the least significant end; `NextBit` is the
+/// index of the next one available.
+#[derive(Debug)]
+pub struct BadgeBits<NextBit: Unsigned> {
+    _next: PhantomData<NextBit>,
+}
+
+/// A single badge bit, reserved for one source of wakeups on a
+/// notification.
+#[derive(Debug)]
+pub struct BadgeBit<Index: Unsigned> {
+    _index: PhantomData<Index>,
+}
+
+impl BadgeBits<U0> {
+    /// The full badge space of a fresh notification. Only make one of these
+    /// per notification.
+    pub fn new() -> Self {
+        BadgeBits { _next: PhantomData }
+    }
+}
+
+impl<NextBit: Unsigned> BadgeBits<NextBit> {
+    /// Claim the next available bit.
+    pub fn alloc(self) -> (BadgeBit<NextBit>, BadgeBits<Add1<NextBit>>)
+    where
+        NextBit: IsLess<BadgeBitCount, Output = True>,
+        NextBit: Add<U1>,
+        Add1<NextBit>: Unsigned,
+    {
+        (
+            BadgeBit {
+                _index: PhantomData,
+            },
+            BadgeBits { _next: PhantomData },
+        )
+    }
+
+    /// How many bits remain to be handed out.
+    pub fn remaining(&self) -> usize
+    where
+        BadgeBitCount: Sub<NextBit>,
+        Diff<BadgeBitCount, NextBit>: Unsigned,
+    {
+        Diff::<BadgeBitCount, NextBit>::USIZE
+    }
+}
+
+impl<Index: Unsigned> BadgeBit<Index> {
+    pub(crate) fn mask() -> usize {
+        1 << Index::USIZE
+    }
+
+    /// The badge to mint onto the capability given to this source.
+    pub fn badge(&self) -> Badge {
+        Badge::from(Self::mask())
+    }
+
+    /// Did this source contribute to the given wakeup?
+    pub fn is_set_in(&self, wakeup: Badge) -> bool {
+        (wakeup.inner & Self::mask()) != 0
+    }
+}
+
+impl<Index: Unsigned> From<BadgeBit<Index>> for Badge {
+    fn from(b: BadgeBit<Index>) -> Self {
+        b.badge()
+    }
+}
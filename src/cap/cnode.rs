@@ -1,11 +1,14 @@
+use core::cmp;
 use core::marker::PhantomData;
 use core::ops::{Add, Sub};
 
+use arrayvec::ArrayVec;
 use selfe_sys::*;
 
 use typenum::operator_aliases::Diff;
 use typenum::*;
 
+use crate::alloc::stats::SlotStats;
 use crate::cap::{role, CNodeRole, Cap, CapType, ChildCap, LocalCap};
 use crate::error::{ErrorExt, SeL4Error};
 use crate::userland::CapRights;
@@ -16,6 +19,9 @@ use crate::userland::CapRights;
 #[derive(Debug)]
 pub struct CNode<Role: CNodeRole> {
     pub(crate) radix: u8,
+    /// The bits of a cptr this CNode's guard takes. All that aren't used by
+    /// the radix, unless the CNode is the root of a two-level CSpace.
+    pub(crate) guard_bits: u8,
     pub(crate) _role: PhantomData<Role>,
 }
 
@@ -34,6 +40,8 @@ pub struct CNodeSlotsData<Size: Unsigned, Role: CNodeRole> {
 pub struct WCNodeSlotsData<Role: CNodeRole> {
     pub(crate) offset: usize,
     pub(crate) size: usize,
+    /// Where the slots started out, to tell how many have been used.
+    pub(crate) start: usize,
     pub(crate) _role: PhantomData<Role>,
 }
 
@@ -78,6 +86,7 @@ impl<Size: Unsigned, CapRole: CNodeRole, Role: CNodeRole> Cap<CNodeSlotsData<Siz
             cap_data: WCNodeSlotsData {
                 offset: self.cap_data.offset,
                 size: Size::USIZE,
+                start: self.cap_data.offset,
                 _role: PhantomData,
             },
         }
@@ -196,6 +205,7 @@ impl LocalCap<ChildCNode> {
                 _role: PhantomData,
                 cap_data: CNode {
                     radix: self.cap_data.radix,
+                    guard_bits: self.cap_data.guard_bits,
                     _role: PhantomData,
                 },
             },
@@ -214,6 +224,13 @@ impl<Role: CNodeRole> LocalCap<WCNodeSlotsData<Role>> {
         self.cap_data.size
     }
 
+    pub fn stats(&self) -> SlotStats {
+        SlotStats {
+            used: self.cap_data.offset - self.cap_data.start,
+            remaining: self.cap_data.size,
+        }
+    }
+
     /// Allocate `count` and return them as weak cnode slots.
     pub fn alloc(
         &mut self,
@@ -230,6 +247,7 @@ impl<Role: CNodeRole> LocalCap<WCNodeSlotsData<Role>> {
             cap_data: WCNodeSlotsData {
                 offset,
                 size: count,
+                start: offset,
                 _role: PhantomData,
             },
             _role: PhantomData,
@@ -291,3 +309,73 @@ impl<Role: CNodeRole> LocalCap<WCNodeSlotsData<Role>> {
         })
     }
 }
+
+/// The most runs of emptied slots a `RecycledSlots` holds on to.
+pub(crate) const MAX_RECYCLED_SLOT_RUNS: usize = 32;
+
+/// Runs of slots whose caps have been deleted, handed out again ahead of
+/// fresh slots, the most recently emptied first. Runs beyond
+/// `MAX_RECYCLED_SLOT_RUNS` are left empty.
+pub(crate) struct RecycledSlots<Role: CNodeRole> {
+    /// The offset and length of each run.
+    runs: ArrayVec<[(usize, usize); MAX_RECYCLED_SLOT_RUNS]>,
+    _role: PhantomData<Role>,
+}
+
+impl<Role: CNodeRole> RecycledSlots<Role> {
+    pub(crate) fn new() -> Self {
+        RecycledSlots {
+            runs: ArrayVec::new(),
+            _role: PhantomData,
+        }
+    }
+
+    /// Take back the `count` empty slots starting at `offset`.
+    pub(crate) fn put_back(&mut self, offset: usize, count: usize) {
+        if count == 0 {
+            return;
+        }
+        // Runs that meet are joined, so larger requests can still be met.
+        let run = match self
+            .runs
+            .iter()
+            .position(|run| run.0 + run.1 == offset || offset + count == run.0)
+        {
+            Some(position) => {
+                let (run_offset, length) = self.runs.remove(position);
+                (cmp::min(run_offset, offset), length + count)
+            }
+            None => (offset, count),
+        };
+        let _ = self.runs.try_push(run);
+    }
+
+    /// `count` consecutive slots of `slots`' CNode, emptied earlier, if
+    /// a run is long enough; otherwise fresh ones from `slots`.
+    pub(crate) fn alloc(
+        &mut self,
+        slots: &mut LocalCap<WCNodeSlotsData<Role>>,
+        count: usize,
+    ) -> Result<LocalCap<WCNodeSlotsData<Role>>, CNodeSlotsError> {
+        let position = match self.runs.iter().rposition(|run| run.1 >= count) {
+            Some(position) => position,
+            None => return slots.alloc(count),
+        };
+        let (offset, length) = self.runs[position];
+        if length == count {
+            self.runs.remove(position);
+        } else {
+            self.runs[position] = (offset + count, length - count);
+        }
+        Ok(Cap {
+            cptr: slots.cptr,
+            cap_data: WCNodeSlotsData {
+                offset,
+                size: count,
+                start: offset,
+                _role: PhantomData,
+            },
+            _role: PhantomData,
+        })
+    }
+}
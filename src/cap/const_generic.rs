@@ -0,0 +1,362 @@
+//! Slots, untypeds and ASID pools sized with const generics rather than
+//! typenum, behind the `const_generics` feature. Each converts to and from
+//! its typenum counterpart, so a root task can move over a piece at a time.
+use core::marker::PhantomData;
+
+use selfe_sys::*;
+
+use typenum::*;
+
+use crate::arch;
+use crate::cap::{
+    memory_kind, role, ASIDPool, CNodeRole, CNodeSlotsData, Cap, CapType, DirectRetype, LocalCNode,
+    LocalCap, PhantomCap, UnassignedASID, Untyped, WCNodeSlotsData, WUntyped,
+};
+use crate::error::{ErrorExt, SeL4Error};
+use crate::userland::CapRights;
+
+use super::asid_pool::InternalASID;
+
+#[derive(Debug)]
+pub struct ConstCNodeSlotsData<Role: CNodeRole, const SIZE: usize> {
+    pub(crate) offset: usize,
+    pub(crate) _role: PhantomData<Role>,
+}
+
+impl<Role: CNodeRole, const SIZE: usize> CapType for ConstCNodeSlotsData<Role, SIZE> {}
+
+pub type ConstCNodeSlots<Role, const SIZE: usize> = LocalCap<ConstCNodeSlotsData<Role, SIZE>>;
+pub type LocalConstCNodeSlots<const SIZE: usize> = ConstCNodeSlots<role::Local, SIZE>;
+pub type ChildConstCNodeSlots<const SIZE: usize> = ConstCNodeSlots<role::Child, SIZE>;
+
+impl<CapRole: CNodeRole, Role: CNodeRole, const SIZE: usize>
+    Cap<ConstCNodeSlotsData<Role, SIZE>, CapRole>
+{
+    pub(crate) fn const_new(cptr: usize, offset: usize) -> Self {
+        Cap {
+            cptr,
+            _role: PhantomData,
+            cap_data: ConstCNodeSlotsData {
+                offset,
+                _role: PhantomData,
+            },
+        }
+    }
+
+    pub fn alloc<const COUNT: usize>(
+        self,
+    ) -> (
+        Cap<ConstCNodeSlotsData<Role, COUNT>, CapRole>,
+        Cap<ConstCNodeSlotsData<Role, { SIZE - COUNT }>, CapRole>,
+    )
+    where
+        [(); SIZE - COUNT]: ,
+    {
+        (
+            Cap::const_new(self.cptr, self.cap_data.offset),
+            Cap::const_new(self.cptr, self.cap_data.offset + COUNT),
+        )
+    }
+
+    /// weaken erases the state-tracking types on a set of CNode
+    /// slots.
+    pub fn weaken(self) -> Cap<WCNodeSlotsData<Role>, CapRole> {
+        Cap {
+            cptr: self.cptr,
+            _role: PhantomData,
+            cap_data: WCNodeSlotsData {
+                offset: self.cap_data.offset,
+                size: SIZE,
+                start: self.cap_data.offset,
+                _role: PhantomData,
+            },
+        }
+    }
+
+    /// The same slots, sized by typenum. `Size` must be `SIZE`.
+    pub fn to_typenum<Size: Unsigned>(self) -> Cap<CNodeSlotsData<Size, Role>, CapRole> {
+        #[allow(clippy::let_unit_value)]
+        let () = SameSize::<Size, SIZE>::CHECK;
+        Cap::<CNodeSlotsData<Size, Role>, CapRole>::internal_new(self.cptr, self.cap_data.offset)
+    }
+}
+
+impl<Size: Unsigned, CapRole: CNodeRole, Role: CNodeRole> Cap<CNodeSlotsData<Size, Role>, CapRole> {
+    /// The same slots, sized by a const generic.
+    pub fn to_const(self) -> Cap<ConstCNodeSlotsData<Role, { Size::USIZE }>, CapRole>
+    where
+        [(); Size::USIZE]: ,
+    {
+        let (cptr, offset, _) = self.elim();
+        Cap::const_new(cptr, offset)
+    }
+}
+
+/// General memory of `1 << BITS` bytes; see `Untyped`.
+#[derive(Debug)]
+pub struct ConstUntyped<const BITS: usize> {}
+
+impl<const BITS: usize> CapType for ConstUntyped<BITS> {}
+
+impl<const BITS: usize> PhantomCap for ConstUntyped<BITS> {
+    fn phantom_instance() -> Self {
+        ConstUntyped {}
+    }
+}
+
+impl<const BITS: usize> LocalCap<ConstUntyped<BITS>> {
+    pub fn split(
+        self,
+        dest_slots: LocalConstCNodeSlots<2>,
+    ) -> Result<
+        (
+            LocalCap<ConstUntyped<{ BITS - 1 }>>,
+            LocalCap<ConstUntyped<{ BITS - 1 }>>,
+        ),
+        SeL4Error,
+    >
+    where
+        [(); BITS - 1]: ,
+    {
+        let offset = self.retype_untypeds(dest_slots.cptr, dest_slots.cap_data.offset, 1)?;
+        Ok((Cap::wrap_cptr(offset), Cap::wrap_cptr(offset + 1)))
+    }
+
+    pub fn quarter(
+        self,
+        dest_slots: LocalConstCNodeSlots<4>,
+    ) -> Result<
+        (
+            LocalCap<ConstUntyped<{ BITS - 2 }>>,
+            LocalCap<ConstUntyped<{ BITS - 2 }>>,
+            LocalCap<ConstUntyped<{ BITS - 2 }>>,
+            LocalCap<ConstUntyped<{ BITS - 2 }>>,
+        ),
+        SeL4Error,
+    >
+    where
+        [(); BITS - 2]: ,
+    {
+        let offset = self.retype_untypeds(dest_slots.cptr, dest_slots.cap_data.offset, 2)?;
+        Ok((
+            Cap::wrap_cptr(offset),
+            Cap::wrap_cptr(offset + 1),
+            Cap::wrap_cptr(offset + 2),
+            Cap::wrap_cptr(offset + 3),
+        ))
+    }
+
+    /// Retype into an object that fits, which the subtraction in the
+    /// bound checks.
+    pub fn retype<TargetCapType: CapType, TargetRole: CNodeRole>(
+        self,
+        dest_slot: ConstCNodeSlots<TargetRole, 1>,
+    ) -> Result<Cap<TargetCapType, TargetRole>, SeL4Error>
+    where
+        TargetCapType: DirectRetype,
+        TargetCapType: PhantomCap,
+        [(); BITS - <TargetCapType as DirectRetype>::SizeBits::USIZE]: ,
+    {
+        unsafe {
+            seL4_Untyped_Retype(
+                self.cptr,                     // _service
+                TargetCapType::sel4_type_id(), // type
+                0,                             // size_bits
+                dest_slot.cptr,                // root
+                0,                             // index
+                0,                             // depth
+                dest_slot.cap_data.offset,     // offset
+                1,                             // num_objects
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::UntypedRetype)?;
+
+        Ok(Cap {
+            cptr: dest_slot.cap_data.offset,
+            cap_data: PhantomCap::phantom_instance(),
+            _role: PhantomData,
+        })
+    }
+
+    /// weaken erases the type-level state-tracking (size).
+    pub fn weaken(self) -> LocalCap<WUntyped<memory_kind::General>> {
+        Cap {
+            cptr: self.cptr,
+            cap_data: WUntyped {
+                size_bits: BITS as u8,
+                kind: memory_kind::General,
+            },
+            _role: PhantomData,
+        }
+    }
+
+    /// The same untyped, sized by typenum. `BitSize` must be `BITS`.
+    pub fn to_typenum<BitSize: Unsigned>(self) -> LocalCap<Untyped<BitSize>> {
+        #[allow(clippy::let_unit_value)]
+        let () = SameSize::<BitSize, BITS>::CHECK;
+        Cap::wrap_cptr(self.cptr)
+    }
+
+    /// Split into untypeds `split_bits` smaller, placing them from
+    /// `offset`, and return `offset`.
+    fn retype_untypeds(
+        &self,
+        dest_cptr: usize,
+        offset: usize,
+        split_bits: usize,
+    ) -> Result<usize, SeL4Error> {
+        unsafe {
+            seL4_Untyped_Retype(
+                self.cptr,                              // _service
+                api_object_seL4_UntypedObject as usize, // type
+                BITS - split_bits,                      // size_bits
+                dest_cptr,                              // root
+                0,                                      // index
+                0,                                      // depth
+                offset,                                 // offset
+                1 << split_bits,                        // num_objects
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::UntypedRetype)?;
+        Ok(offset)
+    }
+}
+
+impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
+    /// The same untyped, sized by a const generic.
+    pub fn to_const(self) -> LocalCap<ConstUntyped<{ BitSize::USIZE }>>
+    where
+        [(); BitSize::USIZE]: ,
+    {
+        Cap::wrap_cptr(self.cptr)
+    }
+}
+
+/// An ASID pool with `FREE` ASIDs left to hand out; see `ASIDPool`.
+#[derive(Debug)]
+pub struct ConstASIDPool<const FREE: usize> {
+    pub(crate) id: usize,
+    pub(crate) next_free_slot: usize,
+}
+
+impl<const FREE: usize> CapType for ConstASIDPool<FREE> {}
+
+impl<const FREE: usize> LocalCap<ConstASIDPool<FREE>> {
+    fn with_free<const OUT: usize>(
+        &self,
+        cptr: usize,
+        taken: usize,
+    ) -> LocalCap<ConstASIDPool<OUT>> {
+        Cap {
+            cptr,
+            _role: PhantomData,
+            cap_data: ConstASIDPool {
+                id: self.cap_data.id,
+                next_free_slot: self.cap_data.next_free_slot + taken,
+            },
+        }
+    }
+
+    pub fn alloc(
+        self,
+    ) -> (
+        LocalCap<UnassignedASID>,
+        LocalCap<ConstASIDPool<{ FREE - 1 }>>,
+    )
+    where
+        [(); FREE - 1]: ,
+    {
+        (
+            Cap {
+                cptr: self.cptr,
+                _role: PhantomData,
+                cap_data: UnassignedASID {
+                    asid: InternalASID {
+                        asid: (self.cap_data.id << arch::ASIDLowBits::USIZE)
+                            | self.cap_data.next_free_slot,
+                    },
+                },
+            },
+            self.with_free(self.cptr, 1),
+        )
+    }
+
+    /// Copy the pool into two slots, handing `LEFT` of its ASIDs to the
+    /// first copy and the `RIGHT` after them to the second.
+    pub fn split<const LEFT: usize, const RIGHT: usize>(
+        self,
+        left_slot: ConstCNodeSlots<role::Local, 1>,
+        right_slot: ConstCNodeSlots<role::Local, 1>,
+        src_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<
+        (
+            LocalCap<ConstASIDPool<LEFT>>,
+            LocalCap<ConstASIDPool<RIGHT>>,
+        ),
+        SeL4Error,
+    >
+    where
+        [(); FREE - (LEFT + RIGHT)]: ,
+    {
+        let left_cptr =
+            self.unchecked_copy(src_cnode, left_slot.to_typenum::<U1>(), CapRights::RWG)?;
+        let right_cptr =
+            self.unchecked_copy(src_cnode, right_slot.to_typenum::<U1>(), CapRights::RWG)?;
+        Ok((
+            self.with_free(left_cptr, 0),
+            self.with_free(right_cptr, LEFT),
+        ))
+    }
+
+    pub fn truncate<const OUT: usize>(self) -> LocalCap<ConstASIDPool<OUT>>
+    where
+        [(); FREE - OUT]: ,
+    {
+        self.with_free(self.cptr, FREE - OUT)
+    }
+
+    /// The same pool, sized by typenum. `FreeSlots` must be `FREE`.
+    pub fn to_typenum<FreeSlots: Unsigned>(self) -> LocalCap<ASIDPool<FreeSlots>> {
+        #[allow(clippy::let_unit_value)]
+        let () = SameSize::<FreeSlots, FREE>::CHECK;
+        Cap {
+            cptr: self.cptr,
+            _role: PhantomData,
+            cap_data: ASIDPool {
+                id: self.cap_data.id,
+                next_free_slot: self.cap_data.next_free_slot,
+                _free_slots: PhantomData,
+            },
+        }
+    }
+}
+
+impl<FreeSlots: Unsigned> LocalCap<ASIDPool<FreeSlots>> {
+    /// The same pool, sized by a const generic.
+    pub fn to_const(self) -> LocalCap<ConstASIDPool<{ FreeSlots::USIZE }>>
+    where
+        [(); FreeSlots::USIZE]: ,
+    {
+        Cap {
+            cptr: self.cptr,
+            _role: PhantomData,
+            cap_data: ConstASIDPool {
+                id: self.cap_data.id,
+                next_free_slot: self.cap_data.next_free_slot,
+            },
+        }
+    }
+}
+
+/// Fails to compile unless `Size` is `SIZE`, for conversions the other way
+/// from `to_const`, where the typenum size can't be worked out from the
+/// const one.
+struct SameSize<Size, const SIZE: usize>(PhantomData<Size>);
+
+impl<Size: Unsigned, const SIZE: usize> SameSize<Size, SIZE> {
+    const CHECK: () = if Size::USIZE != SIZE {
+        panic!("a const generic size was converted to a different typenum size")
+    };
+}
@@ -0,0 +1,463 @@
+//! Two-level CSpaces, for children that hold more caps than a single CNode
+//! comfortably does.
+use core::marker::PhantomData;
+use core::ops::{Add, Sub};
+
+use selfe_sys::*;
+
+use typenum::operator_aliases::{Diff, Sum};
+use typenum::*;
+
+use crate::arch::CNodeSlotBits;
+use crate::cap::{
+    memory_kind, Badge, CNode, Cap, CapType, ChildCNode, ChildCap, CopyAliasable, DirectRetype,
+    LocalCNode, LocalCNodeSlot, LocalCNodeSlots, LocalCap, Mintable, PhantomCap, Untyped,
+};
+use crate::error::{ErrorExt, SeL4Error};
+use crate::pow::{Pow, _Pow};
+use crate::userland::CapRights;
+
+/// The root CNode of a child's two-level CSpace, with a sub-CNode of
+/// `2^SubRadix` slots in each of its `2^RootRadix` slots. Its guard and
+/// radix take only the top of a cptr, and each sub-CNode's the rest.
+#[derive(Debug)]
+pub struct TwoLevelCNode<RootRadix: Unsigned, SubRadix: Unsigned> {
+    pub(crate) guard_bits: u8,
+    pub(crate) _radix: PhantomData<(RootRadix, SubRadix)>,
+}
+
+/// Slots of a two-level CSpace's root CNode, each of which can take a
+/// sub-CNode.
+#[derive(Debug)]
+pub struct RootSlotsData<Size: Unsigned> {
+    pub(crate) offset: usize,
+    pub(crate) _size: PhantomData<Size>,
+}
+
+/// Slots of a sub-CNode in a two-level CSpace, addressed by their path
+/// from the root. Caps put in them come back as child caps whose cptr is
+/// that path.
+#[derive(Debug)]
+pub struct PathSlotsData<Size: Unsigned> {
+    pub(crate) offset: usize,
+    /// The bits the sub-CNode's guard and radix take between them, the
+    /// depth its slots are looked up at.
+    pub(crate) depth: u8,
+    /// The cptr the child names the sub-CNode's first slot by.
+    pub(crate) base: usize,
+    pub(crate) _size: PhantomData<Size>,
+}
+
+impl<RootRadix: Unsigned, SubRadix: Unsigned> CapType for TwoLevelCNode<RootRadix, SubRadix> {}
+
+impl<Size: Unsigned> CapType for RootSlotsData<Size> {}
+
+impl<Size: Unsigned> CapType for PathSlotsData<Size> {}
+
+pub type RootSlots<Size> = LocalCap<RootSlotsData<Size>>;
+pub type RootSlot = RootSlots<U1>;
+
+pub type PathSlots<Size> = LocalCap<PathSlotsData<Size>>;
+pub type PathSlot = PathSlots<U1>;
+
+#[derive(Debug)]
+pub enum CSpaceError {
+    /// The root guard and both radixes take more bits than a cptr has.
+    TooManyBits {
+        guard_bits: u8,
+        radix_bits: u8,
+    },
+    SeL4Error(SeL4Error),
+}
+
+impl From<SeL4Error> for CSpaceError {
+    fn from(e: SeL4Error) -> Self {
+        CSpaceError::SeL4Error(e)
+    }
+}
+
+/// A version of retype_two_level_cnode that concretely specifies the
+/// required untyped size, to work well with type inference.
+pub fn retype_two_level_cnode<RootRadix: Unsigned, SubRadix: Unsigned>(
+    untyped: LocalCap<Untyped<Sum<RootRadix, CNodeSlotBits>, memory_kind::General>>,
+    guard_bits: u8,
+    local_slots: LocalCNodeSlots<U2>,
+) -> Result<
+    (
+        LocalCap<TwoLevelCNode<RootRadix, SubRadix>>,
+        RootSlots<Diff<Pow<RootRadix>, U1>>,
+    ),
+    CSpaceError,
+>
+where
+    RootRadix: _Pow,
+    Pow<RootRadix>: Unsigned,
+
+    Pow<RootRadix>: Sub<U1>,
+    Diff<Pow<RootRadix>, U1>: Unsigned,
+
+    RootRadix: Add<CNodeSlotBits>,
+    Sum<RootRadix, CNodeSlotBits>: Unsigned,
+    Sum<RootRadix, CNodeSlotBits>: IsGreaterOrEqual<Sum<RootRadix, CNodeSlotBits>, Output = True>,
+{
+    untyped.retype_two_level_cnode::<RootRadix, SubRadix>(guard_bits, local_slots)
+}
+
+impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
+    /// Make the root CNode of a two-level CSpace, whose guard takes the top
+    /// `guard_bits` of each cptr.
+    pub fn retype_two_level_cnode<RootRadix: Unsigned, SubRadix: Unsigned>(
+        self,
+        guard_bits: u8,
+        local_slots: LocalCNodeSlots<U2>,
+    ) -> Result<
+        (
+            LocalCap<TwoLevelCNode<RootRadix, SubRadix>>,
+            RootSlots<Diff<Pow<RootRadix>, U1>>,
+        ),
+        CSpaceError,
+    >
+    where
+        RootRadix: _Pow,
+        Pow<RootRadix>: Unsigned,
+
+        Pow<RootRadix>: Sub<U1>,
+        Diff<Pow<RootRadix>, U1>: Unsigned,
+
+        RootRadix: Add<CNodeSlotBits>,
+        Sum<RootRadix, CNodeSlotBits>: Unsigned,
+        BitSize: IsGreaterOrEqual<Sum<RootRadix, CNodeSlotBits>, Output = True>,
+    {
+        let radix_bits = RootRadix::U8 + SubRadix::U8;
+        if usize::from(guard_bits) + usize::from(radix_bits) > seL4_WordBits as usize {
+            return Err(CSpaceError::TooManyBits {
+                guard_bits,
+                radix_bits,
+            });
+        }
+
+        let (scratch_slot, dest_slot) = local_slots.alloc::<U1>();
+        let cptr = retype_guarded_cnode(
+            self.cptr,
+            RootRadix::USIZE,
+            usize::from(guard_bits),
+            scratch_slot,
+            dest_slot,
+        )?;
+
+        Ok((
+            Cap {
+                cptr,
+                _role: PhantomData,
+                cap_data: TwoLevelCNode {
+                    guard_bits,
+                    _radix: PhantomData,
+                },
+            },
+            // Root slot 0 holds nothing, so that the paths through it,
+            // including the null cptr, never name a cap.
+            Cap {
+                cptr,
+                _role: PhantomData,
+                cap_data: RootSlotsData {
+                    offset: 1,
+                    _size: PhantomData,
+                },
+            },
+        ))
+    }
+}
+
+impl<RootRadix: Unsigned, SubRadix: Unsigned> LocalCap<TwoLevelCNode<RootRadix, SubRadix>> {
+    /// Make a sub-CNode and put it in `root_slot`, giving back its slots.
+    /// A copy of it is kept in `local_slots`, through which they are
+    /// filled.
+    pub fn add_sub_cnode(
+        &self,
+        untyped: LocalCap<Untyped<Sum<SubRadix, CNodeSlotBits>, memory_kind::General>>,
+        root_slot: RootSlot,
+        local_slots: LocalCNodeSlots<U2>,
+        parent_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<PathSlots<Pow<SubRadix>>, SeL4Error>
+    where
+        SubRadix: _Pow,
+        Pow<SubRadix>: Unsigned,
+
+        SubRadix: Add<CNodeSlotBits>,
+        Sum<SubRadix, CNodeSlotBits>: Unsigned,
+    {
+        let root_depth = self.cap_data.guard_bits + RootRadix::U8;
+        let sub_depth = seL4_WordBits as u8 - root_depth;
+
+        let (scratch_slot, dest_slot) = local_slots.alloc::<U1>();
+        let sub_cptr = retype_guarded_cnode(
+            untyped.cptr,
+            SubRadix::USIZE,
+            usize::from(sub_depth) - SubRadix::USIZE,
+            scratch_slot,
+            dest_slot,
+        )?;
+
+        let index = root_slot.cap_data.offset;
+        unsafe {
+            seL4_CNode_Copy(
+                self.cptr,            // _service
+                index,                // index
+                root_depth,           // depth
+                parent_cnode.cptr,    // src_root
+                sub_cptr,             // src_index
+                seL4_WordBits as u8,  // src_depth
+                CapRights::RW.into(), // rights
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::CNodeCopy)?;
+
+        Ok(Cap {
+            cptr: sub_cptr,
+            _role: PhantomData,
+            cap_data: PathSlotsData {
+                offset: 0,
+                depth: sub_depth,
+                base: index << sub_depth,
+                _size: PhantomData,
+            },
+        })
+    }
+
+    /// The root CNode, ready to be given to a child as its CSpace.
+    pub fn into_cspace_root(self) -> LocalCap<ChildCNode> {
+        Cap {
+            cptr: self.cptr,
+            _role: PhantomData,
+            cap_data: CNode {
+                radix: RootRadix::U8,
+                guard_bits: self.cap_data.guard_bits,
+                _role: PhantomData,
+            },
+        }
+    }
+}
+
+impl<Size: Unsigned> RootSlots<Size> {
+    pub fn alloc<Count: Unsigned>(self) -> (RootSlots<Count>, RootSlots<Diff<Size, Count>>)
+    where
+        Size: Sub<Count>,
+        Diff<Size, Count>: Unsigned,
+    {
+        (
+            Cap {
+                cptr: self.cptr,
+                _role: PhantomData,
+                cap_data: RootSlotsData {
+                    offset: self.cap_data.offset,
+                    _size: PhantomData,
+                },
+            },
+            Cap {
+                cptr: self.cptr,
+                _role: PhantomData,
+                cap_data: RootSlotsData {
+                    offset: self.cap_data.offset + Count::USIZE,
+                    _size: PhantomData,
+                },
+            },
+        )
+    }
+}
+
+impl<Size: Unsigned> PathSlots<Size> {
+    pub fn alloc<Count: Unsigned>(self) -> (PathSlots<Count>, PathSlots<Diff<Size, Count>>)
+    where
+        Size: Sub<Count>,
+        Diff<Size, Count>: Unsigned,
+    {
+        let (cptr, offset, depth, base) = self.elim();
+        (
+            Cap::path_new(cptr, offset, depth, base),
+            Cap::path_new(cptr, offset + Count::USIZE, depth, base),
+        )
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = PathSlot> {
+        let (cptr, offset, depth, base) = self.elim();
+        (0..Size::USIZE).map(move |n| Cap::path_new(cptr, offset + n, depth, base))
+    }
+
+    /// The cptr the child names the first of these slots by.
+    pub fn path(&self) -> usize {
+        self.cap_data.base | self.cap_data.offset
+    }
+
+    fn path_new(cptr: usize, offset: usize, depth: u8, base: usize) -> Self {
+        Cap {
+            cptr,
+            _role: PhantomData,
+            cap_data: PathSlotsData {
+                offset,
+                depth,
+                base,
+                _size: PhantomData,
+            },
+        }
+    }
+
+    fn elim(self) -> (usize, usize, u8, usize) {
+        (
+            self.cptr,
+            self.cap_data.offset,
+            self.cap_data.depth,
+            self.cap_data.base,
+        )
+    }
+}
+
+impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
+    /// Like `retype`, but into a slot of a two-level CSpace.
+    pub fn retype_to_path<TargetCapType: CapType>(
+        self,
+        dest_slot: PathSlot,
+    ) -> Result<ChildCap<TargetCapType>, SeL4Error>
+    where
+        TargetCapType: DirectRetype,
+        TargetCapType: PhantomCap,
+        BitSize: IsGreaterOrEqual<TargetCapType::SizeBits, Output = True>,
+    {
+        let path = dest_slot.path();
+        unsafe {
+            seL4_Untyped_Retype(
+                self.cptr,                     // _service
+                TargetCapType::sel4_type_id(), // type
+                0,                             // size_bits
+                dest_slot.cptr,                // root
+                0,                             // index
+                0,                             // depth
+                dest_slot.cap_data.offset,     // offset
+                1,                             // num_objects
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::UntypedRetype)?;
+
+        Ok(Cap {
+            cptr: path,
+            cap_data: PhantomCap::phantom_instance(),
+            _role: PhantomData,
+        })
+    }
+}
+
+impl<CT: CapType> LocalCap<CT> {
+    /// Like `copy`, but into a slot of a two-level CSpace.
+    pub fn copy_to_path(
+        &self,
+        src_cnode: &LocalCap<LocalCNode>,
+        dest_slot: PathSlot,
+        rights: CapRights,
+    ) -> Result<ChildCap<CT::CopyOutput>, SeL4Error>
+    where
+        CT: CopyAliasable,
+    {
+        unsafe {
+            seL4_CNode_Copy(
+                dest_slot.cptr,            // _service
+                dest_slot.cap_data.offset, // index
+                dest_slot.cap_data.depth,  // depth
+                src_cnode.cptr,            // src_root
+                self.cptr,                 // src_index
+                seL4_WordBits as u8,       // src_depth
+                rights.into(),             // rights
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::CNodeCopy)?;
+        Ok(Cap {
+            cptr: dest_slot.path(),
+            cap_data: From::from(&self.cap_data),
+            _role: PhantomData,
+        })
+    }
+
+    /// Like `mint`, but into a slot of a two-level CSpace.
+    pub fn mint_to_path(
+        &self,
+        src_cnode: &LocalCap<LocalCNode>,
+        dest_slot: PathSlot,
+        rights: CapRights,
+        badge: Badge,
+    ) -> Result<ChildCap<CT::CopyOutput>, SeL4Error>
+    where
+        CT: Mintable,
+        CT: CopyAliasable,
+        CT: PhantomCap,
+        <CT as CopyAliasable>::CopyOutput: PhantomCap,
+    {
+        unsafe {
+            seL4_CNode_Mint(
+                dest_slot.cptr,            // _service
+                dest_slot.cap_data.offset, // dest index
+                dest_slot.cap_data.depth,  // dest depth
+                src_cnode.cptr,            // src_root
+                self.cptr,                 // src_index
+                seL4_WordBits as u8,       // src_depth
+                rights.into(),             // rights
+                badge.into(),              // badge
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::CNodeMint)?;
+        Ok(Cap {
+            cptr: dest_slot.path(),
+            cap_data: PhantomCap::phantom_instance(),
+            _role: PhantomData,
+        })
+    }
+}
+
+/// Retype a CNode into `scratch_slot`, then mutate it into `dest_slot`
+/// with a guard of `guard_bits`, returning the cptr of the latter.
+fn retype_guarded_cnode(
+    untyped_cptr: usize,
+    radix: usize,
+    guard_bits: usize,
+    scratch_slot: LocalCNodeSlot,
+    dest_slot: LocalCNodeSlot,
+) -> Result<usize, SeL4Error> {
+    let (scratch_cptr, scratch_offset, _) = scratch_slot.elim();
+    let (dest_cptr, dest_offset, _) = dest_slot.elim();
+
+    unsafe {
+        seL4_Untyped_Retype(
+            untyped_cptr,                            // _service
+            api_object_seL4_CapTableObject as usize, // type
+            radix,                                   // size_bits
+            scratch_cptr,                            // root
+            0,                                       // index
+            0,                                       // depth
+            scratch_offset,                          // offset
+            1,                                       // num_objects
+        )
+        .as_result()
+        .map_err(SeL4Error::UntypedRetype)?;
+
+        let guard_data = seL4_CNode_CapData_new(
+            0,               // guard
+            guard_bits as _, // guard size in bits
+        )
+        .words[0];
+
+        seL4_CNode_Mutate(
+            dest_cptr,           // _service: seL4_CNode,
+            dest_offset,         // dest_index: seL4_Word,
+            seL4_WordBits as u8, // dest_depth: seL4_Uint8,
+            scratch_cptr,        // src_root: seL4_CNode,
+            scratch_offset,      // src_index: seL4_Word,
+            seL4_WordBits as u8, // src_depth: seL4_Uint8,
+            guard_data as usize, // badge or guard: seL4_Word,
+        )
+        .as_result()
+        .map_err(SeL4Error::CNodeMutate)?;
+    }
+
+    Ok(dest_offset)
+}
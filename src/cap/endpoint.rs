@@ -2,7 +2,9 @@ use typenum::*;
 
 use selfe_sys::*;
 
-use crate::cap::{CapType, CopyAliasable, DirectRetype, Mintable, PhantomCap};
+use crate::cap::{
+    CapRangeDataReconstruction, CapType, CopyAliasable, DirectRetype, Mintable, PhantomCap,
+};
 
 #[derive(Debug)]
 pub struct Endpoint {}
@@ -26,6 +28,12 @@ impl<'a> From<&'a Endpoint> for Endpoint {
 
 impl Mintable for Endpoint {}
 
+impl CapRangeDataReconstruction for Endpoint {
+    fn reconstruct(_index: usize, _seed: &Self) -> Self {
+        PhantomCap::phantom_instance()
+    }
+}
+
 impl DirectRetype for Endpoint {
     type SizeBits = U4;
     fn sel4_type_id() -> usize {
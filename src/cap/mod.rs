@@ -11,6 +11,9 @@ mod asid_control;
 mod asid_pool;
 mod badge;
 mod cnode;
+#[cfg(feature = "const_generics")]
+mod const_generic;
+mod cspace;
 mod endpoint;
 mod fault_reply_endpoint;
 mod irq_control;
@@ -26,6 +29,9 @@ pub use asid_control::*;
 pub use asid_pool::*;
 pub use badge::*;
 pub use cnode::*;
+#[cfg(feature = "const_generics")]
+pub use const_generic::*;
+pub use cspace::*;
 pub use endpoint::*;
 pub use fault_reply_endpoint::*;
 pub use irq_control::*;
@@ -292,6 +298,22 @@ impl<CT: CapType, Role: CNodeRole> WeakCapRange<CT, Role> {
     pub(crate) fn len(&self) -> usize {
         self.len
     }
+
+    /// Call `f` with each cap in the range, in order.
+    pub fn for_each<E, F: FnMut(&Cap<CT, Role>) -> Result<(), E>>(&self, mut f: F) -> Result<(), E>
+    where
+        CT: CapRangeDataReconstruction,
+    {
+        for index in 0..self.len() {
+            f(&Cap {
+                cptr: self.start_cptr + index,
+                _role: PhantomData,
+                cap_data: CT::reconstruct(index, &self.start_cap_data),
+            })?
+        }
+
+        Ok(())
+    }
 }
 
 /// A helper trait for CapRange and WeakCapRange to assist in iteration.
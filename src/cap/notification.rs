@@ -1,6 +1,9 @@
 use selfe_sys::*;
 
-use crate::cap::{Badge, CapType, CopyAliasable, DirectRetype, LocalCap, Mintable, PhantomCap};
+use crate::cap::{
+    Badge, CapRangeDataReconstruction, CapType, CopyAliasable, DirectRetype, LocalCap, Mintable,
+    PhantomCap,
+};
 
 #[derive(Debug)]
 pub struct Notification {}
@@ -24,6 +27,12 @@ impl<'a> From<&'a Notification> for Notification {
 
 impl Mintable for Notification {}
 
+impl CapRangeDataReconstruction for Notification {
+    fn reconstruct(_index: usize, _seed: &Self) -> Self {
+        PhantomCap::phantom_instance()
+    }
+}
+
 impl DirectRetype for Notification {
     type SizeBits = crate::arch::NotificationBits;
     fn sel4_type_id() -> usize {
@@ -1,7 +1,8 @@
 use selfe_sys::*;
 
 use crate::cap::{
-    page_state, role, CapType, ChildCNode, CopyAliasable, DirectRetype, LocalCap, Page, PhantomCap,
+    page_state, role, CapType, ChildCNode, CopyAliasable, Delible, DirectRetype, LocalCap, Page,
+    PhantomCap,
 };
 use crate::error::{ErrorExt, SeL4Error};
 use crate::userland::FaultSource;
@@ -24,6 +25,8 @@ impl DirectRetype for ThreadControlBlock {
     }
 }
 
+impl Delible for ThreadControlBlock {}
+
 impl CopyAliasable for ThreadControlBlock {
     type CopyOutput = Self;
 }
@@ -75,11 +78,11 @@ impl LocalCap<ThreadControlBlock> {
         ipc_buffer: Option<LocalCap<Page<page_state::Mapped>>>,
     ) -> Result<(), SeL4Error> {
         // Set up the cspace's guard to take the part of the cptr that's not
-        // used by the radix.
+        // used by the radix, or by the sub-CNodes of a two-level cspace.
         let cspace_root_data = unsafe {
             seL4_CNode_CapData_new(
-                0,                                                          // guard
-                (seL4_WordBits - cspace_root.cap_data.radix as usize) as _, // guard size in bits
+                0,                                    // guard
+                cspace_root.cap_data.guard_bits as _, // guard size in bits
             )
         }
         .words[0] as usize;
@@ -116,4 +119,30 @@ impl LocalCap<ThreadControlBlock> {
             .as_result()
             .map_err(SeL4Error::TCBSetPriority)
     }
+
+    /// Read all of this thread's user-visible registers, e.g. to inspect
+    /// where it stopped after a fault.
+    pub fn read_registers(&self) -> Result<seL4_UserContext, SeL4Error> {
+        let mut registers: seL4_UserContext = unsafe { core::mem::zeroed() };
+        unsafe {
+            seL4_TCB_ReadRegisters(
+                self.cptr,
+                0, // suspend_source
+                0, // arch_flags
+                // all the regs
+                core::mem::size_of::<seL4_UserContext>() / core::mem::size_of::<usize>(),
+                &mut registers,
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::TCBReadRegisters)?;
+        Ok(registers)
+    }
+
+    /// Stop this thread from running until it is resumed.
+    pub fn suspend(&mut self) -> Result<(), SeL4Error> {
+        unsafe { seL4_TCB_Suspend(self.cptr) }
+            .as_result()
+            .map_err(SeL4Error::TCBSuspend)
+    }
 }
@@ -7,6 +7,7 @@ use typenum::operator_aliases::{Diff, Prod, Sum};
 
 use typenum::*;
 
+use crate::arch::cap::frame_object_type;
 use crate::arch::{CNodeSlotBits, PageBits};
 use crate::cap::{
     page_state, role, CNode, CNodeRole, CNodeSlot, CNodeSlots, CNodeSlotsError, Cap, CapRange,
@@ -118,27 +119,39 @@ impl<Kind: MemoryKind> LocalCap<WUntyped<Kind>> {
         self,
         slots: &mut Cap<WCNodeSlotsData<CRole>, role::Local>,
     ) -> Result<WeakCapRange<Page<page_state::Unmapped>, CRole>, RetypeError> {
-        if self.cap_data.size_bits < PageBits::U8 {
+        self.retype_frames(PageBits::U8, slots)
+    }
+
+    /// Retype into as many frames of `1 << frame_bits` bytes as fit,
+    /// where `frame_bits` is either `PageBits` or one of the larger frame
+    /// sizes the architecture supports.
+    pub fn retype_frames<CRole: CNodeRole>(
+        self,
+        frame_bits: u8,
+        slots: &mut Cap<WCNodeSlotsData<CRole>, role::Local>,
+    ) -> Result<WeakCapRange<Page<page_state::Unmapped>, CRole>, RetypeError> {
+        let object_type = frame_object_type(frame_bits).ok_or(RetypeError::UnsupportedFrameSize)?;
+        if self.cap_data.size_bits < frame_bits {
             return Err(RetypeError::NotBigEnough);
         }
-        let num_pages = 1 << usize::from(self.cap_data.size_bits - PageBits::U8);
-        if num_pages > KernelRetypeFanOutLimit::USIZE {
+        let num_frames = 1 << usize::from(self.cap_data.size_bits - frame_bits);
+        if num_frames > KernelRetypeFanOutLimit::USIZE {
             return Err(RetypeError::KernelRetypeFanOutLimit);
         }
-        // TODO - REVIEW - Do we need more constraints on num_pages?
+        // TODO - REVIEW - Do we need more constraints on num_frames?
         let dest_slots = slots
-            .alloc(num_pages)
+            .alloc(num_frames)
             .map_err(RetypeError::CNodeSlotsError)?;
         unsafe {
             seL4_Untyped_Retype(
                 self.cptr,                  // _service
-                Page::sel4_type_id(),       // type
+                object_type,                // type
                 0,                          // size_bits
                 dest_slots.cptr,            // root
                 0,                          // index
                 0,                          // depth
                 dest_slots.cap_data.offset, // offset
-                num_pages,                  // num_objects
+                num_frames,                 // num_objects
             )
             .as_result()
             .map_err(SeL4Error::UntypedRetype)?;
@@ -151,7 +164,7 @@ impl<Kind: MemoryKind> LocalCap<WUntyped<Kind>> {
                 /* TODO - kind piping
                  *memory_kind: self.cap_data.kind, */
             },
-            num_pages,
+            num_frames,
         ))
     }
 }
@@ -183,6 +196,44 @@ impl LocalCap<WUntyped<memory_kind::General>> {
 
         Ok(Cap::wrap_cptr(slot.cap_data.offset))
     }
+
+    /// Retype into `count` objects of type `D`, in consecutive slots.
+    pub fn retype_many<D: CapType + PhantomCap + DirectRetype>(
+        self,
+        count: usize,
+        slots: &mut WCNodeSlots,
+    ) -> Result<WeakCapRange<D, role::Local>, RetypeError> {
+        if D::SizeBits::U8 > self.cap_data.size_bits
+            || count > 1 << usize::from(self.cap_data.size_bits - D::SizeBits::U8)
+        {
+            return Err(RetypeError::NotBigEnough);
+        }
+        if count > KernelRetypeFanOutLimit::USIZE {
+            return Err(RetypeError::KernelRetypeFanOutLimit);
+        }
+
+        let dest_slots = slots.alloc(count)?;
+        unsafe {
+            seL4_Untyped_Retype(
+                self.cptr,                  // _service
+                D::sel4_type_id(),          // type
+                0,                          // size_bits
+                dest_slots.cptr,            // root
+                0,                          // index
+                0,                          // depth
+                dest_slots.cap_data.offset, // offset
+                count,                      // num_objects
+            )
+        }
+        .as_result()
+        .map_err(|err| RetypeError::SeL4RetypeError(SeL4Error::UntypedRetype(err)))?;
+
+        Ok(WeakCapRange::new(
+            dest_slots.cap_data.offset,
+            D::phantom_instance(),
+            count,
+        ))
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -306,6 +357,8 @@ pub enum RetypeError {
     NotBigEnough,
     SeL4RetypeError(SeL4Error),
     CNodeSlotsError(CNodeSlotsError),
+    /// The architecture has no frame object of the requested size.
+    UnsupportedFrameSize,
 }
 
 impl From<SeL4Error> for RetypeError {
@@ -739,6 +792,7 @@ impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
                 _role: PhantomData,
                 cap_data: CNode {
                     radix: ChildRadix::to_u8(),
+                    guard_bits: (seL4_WordBits - ChildRadix::to_usize()) as u8,
                     _role: PhantomData,
                 },
             },
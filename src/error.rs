@@ -17,6 +17,7 @@ pub enum SeL4Error {
     TCBReadRegisters(KernelError),
     TCBSetPriority(KernelError),
     TCBResume(KernelError),
+    TCBSuspend(KernelError),
     CNodeMutate(KernelError),
     CNodeMove(KernelError),
     CNodeDelete(KernelError),
@@ -25,6 +26,8 @@ pub enum SeL4Error {
     IRQHandlerAck(KernelError),
     GetPageAddr(KernelError),
     PageCleanInvalidateData(KernelError),
+    PageCleanData(KernelError),
+    PageInvalidateData(KernelError),
     CNodeRevoke(KernelError),
     VCPUInjectIRQ(KernelError),
     VCPUReadRegisters(KernelError),
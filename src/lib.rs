@@ -1,6 +1,9 @@
 #![no_std]
 #![recursion_limit = "256"]
+#![feature(const_panic)]
 #![feature(proc_macro_hygiene)]
+#![cfg_attr(feature = "const_generics", feature(generic_const_exprs))]
+#![cfg_attr(feature = "const_generics", allow(incomplete_features))]
 #![allow(
     clippy::too_many_arguments,
     clippy::type_complexity,
@@ -26,6 +29,7 @@ pub mod bootstrap;
 pub mod cap;
 pub mod error;
 pub mod pow;
+pub mod sync;
 #[cfg(feature = "test_support")]
 pub mod test_support;
 pub mod userland;
@@ -0,0 +1,351 @@
+//! Blocking synchronization primitives for threads that share a VSpace,
+//! which only touch their notification when a thread has to block.
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::cap::{
+    role, CNodeRole, CNodeSlot, Cap, DirectRetype, LocalCNode, LocalCNodeSlot, LocalCap,
+    Notification, Untyped,
+};
+use crate::error::SeL4Error;
+use crate::userland::CapRights;
+
+/// The resources needed to hand out handles to a single synchronization
+/// primitive. The state lives at an address every participating thread
+/// can see, e.g. in a `static`.
+pub struct SyncSetup<S: 'static> {
+    state: &'static S,
+    notification: LocalCap<Notification>,
+}
+
+/// One thread's view of a synchronization primitive.
+///
+/// Designed to be handed to a new thread as a member of the initial thread
+/// parameters struct.
+pub struct Handle<S: 'static, Role: CNodeRole> {
+    state: &'static S,
+    notification: Cap<Notification, Role>,
+}
+
+pub type Mutex<T, Role> = Handle<MutexState<T>, Role>;
+pub type Semaphore<Role> = Handle<SemaphoreState, Role>;
+pub type Condvar<Role> = Handle<CondvarState, Role>;
+pub type Barrier<Role> = Handle<BarrierState, Role>;
+
+impl<S: 'static> SyncSetup<S> {
+    pub fn new(
+        state: &'static S,
+        notification_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
+        local_slot: LocalCNodeSlot,
+    ) -> Result<Self, SeL4Error> {
+        let notification: LocalCap<Notification> = notification_ut.retype(local_slot)?;
+        Ok(SyncSetup {
+            state,
+            notification,
+        })
+    }
+
+    /// Make a handle for one participating thread, placing its copy of
+    /// the notification in `dest_slot`.
+    pub fn participant<Role: CNodeRole>(
+        &self,
+        local_cnode: &LocalCap<LocalCNode>,
+        dest_slot: CNodeSlot<Role>,
+    ) -> Result<Handle<S, Role>, SeL4Error> {
+        let notification = self
+            .notification
+            .copy(local_cnode, dest_slot, CapRights::RWG)?;
+        Ok(Handle {
+            state: self.state,
+            notification,
+        })
+    }
+}
+
+// Mutex
+
+/// The shared half of a `Mutex`.
+pub struct MutexState<T> {
+    // 0: unlocked, 1: locked, 2: locked and somebody may be waiting
+    lock: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for MutexState<T> {}
+unsafe impl<T: Send> Send for MutexState<T> {}
+
+const UNLOCKED: usize = 0;
+const LOCKED: usize = 1;
+const CONTENDED: usize = 2;
+
+impl<T> MutexState<T> {
+    pub const fn new(data: T) -> Self {
+        MutexState {
+            lock: AtomicUsize::new(UNLOCKED),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T: 'static> {
+    mutex: &'a Mutex<T, role::Local>,
+}
+
+impl<T: 'static> Mutex<T, role::Local> {
+    pub fn lock(&self) -> MutexGuard<T> {
+        if self
+            .state
+            .lock
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // Anybody who has had to wait marks the lock as contended so that
+            // whoever ends up holding it signals on the way out.
+            while self.state.lock.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+                let _ = self.notification.wait();
+            }
+        }
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        self.state
+            .lock
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    fn unlock(&self) {
+        if self.state.lock.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            self.notification.signal();
+        }
+    }
+}
+
+impl<'a, T: 'static> Deref for MutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.state.data.get() }
+    }
+}
+
+impl<'a, T: 'static> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.state.data.get() }
+    }
+}
+
+impl<'a, T: 'static> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock()
+    }
+}
+
+// Semaphore
+
+/// The shared half of a counting `Semaphore`.
+pub struct SemaphoreState {
+    count: AtomicUsize,
+    sleepers: AtomicUsize,
+}
+
+impl SemaphoreState {
+    pub const fn new(initial_count: usize) -> Self {
+        SemaphoreState {
+            count: AtomicUsize::new(initial_count),
+            sleepers: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Semaphore<role::Local> {
+    pub fn try_acquire(&self) -> bool {
+        let mut count = self.state.count.load(Ordering::Relaxed);
+        while count > 0 {
+            match self.state.count.compare_exchange_weak(
+                count,
+                count - 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    // Releases that happened while nobody was blocked yet
+                    // only leave a single pending signal behind; pass it on
+                    // if there is still something for another sleeper.
+                    if count > 1 && self.state.sleepers.load(Ordering::SeqCst) > 0 {
+                        self.notification.signal();
+                    }
+                    return true;
+                }
+                Err(c) => count = c,
+            }
+        }
+        false
+    }
+
+    pub fn acquire(&self) {
+        loop {
+            if self.try_acquire() {
+                return;
+            }
+            self.state.sleepers.fetch_add(1, Ordering::SeqCst);
+            // Re-check now that any releaser is sure to see us.
+            if self.state.count.load(Ordering::SeqCst) == 0 {
+                let _ = self.notification.wait();
+            }
+            self.state.sleepers.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    pub fn release(&self) {
+        self.state.count.fetch_add(1, Ordering::SeqCst);
+        if self.state.sleepers.load(Ordering::SeqCst) > 0 {
+            self.notification.signal();
+        }
+    }
+}
+
+// Condvar
+
+/// The shared half of a `Condvar`.
+pub struct CondvarState {
+    // Threads which have started waiting and have not yet been notified
+    waiters: AtomicUsize,
+    // Wakeups handed out by notify_* which nobody has taken yet
+    tokens: AtomicUsize,
+}
+
+impl CondvarState {
+    pub const fn new() -> Self {
+        CondvarState {
+            waiters: AtomicUsize::new(0),
+            tokens: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// Take one token from `tokens` if there are any, passing the signal on if
+/// more remain. A notification only remembers that it was signaled, not
+/// how many times, so a wakeup for several waiters is handed out as tokens.
+fn take_token(tokens: &AtomicUsize, notification: &LocalCap<Notification>) -> bool {
+    let mut available = tokens.load(Ordering::SeqCst);
+    while available > 0 {
+        match tokens.compare_exchange_weak(
+            available,
+            available - 1,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(_) => {
+                if available > 1 {
+                    notification.signal();
+                }
+                return true;
+            }
+            Err(a) => available = a,
+        }
+    }
+    false
+}
+
+impl Condvar<role::Local> {
+    /// Release the mutex, block until notified, and re-acquire the mutex.
+    ///
+    /// As with any condition variable, callers should re-check their
+    /// condition in a loop.
+    pub fn wait<'a, T: 'static>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex;
+        // Register before letting go of the mutex so that a notify issued
+        // by the next holder is sure to count us.
+        self.state.waiters.fetch_add(1, Ordering::SeqCst);
+        drop(guard);
+        while !take_token(&self.state.tokens, &self.notification) {
+            let _ = self.notification.wait();
+        }
+        mutex.lock()
+    }
+
+    pub fn notify_one(&self) {
+        let mut waiters = self.state.waiters.load(Ordering::SeqCst);
+        while waiters > 0 {
+            match self.state.waiters.compare_exchange_weak(
+                waiters,
+                waiters - 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => {
+                    self.state.tokens.fetch_add(1, Ordering::SeqCst);
+                    self.notification.signal();
+                    return;
+                }
+                Err(w) => waiters = w,
+            }
+        }
+    }
+
+    pub fn notify_all(&self) {
+        let waiters = self.state.waiters.swap(0, Ordering::SeqCst);
+        if waiters > 0 {
+            self.state.tokens.fetch_add(waiters, Ordering::SeqCst);
+            self.notification.signal();
+        }
+    }
+}
+
+// Barrier
+
+/// The shared half of a `Barrier`.
+pub struct BarrierState {
+    parties: usize,
+    arrived: AtomicUsize,
+    generation: AtomicUsize,
+    tokens: AtomicUsize,
+}
+
+impl BarrierState {
+    pub const fn new(parties: usize) -> Self {
+        BarrierState {
+            parties,
+            arrived: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+            tokens: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Barrier<role::Local> {
+    /// Block until `parties` threads have called `wait`. Exactly one of
+    /// them, the last to arrive, gets `true` back.
+    pub fn wait(&self) -> bool {
+        let generation = self.state.generation.load(Ordering::SeqCst);
+        let arrived = self.state.arrived.fetch_add(1, Ordering::SeqCst) + 1;
+        if arrived >= self.state.parties {
+            self.state.arrived.store(0, Ordering::SeqCst);
+            self.state.generation.fetch_add(1, Ordering::SeqCst);
+            if self.state.parties > 1 {
+                self.state
+                    .tokens
+                    .fetch_add(self.state.parties - 1, Ordering::SeqCst);
+                self.notification.signal();
+            }
+            return true;
+        }
+        loop {
+            // Only take a token once our own generation is complete, so
+            // early arrivals for the next round can't slip through.
+            if self.state.generation.load(Ordering::SeqCst) != generation {
+                if take_token(&self.state.tokens, &self.notification) {
+                    return false;
+                }
+            } else if self.state.tokens.load(Ordering::SeqCst) > 0 {
+                // The previous round is still draining, and we may have just
+                // swallowed the wakeup meant for one of its stragglers.
+                self.notification.signal();
+            }
+            let _ = self.notification.wait();
+        }
+    }
+}
@@ -61,12 +61,14 @@ pub fn execute_tests<'t, R: types::TestReporter>(
         untyped,
         asid_pool,
         mut scratch,
+        vspace,
         mapped_memory_region,
         cnode,
         thread_authority,
         vspace_paging_root,
         user_image,
         irq_control,
+        device_buddy,
     } = resources;
     let mut successes = 0;
     let mut failures = 0;
@@ -88,12 +90,14 @@ pub fn execute_tests<'t, R: types::TestReporter>(
                     inner_untyped,
                     inner_asid_pool,
                     &mut scratch,
+                    vspace,
                     inner_mapped_memory_region,
                     cnode,
                     thread_authority,
                     vspace_paging_root,
                     user_image,
                     inner_irq_control,
+                    device_buddy,
                 );
                 reporter.report(name, outcome);
                 if outcome == types::TestOutcome::Success {
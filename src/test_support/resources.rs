@@ -1,7 +1,6 @@
 use selfe_sys::seL4_BootInfo;
 use typenum::*;
 
-use crate::arch;
 use crate::bootstrap::*;
 use crate::cap::*;
 use crate::test_support::MaxMappedMemoryRegionBitSize;
@@ -11,7 +10,6 @@ pub struct Resources {
     pub(super) slots: LocalCNodeSlots<super::types::MaxTestCNodeSlots>,
     pub(super) untyped: LocalCap<Untyped<super::types::MaxTestUntypedSize>>,
     pub(super) asid_pool: LocalCap<ASIDPool<super::types::MaxTestASIDPoolSize>>,
-    #[allow(dead_code)]
     pub(super) vspace: VSpace<vspace_state::Imaged, role::Local>,
     pub(super) scratch: ScratchRegion,
     pub(super) mapped_memory_region: MappedMemoryRegion<
@@ -23,6 +21,7 @@ pub struct Resources {
     pub(super) vspace_paging_root: LocalCap<crate::arch::PagingRoot>,
     pub(super) user_image: UserImage<role::Local>,
     pub(super) irq_control: LocalCap<IRQControl>,
+    pub(super) device_buddy: crate::alloc::DeviceBuddy,
 }
 
 pub struct TestResourceRefs<'t> {
@@ -30,6 +29,7 @@ pub struct TestResourceRefs<'t> {
     pub(super) untyped: &'t mut LocalCap<Untyped<super::types::MaxTestUntypedSize>>,
     pub(super) asid_pool: &'t mut LocalCap<ASIDPool<super::types::MaxTestASIDPoolSize>>,
     pub(super) scratch: &'t mut ScratchRegion<crate::userland::process::DefaultStackPageCount>,
+    pub(super) vspace: &'t mut VSpace<vspace_state::Imaged, role::Local>,
     pub(super) mapped_memory_region: &'t mut MappedMemoryRegion<
         super::types::MaxMappedMemoryRegionBitSize,
         crate::vspace::shared_status::Exclusive,
@@ -39,6 +39,7 @@ pub struct TestResourceRefs<'t> {
     pub(super) vspace_paging_root: &'t LocalCap<crate::arch::PagingRoot>,
     pub(super) user_image: &'t UserImage<role::Local>,
     pub(super) irq_control: &'t mut LocalCap<IRQControl>,
+    pub(super) device_buddy: &'t mut crate::alloc::DeviceBuddy,
 }
 
 type PageFallbackNextSize = Sum<U1, <Page<page_state::Unmapped> as DirectRetype>::SizeBits>;
@@ -48,6 +49,7 @@ impl Resources {
     pub fn with_debug_reporting(
         raw_boot_info: &'static seL4_BootInfo,
         mut allocator: crate::alloc::micro_alloc::Allocator,
+        device_allocator: crate::alloc::micro_alloc::DeviceAllocator,
     ) -> Result<(Self, impl super::TestReporter), super::TestSetupError> {
         let (cnode, local_slots) = root_cnode(&raw_boot_info);
         // TODO - Refine sizes of VSpace untyped and slots
@@ -128,7 +130,7 @@ impl Resources {
         let mapped_memory_region = root_vspace.map_region(
             unmapped_region,
             crate::userland::CapRights::RW,
-            arch::vm_attributes::DEFAULT,
+            memory_attributes::Cached,
         )?;
         let (slots, _local_slots) = local_slots.alloc();
         Ok((
@@ -152,6 +154,7 @@ impl Resources {
                 },
                 user_image,
                 irq_control,
+                device_buddy: device_allocator.into(),
             },
             crate::debug::DebugOutHandle,
         ))
@@ -163,12 +166,14 @@ impl Resources {
             untyped: &mut self.untyped,
             asid_pool: &mut self.asid_pool,
             scratch: &mut self.scratch,
+            vspace: &mut self.vspace,
             mapped_memory_region: &mut self.mapped_memory_region,
             cnode: &self.cnode,
             thread_authority: &self.thread_authority,
             vspace_paging_root: &self.vspace_paging_root,
             user_image: &self.user_image,
             irq_control: &mut self.irq_control,
+            device_buddy: &mut self.device_buddy,
         }
     }
 }
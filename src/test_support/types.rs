@@ -22,6 +22,7 @@ pub type RunTest = dyn Fn(
     LocalCap<Untyped<MaxTestUntypedSize>>,
     LocalCap<ASIDPool<MaxTestASIDPoolSize>>,
     &mut ScratchRegion<crate::userland::process::DefaultStackPageCount>,
+    &mut VSpace,
     crate::vspace::MappedMemoryRegion<
         MaxMappedMemoryRegionBitSize,
         crate::vspace::shared_status::Exclusive,
@@ -31,6 +32,7 @@ pub type RunTest = dyn Fn(
     &LocalCap<crate::arch::PagingRoot>,
     &UserImage<role::Local>,
     LocalCap<IRQControl>,
+    &mut crate::alloc::DeviceBuddy,
 ) -> (&'static str, TestOutcome);
 
 pub trait TestReporter {
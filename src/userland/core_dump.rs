@@ -0,0 +1,297 @@
+//! Post-mortem snapshots of crashed children, in the ELF core file format
+//! understood by gdb.
+use core::cmp;
+use core::mem::size_of;
+
+use selfe_sys::seL4_UserContext;
+
+use crate::arch::fault::Fault;
+use crate::arch::userland::core_dump::{general_registers, ELF_MACHINE, GENERAL_REGISTER_COUNT};
+use crate::arch::PageBytes;
+use crate::error::SeL4Error;
+use crate::userland::CapRights;
+use typenum::Unsigned;
+
+/// Somewhere to stream a core file to, e.g. a UART or a storage service.
+pub trait ByteSink {
+    type Error;
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug)]
+pub enum CoreDumpError<E> {
+    SeL4Error(SeL4Error),
+    SinkError(E),
+}
+
+impl<E> From<SeL4Error> for CoreDumpError<E> {
+    fn from(s: SeL4Error) -> Self {
+        CoreDumpError::SeL4Error(s)
+    }
+}
+
+/// A piece of the child's address space to include in the dump: `data` is
+/// a local view of the memory the child has mapped at `vaddr`.
+#[derive(Debug, Clone, Copy)]
+pub struct CoreDumpRegion<'a> {
+    pub vaddr: usize,
+    pub data: &'a [u8],
+    pub rights: CapRights,
+}
+
+impl<'a> CoreDumpRegion<'a> {
+    pub fn new(vaddr: usize, data: &'a [u8], rights: CapRights) -> Self {
+        CoreDumpRegion {
+            vaddr,
+            data,
+            rights,
+        }
+    }
+}
+
+/// A `ByteSink` which fills a fixed buffer.
+pub struct SliceSink<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+#[derive(Debug)]
+pub struct SliceSinkFull;
+
+impl<'a> SliceSink<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        SliceSink { buffer, len: 0 }
+    }
+
+    /// The bytes written so far.
+    pub fn written(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl<'a> ByteSink for SliceSink<'a> {
+    type Error = SliceSinkFull;
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), SliceSinkFull> {
+        let end = self.len + bytes.len();
+        if end > self.buffer.len() {
+            return Err(SliceSinkFull);
+        }
+        self.buffer[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+}
+
+const WORD: usize = size_of::<usize>();
+
+#[cfg(target_pointer_width = "32")]
+const ELF_CLASS: u8 = 1; // ELFCLASS32
+#[cfg(target_pointer_width = "64")]
+const ELF_CLASS: u8 = 2; // ELFCLASS64
+
+const ELF_HEADER_SIZE: usize = 16 + 2 + 2 + 4 + 3 * WORD + 4 + 6 * 2;
+// The field order differs between the 32 and 64 bit variants, but it
+// comes out at two 32-bit fields plus six words either way.
+const PROGRAM_HEADER_SIZE: usize = 2 * 4 + 6 * WORD;
+
+const ET_CORE: u16 = 4;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const NOTE_NAME: &[u8; 8] = b"CORE\0\0\0\0";
+const NOTE_NAME_SIZE: u32 = 5;
+
+// elf_prstatus: pr_info (3 ints), pr_cursig (short, padded), pr_sigpend
+// and pr_sighold (words), four pids (ints), then four timevals (two words
+// each) ahead of the registers and the trailing pr_fpvalid int.
+const PRSTATUS_REGISTERS_OFFSET: usize = 3 * 4 + 2 + 2 + 2 * WORD + 4 * 4 + 4 * 2 * WORD;
+const PRSTATUS_SIZE: usize = align_up(
+    PRSTATUS_REGISTERS_OFFSET + GENERAL_REGISTER_COUNT * WORD + 4,
+    WORD,
+);
+const NOTE_SIZE: usize = 3 * 4 + NOTE_NAME.len() + align_up(PRSTATUS_SIZE, 4);
+
+const SIGILL: u16 = 4;
+const SIGABRT: u16 = 6;
+const SIGSEGV: u16 = 11;
+const SIGSYS: u16 = 31;
+
+const fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+/// The POSIX signal gdb will report as the reason the child stopped.
+fn signal_for(fault: &Fault) -> u16 {
+    match fault {
+        Fault::VMFault(_) => SIGSEGV,
+        Fault::UserException(_) => SIGILL,
+        Fault::UnknownSyscall(_) => SIGSYS,
+        _ => SIGABRT,
+    }
+}
+
+/// Write a core file describing a thread with the given `registers` which
+/// stopped because of `fault`, including the contents of `regions`. It is
+/// streamed out in a single pass, so it never has to fit in memory.
+pub fn write_core_dump<S: ByteSink>(
+    registers: &seL4_UserContext,
+    fault: Option<&Fault>,
+    regions: &[CoreDumpRegion],
+    sink: &mut S,
+) -> Result<(), S::Error> {
+    let page_size = PageBytes::USIZE;
+    let mut out = Emitter { sink, written: 0 };
+
+    let program_header_count = 1 + regions.len();
+    let note_offset = ELF_HEADER_SIZE + program_header_count * PROGRAM_HEADER_SIZE;
+    let first_segment_offset = align_up(note_offset + NOTE_SIZE, page_size);
+
+    // ELF header
+    out.bytes(&[0x7f, b'E', b'L', b'F', ELF_CLASS, 1, 1, 0])?; // LSB, EV_CURRENT, SYSV
+    out.zeroes(8)?;
+    out.u16(ET_CORE)?;
+    out.u16(ELF_MACHINE)?;
+    out.u32(1)?; // e_version
+    out.word(0)?; // e_entry
+    out.word(ELF_HEADER_SIZE)?; // e_phoff
+    out.word(0)?; // e_shoff
+    out.u32(0)?; // e_flags
+    out.u16(ELF_HEADER_SIZE as u16)?;
+    out.u16(PROGRAM_HEADER_SIZE as u16)?;
+    out.u16(program_header_count as u16)?;
+    out.u16(0)?; // e_shentsize
+    out.u16(0)?; // e_shnum
+    out.u16(0)?; // e_shstrndx
+
+    // Program headers
+    out.program_header(PT_NOTE, 0, note_offset, 0, NOTE_SIZE, NOTE_SIZE, 4)?;
+    let mut segment_offset = first_segment_offset;
+    for region in regions {
+        // Pages are mapped executable unless asked otherwise, so read-only
+        // regions are most likely code
+        let flags = if region.rights.is_writable() {
+            PF_R | PF_W
+        } else {
+            PF_R | PF_X
+        };
+        out.program_header(
+            PT_LOAD,
+            flags,
+            segment_offset,
+            region.vaddr,
+            region.data.len(),
+            region.data.len(),
+            page_size,
+        )?;
+        segment_offset += align_up(region.data.len(), page_size);
+    }
+
+    // The NT_PRSTATUS note
+    out.u32(NOTE_NAME_SIZE)?;
+    out.u32(PRSTATUS_SIZE as u32)?;
+    out.u32(NT_PRSTATUS)?;
+    out.bytes(NOTE_NAME)?;
+    let signal = fault.map_or(0, signal_for);
+    out.u32(u32::from(signal))?; // pr_info.si_signo
+    out.zeroes(2 * 4)?; // pr_info.si_code, si_errno
+    out.u16(signal)?; // pr_cursig
+    out.zeroes(2 + 2 * WORD)?; // padding, pr_sigpend, pr_sighold
+    out.u32(1)?; // pr_pid
+    out.zeroes(3 * 4 + 4 * 2 * WORD)?; // pr_ppid, pr_pgrp, pr_sid, times
+    for r in general_registers(registers).iter() {
+        out.word(*r)?;
+    }
+    out.zeroes(
+        align_up(PRSTATUS_SIZE, 4) - (PRSTATUS_REGISTERS_OFFSET + GENERAL_REGISTER_COUNT * WORD),
+    )?;
+
+    // Segment contents
+    out.zeroes(first_segment_offset - out.written)?;
+    for region in regions {
+        out.bytes(region.data)?;
+        out.zeroes(align_up(region.data.len(), page_size) - region.data.len())?;
+    }
+    Ok(())
+}
+
+struct Emitter<'s, S: ByteSink> {
+    sink: &'s mut S,
+    written: usize,
+}
+
+impl<'s, S: ByteSink> Emitter<'s, S> {
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), S::Error> {
+        self.written += bytes.len();
+        self.sink.write_all(bytes)
+    }
+
+    fn zeroes(&mut self, mut count: usize) -> Result<(), S::Error> {
+        const ZEROES: [u8; 64] = [0; 64];
+        while count > 0 {
+            let n = cmp::min(count, ZEROES.len());
+            self.bytes(&ZEROES[..n])?;
+            count -= n;
+        }
+        Ok(())
+    }
+
+    fn u16(&mut self, value: u16) -> Result<(), S::Error> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn u32(&mut self, value: u32) -> Result<(), S::Error> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn word(&mut self, value: usize) -> Result<(), S::Error> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    fn program_header(
+        &mut self,
+        kind: u32,
+        flags: u32,
+        offset: usize,
+        vaddr: usize,
+        file_size: usize,
+        mem_size: usize,
+        align: usize,
+    ) -> Result<(), S::Error> {
+        self.u32(kind)?;
+        self.word(offset)?;
+        self.word(vaddr)?;
+        self.word(0)?; // p_paddr
+        self.word(file_size)?;
+        self.word(mem_size)?;
+        self.u32(flags)?;
+        self.word(align)
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    fn program_header(
+        &mut self,
+        kind: u32,
+        flags: u32,
+        offset: usize,
+        vaddr: usize,
+        file_size: usize,
+        mem_size: usize,
+        align: usize,
+    ) -> Result<(), S::Error> {
+        self.u32(kind)?;
+        self.u32(flags)?;
+        self.word(offset)?;
+        self.word(vaddr)?;
+        self.word(0)?; // p_paddr
+        self.word(file_size)?;
+        self.word(mem_size)?;
+        self.word(align)
+    }
+}
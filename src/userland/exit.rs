@@ -0,0 +1,218 @@
+//! Orderly shutdown of child processes, reported over the endpoint the
+//! kernel delivers their faults on so the parent waits in one place.
+use core::fmt;
+
+use crate::arch::fault::Fault;
+use crate::cap::{
+    role, CNodeRole, CNodeSlot, ChildCNodeSlot, DirectRetype, Endpoint, LocalCNode, LocalCNodeSlot,
+    LocalCap, Untyped,
+};
+use crate::userland::{
+    fault_or_message_channel, yield_forever, FaultManagementError, FaultOrMessage,
+    FaultOrMessageHandler, FaultSource, IPCError, RetypeForSetup, Sender,
+};
+
+/// How many bytes of the panicking source file's path are kept.
+pub const PANIC_FILE_CAPACITY: usize = 64;
+/// How many bytes of the formatted panic message are kept.
+pub const PANIC_MESSAGE_CAPACITY: usize = 192;
+
+/// How a child process came to an end.
+#[derive(Debug)]
+pub enum ProcessOutcome {
+    /// The child called `ExitReporter::exit` with this status code.
+    Exited(i32),
+    /// The kernel delivered a fault on the child's behalf.
+    Faulted(Fault),
+    /// The child panicked and managed to report where and why.
+    Panicked(PanicReport),
+}
+
+/// A fixed-size record of a panic's location and message, small enough to
+/// travel in a single IPC message. Over-long paths and messages are
+/// truncated.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct PanicReport {
+    file: [u8; PANIC_FILE_CAPACITY],
+    file_len: usize,
+    line: u32,
+    column: u32,
+    message: [u8; PANIC_MESSAGE_CAPACITY],
+    message_len: usize,
+}
+
+impl PanicReport {
+    pub fn new(file: &str, line: u32, column: u32, message: fmt::Arguments) -> Self {
+        let mut report = PanicReport {
+            file: [0; PANIC_FILE_CAPACITY],
+            file_len: 0,
+            line,
+            column,
+            message: [0; PANIC_MESSAGE_CAPACITY],
+            message_len: 0,
+        };
+        let mut file_writer = TruncatingWriter {
+            buffer: &mut report.file,
+            len: 0,
+        };
+        let _ = fmt::Write::write_str(&mut file_writer, file);
+        report.file_len = file_writer.len;
+
+        let mut message_writer = TruncatingWriter {
+            buffer: &mut report.message,
+            len: 0,
+        };
+        let _ = fmt::write(&mut message_writer, message);
+        report.message_len = message_writer.len;
+        report
+    }
+
+    pub fn file(&self) -> &str {
+        core::str::from_utf8(&self.file[..self.file_len]).unwrap_or("")
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+
+    pub fn message(&self) -> &str {
+        core::str::from_utf8(&self.message[..self.message_len]).unwrap_or("")
+    }
+}
+
+impl fmt::Debug for PanicReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PanicReport")
+            .field("file", &self.file())
+            .field("line", &self.line)
+            .field("column", &self.column)
+            .field("message", &self.message())
+            .finish()
+    }
+}
+
+impl fmt::Display for PanicReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "panicked at '{}', {}:{}:{}",
+            self.message(),
+            self.file(),
+            self.line,
+            self.column
+        )
+    }
+}
+
+/// Fills a byte buffer with as much of the written text as fits, stopping
+/// short at a character boundary so the result is always valid UTF-8.
+struct TruncatingWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> fmt::Write for TruncatingWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let space = self.buffer.len() - self.len;
+        let mut take = core::cmp::min(space, s.len());
+        while !s.is_char_boundary(take) {
+            take -= 1;
+        }
+        self.buffer[self.len..self.len + take].copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take;
+        if take < s.len() {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+enum ExitMessage {
+    Exited(i32),
+    Panicked(PanicReport),
+}
+
+/// Wire up a child process to report its end to `handler_slot`'s owner.
+///
+/// The `FaultSource` should be handed to the child's process constructor so
+/// that its faults are routed to the same place, and the `ExitReporter`
+/// passed to the child as part of its process parameters.
+pub fn exit_channel<HandlerRole: CNodeRole>(
+    local_cnode: &LocalCap<LocalCNode>,
+    untyped: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
+    endpoint_slot: LocalCNodeSlot,
+    fault_source_slot: ChildCNodeSlot,
+    handler_slot: CNodeSlot<HandlerRole>,
+) -> Result<
+    (
+        FaultSource<role::Child>,
+        ExitReporter<role::Child>,
+        ExitWatcher<HandlerRole>,
+    ),
+    FaultManagementError,
+> {
+    let (fault_source, sender, handler) = fault_or_message_channel(
+        local_cnode,
+        untyped,
+        endpoint_slot,
+        fault_source_slot,
+        handler_slot,
+    )?;
+    Ok((
+        fault_source,
+        ExitReporter { sender },
+        ExitWatcher { handler },
+    ))
+}
+
+/// The child's side of an exit channel.
+pub struct ExitReporter<Role: CNodeRole> {
+    sender: Sender<ExitMessage, Role>,
+}
+
+impl RetypeForSetup for ExitReporter<role::Local> {
+    type Output = ExitReporter<role::Child>;
+}
+
+impl ExitReporter<role::Local> {
+    /// Report a successful exit with `code` and stop doing anything useful.
+    ///
+    /// Returns only once the parent has received the code; after that the
+    /// parent is expected to suspend or tear down this process.
+    pub fn exit(&self, code: i32) -> ! {
+        let _ = self.sender.blocking_send(&ExitMessage::Exited(code));
+        yield_forever()
+    }
+
+    /// Report a panic and stop doing anything useful.
+    pub fn panicked(&self, report: &PanicReport) -> ! {
+        let _ = self.sender.blocking_send(&ExitMessage::Panicked(*report));
+        yield_forever()
+    }
+}
+
+/// The parent's side of an exit channel.
+pub struct ExitWatcher<Role: CNodeRole> {
+    handler: FaultOrMessageHandler<ExitMessage, Role>,
+}
+
+impl ExitWatcher<role::Local> {
+    /// Block until the child exits, panics or faults.
+    pub fn wait(&self) -> Result<ProcessOutcome, IPCError> {
+        Ok(match self.handler.await_message()? {
+            FaultOrMessage::Fault(fault) => ProcessOutcome::Faulted(fault),
+            FaultOrMessage::Message(ExitMessage::Exited(code)) => ProcessOutcome::Exited(code),
+            FaultOrMessage::Message(ExitMessage::Panicked(report)) => {
+                ProcessOutcome::Panicked(report)
+            }
+        })
+    }
+}
@@ -4,12 +4,12 @@ use selfe_sys::*;
 
 use crate::arch;
 use crate::cap::{
-    role, Badge, CNode, CNodeRole, CNodeSlot, Cap, DirectRetype, Endpoint, LocalCNode,
+    role, Badge, BadgeBits, CNode, CNodeRole, CNodeSlot, Cap, DirectRetype, Endpoint, LocalCNode,
     LocalCNodeSlot, LocalCNodeSlots, LocalCap, Notification, Untyped,
 };
 use crate::error::SeL4Error;
 use crate::userland::multi_consumer::WakerSetup;
-use crate::userland::shared_memory_ipc::WAKER_BADGE;
+use crate::userland::shared_memory_ipc::WakerBadgeBit;
 use crate::userland::CapRights;
 use crate::vspace::VSpaceError;
 use typenum::U2;
@@ -95,6 +95,10 @@ pub fn call_channel_with_waker<Req: Send + Sync, Rsp: Send + Sync, ResponderRole
     let (local_slot, _local_slots) = local_slots.alloc();
     let notification: LocalCap<Notification> = notification_ut.retype(local_slot)?;
 
+    // Keep the same badge layout as the extended call channel's waker.
+    let (_, waker_bits) = BadgeBits::new().alloc();
+    let (waker_bit, _waker_bits): (WakerBadgeBit, _) = waker_bits.alloc();
+
     Ok((
         IpcSetup {
             endpoint: local_endpoint,
@@ -110,7 +114,7 @@ pub fn call_channel_with_waker<Req: Send + Sync, Rsp: Send + Sync, ResponderRole
         },
         Cap::wrap_cptr(notification.cptr),
         WakerSetup {
-            interrupt_badge: Badge::from(WAKER_BADGE),
+            interrupt_badge: waker_bit.badge(),
             notification,
         },
     ))
@@ -131,6 +135,25 @@ impl<'a, Req, Rsp> IpcSetup<'a, Req, Rsp> {
             _rsp: PhantomData,
         })
     }
+
+    /// Like `create_caller`, but the caller's endpoint carries `badge`, so
+    /// that a responder using `reply_recv_with_badge` can tell callers
+    /// apart.
+    pub fn create_badged_caller<Role: CNodeRole>(
+        &self,
+        caller_slot: CNodeSlot<Role>,
+        badge: Badge,
+    ) -> Result<Caller<Req, Rsp, Role>, IPCError> {
+        let caller_endpoint =
+            self.endpoint
+                .mint(self.endpoint_cnode, caller_slot, CapRights::RWG, badge)?;
+
+        Ok(Caller {
+            endpoint: caller_endpoint,
+            _req: PhantomData,
+            _rsp: PhantomData,
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -432,6 +455,48 @@ impl<Req, Rsp> Responder<Req, Rsp, role::Local> {
         }
     }
 
+    /// Serve requests forever, passing `f` the badge of the caller along
+    /// with each request. Unlike `reply_recv_with_notification`, every
+    /// message is taken to be a request, badged or not, so callers should
+    /// be made with `IpcSetup::create_badged_caller`.
+    pub fn reply_recv_with_badge<F>(self, mut f: F) -> Result<Rsp, IPCError>
+    where
+        F: FnMut(Badge, Req) -> Rsp,
+    {
+        // Can safely use unchecked_new because we check sizing during the creation of
+        // Responder
+        let mut ipc_buffer = unsafe { IPCBuffer::unchecked_new() };
+        let mut sender_badge: usize = 0;
+        // Do a regular receive to seed our initial value
+        let mut msg_info: MessageInfo =
+            unsafe { seL4_Recv(self.endpoint.cptr, &mut sender_badge as *mut usize) }.into();
+
+        let request_length_in_words = type_length_in_words::<Req>();
+        loop {
+            if msg_info.length_words() != request_length_in_words {
+                // See `reply_recv_with_notification`. Rather than spinning on
+                // the bad message, drop it and wait for the next one, leaving
+                // its sender blocked.
+                debug_println!("Request size incoming ({} words) does not match static size expectation ({} words).",
+                msg_info.length_words(), request_length_in_words);
+                msg_info =
+                    unsafe { seL4_Recv(self.endpoint.cptr, &mut sender_badge as *mut usize) }
+                        .into();
+                continue;
+            }
+            let response = f(Badge::from(sender_badge), ipc_buffer.copy_req_from_buffer());
+            ipc_buffer.copy_rsp_into_buffer(&response);
+            msg_info = unsafe {
+                seL4_ReplyRecv(
+                    self.endpoint.cptr,
+                    type_length_message_info::<Rsp>(),
+                    &mut sender_badge as *mut usize,
+                )
+            }
+            .into();
+        }
+    }
+
     pub fn recv_reply_once<F>(&self, mut f: F) -> Result<(), IPCError>
     where
         F: FnMut(Req) -> Rsp,
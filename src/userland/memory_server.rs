@@ -0,0 +1,401 @@
+//! A memory server, which hands out untyped memory while the system runs
+//! rather than having the root task decide up front how much each child
+//! gets.
+use core::marker::PhantomData;
+
+use arrayvec::ArrayVec;
+use typenum::*;
+
+use crate::alloc::ut_buddy::UTBuddyError;
+use crate::alloc::WUTBuddy;
+use crate::arch::{MaxUntypedSize, MinUntypedSize, PageBits};
+use crate::cap::{
+    memory_kind, role, Badge, Cap, LocalCNode, LocalCap, RecycledSlots, WCNodeSlots,
+    WCNodeSlotsData, WUntyped,
+};
+use crate::userland::{CapRights, IPCError, Responder};
+use crate::vspace::{
+    memory_attributes, shared_status, MemoryAttributes, VSpace, WeakMappedMemoryRegion,
+    WeakMemoryRegion,
+};
+
+/// The most clients one `MemoryServer` serves.
+pub const MAX_MEMORY_CLIENTS: usize = 8;
+
+/// The most grants a client can hold at once.
+pub const MAX_GRANTS_PER_CLIENT: usize = 16;
+
+#[derive(Debug)]
+pub enum MemoryServerError {
+    /// Unbadged messages can't be told apart, so clients need a badge.
+    ZeroBadge,
+    DuplicateBadge,
+    TooManyClients,
+}
+
+/// What a client can ask the memory server for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryRequest {
+    /// `count` pages, mapped read-write at `vaddr`, which must be aligned
+    /// to the size of the grant. Grants are whole untypeds, so `count` is
+    /// rounded up to a power of two, and charged to the quota at that size.
+    Pages { count: usize, vaddr: usize },
+    /// An untyped of `1 << size_bits` bytes, placed in the client's CSpace.
+    Untyped { size_bits: u8 },
+    /// Give back a grant. Pages are unmapped, and anything retyped from
+    /// an untyped is revoked.
+    Return { grant: usize },
+    /// How much of its quota the client has used.
+    Quota,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryResponse {
+    Pages {
+        grant: usize,
+    },
+    /// `cptr` is where the untyped was placed in the client's CSpace.
+    Untyped {
+        grant: usize,
+        cptr: usize,
+    },
+    Returned,
+    Quota {
+        used: usize,
+        limit: usize,
+    },
+    Refused(MemoryRefusal),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryRefusal {
+    QuotaExceeded,
+    OutOfMemory,
+    /// The server has no slots left for the pages, or the client has none
+    /// left for the untyped.
+    OutOfSlots,
+    UnknownClient,
+    UnknownGrant,
+    TooManyGrants,
+    MappingFailed,
+    InvalidRequest,
+    /// The memory could not be given back to the server's allocator. The
+    /// grant is gone, but still counts against the quota.
+    NotReclaimed,
+}
+
+impl From<UTBuddyError> for MemoryRefusal {
+    fn from(e: UTBuddyError) -> Self {
+        match e {
+            UTBuddyError::NotEnoughSlots => MemoryRefusal::OutOfSlots,
+            _ => MemoryRefusal::OutOfMemory,
+        }
+    }
+}
+
+enum GrantKind {
+    Pages(WeakMappedMemoryRegion<shared_status::Exclusive>),
+    /// Copied into the client's CSpace; revoking the server's cap deletes
+    /// the copy.
+    Untyped,
+}
+
+struct Grant {
+    /// The server's cap to the untyped, which doubles as the grant's id.
+    untyped: usize,
+    size_bits: u8,
+    /// The first of the server's slots holding the pages, or the client's
+    /// slot holding the untyped.
+    first_slot: usize,
+    kind: GrantKind,
+}
+
+struct Client {
+    badge: Badge,
+    quota: usize,
+    used: usize,
+    vspace: VSpace,
+    slots: LocalCap<WCNodeSlotsData<role::Child>>,
+    recycled_slots: RecycledSlots<role::Child>,
+    grants: ArrayVec<[Grant; MAX_GRANTS_PER_CLIENT]>,
+}
+
+/// Serves untyped memory to badged clients, each within its own quota.
+/// The slots of returned grants are reused, as are, by the `WUTBuddy`,
+/// those of splits merged again.
+pub struct MemoryServer<'a> {
+    untyped: WUTBuddy,
+    slots: WCNodeSlots,
+    recycled_slots: RecycledSlots<role::Local>,
+    cnode: &'a LocalCap<LocalCNode>,
+    clients: ArrayVec<[Client; MAX_MEMORY_CLIENTS]>,
+}
+
+impl<'a> MemoryServer<'a> {
+    /// Serve memory from `untyped`, using `slots` for the splits and
+    /// page caps it needs along the way.
+    pub fn new(untyped: WUTBuddy, slots: WCNodeSlots, cnode: &'a LocalCap<LocalCNode>) -> Self {
+        MemoryServer {
+            untyped,
+            slots,
+            recycled_slots: RecycledSlots::new(),
+            cnode,
+            clients: ArrayVec::new(),
+        }
+    }
+
+    /// Serve the client calling with `badge` up to `quota_bytes` at once.
+    /// Pages are mapped into `vspace`, and untypeds placed in `slots`.
+    pub fn add_client(
+        &mut self,
+        badge: Badge,
+        quota_bytes: usize,
+        vspace: VSpace,
+        slots: LocalCap<WCNodeSlotsData<role::Child>>,
+    ) -> Result<(), MemoryServerError> {
+        if badge == Badge::from(0) {
+            return Err(MemoryServerError::ZeroBadge);
+        }
+        if self.clients.iter().any(|c| c.badge == badge) {
+            return Err(MemoryServerError::DuplicateBadge);
+        }
+        self.clients
+            .try_push(Client {
+                badge,
+                quota: quota_bytes,
+                used: 0,
+                vspace,
+                slots,
+                recycled_slots: RecycledSlots::new(),
+                grants: ArrayVec::new(),
+            })
+            .map_err(|_| MemoryServerError::TooManyClients)
+    }
+
+    /// Answer one request from the client calling with `badge`.
+    pub fn handle(&mut self, badge: Badge, request: MemoryRequest) -> MemoryResponse {
+        let client = match self.clients.iter_mut().find(|c| c.badge == badge) {
+            Some(client) => client,
+            None => return MemoryResponse::Refused(MemoryRefusal::UnknownClient),
+        };
+        let result = match request {
+            MemoryRequest::Pages { count, vaddr } => client.grant_pages(
+                &mut self.untyped,
+                &mut self.slots,
+                &mut self.recycled_slots,
+                self.cnode,
+                count,
+                vaddr,
+            ),
+            MemoryRequest::Untyped { size_bits } => {
+                client.grant_untyped(&mut self.untyped, &mut self.slots, self.cnode, size_bits)
+            }
+            MemoryRequest::Return { grant } => client.take_back(
+                &mut self.untyped,
+                &mut self.recycled_slots,
+                self.cnode,
+                grant,
+            ),
+            MemoryRequest::Quota => Ok(MemoryResponse::Quota {
+                used: client.used,
+                limit: client.quota,
+            }),
+        };
+        result.unwrap_or_else(MemoryResponse::Refused)
+    }
+
+    /// Answer requests arriving at `responder` forever.
+    pub fn serve(
+        mut self,
+        responder: Responder<MemoryRequest, MemoryResponse, role::Local>,
+    ) -> Result<MemoryResponse, IPCError> {
+        responder.reply_recv_with_badge(move |badge, request| self.handle(badge, request))
+    }
+}
+
+impl Client {
+    fn grant_pages(
+        &mut self,
+        untyped: &mut WUTBuddy,
+        slots: &mut WCNodeSlots,
+        recycled_slots: &mut RecycledSlots<role::Local>,
+        cnode: &LocalCap<LocalCNode>,
+        count: usize,
+        vaddr: usize,
+    ) -> Result<MemoryResponse, MemoryRefusal> {
+        if count == 0 || count > 1 << (MaxUntypedSize::USIZE - PageBits::USIZE) {
+            return Err(MemoryRefusal::InvalidRequest);
+        }
+        let size_bits = PageBits::U8 + count.next_power_of_two().trailing_zeros() as u8;
+        if vaddr & ((1 << size_bits) - 1) != 0 {
+            return Err(MemoryRefusal::InvalidRequest);
+        }
+        self.check_room(size_bits)?;
+
+        let ut = untyped.alloc(slots, size_bits)?;
+        let ut_cptr = ut.cptr;
+        let page_count = 1 << (size_bits - PageBits::U8);
+        let mut page_slots = match recycled_slots.alloc(slots, page_count) {
+            Ok(page_slots) => page_slots,
+            Err(_) => {
+                let _ = untyped.free(ut, cnode);
+                return Err(MemoryRefusal::OutOfSlots);
+            }
+        };
+        let first_slot = page_slots.cap_data.offset;
+        let region = match WeakMemoryRegion::new(ut, &mut page_slots) {
+            Ok(region) => region,
+            Err(_) => {
+                if untyped
+                    .free(general_untyped(ut_cptr, size_bits), cnode)
+                    .is_ok()
+                {
+                    recycled_slots.put_back(first_slot, page_count);
+                }
+                return Err(MemoryRefusal::OutOfMemory);
+            }
+        };
+        let region = match self.vspace.weak_map_region_at_addr(
+            region,
+            vaddr,
+            CapRights::RW,
+            memory_attributes::Cached.vm_attributes(),
+        ) {
+            Ok(region) => region,
+            Err(_) => {
+                // Revoking the untyped deletes the page caps made from it.
+                if untyped
+                    .free(general_untyped(ut_cptr, size_bits), cnode)
+                    .is_ok()
+                {
+                    recycled_slots.put_back(first_slot, page_count);
+                }
+                return Err(MemoryRefusal::MappingFailed);
+            }
+        };
+
+        self.grants.push(Grant {
+            untyped: ut_cptr,
+            size_bits,
+            first_slot,
+            kind: GrantKind::Pages(region),
+        });
+        self.used += 1 << size_bits;
+        Ok(MemoryResponse::Pages { grant: ut_cptr })
+    }
+
+    fn grant_untyped(
+        &mut self,
+        untyped: &mut WUTBuddy,
+        slots: &mut WCNodeSlots,
+        cnode: &LocalCap<LocalCNode>,
+        size_bits: u8,
+    ) -> Result<MemoryResponse, MemoryRefusal> {
+        if size_bits < MinUntypedSize::U8 || size_bits > MaxUntypedSize::U8 {
+            return Err(MemoryRefusal::InvalidRequest);
+        }
+        self.check_room(size_bits)?;
+        let mut dest_slot = self
+            .recycled_slots
+            .alloc(&mut self.slots, 1)
+            .map_err(|_| MemoryRefusal::OutOfSlots)?;
+        let first_slot = dest_slot.cap_data.offset;
+
+        let ut = match untyped.alloc(slots, size_bits) {
+            Ok(ut) => ut,
+            Err(e) => {
+                self.recycled_slots.put_back(first_slot, 1);
+                return Err(e.into());
+            }
+        };
+        let copied = dest_slot
+            .alloc_strong::<U1>()
+            .map_err(|_| ())
+            .and_then(|dest_slot| {
+                ut.unchecked_copy(cnode, dest_slot, CapRights::RWG)
+                    .map_err(|_| ())
+            });
+        let cptr = match copied {
+            Ok(cptr) => cptr,
+            Err(()) => {
+                let _ = untyped.free(ut, cnode);
+                self.recycled_slots.put_back(first_slot, 1);
+                return Err(MemoryRefusal::OutOfSlots);
+            }
+        };
+
+        self.grants.push(Grant {
+            untyped: ut.cptr,
+            size_bits,
+            first_slot,
+            kind: GrantKind::Untyped,
+        });
+        self.used += 1 << size_bits;
+        Ok(MemoryResponse::Untyped {
+            grant: ut.cptr,
+            cptr,
+        })
+    }
+
+    fn take_back(
+        &mut self,
+        untyped: &mut WUTBuddy,
+        recycled_slots: &mut RecycledSlots<role::Local>,
+        cnode: &LocalCap<LocalCNode>,
+        grant: usize,
+    ) -> Result<MemoryResponse, MemoryRefusal> {
+        let index = self
+            .grants
+            .iter()
+            .position(|g| g.untyped == grant)
+            .ok_or(MemoryRefusal::UnknownGrant)?;
+        let grant = self.grants.remove(index);
+        let is_pages = match grant.kind {
+            GrantKind::Pages(region) => {
+                // Should this fail, revoking the untyped below still deletes
+                // the frames, and with them the mappings.
+                let _ = self.vspace.weak_unmap_region(region);
+                true
+            }
+            GrantKind::Untyped => false,
+        };
+        untyped
+            .free(general_untyped(grant.untyped, grant.size_bits), cnode)
+            .map_err(|_| MemoryRefusal::NotReclaimed)?;
+
+        // Revoking the untyped emptied the slots of everything made or
+        // copied from it.
+        if is_pages {
+            recycled_slots.put_back(grant.first_slot, 1 << (grant.size_bits - PageBits::U8));
+        } else {
+            self.recycled_slots.put_back(grant.first_slot, 1);
+        }
+        self.used -= 1 << grant.size_bits;
+        Ok(MemoryResponse::Returned)
+    }
+
+    /// Whether another grant of `size_bits` fits in the quota, and there is
+    /// room to keep track of it.
+    fn check_room(&self, size_bits: u8) -> Result<(), MemoryRefusal> {
+        if self.used + (1 << size_bits) > self.quota {
+            return Err(MemoryRefusal::QuotaExceeded);
+        }
+        if self.grants.is_full() {
+            return Err(MemoryRefusal::TooManyGrants);
+        }
+        Ok(())
+    }
+}
+
+/// The server's cap to a granted untyped, rebuilt from what was recorded
+/// about it.
+fn general_untyped(cptr: usize, size_bits: u8) -> LocalCap<WUntyped<memory_kind::General>> {
+    Cap {
+        cptr,
+        cap_data: WUntyped {
+            size_bits,
+            kind: memory_kind::General,
+        },
+        _role: PhantomData,
+    }
+}
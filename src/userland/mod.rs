@@ -1,15 +1,23 @@
+mod core_dump;
+mod exit;
 mod fault;
 mod ipc;
 mod irq;
+mod memory_server;
 mod multi_consumer;
 pub(crate) mod process;
-mod rights;
+pub(crate) mod rights;
 mod shared_memory_ipc;
+pub(crate) mod vm_fault;
 
+pub use crate::userland::core_dump::*;
+pub use crate::userland::exit::*;
 pub use crate::userland::fault::*;
 pub use crate::userland::ipc::*;
 pub use crate::userland::irq::*;
+pub use crate::userland::memory_server::*;
 pub use crate::userland::multi_consumer::*;
 pub use crate::userland::process::*;
 pub use crate::userland::rights::*;
 pub use crate::userland::shared_memory_ipc::*;
+pub use crate::userland::vm_fault::*;
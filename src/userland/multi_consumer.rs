@@ -44,18 +44,18 @@ use generic_array::ArrayLength;
 use selfe_sys::{seL4_Signal, seL4_Wait};
 use typenum::*;
 
-use crate::arch::{self, PageBits};
+use crate::arch::PageBits;
 use crate::cap::{
-    irq_state, role, Badge, CNodeRole, CNodeSlot, Cap, ChildCNodeSlot, ChildCNodeSlots,
-    DirectRetype, IRQControl, IRQError, IRQHandler, InternalASID, LocalCNode, LocalCNodeSlot,
-    LocalCNodeSlots, LocalCap, MaxIRQCount, Notification, PhantomCap, Untyped,
+    irq_state, role, Badge, BadgeBit, BadgeBits, CNodeRole, CNodeSlot, Cap, ChildCNodeSlot,
+    ChildCNodeSlots, DirectRetype, IRQControl, IRQError, IRQHandler, InternalASID, LocalCNode,
+    LocalCNodeSlot, LocalCNodeSlots, LocalCap, MaxIRQCount, Notification, PhantomCap, Untyped,
 };
 use crate::error::SeL4Error;
 use crate::pow::{Pow, _Pow};
 use crate::userland::CapRights;
 use crate::vspace::{
-    shared_status, KernelRetypeFanOutLimit, MappedMemoryRegion, NumPages, ScratchRegion,
-    UnmappedMemoryRegion, VSpace, VSpaceError,
+    memory_attributes, shared_status, GuardKind, KernelRetypeFanOutLimit, MappedMemoryRegion,
+    NumPages, ScratchRegion, UnmappedMemoryRegion, VSpace, VSpaceError,
 };
 
 /// A multi-consumer that consumes interrupt-style notifications
@@ -183,6 +183,29 @@ impl From<VSpaceError> for MultiConsumerError {
     }
 }
 
+/// The badge bit layout shared by every multi-consumer notification: the
+/// interrupt (or waker) path comes first, then one bit per queue in the
+/// order the queues are added.
+struct BadgeLayout {
+    interrupt: BadgeBit<U0>,
+    queues: (BadgeBit<U1>, BadgeBit<U2>, BadgeBit<U3>, BadgeBit<U4>),
+}
+
+impl BadgeLayout {
+    fn new() -> Self {
+        let bits = BadgeBits::new();
+        let (interrupt, bits) = bits.alloc();
+        let (e, bits) = bits.alloc();
+        let (f, bits) = bits.alloc();
+        let (g, bits) = bits.alloc();
+        let (h, _bits) = bits.alloc();
+        BadgeLayout {
+            interrupt,
+            queues: (e, f, g, h),
+        }
+    }
+}
+
 /// Wrapper around the necessary resources
 /// to add a new producer to a given queue
 /// ingested by a multi-consumer (e.g. `Consumer1`)
@@ -241,7 +264,7 @@ where
         let (local_slot, local_slots) = local_slots.alloc();
         let unbadged_notification: LocalCap<Notification> = notification_ut.retype(local_slot)?;
 
-        let interrupt_badge = Badge::from(1);
+        let interrupt_badge = BadgeLayout::new().interrupt.badge();
 
         let (local_slot, local_slots) = local_slots.alloc();
         let notification =
@@ -289,7 +312,7 @@ where
         let (local_slot, local_slots) = local_slots.alloc();
         let unbadged_notification: LocalCap<Notification> = notification_ut.retype(local_slot)?;
 
-        let interrupt_badge = Badge::from(1);
+        let interrupt_badge = BadgeLayout::new().interrupt.badge();
 
         let (local_slot, local_slots) = local_slots.alloc();
         let notification =
@@ -384,9 +407,7 @@ where
             )?;
         consumer_token.consumer_vspace_asid = Some(consumer_vspace.asid());
 
-        // Assumes we are using the one-hot style for identifying the interrupt badge
-        // index
-        let fresh_queue_badge = Badge::from(self.interrupt_badge.inner << 1);
+        let fresh_queue_badge = (BadgeLayout::new().queues.0).badge();
         let producer_setup: ProducerSetup<E, ELen, EQueueSizeBits> = ProducerSetup {
             consumer_vspace_asid: consumer_vspace.asid(),
             shared_region,
@@ -480,8 +501,9 @@ where
             CapRights::RWG,
             Badge::from(0x00), // Only for Wait'ing, no need to set badge bits
         )?;
-        let interrupt_badge = Badge::from(1 << 0);
-        let queue_badge = Badge::from(1 << 1);
+        let layout = BadgeLayout::new();
+        let interrupt_badge = layout.interrupt.badge();
+        let queue_badge = (layout.queues.0).badge();
 
         let producer_setup: ProducerSetup<E, ELen, EQueueSizeBits> = ProducerSetup {
             consumer_vspace_asid: consumer_vspace.asid(),
@@ -588,7 +610,7 @@ where
                 shared_slots,
             )?;
 
-        let fresh_queue_badge = Badge::from(self.queue_badge.inner << 1);
+        let fresh_queue_badge = (BadgeLayout::new().queues.1).badge();
         let producer_setup: ProducerSetup<F, FLen, FQueueSizeBits> = ProducerSetup {
             consumer_vspace_asid: consumer_vspace.asid(),
             shared_region,
@@ -689,7 +711,7 @@ where
                 shared_slots,
             )?;
 
-        let fresh_queue_badge = Badge::from((self.queues.1).0.inner << 1);
+        let fresh_queue_badge = (BadgeLayout::new().queues.2).badge();
         let producer_setup: ProducerSetup<G, GLen, GQueueSizeBits> = ProducerSetup {
             consumer_vspace_asid: consumer_vspace.asid(),
             shared_region,
@@ -791,7 +813,7 @@ where
                 shared_slots,
             )?;
 
-        let fresh_queue_badge = Badge::from((self.queues.1).0.inner << 1);
+        let fresh_queue_badge = (BadgeLayout::new().queues.3).badge();
         let producer_setup: ProducerSetup<H, HLen, HQueueSizeBits> = ProducerSetup {
             consumer_vspace_asid: consumer_vspace.asid(),
             shared_region,
@@ -887,15 +909,20 @@ where
 
     // put guard pages on either side of the shared region, so any overruns
     // become page faults instead of data corruption.
-    consumer_vspace.skip_pages(1)?;
-    let consumer_shared_region = consumer_vspace.map_shared_region(
+    let guard_pages = core::cmp::max(1, consumer_vspace.guard_spans().region_pages);
+    let guard = consumer_vspace.place_guard_pages_below(GuardKind::Region, guard_pages)?;
+    let consumer_shared_region = consumer_vspace.map_shared_region_unguarded(
         &shared_region,
         CapRights::RW,
-        arch::vm_attributes::DEFAULT,
+        memory_attributes::Cached,
         shared_slots,
         local_cnode,
     )?;
-    consumer_vspace.skip_pages(1)?;
+    consumer_vspace.place_guard_above(
+        guard,
+        consumer_shared_region.vaddr(),
+        consumer_shared_region.size_bytes(),
+    )?;
 
     Ok((shared_region, consumer_shared_region))
 }
@@ -1388,7 +1415,7 @@ impl<T: Sized + Sync + Send, Role: CNodeRole> Producer<Role, T> {
         let producer_shared_region = dest_vspace.map_shared_region(
             &setup.shared_region,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT,
+            memory_attributes::Cached,
             local_slots,
             local_cnode,
         )?;
@@ -10,7 +10,10 @@ use crate::vspace::VSpaceError;
 pub(crate) use crate::arch::userland::process::*;
 
 mod thread;
-pub use thread::{Thread, ThreadSetupError};
+pub use thread::{JoinHandle, JoinableParams, JoinableThread, Thread, ThreadSetupError};
+
+mod tls;
+pub use tls::TLSImage;
 
 mod standard;
 pub use standard::StandardProcess;
@@ -4,7 +4,7 @@ use typenum::*;
 
 use selfe_sys::*;
 
-use crate::arch::{self, PageBits};
+use crate::arch::PageBits;
 use crate::cap::{
     role, CNodeRole, CNodeSlotsError, Cap, ChildCNode, DirectRetype, LocalCNode, LocalCNodeSlots,
     LocalCap, ThreadControlBlock, ThreadPriorityAuthority, Untyped, WCNodeSlotsData,
@@ -91,7 +91,7 @@ impl<StackBitSize: Unsigned> SelfHostedProcess<StackBitSize> {
         let ipc_buffer = vspace.map_region(
             ipc_buffer.to_region(),
             CapRights::RW,
-            arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+            memory_attributes::ExecuteNever(memory_attributes::Cached),
         )?;
 
         // allocate the thread control block
@@ -105,21 +105,25 @@ impl<StackBitSize: Unsigned> SelfHostedProcess<StackBitSize> {
             Some(ipc_buffer.to_page()),
         )?;
 
-        // Reserve a guard page before the stack
-        vspace.skip_pages(1)?;
+        // Reserve a guard before the stack
+        let stack_guard = vspace.place_guard_below(GuardKind::Stack)?;
 
         // Map the stack to the target address space
         let stack_top = parent_mapped_region.vaddr() + parent_mapped_region.size_bytes();
         let (unmapped_stack_pages, local_stack_pages) =
             parent_mapped_region.share(stack_slots, parent_cnode, CapRights::RW)?;
-        let mapped_stack_pages = vspace.map_shared_region_and_consume(
+        let mapped_stack_pages = vspace.map_region_unguarded(
             unmapped_stack_pages,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+            memory_attributes::ExecuteNever(memory_attributes::Cached),
         )?;
 
-        // Reserve a guard page after the stack.
-        vspace.skip_pages(1)?;
+        // Reserve a guard after the stack.
+        vspace.place_guard_above(
+            stack_guard,
+            mapped_stack_pages.vaddr(),
+            mapped_stack_pages.size_bytes(),
+        )?;
 
         let root_slot = cap_transfer_slots.alloc_strong().map_err(|e| match e {
             CNodeSlotsError::NotEnoughSlots => ProcessSetupError::NotEnoughCNodeSlots,
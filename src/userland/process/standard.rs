@@ -1,7 +1,9 @@
+use crate::arch::fault::Fault;
 use crate::arch::{self, *};
 use crate::cap::*;
 use crate::pow::{Pow, _Pow};
 use crate::userland::rights::CapRights;
+use crate::userland::{ByteSink, CoreDumpError, CoreDumpRegion, KnownRegion, RegionKind};
 use crate::vspace::*;
 use core::ops::{Add, Sub};
 
@@ -22,6 +24,10 @@ use super::*;
 ///  * An IPC buffer and CSpace and fault handler associated with that TCB.
 pub struct StandardProcess<StackBitSize: Unsigned = DefaultStackBitSize> {
     tcb: LocalCap<ThreadControlBlock>,
+    // Where the stack and IPC buffer ended up in the child's VSpace
+    stack_vaddr: usize,
+    stack_guard_size: usize,
+    ipc_buffer_vaddr: usize,
     _stack_bit_size: PhantomData<StackBitSize>,
 }
 
@@ -94,19 +100,46 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
             return Err(ProcessSetupError::ProcessParameterHandoffSizeMismatch);
         }
 
-        // Reserve a guard page before the stack
-        vspace.skip_pages(1)?;
+        // Reserve a guard before the stack
+        let stack_guard_size = vspace.guard_spans().stack_pages * arch::PageBytes::USIZE;
+        let stack_guard = vspace.place_guard_below(GuardKind::Stack)?;
 
         // Map the stack to the target address space
         let stack_top = parent_mapped_region.vaddr() + parent_mapped_region.size_bytes();
         let (unmapped_stack_pages, local_stack_pages): (UnmappedMemoryRegion<StackBitSize, _>, _) =
             parent_mapped_region.share(stack_slots, parent_cnode, CapRights::RW)?;
-        let mapped_stack_pages = vspace.map_shared_region_and_consume(
+        let mapped_stack_pages = vspace.map_region_unguarded(
             unmapped_stack_pages,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+            memory_attributes::ExecuteNever(memory_attributes::Cached),
         )?;
 
+        let stack_vaddr = mapped_stack_pages.vaddr();
+        let mut stack_top = stack_top;
+        let mut child_stack_top = mapped_stack_pages.vaddr() + mapped_stack_pages.size_bytes();
+
+        // ELF images may carry a PT_TLS segment, in which case the initial
+        // thread gets its TLS area at the very top of its stack.
+        let tls = match entry_point {
+            EntryPoint::Elf(elf_data) => TLSImage::from_elf(elf_data)?,
+            EntryPoint::Fork(_) => None,
+        };
+        let thread_pointer = match tls {
+            Some(tls) => {
+                if tls.area_size() + core::mem::size_of::<SetupVer<T>>()
+                    > 2usize.pow(StackBitSize::U32)
+                {
+                    return Err(ProcessSetupError::ProcessParameterTooBigForStack);
+                }
+                let (tls_used, thread_pointer) =
+                    unsafe { tls.write_area(stack_top, child_stack_top) };
+                stack_top -= tls_used;
+                child_stack_top -= tls_used;
+                Some(thread_pointer)
+            }
+            None => None,
+        };
+
         // map the child stack into local memory so we can copy the contents
         // of the process params into it
         let (mut registers, param_size_on_stack) = unsafe {
@@ -114,16 +147,18 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
                 &process_parameter as *const SetupVer<T> as *const usize,
                 core::mem::size_of::<SetupVer<T>>(),
                 stack_top as *mut usize,
-                mapped_stack_pages.vaddr() + mapped_stack_pages.size_bytes(),
+                child_stack_top,
             )
         };
 
         local_stack_pages.flush()?;
 
-        let stack_pointer =
-            mapped_stack_pages.vaddr() + mapped_stack_pages.size_bytes() - param_size_on_stack;
+        let stack_pointer = child_stack_top - param_size_on_stack;
 
         registers.sp = stack_pointer;
+        if let Some(thread_pointer) = thread_pointer {
+            set_thread_pointer(&mut registers, thread_pointer);
+        }
 
         registers.pc = match entry_point {
             EntryPoint::Fork(f) => f as usize,
@@ -141,8 +176,9 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
             set_thread_link_register(&mut registers, yield_forever);
         }
 
-        // Reserve a guard page after the stack
-        vspace.skip_pages(1)?;
+        // Reserve a guard after the stack, and remember both so that
+        // faults in them can be told apart from any other
+        vspace.place_guard_above(stack_guard, stack_vaddr, 1 << StackBitSize::USIZE)?;
 
         // Allocate and map the ipc buffer
         let (ipc_slots, misc_slots) = misc_slots.alloc();
@@ -150,8 +186,9 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
         let ipc_buffer = vspace.map_region(
             ipc_buffer.to_region(),
             CapRights::RW,
-            arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+            memory_attributes::ExecuteNever(memory_attributes::Cached),
         )?;
+        let ipc_buffer_vaddr = ipc_buffer.vaddr();
 
         //// allocate the thread control block
         let (tcb_slots, _slots) = misc_slots.alloc();
@@ -181,10 +218,19 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
         }
         Ok(StandardProcess {
             tcb,
+            stack_vaddr,
+            stack_guard_size,
+            ipc_buffer_vaddr,
             _stack_bit_size: PhantomData,
         })
     }
 
+    /// Where the stack starts in the child's VSpace, e.g. for naming it
+    /// with `VSpace::name_region`.
+    pub fn stack_vaddr(&self) -> usize {
+        self.stack_vaddr
+    }
+
     pub fn set_name(&mut self, name: &str) {
         let mut c_str = [0u8; 256];
         for (n, byte) in name.bytes().take(255).enumerate() {
@@ -211,6 +257,70 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
             .map_err(SeL4Error::TCBResume)
     }
 
+    /// Stop the process from running, e.g. once it has reported its
+    /// `ProcessOutcome` through an `ExitWatcher`.
+    pub fn stop(&mut self) -> Result<(), SeL4Error> {
+        self.tcb.suspend()
+    }
+
+    /// Stop the process for good and delete its TCB capability.
+    ///
+    /// The rest of the process' resources (its CSpace, VSpace, stack and
+    /// the untyped memory they were made from) belong to whoever set it
+    /// up, and may be reclaimed once this returns, e.g. by letting a
+    /// `with_temporary` scope end.
+    pub fn terminate(mut self, parent_cnode: &LocalCap<LocalCNode>) -> Result<(), SeL4Error> {
+        self.stop()?;
+        self.tcb.delete(parent_cnode)
+    }
+
+    /// The parts of the child's address space set up here: the stack, the
+    /// guard pages either side of it, and the IPC buffer. Useful for making
+    /// sense of its faults with `VMFault::explain`.
+    pub fn known_regions(&self) -> [KnownRegion; 4] {
+        let page_size = arch::PageBytes::USIZE;
+        let stack_size = 1 << StackBitSize::USIZE;
+        [
+            KnownRegion::new(
+                self.stack_vaddr - self.stack_guard_size,
+                self.stack_guard_size,
+                RegionKind::StackGuard,
+                CapRights::R,
+            ),
+            KnownRegion::new(
+                self.stack_vaddr,
+                stack_size,
+                RegionKind::Stack,
+                CapRights::RW,
+            ),
+            KnownRegion::new(
+                self.stack_vaddr + stack_size,
+                self.stack_guard_size,
+                RegionKind::StackGuard,
+                CapRights::R,
+            ),
+            KnownRegion::new(
+                self.ipc_buffer_vaddr,
+                page_size,
+                RegionKind::IPCBuffer,
+                CapRights::RW,
+            ),
+        ]
+    }
+
+    /// Stream out an ELF core file of this (presumably stopped or faulted)
+    /// process, covering its current registers and the given `regions`.
+    pub fn write_core_dump<S: ByteSink>(
+        &self,
+        fault: Option<&Fault>,
+        regions: &[CoreDumpRegion],
+        sink: &mut S,
+    ) -> Result<(), CoreDumpError<S::Error>> {
+        let registers = self.tcb.read_registers()?;
+        crate::userland::write_core_dump(&registers, fault, regions, sink)
+            .map_err(CoreDumpError::SinkError)
+    }
+
     pub fn elim(self) -> usize {
         self.tcb.cptr
     }
@@ -1,6 +1,7 @@
 use crate::arch::*;
 use crate::cap::*;
 use crate::pow::{Pow, _Pow};
+use crate::userland::CapRights;
 use crate::vspace::*;
 use core::ops::Sub;
 
@@ -19,6 +20,10 @@ use super::*;
 ///    `seL4_UserContext` and/or its stack.
 ///  * Said seL4_UserContext written into the TCB.
 ///  * An IPC buffer and CSpace and fault handler associated with that TCB.
+///
+/// The stack is mapped into the VSpace the thread shares, with guards of
+/// `GuardSpans::stack_pages` either side of it, so that overflows fault
+/// in a guard rather than run into whatever was mapped below it.
 pub struct Thread<StackBitSize: Unsigned = DefaultStackBitSize> {
     tcb: LocalCap<ThreadControlBlock>,
     _stack_bit_size: PhantomData<StackBitSize>,
@@ -26,9 +31,9 @@ pub struct Thread<StackBitSize: Unsigned = DefaultStackBitSize> {
 
 impl<StackBitSize: Unsigned> Thread<StackBitSize> {
     pub fn new<T: RetypeForSetup>(
-        virtual_address_space_root: &LocalCap<crate::arch::PagingRoot>,
+        vspace: &mut VSpace,
         cspace: LocalCap<ChildCNode>,
-        stack_region: MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+        stack_region: UnmappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
         function_descriptor: extern "C" fn(T) -> (),
         process_parameter: SetupVer<T>,
         ipc_buffer: MappedMemoryRegion<PageBits, shared_status::Exclusive>,
@@ -36,7 +41,153 @@ impl<StackBitSize: Unsigned> Thread<StackBitSize> {
         slots: LocalCNodeSlots<U1>,
         priority_authority: &LocalCap<ThreadPriorityAuthority>,
         fault_source: Option<crate::userland::FaultSource<role::Child>>,
+        tls: Option<TLSImage>,
     ) -> Result<Thread<StackBitSize>, ThreadSetupError>
+    where
+        StackBitSize: IsGreaterOrEqual<PageBits>,
+        StackBitSize: Sub<PageBits>,
+        <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+        <StackBitSize as Sub<PageBits>>::Output: _Pow,
+        Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let stack_region = Self::map_stack(vspace, stack_region)?;
+        let tcb = Self::setup_tcb::<T, _>(
+            vspace.root(),
+            cspace,
+            stack_region,
+            function_descriptor as usize,
+            process_parameter,
+            ipc_buffer,
+            tcb_ut,
+            slots,
+            priority_authority,
+            fault_source,
+            0,
+            tls,
+        )?;
+        Ok(Thread {
+            tcb,
+            _stack_bit_size: PhantomData,
+        })
+    }
+
+    /// Set up a thread whose function returns a value, which can be
+    /// collected with `JoinHandle::join` once the thread has been started.
+    ///
+    /// The exit value is left at the top of the stack region, so like the
+    /// thread parameters it must fit there.
+    pub fn new_joinable<T: RetypeForSetup, R: Send + Sync>(
+        vspace: &mut VSpace,
+        cspace: LocalCap<ChildCNode>,
+        stack_region: UnmappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+        function: fn(T) -> R,
+        process_parameter: SetupVer<T>,
+        ipc_buffer: MappedMemoryRegion<PageBits, shared_status::Exclusive>,
+        tcb_ut: LocalCap<Untyped<<ThreadControlBlock as DirectRetype>::SizeBits>>,
+        exit_notification_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
+        local_cnode: &LocalCap<LocalCNode>,
+        slots: LocalCNodeSlots<U2>,
+        exit_notification_slot: ChildCNodeSlot,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        fault_source: Option<crate::userland::FaultSource<role::Child>>,
+        tls: Option<TLSImage>,
+    ) -> Result<JoinableThread<R, StackBitSize>, ThreadSetupError>
+    where
+        StackBitSize: IsGreaterOrEqual<PageBits>,
+        StackBitSize: Sub<PageBits>,
+        <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+        <StackBitSize as Sub<PageBits>>::Output: _Pow,
+        Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let stack_region = Self::map_stack(vspace, stack_region)?;
+        let stack_top = stack_region.vaddr() + stack_region.size_bytes();
+        let exit_value_align = core::cmp::max(core::mem::align_of::<R>(), 16);
+        let exit_value_addr = (stack_top - core::mem::size_of::<R>()) & !(exit_value_align - 1);
+        if exit_value_addr < stack_region.vaddr() {
+            return Err(ThreadSetupError::ThreadExitValueTooBigForStack);
+        }
+
+        let (notification_slot, slots) = slots.alloc();
+        let exit_notification: LocalCap<Notification> =
+            exit_notification_ut.retype(notification_slot)?;
+        let exited = exit_notification.copy(local_cnode, exit_notification_slot, CapRights::RWG)?;
+
+        let params: SetupVer<JoinableParams<T, R, role::Local>> = JoinableParams {
+            function: function as usize,
+            param: process_parameter,
+            exit_value: exit_value_addr,
+            exited,
+            _exit_value: PhantomData,
+        };
+
+        let tcb = Self::setup_tcb::<JoinableParams<T, R, role::Local>, _>(
+            vspace.root(),
+            cspace,
+            stack_region,
+            joinable_entry::<T, R> as usize,
+            params,
+            ipc_buffer,
+            tcb_ut,
+            slots,
+            priority_authority,
+            fault_source,
+            stack_top - exit_value_addr,
+            tls,
+        )?;
+        Ok(JoinableThread {
+            thread: Thread {
+                tcb,
+                _stack_bit_size: PhantomData,
+            },
+            exit_notification,
+            exit_value_addr,
+            _exit_value: PhantomData,
+        })
+    }
+
+    /// Map the stack into the VSpace the thread shares, between guards.
+    fn map_stack(
+        vspace: &mut VSpace,
+        stack_region: UnmappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+    ) -> Result<
+        MappedMemoryRegion<
+            StackBitSize,
+            shared_status::Exclusive,
+            role::Local,
+            memory_attributes::ExecuteNever<memory_attributes::Cached>,
+        >,
+        ThreadSetupError,
+    >
+    where
+        StackBitSize: IsGreaterOrEqual<PageBits>,
+        StackBitSize: Sub<PageBits>,
+        <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+        <StackBitSize as Sub<PageBits>>::Output: _Pow,
+        Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    {
+        Ok(vspace.map_stack_region(
+            stack_region,
+            memory_attributes::ExecuteNever(memory_attributes::Cached),
+        )?)
+    }
+
+    /// Shared setup for both kinds of thread. `reserved_top_bytes` are left
+    /// untouched at the top of the stack region; below them come the TLS
+    /// area, if any, and then the thread parameters.
+    fn setup_tcb<T: RetypeForSetup, A: MemoryAttributes>(
+        virtual_address_space_root: &LocalCap<crate::arch::PagingRoot>,
+        cspace: LocalCap<ChildCNode>,
+        stack_region: MappedMemoryRegion<StackBitSize, shared_status::Exclusive, role::Local, A>,
+        entry_point: usize,
+        process_parameter: SetupVer<T>,
+        ipc_buffer: MappedMemoryRegion<PageBits, shared_status::Exclusive>,
+        tcb_ut: LocalCap<Untyped<<ThreadControlBlock as DirectRetype>::SizeBits>>,
+        slots: LocalCNodeSlots<U1>,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        fault_source: Option<crate::userland::FaultSource<role::Child>>,
+        reserved_top_bytes: usize,
+        tls: Option<TLSImage>,
+    ) -> Result<LocalCap<ThreadControlBlock>, ThreadSetupError>
     where
         StackBitSize: IsGreaterOrEqual<PageBits>,
         StackBitSize: Sub<PageBits>,
@@ -47,10 +198,13 @@ impl<StackBitSize: Unsigned> Thread<StackBitSize> {
         if ipc_buffer.asid() != stack_region.asid() {
             return Err(ThreadSetupError::StackRegionASIDMustMatchIPCBufferASID);
         }
+        let tls_size = tls.as_ref().map_or(0, |tls| tls.area_size());
         // TODO - lift these checks to compile-time, as static assertions
         // Note - This comparison is conservative because technically
         // we can fit some of the params into available registers.
-        if core::mem::size_of::<SetupVer<T>>() > 2usize.pow(StackBitSize::U32) {
+        if core::mem::size_of::<SetupVer<T>>() + reserved_top_bytes + tls_size
+            > 2usize.pow(StackBitSize::U32)
+        {
             return Err(ThreadSetupError::ThreadParameterTooBigForStack);
         }
         if core::mem::size_of::<SetupVer<T>>() != core::mem::size_of::<T>() {
@@ -58,9 +212,17 @@ impl<StackBitSize: Unsigned> Thread<StackBitSize> {
         }
 
         // Map the stack to the target address space
-        let stack_top = stack_region.vaddr() + stack_region.size_bytes();
+        let mut stack_top = stack_region.vaddr() + stack_region.size_bytes() - reserved_top_bytes;
         let mapped_stack_pages = stack_region;
 
+        // The thread shares our address space, so its TLS area can be
+        // written in place.
+        let thread_pointer = tls.map(|tls| {
+            let (tls_used, thread_pointer) = unsafe { tls.write_area(stack_top, stack_top) };
+            stack_top -= tls_used;
+            thread_pointer
+        });
+
         // map the child stack into local memory so we can copy the contents
         // of the process params into it
         let (mut registers, param_size_on_stack) = unsafe {
@@ -68,15 +230,17 @@ impl<StackBitSize: Unsigned> Thread<StackBitSize> {
                 &process_parameter as *const SetupVer<T> as *const usize,
                 core::mem::size_of::<SetupVer<T>>(),
                 stack_top as *mut usize,
-                mapped_stack_pages.vaddr() + mapped_stack_pages.size_bytes(),
+                stack_top,
             )
         };
 
-        let stack_pointer =
-            mapped_stack_pages.vaddr() + mapped_stack_pages.size_bytes() - param_size_on_stack;
+        let stack_pointer = stack_top - param_size_on_stack;
 
         registers.sp = stack_pointer;
-        registers.pc = function_descriptor as usize;
+        registers.pc = entry_point;
+        if let Some(thread_pointer) = thread_pointer {
+            set_thread_pointer(&mut registers, thread_pointer);
+        }
 
         // TODO - Probably ought to suspend or destroy the thread instead of endlessly
         // yielding
@@ -108,10 +272,7 @@ impl<StackBitSize: Unsigned> Thread<StackBitSize> {
             // plan on actually using it
             tcb.set_priority(priority_authority, 255)?;
         }
-        Ok(Thread {
-            tcb,
-            _stack_bit_size: PhantomData,
-        })
+        Ok(tcb)
     }
 
     pub fn start(self) -> Result<(), SeL4Error> {
@@ -120,11 +281,83 @@ impl<StackBitSize: Unsigned> Thread<StackBitSize> {
             .map_err(SeL4Error::TCBResume)
     }
 }
+
+/// The parameters handed to a joinable thread's entry trampoline.
+pub struct JoinableParams<T, R, Role: CNodeRole> {
+    // The user's `fn(T) -> R`, type-erased so that the parent-side version
+    // of this struct can carry it alongside a `SetupVer<T>`.
+    function: usize,
+    param: T,
+    exit_value: usize,
+    exited: Cap<Notification, Role>,
+    _exit_value: PhantomData<R>,
+}
+
+impl<T: RetypeForSetup, R: Send + Sync> RetypeForSetup for JoinableParams<T, R, role::Local> {
+    type Output = JoinableParams<SetupVer<T>, R, role::Child>;
+}
+
+extern "C" fn joinable_entry<T, R>(params: JoinableParams<T, R, role::Local>) {
+    let function: fn(T) -> R = unsafe { core::mem::transmute(params.function) };
+    let value = function(params.param);
+    unsafe { core::ptr::write(params.exit_value as *mut R, value) };
+    params.exited.signal();
+}
+
+/// A thread which has been set up to hand back a value of type `R` when
+/// its function returns.
+pub struct JoinableThread<R, StackBitSize: Unsigned = DefaultStackBitSize> {
+    thread: Thread<StackBitSize>,
+    exit_notification: LocalCap<Notification>,
+    exit_value_addr: usize,
+    _exit_value: PhantomData<R>,
+}
+
+impl<R, StackBitSize: Unsigned> JoinableThread<R, StackBitSize> {
+    pub fn start(self) -> Result<JoinHandle<R>, SeL4Error> {
+        let JoinableThread {
+            thread,
+            exit_notification,
+            exit_value_addr,
+            ..
+        } = self;
+        unsafe { seL4_TCB_Resume(thread.tcb.cptr) }
+            .as_result()
+            .map_err(SeL4Error::TCBResume)?;
+        Ok(JoinHandle {
+            tcb: thread.tcb,
+            exit_notification,
+            exit_value_addr,
+            _exit_value: PhantomData,
+        })
+    }
+}
+
+/// The parent's handle on a running joinable thread.
+pub struct JoinHandle<R> {
+    tcb: LocalCap<ThreadControlBlock>,
+    exit_notification: LocalCap<Notification>,
+    exit_value_addr: usize,
+    _exit_value: PhantomData<R>,
+}
+
+impl<R> JoinHandle<R> {
+    /// Block until the thread's function returns, then suspend the thread
+    /// and hand back the value it returned.
+    pub fn join(mut self) -> Result<R, SeL4Error> {
+        let _ = self.exit_notification.wait();
+        self.tcb.suspend()?;
+        Ok(unsafe { core::ptr::read(self.exit_value_addr as *const R) })
+    }
+}
+
 #[derive(Debug)]
 pub enum ThreadSetupError {
     ThreadParameterTooBigForStack,
     ThreadParameterHandoffSizeMismatch,
     StackRegionASIDMustMatchIPCBufferASID,
+    ThreadExitValueTooBigForStack,
+    VSpaceError(VSpaceError),
     SeL4Error(SeL4Error),
 }
 
@@ -133,3 +366,9 @@ impl From<SeL4Error> for ThreadSetupError {
         ThreadSetupError::SeL4Error(e)
     }
 }
+
+impl From<VSpaceError> for ThreadSetupError {
+    fn from(e: VSpaceError) -> Self {
+        ThreadSetupError::VSpaceError(e)
+    }
+}
@@ -0,0 +1,101 @@
+use core::cmp;
+use core::mem::size_of;
+use core::ptr;
+
+use super::ProcessSetupError;
+
+/// The ARM ABIs use TLS "variant 1": the thread pointer points at a
+/// two-word thread control block, and the thread's TLS block follows it
+/// (suitably aligned).
+const TLS_TCB_SIZE: usize = 2 * size_of::<usize>();
+
+/// Keep the TLS area (and so the stack pointer below it) at least this
+/// aligned, which satisfies both the aarch32 and aarch64 stack alignment
+/// requirements.
+const TLS_AREA_MIN_ALIGN: usize = 16;
+
+/// The initialization image for each thread's TLS block, as described by
+/// an ELF `PT_TLS` program header: the `.tdata` contents, followed by
+/// zeroes for `.tbss` up to `mem_size`.
+#[derive(Debug, Clone, Copy)]
+pub struct TLSImage<'a> {
+    template: &'a [u8],
+    mem_size: usize,
+    align: usize,
+}
+
+impl<'a> TLSImage<'a> {
+    pub fn new(template: &'a [u8], mem_size: usize, align: usize) -> Self {
+        TLSImage {
+            template,
+            mem_size: cmp::max(mem_size, template.len()),
+            align: cmp::max(align, 1),
+        }
+    }
+
+    /// Extract the TLS image from an ELF binary's `PT_TLS` segment, if it
+    /// has one.
+    pub fn from_elf(elf_data: &'a [u8]) -> Result<Option<Self>, ProcessSetupError> {
+        let elf = xmas_elf::ElfFile::new(elf_data).map_err(ProcessSetupError::ElfParseError)?;
+        let tls_header = match elf
+            .program_iter()
+            .find(|h| h.get_type() == Ok(xmas_elf::program::Type::Tls))
+        {
+            Some(h) => h,
+            None => return Ok(None),
+        };
+        let offset = tls_header.offset() as usize;
+        let file_size = tls_header.file_size() as usize;
+        let template =
+            elf_data
+                .get(offset..offset + file_size)
+                .ok_or(ProcessSetupError::ElfParseError(
+                    "TLS segment extends past the end of the ELF data",
+                ))?;
+        Ok(Some(TLSImage::new(
+            template,
+            tls_header.mem_size() as usize,
+            tls_header.align() as usize,
+        )))
+    }
+
+    fn block_offset(&self) -> usize {
+        align_up(TLS_TCB_SIZE, self.align)
+    }
+
+    fn area_align(&self) -> usize {
+        cmp::max(self.align, TLS_AREA_MIN_ALIGN)
+    }
+
+    /// The most space a TLS area for this image can take up, including
+    /// alignment padding.
+    pub fn area_size(&self) -> usize {
+        align_up(self.block_offset() + self.mem_size, self.area_align()) + self.area_align()
+    }
+
+    /// Lay out a fresh TLS area just below `local_top`, which is the local
+    /// address of the same memory the thread sees at `child_top`. The two
+    /// must agree modulo the TLS alignment, e.g. both be page aligned.
+    ///
+    /// Returns the number of bytes used below the top and the value for
+    /// the thread's thread pointer register.
+    pub(crate) unsafe fn write_area(&self, local_top: usize, child_top: usize) -> (usize, usize) {
+        let area_align = self.area_align();
+        let local_start = (local_top - (self.block_offset() + self.mem_size)) & !(area_align - 1);
+        let used = local_top - local_start;
+
+        let area = local_start as *mut u8;
+        ptr::write_bytes(area, 0, used);
+        ptr::copy_nonoverlapping(
+            self.template.as_ptr(),
+            area.add(self.block_offset()),
+            self.template.len(),
+        );
+
+        (used, child_top - used)
+    }
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
@@ -1,18 +1,20 @@
 use core::marker::PhantomData;
 
 use selfe_sys::{seL4_Signal, seL4_Wait};
-use typenum::{Unsigned, U2, U4};
+use typenum::{Unsigned, U1, U2, U3, U4};
 
-use crate::arch::{self, PageBits, PageBytes};
+use crate::arch::{PageBits, PageBytes};
 use crate::cap::{
-    role, Badge, CNodeRole, CNodeSlots, Cap, DirectRetype, LocalCNode, LocalCNodeSlots, LocalCap,
-    Notification, Untyped,
+    role, BadgeBit, BadgeBits, CNodeRole, CNodeSlots, Cap, DirectRetype, LocalCNode,
+    LocalCNodeSlots, LocalCap, Notification, Untyped,
 };
 use crate::userland::multi_consumer::WakerSetup;
 use crate::userland::{CapRights, IPCError};
-use crate::vspace::{UnmappedMemoryRegion, VSpace};
+use crate::vspace::{memory_attributes, UnmappedMemoryRegion, VSpace};
 
-pub(crate) const WAKER_BADGE: usize = 2;
+/// The bit of a request-ready notification's badge reserved for the
+/// `WakerSetup` path; the caller's request signal sits below it.
+pub(crate) type WakerBadgeBit = BadgeBit<U1>;
 
 pub mod sync {
     use super::*;
@@ -53,7 +55,7 @@ pub mod sync {
         let caller_shared_region = caller_vspace.map_shared_region(
             &shared_region,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT,
+            memory_attributes::Cached,
             slot,
             local_cnode,
         )?;
@@ -61,7 +63,7 @@ pub mod sync {
         let responder_shared_region = responder_vspace.map_shared_region_and_consume(
             shared_region,
             CapRights::RW,
-            arch::vm_attributes::DEFAULT,
+            memory_attributes::Cached,
         )?;
 
         let (slot, local_slots) = local_slots.alloc();
@@ -70,12 +72,24 @@ pub mod sync {
         let (slot, _local_slots) = local_slots.alloc();
         let local_response_ready: LocalCap<Notification> = response_notification_ut.retype(slot)?;
 
+        let request_bits = BadgeBits::new();
+        let (caller_request_bit, request_bits) = request_bits.alloc();
+        let (waker_bit, request_bits): (WakerBadgeBit, _) = request_bits.alloc();
+        let (responder_request_bit, _request_bits) = request_bits.alloc();
+
+        // Each side's response bit sits just above its request bit.
+        let response_bits = BadgeBits::new();
+        let (_, response_bits) = response_bits.alloc();
+        let (caller_response_bit, response_bits): (BadgeBit<U1>, _) = response_bits.alloc();
+        let (_, response_bits) = response_bits.alloc();
+        let (responder_response_bit, _response_bits): (BadgeBit<U3>, _) = response_bits.alloc();
+
         let (caller_slot, caller_slots) = caller_slots.alloc();
         let caller_request_ready = local_request_ready.mint(
             local_cnode,
             caller_slot,
             CapRights::RWG,
-            Badge::from(1 << 0),
+            caller_request_bit.badge(),
         )?;
 
         let (caller_slot, _caller_slots) = caller_slots.alloc();
@@ -83,7 +97,7 @@ pub mod sync {
             local_cnode,
             caller_slot,
             CapRights::RWG,
-            Badge::from(1 << 1),
+            caller_response_bit.badge(),
         )?;
 
         let caller = ExtendedCaller {
@@ -102,7 +116,7 @@ pub mod sync {
             local_cnode,
             responder_slot,
             CapRights::RWG,
-            Badge::from(1 << 2),
+            responder_request_bit.badge(),
         )?;
 
         let (responder_slot, _responder_slots) = responder_slots.alloc();
@@ -110,7 +124,7 @@ pub mod sync {
             local_cnode,
             responder_slot,
             CapRights::RWG,
-            Badge::from(1 << 3),
+            responder_response_bit.badge(),
         )?;
 
         let responder = ExtendedResponder {
@@ -125,7 +139,7 @@ pub mod sync {
         };
 
         let waker_setup = WakerSetup {
-            interrupt_badge: Badge::from(WAKER_BADGE),
+            interrupt_badge: waker_bit.badge(),
             notification: local_request_ready,
         };
 
@@ -225,7 +239,7 @@ pub mod sync {
             loop {
                 unsafe {
                     seL4_Wait(inner.request_ready.cptr, &mut sender_badge as *mut usize);
-                    if sender_badge == WAKER_BADGE {
+                    if sender_badge == WakerBadgeBit::mask() {
                         // nonzero badges are from a notification
                         state = g(sender_badge, state);
                     } else {
@@ -0,0 +1,211 @@
+//! Making sense of VM faults: decoding the fault status and placing the
+//! address amongst the regions a child is known to have.
+use core::fmt;
+
+use crate::arch::fault::VMFault;
+use crate::userland::CapRights;
+use crate::vspace::Guard;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VMFaultCause {
+    /// Nothing is mapped at the address.
+    Translation,
+    /// Something is mapped, but not with rights allowing this access.
+    Permission,
+    Alignment,
+    AccessFlag,
+    /// arm short-descriptor domain faults.
+    Domain,
+    AddressSize,
+    ExternalAbort,
+    /// Any other status code, as reported.
+    Other(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VMFaultAccess {
+    Read,
+    Write,
+    Execute,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VMFaultDetails {
+    pub cause: VMFaultCause,
+    /// The level of the paging structure lookup at which the fault occurred,
+    /// where the architecture reports one.
+    pub level: Option<u8>,
+    pub access: VMFaultAccess,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Code,
+    Data,
+    Stack,
+    StackGuard,
+    IPCBuffer,
+    Shared,
+    Device,
+    Other,
+}
+
+/// A part of a child's address space its parent knows about.
+#[derive(Debug, Clone, Copy)]
+pub struct KnownRegion {
+    pub start: usize,
+    pub size: usize,
+    pub kind: RegionKind,
+    pub rights: CapRights,
+}
+
+impl KnownRegion {
+    pub fn new(start: usize, size: usize, kind: RegionKind, rights: CapRights) -> Self {
+        KnownRegion {
+            start,
+            size,
+            kind,
+            rights,
+        }
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        address >= self.start && address - self.start < self.size
+    }
+}
+
+/// A decoded VM fault together with the region it hit, if known.
+/// `Display` renders a one-line human-readable summary.
+#[derive(Debug, Clone, Copy)]
+pub struct VMFaultReport {
+    pub address: usize,
+    pub program_counter: usize,
+    pub details: VMFaultDetails,
+    pub region: Option<KnownRegion>,
+    /// The guard the fault landed in, as filled in by
+    /// `VSpace::explain_fault`.
+    pub guard: Option<Guard>,
+}
+
+/// Decode a fault status code in the VMSA long-descriptor format, as used
+/// by aarch64 (ESR_ELx) and by aarch32 with the LPAE/hypervisor extensions.
+pub(crate) fn decode_long_descriptor_status(
+    status: usize,
+    is_instruction_fault: bool,
+) -> VMFaultDetails {
+    let fault_status_code = status & 0x3f;
+    let level = Some((fault_status_code & 0b11) as u8);
+    let (cause, level) = match fault_status_code >> 2 {
+        0b0000 => (VMFaultCause::AddressSize, level),
+        0b0001 => (VMFaultCause::Translation, level),
+        0b0010 => (VMFaultCause::AccessFlag, level),
+        0b0011 => (VMFaultCause::Permission, level),
+        0b0101 => (VMFaultCause::ExternalAbort, level),
+        _ => match fault_status_code {
+            0b01_0000 => (VMFaultCause::ExternalAbort, None),
+            0b10_0001 => (VMFaultCause::Alignment, None),
+            _ => (VMFaultCause::Other(status), None),
+        },
+    };
+    VMFaultDetails {
+        cause,
+        level,
+        access: if is_instruction_fault {
+            VMFaultAccess::Execute
+        } else if status & (1 << 6) != 0 {
+            VMFaultAccess::Write
+        } else {
+            VMFaultAccess::Read
+        },
+    }
+}
+
+impl VMFault {
+    /// Decode this fault and resolve its address against `regions`.
+    pub fn explain(&self, regions: &[KnownRegion]) -> VMFaultReport {
+        VMFaultReport {
+            address: self.address,
+            program_counter: self.program_counter,
+            details: self.details(),
+            region: regions.iter().find(|r| r.contains(self.address)).copied(),
+            guard: None,
+        }
+    }
+}
+
+impl fmt::Display for VMFaultAccess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            VMFaultAccess::Read => "read",
+            VMFaultAccess::Write => "write",
+            VMFaultAccess::Execute => "instruction fetch",
+        })
+    }
+}
+
+impl fmt::Display for VMFaultCause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VMFaultCause::Translation => f.write_str("translation fault"),
+            VMFaultCause::Permission => f.write_str("permission fault"),
+            VMFaultCause::Alignment => f.write_str("alignment fault"),
+            VMFaultCause::AccessFlag => f.write_str("access flag fault"),
+            VMFaultCause::Domain => f.write_str("domain fault"),
+            VMFaultCause::AddressSize => f.write_str("address size fault"),
+            VMFaultCause::ExternalAbort => f.write_str("external abort"),
+            VMFaultCause::Other(status) => write!(f, "fault with status {:#x}", status),
+        }
+    }
+}
+
+impl fmt::Display for RegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            RegionKind::Code => "code",
+            RegionKind::Data => "data",
+            RegionKind::Stack => "stack",
+            RegionKind::StackGuard => "stack guard",
+            RegionKind::IPCBuffer => "IPC buffer",
+            RegionKind::Shared => "shared memory",
+            RegionKind::Device => "device memory",
+            RegionKind::Other => "mapped",
+        })
+    }
+}
+
+impl fmt::Display for VMFaultReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = self.details.access;
+        if let Some(guard) = self.guard {
+            write!(f, "{} ({})", guard, access)?;
+        } else {
+            match (self.region, self.details.cause) {
+                (Some(region), _) if region.kind == RegionKind::StackGuard => {
+                    write!(f, "stack guard hit ({})", access)?
+                }
+                (Some(region), VMFaultCause::Permission)
+                    if access == VMFaultAccess::Write && !region.rights.is_writable() =>
+                {
+                    write!(f, "write to read-only {} page", region.kind)?
+                }
+                (Some(region), VMFaultCause::Permission) if access == VMFaultAccess::Execute => {
+                    write!(
+                        f,
+                        "instruction fetch from non-executable {} page",
+                        region.kind
+                    )?
+                }
+                (Some(region), cause) => {
+                    write!(f, "{} in {} page ({})", cause, region.kind, access)?
+                }
+                (None, VMFaultCause::Translation) => write!(f, "{} of unmapped address", access)?,
+                (None, cause) => write!(f, "{} ({})", cause, access)?,
+            }
+        }
+        write!(f, " at {:#x}, pc {:#x}", self.address, self.program_counter)?;
+        if let Some(level) = self.details.level {
+            write!(f, ", level {}", level)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,250 @@
+//! Copy-on-write sharing of a region between address spaces, where the
+//! first write to a page gives the writer a private copy of just that page.
+use core::marker::PhantomData;
+use core::ops::Sub;
+
+use arrayvec::ArrayVec;
+use selfe_sys::{seL4_CNode_Delete, seL4_WordBits};
+use typenum::*;
+
+use super::{
+    memory_attributes, shared_status, MappedMemoryRegion, MappedRights, MemoryAttributes, NumPages,
+    ScratchRegion, VSpace, VSpaceError, WeakMappedMemoryRegion, WeakMemoryRegion,
+    WeakUnmappedMemoryRegion,
+};
+use crate::arch::fault::VMFault;
+use crate::arch::{PageBits, PageBytes};
+use crate::cap::{
+    page_state, role, Cap, InternalASID, LocalCNode, LocalCNodeSlot, LocalCNodeSlots, LocalCap,
+    Page, Untyped, WeakCopyError,
+};
+use crate::error::{ErrorExt, SeL4Error};
+use crate::pow::{Pow, _Pow};
+use crate::userland::{CapRights, VMFaultAccess, VMFaultCause};
+
+/// The most address spaces one `CowRegion` can be mapped into.
+pub const MAX_COW_MAPPINGS: usize = 8;
+
+#[derive(Debug)]
+pub enum CowError {
+    /// The fault was not a write to a copy-on-write mapping of this region.
+    NotCopyOnWrite,
+    /// The region isn't mapped into the given address space.
+    NotMapped,
+    TooManyMappings,
+    InsufficientCNodeSlots,
+    VSpaceError(VSpaceError),
+    SeL4Error(SeL4Error),
+}
+
+impl From<VSpaceError> for CowError {
+    fn from(e: VSpaceError) -> Self {
+        CowError::VSpaceError(e)
+    }
+}
+
+impl From<SeL4Error> for CowError {
+    fn from(e: SeL4Error) -> Self {
+        CowError::SeL4Error(e)
+    }
+}
+
+/// Where a `CowRegion` is mapped read-only.
+struct CowMapping {
+    asid: InternalASID,
+    vaddr: usize,
+    /// The copies of the original page caps this mapping is made of.
+    start_cptr: usize,
+}
+
+/// A `1 << SizeBits` bytes region whose original contents stay mapped,
+/// read-only, in the local address space, and which is mapped
+/// copy-on-write elsewhere. Every mapping of it, and every private copy
+/// of one of its pages, is made with the original's attributes `A`.
+pub struct CowRegion<SizeBits: Unsigned, A: MemoryAttributes = memory_attributes::Cached>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    original: WeakMappedMemoryRegion<shared_status::Shared>,
+    mappings: ArrayVec<[CowMapping; MAX_COW_MAPPINGS]>,
+    _size_bits: PhantomData<SizeBits>,
+    _attributes: PhantomData<A>,
+}
+
+impl<SizeBits: Unsigned, A: MemoryAttributes> CowRegion<SizeBits, A>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Freeze `region`, mapped in the local `vspace`, as the original
+    /// contents: it is made read-only here too, so that what was shared
+    /// can't change underneath the copy-on-write mappings.
+    ///
+    /// `vspace` must be the address space `handle_write_fault` is called
+    /// from, which reads the original through this mapping. Writes to the
+    /// original there fault like any other write to a read-only page:
+    /// only the address spaces it is mapped into get private copies.
+    pub fn new<R: MappedRights>(
+        region: MappedMemoryRegion<SizeBits, shared_status::Exclusive, role::Local, A, R>,
+        vspace: &mut VSpace,
+    ) -> Result<Self, CowError> {
+        let original = vspace
+            .weak_protect_region(region.weaken(), CapRights::R, A::default().vm_attributes())
+            .map_err(|(e, _)| e)?;
+        Ok(CowRegion {
+            original: original.to_shared(),
+            mappings: ArrayVec::new(),
+            _size_bits: PhantomData,
+            _attributes: PhantomData,
+        })
+    }
+
+    /// The original contents.
+    pub fn as_slice(&self) -> &[u8] {
+        self.original.as_slice()
+    }
+
+    /// Map the region copy-on-write into `vspace`, wherever it fits, and
+    /// return the address it was mapped at.
+    pub fn map_into(
+        &mut self,
+        vspace: &mut VSpace,
+        slots: LocalCNodeSlots<NumPages<SizeBits>>,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<usize, CowError> {
+        if self.mappings.is_full() {
+            return Err(CowError::TooManyMappings);
+        }
+        let copies = self
+            .original
+            .caps
+            .copy(cnode, &mut slots.weaken(), CapRights::R)
+            .map_err(|e| match e {
+                WeakCopyError::NotEnoughSlots => CowError::InsufficientCNodeSlots,
+                WeakCopyError::SeL4Error(e) => CowError::SeL4Error(e),
+            })?;
+        let start_cptr = copies.start_cptr;
+        let unmapped: WeakUnmappedMemoryRegion<shared_status::Shared> =
+            WeakMemoryRegion::try_from_caps(copies, self.original.kind, SizeBits::U8, PageBits::U8)
+                .map_err(|_| VSpaceError::InvalidRegionSize)?;
+        let mapped: WeakMappedMemoryRegion<shared_status::Shared> = vspace
+            .weak_map_region_internal(unmapped, CapRights::R, A::default().vm_attributes())?;
+        self.mappings.push(CowMapping {
+            asid: vspace.asid(),
+            vaddr: mapped.vaddr(),
+            start_cptr,
+        });
+        Ok(mapped.vaddr())
+    }
+
+    /// Whether `fault`, in `vspace`, is a write to one of this region's
+    /// copy-on-write mappings, to be resolved with `handle_write_fault`.
+    pub fn claims(&self, fault: &VMFault, vspace: &VSpace) -> bool {
+        self.written_mapping(fault, vspace).is_some()
+    }
+
+    /// Resolve a write `fault` in `vspace` against this region's
+    /// copy-on-write mappings: the page written to is replaced by a
+    /// private, writable copy of it, made from `page_ut`. The faulting
+    /// thread may be resumed once this returns.
+    ///
+    /// Faults which aren't copy-on-write writes to this region are refused
+    /// with `CowError::NotCopyOnWrite`, after `page_ut` and `page_slot`
+    /// have been spent; check `claims` first when trying several regions
+    /// in turn.
+    pub fn handle_write_fault(
+        &self,
+        fault: &VMFault,
+        vspace: &mut VSpace,
+        page_ut: LocalCap<Untyped<PageBits>>,
+        page_slot: LocalCNodeSlot,
+        local_vspace_scratch: &mut ScratchRegion,
+    ) -> Result<MappedMemoryRegion<PageBits, shared_status::Exclusive, role::Local, A>, CowError>
+    {
+        let mapping = self
+            .written_mapping(fault, vspace)
+            .ok_or(CowError::NotCopyOnWrite)?;
+        let index = (fault.address - mapping.vaddr) >> PageBits::USIZE;
+        let page_vaddr = mapping.vaddr + index * PageBytes::USIZE;
+
+        let original_page =
+            &self.as_slice()[index * PageBytes::USIZE..(index + 1) * PageBytes::USIZE];
+        let fresh_page: LocalCap<Page<page_state::Unmapped>> = page_ut.retype(page_slot)?;
+        let mut private_page = fresh_page.to_region();
+        local_vspace_scratch.temporarily_map_region(&mut private_page, |page| {
+            page.as_mut_slice().copy_from_slice(original_page);
+            page.flush()
+        })??;
+
+        let shared_page: LocalCap<Page<page_state::Mapped>> = Cap {
+            cptr: mapping.start_cptr + index,
+            cap_data: Page {
+                state: page_state::Mapped {
+                    vaddr: page_vaddr,
+                    asid: mapping.asid,
+                    rights: CapRights::R,
+                },
+            },
+            _role: PhantomData,
+        };
+        let _ = shared_page.unmap_frame(PageBits::U8)?;
+        vspace.mappings.carve_out(page_vaddr, PageBytes::USIZE);
+        Ok(vspace
+            .map_region_at_addr(private_page, page_vaddr, CapRights::RW, A::default())
+            .map_err(|(e, _)| e)?)
+    }
+
+    fn written_mapping(&self, fault: &VMFault, vspace: &VSpace) -> Option<&CowMapping> {
+        let details = fault.details();
+        if details.cause != VMFaultCause::Permission || details.access != VMFaultAccess::Write {
+            return None;
+        }
+        self.mappings.iter().find(|m| {
+            m.asid == vspace.asid()
+                && fault.address >= m.vaddr
+                && fault.address - m.vaddr < self.original.size_bytes()
+        })
+    }
+
+    /// Unmap the region from `vspace`, making room for another mapping of
+    /// it, and hand back the slots its mapping there was made with. The
+    /// private copies made by `handle_write_fault` belong to the caller,
+    /// and stay mapped.
+    pub fn unmap_from(
+        &mut self,
+        vspace: &mut VSpace,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<LocalCNodeSlots<NumPages<SizeBits>>, CowError> {
+        let position = self
+            .mappings
+            .iter()
+            .position(|m| m.asid == vspace.asid())
+            .ok_or(CowError::NotMapped)?;
+        let start_cptr = self.mappings[position].start_cptr;
+        // Deleting the copies of the original page caps unmaps whichever
+        // of them haven't been replaced by private copies already.
+        for cptr in start_cptr..start_cptr + <NumPages<SizeBits>>::USIZE {
+            unsafe {
+                seL4_CNode_Delete(
+                    cnode.cptr,          // _service
+                    cptr,                // index
+                    seL4_WordBits as u8, // depth
+                )
+            }
+            .as_result()
+            .map_err(SeL4Error::CNodeDelete)?;
+        }
+        vspace
+            .mappings
+            .forget_frames(start_cptr, <NumPages<SizeBits>>::USIZE);
+        self.mappings.remove(position);
+        Ok(LocalCNodeSlots::internal_new(cnode.cptr, start_cptr))
+    }
+}
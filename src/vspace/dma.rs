@@ -0,0 +1,225 @@
+//! Buffers shared with DMA-capable devices, whose type records whether
+//! the CPU or a device owns their contents.
+use core::marker::PhantomData;
+use core::ops::Sub;
+
+use typenum::*;
+
+use super::{
+    memory_attributes, shared_status, KernelRetypeFanOutLimit, MappedMemoryRegion, NumPages,
+    UnmappedMemoryRegion, VSpace, VSpaceError,
+};
+use crate::arch::{self, PageBits};
+use crate::cap::{LocalCNodeSlots, LocalCap, Untyped};
+use crate::error::SeL4Error;
+use crate::pow::{Pow, _Pow};
+use crate::userland::CapRights;
+
+/// Which way the data flows while a device owns a buffer.
+pub trait Direction: private::SealedDirection {
+    /// Dirty lines must reach memory before the device reads it.
+    const CLEAN_FOR_DEVICE: bool;
+    /// Lines fetched while the device was writing must be discarded
+    /// before the CPU reads the result.
+    const INVALIDATE_FOR_CPU: bool;
+}
+
+pub mod direction {
+    use super::Direction;
+
+    /// The device only reads the buffer, e.g. a transmit buffer.
+    pub struct ToDevice;
+    impl Direction for ToDevice {
+        const CLEAN_FOR_DEVICE: bool = true;
+        const INVALIDATE_FOR_CPU: bool = false;
+    }
+
+    /// The device only writes the buffer, e.g. a receive buffer.
+    pub struct FromDevice;
+    impl Direction for FromDevice {
+        const CLEAN_FOR_DEVICE: bool = false;
+        const INVALIDATE_FOR_CPU: bool = true;
+    }
+
+    /// The device both reads and writes the buffer.
+    pub struct Bidirectional;
+    impl Direction for Bidirectional {
+        const CLEAN_FOR_DEVICE: bool = true;
+        const INVALIDATE_FOR_CPU: bool = true;
+    }
+}
+
+/// Who may currently touch a buffer's contents.
+pub trait Owner: private::SealedOwner {}
+
+pub mod owner {
+    use core::marker::PhantomData;
+
+    use super::{Direction, Owner};
+
+    /// The buffer may be read and written by the CPU.
+    pub struct Cpu;
+    impl Owner for Cpu {}
+
+    /// The buffer has been handed to a device for a transfer in direction
+    /// `D`, and must not be touched by the CPU until it is handed back.
+    pub struct Device<D: Direction> {
+        _direction: PhantomData<D>,
+    }
+    impl<D: Direction> Owner for Device<D> {}
+}
+
+mod private {
+    use super::direction::{Bidirectional, FromDevice, ToDevice};
+    use super::owner::{Cpu, Device};
+    use super::Direction;
+
+    pub trait SealedDirection {}
+    impl SealedDirection for ToDevice {}
+    impl SealedDirection for FromDevice {}
+    impl SealedDirection for Bidirectional {}
+
+    pub trait SealedOwner {}
+    impl SealedOwner for Cpu {}
+    impl<D: Direction> SealedOwner for Device<D> {}
+}
+
+/// A `1 << SizeBits` bytes, physically contiguous buffer for DMA,
+/// currently owned by `O`. Handing it over in either direction does the
+/// cache maintenance the transfer calls for, to the point of coherency.
+/// Memory shared with a device for its whole lifetime, such as descriptor
+/// rings, is often better mapped `memory_attributes::Uncached` instead.
+pub struct DmaBuffer<SizeBits: Unsigned, O: Owner = owner::Cpu>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    region: MappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+    paddr: usize,
+    _owner: PhantomData<O>,
+}
+
+impl<SizeBits: Unsigned> DmaBuffer<SizeBits, owner::Cpu>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Retype `ut` into pages and map them, cacheable and read-write, into
+    /// the local `vspace`. An untyped is contiguous in physical memory, so
+    /// the buffer is as well.
+    pub fn new(
+        ut: LocalCap<Untyped<SizeBits>>,
+        slots: LocalCNodeSlots<NumPages<SizeBits>>,
+        vspace: &mut VSpace,
+    ) -> Result<Self, VSpaceError>
+    where
+        Pow<<SizeBits as Sub<PageBits>>::Output>:
+            IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
+    {
+        let region = UnmappedMemoryRegion::new(ut, slots)?;
+        let region = vspace.map_region(region, CapRights::RW, memory_attributes::Cached)?;
+        Ok(DmaBuffer::from_region(region)?)
+    }
+
+    /// Use an already mapped local region as a DMA buffer.
+    pub fn from_region(
+        region: MappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+    ) -> Result<Self, SeL4Error> {
+        let paddr = region.paddr()?;
+        Ok(DmaBuffer {
+            region,
+            paddr,
+            _owner: PhantomData,
+        })
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self.region.as_slice()
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.region.as_mut_slice()
+    }
+
+    /// Hand the buffer to a device for a transfer in direction `D`.
+    pub fn into_device<D: Direction>(
+        self,
+    ) -> Result<DmaBuffer<SizeBits, owner::Device<D>>, (SeL4Error, Self)> {
+        // Nothing written by the CPU may later be evicted over what the
+        // device writes, so buffers the device only fills are cleaned too.
+        let op: unsafe fn(usize, usize, usize) -> Result<(), SeL4Error> = if D::CLEAN_FOR_DEVICE {
+            arch::clean_frame_range
+        } else {
+            arch::clean_invalidate_frame_range
+        };
+        match self.region.maintain_range(0, self.size_bytes(), op) {
+            Ok(()) => Ok(self.with_owner()),
+            Err(e) => Err((e, self)),
+        }
+    }
+
+    /// Give up the buffer's DMA role, e.g. to unmap it.
+    pub fn into_region(self) -> MappedMemoryRegion<SizeBits, shared_status::Exclusive> {
+        self.region
+    }
+}
+
+impl<SizeBits: Unsigned, D: Direction> DmaBuffer<SizeBits, owner::Device<D>>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Take the buffer back once the device has finished with it.
+    pub fn into_cpu(self) -> Result<DmaBuffer<SizeBits, owner::Cpu>, (SeL4Error, Self)> {
+        // The CPU may have speculatively fetched lines while the device
+        // was writing, so those must go.
+        if D::INVALIDATE_FOR_CPU {
+            if let Err(e) =
+                self.region
+                    .maintain_range(0, self.size_bytes(), arch::invalidate_frame_range)
+            {
+                return Err((e, self));
+            }
+        }
+        Ok(self.with_owner())
+    }
+}
+
+impl<SizeBits: Unsigned, O: Owner> DmaBuffer<SizeBits, O>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    pub fn vaddr(&self) -> usize {
+        self.region.vaddr()
+    }
+
+    /// The physical address of the start of the buffer, for the device.
+    pub fn paddr(&self) -> usize {
+        self.paddr
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.region.size_bytes()
+    }
+
+    fn with_owner<NewOwner: Owner>(self) -> DmaBuffer<SizeBits, NewOwner> {
+        DmaBuffer {
+            region: self.region,
+            paddr: self.paddr,
+            _owner: PhantomData,
+        }
+    }
+}
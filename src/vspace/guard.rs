@@ -0,0 +1,169 @@
+//! Guard spans: deliberately unmapped stretches of address space placed
+//! around stacks and regions, so that running off either end of one
+//! faults instead of silently corrupting its neighbour.
+use core::fmt;
+
+use arrayvec::{ArrayString, ArrayVec};
+
+/// The most guards a single `VSpace` keeps track of.
+pub const MAX_GUARDS: usize = 16;
+
+/// Guards are named after what they protect, truncated to this many bytes.
+pub const MAX_GUARD_NAME_BYTES: usize = 16;
+
+/// How many pages of guard a `VSpace` places either side of what it maps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GuardSpans {
+    /// Pages of guard either side of a stack.
+    pub stack_pages: usize,
+    /// Pages of guard either side of each region placed by the VSpace
+    /// itself, e.g. by `map_region`, or reserved with `reserve`. Regions
+    /// mapped at an explicit address never get guards.
+    pub region_pages: usize,
+}
+
+impl Default for GuardSpans {
+    /// A page either side of stacks and none around regions, so that
+    /// repeatedly mapped regions stay packed together.
+    fn default() -> Self {
+        GuardSpans {
+            stack_pages: 1,
+            region_pages: 0,
+        }
+    }
+}
+
+/// What a guard protects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardKind {
+    Stack,
+    Region,
+}
+
+/// Which end of the protected span a guard sits at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardSide {
+    Below,
+    Above,
+}
+
+/// An unmapped span of address space next to a stack or region.
+#[derive(Debug, Clone, Copy)]
+pub struct Guard {
+    pub start: usize,
+    pub size: usize,
+    pub kind: GuardKind,
+    pub side: GuardSide,
+    /// The start of the stack or region being protected.
+    pub region_start: usize,
+    name: ArrayString<[u8; MAX_GUARD_NAME_BYTES]>,
+}
+
+impl Guard {
+    pub fn contains(&self, address: usize) -> bool {
+        address >= self.start && address - self.start < self.size
+    }
+
+    /// The name given to the protected stack or region with
+    /// `VSpace::name_region`, if any.
+    pub fn name(&self) -> Option<&str> {
+        if self.name.is_empty() {
+            None
+        } else {
+            Some(&*self.name)
+        }
+    }
+}
+
+impl fmt::Display for Guard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Stacks grow down, so running off their bottom is the overflow.
+        let what = match (self.kind, self.side) {
+            (GuardKind::Stack, GuardSide::Below) => "stack overflow in thread",
+            (GuardKind::Stack, GuardSide::Above) => "stack underflow in thread",
+            (GuardKind::Region, GuardSide::Above) => "overrun of region",
+            (GuardKind::Region, GuardSide::Below) => "underrun of region",
+        };
+        match self.name() {
+            Some(name) => write!(f, "{} {}", what, name),
+            None => write!(f, "{} at {:#x}", what, self.region_start),
+        }
+    }
+}
+
+/// The guards placed in a `VSpace`, along with its guard configuration,
+/// kept so that a fault in one can be reported as e.g. a stack overflow.
+#[derive(Default)]
+pub(super) struct GuardTable {
+    pub(super) spans: GuardSpans,
+    guards: ArrayVec<[Guard; MAX_GUARDS]>,
+    /// How many guards didn't fit in the table. Their spans are still
+    /// left unmapped.
+    untracked: usize,
+}
+
+impl GuardTable {
+    pub(super) fn as_slice(&self) -> &[Guard] {
+        &self.guards
+    }
+
+    pub(super) fn pages_for(&self, kind: GuardKind) -> usize {
+        match kind {
+            GuardKind::Stack => self.spans.stack_pages,
+            GuardKind::Region => self.spans.region_pages,
+        }
+    }
+
+    pub(super) fn untracked(&self) -> usize {
+        self.untracked
+    }
+
+    pub(super) fn record(
+        &mut self,
+        start: usize,
+        size: usize,
+        kind: GuardKind,
+        side: GuardSide,
+        region_start: usize,
+    ) {
+        if size == 0 {
+            return;
+        }
+        if self.guards.is_full() {
+            self.untracked += 1;
+            return;
+        }
+        self.guards.push(Guard {
+            start,
+            size,
+            kind,
+            side,
+            region_start,
+            name: ArrayString::new(),
+        });
+    }
+
+    pub(super) fn name_region(&mut self, region_start: usize, name: &str) {
+        for guard in self
+            .guards
+            .iter_mut()
+            .filter(|g| g.region_start == region_start)
+        {
+            guard.name.clear();
+            for c in name.chars() {
+                if guard.name.try_push(c).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// The guard below a stack or region, placed before the span it protects.
+/// Hand it back to `VSpace::place_guard_above` once that span is mapped.
+#[must_use]
+pub(crate) struct PendingGuard {
+    pub(super) kind: GuardKind,
+    pub(super) pages: usize,
+    pub(super) start: usize,
+}
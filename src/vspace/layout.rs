@@ -0,0 +1,254 @@
+//! What a `VSpace` has mapped where, recorded as each mapping is made.
+use core::fmt;
+
+use arrayvec::ArrayVec;
+use typenum::Unsigned;
+
+use super::Guard;
+use crate::arch::PageBits;
+use crate::cap::WeakMemoryKind;
+use crate::userland::CapRights;
+
+/// The most mappings a single `VSpace` keeps track of. Mappings made
+/// beyond this still work, they just aren't listed.
+pub const MAX_TRACKED_MAPPINGS: usize = 32;
+
+/// Where the memory behind a mapping came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MappingSource {
+    /// The code and data of the process image, mapped when the VSpace
+    /// was created.
+    Image,
+    /// A memory region, made of the frames whose caps start at
+    /// `start_cptr`.
+    Region {
+        kind: WeakMemoryKind,
+        start_cptr: usize,
+    },
+}
+
+/// A span of a `VSpace` mapped with uniform rights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mapping {
+    pub start: usize,
+    pub size: usize,
+    pub rights: CapRights,
+    /// Whether the frames behind the mapping may be mapped elsewhere too.
+    pub shared: bool,
+    pub source: MappingSource,
+}
+
+impl Mapping {
+    pub fn end(&self) -> usize {
+        self.start + self.size
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        address >= self.start && address - self.start < self.size
+    }
+}
+
+/// The mappings made in a `VSpace`, kept in address order.
+#[derive(Default)]
+pub(super) struct MappingTable {
+    mappings: ArrayVec<[Mapping; MAX_TRACKED_MAPPINGS]>,
+    /// How many mappings didn't fit in the table.
+    untracked: usize,
+}
+
+impl MappingTable {
+    pub(super) fn as_slice(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    pub(super) fn untracked(&self) -> usize {
+        self.untracked
+    }
+
+    pub(super) fn record(&mut self, mapping: Mapping) {
+        let index = self
+            .mappings
+            .iter()
+            .position(|m| m.start > mapping.start)
+            .unwrap_or_else(|| self.mappings.len());
+        // The image is mapped a page at a time, so extend the previous
+        // span where possible rather than list every page.
+        if mapping.source == MappingSource::Image && index > 0 {
+            let previous = &mut self.mappings[index - 1];
+            if previous.source == MappingSource::Image
+                && previous.end() == mapping.start
+                && previous.rights == mapping.rights
+            {
+                previous.size += mapping.size;
+                return;
+            }
+        }
+        if self.mappings.is_full() {
+            self.untracked += 1;
+            return;
+        }
+        self.mappings.insert(index, mapping);
+    }
+
+    pub(super) fn forget(&mut self, start: usize, size: usize) {
+        if let Some(index) = self
+            .mappings
+            .iter()
+            .position(|m| m.start == start && m.size == size)
+        {
+            self.mappings.remove(index);
+        }
+    }
+
+    /// Stop listing the pages from `start` to `start + size` as part of
+    /// the region mapping them, e.g. because some of its pages have been
+    /// replaced. The rest of the mapping stays listed, split in two if
+    /// need be.
+    pub(super) fn carve_out(&mut self, start: usize, size: usize) {
+        let index = match self.mappings.iter().position(|m| m.contains(start)) {
+            Some(index) => index,
+            None => return,
+        };
+        let mapping = self.mappings[index];
+        let end = start + size;
+        if mapping.start == start {
+            self.mappings.remove(index);
+        } else {
+            self.mappings[index].size = start - mapping.start;
+        }
+        if end < mapping.end() {
+            let source = match mapping.source {
+                MappingSource::Region { kind, start_cptr } => MappingSource::Region {
+                    kind,
+                    start_cptr: start_cptr + ((end - mapping.start) >> PageBits::USIZE),
+                },
+                MappingSource::Image => MappingSource::Image,
+            };
+            self.record(Mapping {
+                start: end,
+                size: mapping.end() - end,
+                source,
+                ..mapping
+            });
+        }
+    }
+
+    /// Forget every listed mapping made of the frames whose caps are the
+    /// `count` starting at `start_cptr`.
+    pub(super) fn forget_frames(&mut self, start_cptr: usize, count: usize) {
+        self.mappings.retain(|m| match m.source {
+            MappingSource::Region {
+                start_cptr: cptr, ..
+            } => cptr < start_cptr || cptr - start_cptr >= count,
+            MappingSource::Image => true,
+        });
+    }
+
+    pub(super) fn set_rights(&mut self, start: usize, size: usize, rights: CapRights) {
+        if let Some(m) = self
+            .mappings
+            .iter_mut()
+            .find(|m| m.start == start && m.size == size)
+        {
+            m.rights = rights;
+        }
+    }
+}
+
+/// A printable snapshot of a `VSpace`'s layout: its mappings and guards
+/// in address order, followed by the address space still available to
+/// place regions in.
+pub struct Layout<'a> {
+    pub(super) mappings: &'a [Mapping],
+    pub(super) guards: &'a [Guard],
+    pub(super) untracked: usize,
+    pub(super) available: (usize, usize),
+}
+
+impl<'a> Layout<'a> {
+    pub fn mappings(&self) -> &'a [Mapping] {
+        self.mappings
+    }
+
+    pub fn guards(&self) -> &'a [Guard] {
+        self.guards
+    }
+
+    /// The lowest and highest addresses between which regions are still
+    /// placed automatically.
+    pub fn available(&self) -> (usize, usize) {
+        self.available
+    }
+}
+
+impl fmt::Display for MappingSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MappingSource::Image => f.write_str("image"),
+            MappingSource::Region {
+                kind: WeakMemoryKind::General,
+                start_cptr,
+            } => write!(f, "region (caps from {})", start_cptr),
+            MappingSource::Region {
+                kind: WeakMemoryKind::Device { paddr },
+                start_cptr,
+            } => write!(
+                f,
+                "device region at paddr {:#x} (caps from {})",
+                paddr, start_cptr
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#010x}-{:#010x} {:?} {} {}",
+            self.start,
+            self.end(),
+            self.rights,
+            if self.shared { "shared" } else { "exclusive" },
+            self.source
+        )
+    }
+}
+
+impl<'a> fmt::Display for Layout<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Both lists are short, so merge them by address the simple way.
+        let mut mappings = self.mappings.iter().peekable();
+        let mut guards = self
+            .guards
+            .iter()
+            .collect::<ArrayVec<[&Guard; super::MAX_GUARDS]>>();
+        guards.sort_unstable_by_key(|g| g.start);
+        let mut guards = guards.into_iter().peekable();
+        loop {
+            let guard_next = match (mappings.peek(), guards.peek()) {
+                (Some(m), Some(g)) => g.start < m.start,
+                (None, Some(_)) => true,
+                (Some(_), None) => false,
+                (None, None) => break,
+            };
+            if guard_next {
+                let g = guards.next().unwrap();
+                writeln!(
+                    f,
+                    "{:#010x}-{:#010x} guard: {}",
+                    g.start,
+                    g.start + g.size,
+                    g
+                )?;
+            } else {
+                writeln!(f, "{}", mappings.next().unwrap())?;
+            }
+        }
+        if self.untracked > 0 {
+            writeln!(f, "({} more mappings not tracked)", self.untracked)?;
+        }
+        let (bottom, top) = self.available;
+        write!(f, "available: {:#010x}-{:#010x}", bottom, top)
+    }
+}
@@ -7,21 +7,34 @@
 use core::marker::PhantomData;
 use core::ops::Sub;
 
+use selfe_sys::{seL4_CNode_Delete, seL4_CNode_Revoke, seL4_WordBits};
+
 use typenum::*;
 
 use crate::alloc::ut_buddy::{self, UTBuddyError, WUTBuddy};
+use crate::arch::fault::VMFault;
 use crate::arch::{self, AddressSpace, PageBits, PageBytes, PagingRoot, PagingRootLowerLevel};
 use crate::bootstrap::UserImage;
 use crate::cap::{
     memory_kind, page_state, role, AssignedASID, CNodeRole, CNodeSlots, Cap, CapRange, CapType,
     ChildCNodeSlot, DirectRetype, InternalASID, LocalCNode, LocalCNodeSlots, LocalCap, Page,
-    PhantomCap, RetypeError, UnassignedASID, Untyped, WCNodeSlots, WCNodeSlotsData, WUntyped,
-    WeakCapRange, WeakCopyError,
+    PhantomCap, ReleasedASID, RetypeError, UnassignedASID, Untyped, WCNodeSlots, WCNodeSlotsData,
+    WUntyped, WeakCapRange, WeakCopyError,
 };
-use crate::error::SeL4Error;
+use crate::error::{ErrorExt, SeL4Error};
 use crate::pow::{Pow, _Pow};
-use crate::userland::CapRights;
+use crate::userland::{CapRights, KnownRegion, Rights, VMFaultReport};
+mod cow;
+pub mod dma;
+mod guard;
+mod layout;
 mod region;
+pub use cow::{CowError, CowRegion, MAX_COW_MAPPINGS};
+use guard::GuardTable;
+pub(crate) use guard::PendingGuard;
+pub use guard::{Guard, GuardKind, GuardSide, GuardSpans, MAX_GUARDS, MAX_GUARD_NAME_BYTES};
+use layout::MappingTable;
+pub use layout::{Layout, Mapping, MappingSource, MAX_TRACKED_MAPPINGS};
 pub use region::*;
 
 include!(concat!(env!("OUT_DIR"), "/KERNEL_RETYPE_FAN_OUT_LIMIT"));
@@ -271,6 +284,8 @@ where
 
 // 2^12 / PageCount
 pub type NumPages<Size> = Pow<op!(Size - PageBits)>;
+/// The number of `FrameBits`-sized frames in a region of `Size` bits.
+pub type NumFrames<Size, FrameBits> = Pow<op!(Size - FrameBits)>;
 
 pub enum ProcessCodeImageConfig<'a> {
     ReadOnly,
@@ -301,6 +316,10 @@ pub struct VSpace<State: VSpaceState = vspace_state::Imaged, CapRole: CNodeRole
     untyped: WUTBuddy<CapRole>,
     slots: Cap<WCNodeSlotsData<CapRole>, CapRole>,
     available_address_range: AvailableAddressRange,
+    /// The guard spans placed around stacks and regions so far.
+    guards: GuardTable,
+    /// What has been mapped where, for introspection.
+    mappings: MappingTable,
     _state: PhantomData<State>,
 }
 
@@ -319,6 +338,8 @@ impl VSpace<vspace_state::Empty, role::Local> {
             untyped: ut_buddy::weak_ut_buddy(untyped),
             slots,
             available_address_range: AvailableAddressRange::default(),
+            guards: GuardTable::default(),
+            mappings: MappingTable::default(),
             _state: PhantomData,
         })
     }
@@ -336,6 +357,45 @@ impl<State: VSpaceState, CapRole: CNodeRole> VSpace<State, CapRole> {
 }
 
 impl<State: VSpaceState> VSpace<State, role::Local> {
+    /// Tear down this address space by deleting its root, along with any
+    /// copies of it, such as the one a thread running in it holds. The
+    /// kernel frees the ASID it was assigned, which is handed back for
+    /// returning to the `ASIDManager` it came from. Pages mapped into it
+    /// are left to their owners, though their caps still count as mapped.
+    /// The paging structures it made, and the untyped and slots it was
+    /// given to make them with, are not reclaimed; that takes revoking the
+    /// untyped they were carved out of.
+    pub fn destroy(self, cnode: &LocalCap<LocalCNode>) -> Result<ReleasedASID, VSpaceError> {
+        unsafe {
+            seL4_CNode_Revoke(
+                cnode.cptr,          // _service
+                self.root.cptr,      // index
+                seL4_WordBits as u8, // depth
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::CNodeRevoke)?;
+        unsafe {
+            seL4_CNode_Delete(
+                cnode.cptr,          // _service
+                self.root.cptr,      // index
+                seL4_WordBits as u8, // depth
+            )
+        }
+        .as_result()
+        .map_err(SeL4Error::CNodeDelete)?;
+        Ok(ReleasedASID { asid: self.asid })
+    }
+
+    /// Give up on an address space that failed partway through being set
+    /// up, destroying it so that the kernel frees the ASID it was just
+    /// assigned. A constructor that fails thus leaves its ASID unassigned,
+    /// as the `ASIDManager` it came from expects. Returns `cause`.
+    fn abandon(self, cnode: &LocalCap<LocalCNode>, cause: VSpaceError) -> VSpaceError {
+        let _ = self.destroy(cnode);
+        cause
+    }
+
     /// A thin wrapper around self.layers.map_layer that reduces the amount
     /// of repetitive, visible self-reference
     fn map_page_at_addr_without_watermarking(
@@ -373,6 +433,40 @@ impl<State: VSpaceState> VSpace<State, role::Local> {
                 e => VSpaceError::MappingError(e),
             })
     }
+
+    /// Map one of the frames backing a region, which is a page unless
+    /// the region was built from large frames of `frame_bits` bits.
+    fn map_frame(
+        &mut self,
+        frame: &LocalCap<Page<page_state::Unmapped>>,
+        frame_bits: u8,
+        address: usize,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<(), MappingError> {
+        if frame_bits == PageBits::U8 {
+            self.layers.map_layer(
+                frame,
+                address,
+                &mut self.root,
+                rights,
+                vm_attributes,
+                &mut self.untyped,
+                &mut self.slots,
+            )
+        } else {
+            self.layers.map_large_frame(
+                frame,
+                frame_bits,
+                address,
+                &mut self.root,
+                rights,
+                vm_attributes,
+                &mut self.untyped,
+                &mut self.slots,
+            )
+        }
+    }
 }
 
 // 0xfff, for 4k pages
@@ -413,9 +507,14 @@ fn iterate_by_page(start: usize, end: usize) -> impl Iterator<Item = (usize, usi
 
 impl VSpace<vspace_state::Imaged, role::Local> {
     /// Unmap a region.
-    pub fn unmap_region<SizeBits: Unsigned, SS: SharedStatus>(
+    pub fn unmap_region<
+        SizeBits: Unsigned,
+        SS: SharedStatus,
+        A: MemoryAttributes,
+        MR: MappedRights,
+    >(
         &mut self,
-        region: MappedMemoryRegion<SizeBits, SS>,
+        region: MappedMemoryRegion<SizeBits, SS, role::Local, A, MR>,
     ) -> Result<UnmappedMemoryRegion<SizeBits, SS>, VSpaceError>
     where
         SizeBits: IsGreaterOrEqual<PageBits>,
@@ -425,7 +524,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
     {
         self.weak_unmap_region(region.weaken())
-            .and_then(|r| r.as_strong::<SizeBits>())
+            .and_then(|r| r.as_strong::<SizeBits, _, _>())
     }
     /// Unmap a weak region.
     pub fn weak_unmap_region<SS: SharedStatus>(
@@ -437,22 +536,116 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         }
         let start_cptr = region.caps.start_cptr;
         let size_bits = region.size_bits();
+        let granule_bits = region.granule_bits();
+        self.mappings.forget(region.vaddr(), region.size_bytes());
         for page_cap in region.caps.into_iter() {
-            let _ = self.unmap_page(page_cap)?;
+            let _ = self.unmap_page(page_cap, granule_bits)?;
         }
         Ok(WeakMemoryRegion::unchecked_new(
             start_cptr,
             page_state::Unmapped,
             region.kind,
             size_bits,
+            granule_bits,
+        ))
+    }
+
+    /// Change the rights a region is mapped with, in place, and record
+    /// them in its type, e.g. to seal a buffer read-only once it has
+    /// been filled:
+    ///
+    /// ```not_rust
+    /// let sealed = vspace.protect_region(region, mapped_rights::R {})?;
+    /// ```
+    ///
+    /// The kernel caps the new rights at those of the region's page caps.
+    /// If the kernel refuses part-way through, the region comes back
+    /// with its frames mapped with a mix of old and new rights.
+    pub fn protect_region<
+        SizeBits: Unsigned,
+        SS: SharedStatus,
+        A: MemoryAttributes,
+        OldRights: MappedRights,
+        NewRights: MappedRights + Rights,
+    >(
+        &mut self,
+        region: MappedMemoryRegion<SizeBits, SS, role::Local, A, OldRights>,
+        _rights: NewRights,
+    ) -> Result<
+        MappedMemoryRegion<SizeBits, SS, role::Local, A, NewRights>,
+        (
+            VSpaceError,
+            Option<MappedMemoryRegion<SizeBits, SS, role::Local, A, OldRights>>,
+        ),
+    >
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        match self.weak_protect_region(
+            region.weaken(),
+            NewRights::as_caprights(),
+            A::default().vm_attributes(),
+        ) {
+            Ok(r) => Ok(r.as_strong::<SizeBits, _, _>().map_err(|e| (e, None))?),
+            Err((e, r)) => Err((e, r.as_strong::<SizeBits, _, _>().ok())),
+        }
+    }
+
+    /// Change the rights a weak region is mapped with, in place. As the
+    /// region doesn't know how it was mapped, the `vm_attributes` must be
+    /// given again.
+    pub fn weak_protect_region<SS: SharedStatus>(
+        &mut self,
+        region: WeakMappedMemoryRegion<SS>,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<WeakMappedMemoryRegion<SS>, (VSpaceError, WeakMappedMemoryRegion<SS>)> {
+        if self.asid != region.asid() {
+            return Err((VSpaceError::ASIDMismatch, region));
+        }
+        let vaddr = region.vaddr();
+        let frame_bytes = 1 << region.granule_bits();
+        for index in 0..region.caps.len() {
+            let frame_vaddr = vaddr + index * frame_bytes;
+            let mut frame: LocalCap<Page<page_state::Mapped>> = Cap {
+                cptr: region.caps.start_cptr + index,
+                cap_data: Page {
+                    state: page_state::Mapped {
+                        vaddr: frame_vaddr,
+                        asid: self.asid,
+                        rights: region.rights(),
+                    },
+                },
+                _role: PhantomData,
+            };
+            if let Err(e) = frame.remap(frame_vaddr, &self.root, rights, vm_attributes) {
+                return Err((VSpaceError::SeL4Error(e), region));
+            }
+        }
+        self.mappings.set_rights(vaddr, region.size_bytes(), rights);
+        Ok(WeakMappedMemoryRegion::unchecked_new(
+            region.caps.start_cptr,
+            page_state::Mapped {
+                vaddr,
+                asid: self.asid,
+                rights,
+            },
+            region.kind,
+            region.size_bits(),
+            region.granule_bits(),
         ))
     }
 
     fn unmap_page(
         &mut self,
         page: LocalCap<Page<page_state::Mapped>>,
+        frame_bits: u8,
     ) -> Result<LocalCap<Page<page_state::Unmapped>>, SeL4Error> {
-        page.unmap()
+        page.unmap_frame(frame_bits)
     }
 
     // This function will move the caps into the child's CSpace so
@@ -471,6 +664,8 @@ impl VSpace<vspace_state::Imaged, role::Local> {
             untyped,
             slots: _,
             available_address_range,
+            guards,
+            mappings,
             ..
         } = self;
         let child_root = root.move_to_slot(src_cnode, child_root_slot)?;
@@ -490,6 +685,8 @@ impl VSpace<vspace_state::Imaged, role::Local> {
             untyped: child_untyped,
             slots: child_paging_slots,
             available_address_range,
+            guards,
+            mappings,
             _state: PhantomData,
         })
     }
@@ -530,169 +727,229 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         paging_untyped: LocalCap<WUntyped<memory_kind::General>>,
         // Things relating to user image code
         elf_data: &[u8],
-        mut page_slots: WCNodeSlots,
+        page_slots: WCNodeSlots,
         elf_writable_mem: LocalCap<WUntyped<memory_kind::General>>,
         user_image: &UserImage<role::Local>,
         parent_cnode: &LocalCap<LocalCNode>,
         local_vspace_scratch: &mut ScratchRegion,
+    ) -> Result<Self, VSpaceError> {
+        Self::new_from_shared_elf_weak(
+            paging_root,
+            asid,
+            slots,
+            paging_untyped,
+            &SharedElfSegments::new(elf_data, user_image)?,
+            page_slots,
+            elf_writable_mem,
+            parent_cnode,
+            local_vspace_scratch,
+        )
+    }
+
+    /// Set up another instance of an image whose read-only segments have
+    /// been resolved ahead of time, mapping the same frames for them as
+    /// every other instance. Only the writable segments are copied.
+    pub fn new_from_shared_elf<E: ElfProc>(
+        paging_root: LocalCap<PagingRoot>,
+        asid: LocalCap<UnassignedASID>,
+        slots: WCNodeSlots,
+        paging_untyped: LocalCap<WUntyped<memory_kind::General>>,
+        segments: &SharedElfSegments,
+        page_slots: LocalCNodeSlots<E::RequiredPages>,
+        elf_writable_mem: LocalCap<Untyped<E::RequiredMemoryBits>>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        local_vspace_scratch: &mut ScratchRegion,
+    ) -> Result<Self, VSpaceError> {
+        Self::new_from_shared_elf_weak(
+            paging_root,
+            asid,
+            slots,
+            paging_untyped,
+            segments,
+            page_slots.weaken(),
+            elf_writable_mem.weaken(),
+            parent_cnode,
+            local_vspace_scratch,
+        )
+    }
+
+    pub fn new_from_shared_elf_weak(
+        paging_root: LocalCap<PagingRoot>,
+        asid: LocalCap<UnassignedASID>,
+        slots: WCNodeSlots,
+        paging_untyped: LocalCap<WUntyped<memory_kind::General>>,
+        segments: &SharedElfSegments,
+        page_slots: WCNodeSlots,
+        elf_writable_mem: LocalCap<WUntyped<memory_kind::General>>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        local_vspace_scratch: &mut ScratchRegion,
     ) -> Result<Self, VSpaceError> {
         let mut vspace =
             VSpace::<vspace_state::Empty>::new(paging_root, asid, slots, paging_untyped)?;
+        if let Err(e) = Self::map_shared_elf(
+            &mut vspace,
+            segments,
+            page_slots,
+            elf_writable_mem,
+            parent_cnode,
+            local_vspace_scratch,
+        ) {
+            return Err(vspace.abandon(parent_cnode, e));
+        }
+
+        let mut vspace = VSpace {
+            root: vspace.root,
+            asid: vspace.asid,
+            layers: vspace.layers,
+            untyped: vspace.untyped,
+            slots: vspace.slots,
+            available_address_range: vspace.available_address_range,
+            guards: vspace.guards,
+            mappings: vspace.mappings,
+            _state: PhantomData,
+        };
+
+        // allocate a padding page
+        if let Err(e) = vspace.skip_pages(1) {
+            return Err(vspace.abandon(parent_cnode, e));
+        }
+
+        Ok(vspace)
+    }
 
+    fn map_shared_elf(
+        vspace: &mut VSpace<vspace_state::Empty>,
+        segments: &SharedElfSegments,
+        mut page_slots: WCNodeSlots,
+        elf_writable_mem: LocalCap<WUntyped<memory_kind::General>>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        local_vspace_scratch: &mut ScratchRegion,
+    ) -> Result<(), VSpaceError> {
+        let elf_data = segments.elf_data();
         let elf = xmas_elf::ElfFile::new(elf_data).map_err(VSpaceError::ElfParseError)?;
 
         let mut writable_segment_pages_iter =
             elf_writable_mem.retype_pages(&mut page_slots)?.into_iter();
 
+        // Writable segments need to be copied into memory owned by the
+        // new process.
         for program_header in elf
             .program_iter()
             .filter(|h| h.get_type() == Ok(xmas_elf::program::Type::Load))
+            .filter(|h| h.flags().is_write())
         {
             let target_vaddr = program_header.virtual_addr() as usize;
-            let offset = program_header.offset();
 
             let file_size = program_header.file_size() as usize;
-            let flags = program_header.flags();
 
-            let vm_attrs = if flags.is_execute() {
+            let vm_attrs = if program_header.flags().is_execute() {
                 arch::vm_attributes::PROGRAM_CODE
             } else {
                 arch::vm_attributes::PROGRAM_DATA
             };
 
-            if flags.is_write() {
-                // Writable segments need to be copied into memory owned by the
-                // new process.
+            // how much space this segment occupies in memory. For writable
+            // segments, this is often larger than the size in the file, for
+            // things like the BSS section. This memory is zeroed out
+            // below.
+            let mem_size = program_header.mem_size() as usize;
+            let src_offset = program_header.offset() as usize;
+
+            for (target_vaddr_start, target_vaddr_end) in
+                iterate_by_page(target_vaddr, target_vaddr + mem_size)
+            {
+                let curr_page_vaddr = target_vaddr_start & !PAGE_MASK;
+                // debug_println!("-- setting up writable range {:X}-{:X}", target_vaddr_start,
+                // target_vaddr_end);
+
+                // if this fails, it means that we weren't given enough
+                // resources to map all the pages.This shouldn't happen, as
+                // we've got it written down in a type that's extracted from
+                // the binary itself.
+                let dest_page = writable_segment_pages_iter
+                    .next()
+                    .ok_or(VSpaceError::InsufficientResourcesForElf)?;
+
+                let mut unmapped_region = dest_page.to_region();
+                let _ = local_vspace_scratch.temporarily_map_region::<PageBits, _, _>(
+                    &mut unmapped_region,
+                    |temp_mapped_region| {
+                        let dest_mem = temp_mapped_region.as_mut_slice();
+
+                        // zero out the whole page
+                        for dest in &mut dest_mem[..] {
+                            *dest = 0;
+                        }
 
-                // how much space this segment occupies in memory. For writable
-                // segments, this is often larger than the size in the file, for
-                // things like the BSS section. This memory is zeroed out
-                // below.
-                let mem_size = program_header.mem_size() as usize;
-                let src_offset = program_header.offset() as usize;
+                        // if this overlaps with any file-provided data, copy it over
+                        if target_vaddr_start < (target_vaddr + file_size) {
+                            let src_start = src_offset + (target_vaddr_start - target_vaddr);
+                            let src_end = core::cmp::min(
+                                src_offset + (target_vaddr_end - target_vaddr),
+                                src_offset + file_size,
+                            );
+
+                            let data_start_in_page = src_start & PAGE_MASK;
+                            let data_end_in_page = src_end & PAGE_MASK;
+                            let data_end_in_page = if data_end_in_page == 0 {
+                                arch::PageBytes::USIZE
+                            } else {
+                                data_end_in_page
+                            };
+                            let dest_slice = &mut dest_mem[data_start_in_page..data_end_in_page];
+                            dest_slice.copy_from_slice(&elf_data[src_start..src_end]);
+                        }
 
-                for (target_vaddr_start, target_vaddr_end) in
-                    iterate_by_page(target_vaddr, target_vaddr + mem_size)
-                {
-                    let curr_page_vaddr = target_vaddr_start & !PAGE_MASK;
-                    // debug_println!("-- setting up writable range {:X}-{:X}", target_vaddr_start,
-                    // target_vaddr_end);
+                        temp_mapped_region.flush().unwrap();
+                    },
+                );
+
+                let _ = vspace.map_page_at_addr_without_watermarking(
+                    unmapped_region.to_page(),
+                    curr_page_vaddr,
+                    CapRights::RW,
+                    vm_attrs,
+                )?;
+
+                vspace
+                    .available_address_range
+                    .observe_mapping(curr_page_vaddr, PageBits::U8)?;
+                vspace
+                    .mappings
+                    .record(image_page_mapping(curr_page_vaddr, CapRights::RW));
+            }
+        }
 
-                    // if this fails, it means that we weren't given enough
+        // Read-only segments map the frames they already occupy in our
+        // own image.
+        for (segment_vaddr, pages, vm_attributes) in segments.segments() {
+            let copied_pages = pages
+                .copy(parent_cnode, &mut page_slots, CapRights::R)
+                .map_err(|e| match e {
+                    // If this fails, it means that we weren't given enough
                     // resources to map all the pages.This shouldn't happen, as
                     // we've got it written down in a type that's extracted from
                     // the binary itself.
-                    let dest_page = writable_segment_pages_iter
-                        .next()
-                        .ok_or(VSpaceError::InsufficientResourcesForElf)?;
-
-                    let mut unmapped_region = dest_page.to_region();
-                    let _ = local_vspace_scratch.temporarily_map_region::<PageBits, _, _>(
-                        &mut unmapped_region,
-                        |temp_mapped_region| {
-                            let dest_mem = temp_mapped_region.as_mut_slice();
-
-                            // zero out the whole page
-                            for dest in &mut dest_mem[..] {
-                                *dest = 0;
-                            }
-
-                            // if this overlaps with any file-provided data, copy it over
-                            if target_vaddr_start < (target_vaddr + file_size) {
-                                let src_start = src_offset + (target_vaddr_start - target_vaddr);
-                                let src_end = core::cmp::min(
-                                    src_offset + (target_vaddr_end - target_vaddr),
-                                    src_offset + file_size,
-                                );
-
-                                let data_start_in_page = src_start & PAGE_MASK;
-                                let data_end_in_page = src_end & PAGE_MASK;
-                                let data_end_in_page = if data_end_in_page == 0 {
-                                    arch::PageBytes::USIZE
-                                } else {
-                                    data_end_in_page
-                                };
-                                let dest_slice =
-                                    &mut dest_mem[data_start_in_page..data_end_in_page];
-                                dest_slice.copy_from_slice(&elf_data[src_start..src_end]);
-                            }
-
-                            temp_mapped_region.flush().unwrap();
-                        },
-                    );
-
-                    let _ = vspace.map_page_at_addr_without_watermarking(
-                        unmapped_region.to_page(),
-                        curr_page_vaddr,
-                        CapRights::RW,
-                        vm_attrs,
-                    )?;
-
-                    vspace
-                        .available_address_range
-                        .observe_mapping(curr_page_vaddr, PageBits::U8)?;
-                }
-            } else {
-                // If the elf headers say to map something as read only, we can map in the pages
-                // directly from bootinfo
-
-                // The address of the elf data in the address space executing this code
-                let elf_vaddr_here =
-                    (elf_data as *const [u8] as *const u8 as usize) + offset as usize;
-
-                // mask off the lower bits to get the address of the start page
-                // in the local address space
-                let start_page_vaddr_here = elf_vaddr_here & !PAGE_MASK;
-
-                // TODO just index into the pages, instead of iterating past them
-                for user_image_page in user_image
-                    .pages_iter()
-                    .skip_while(|p| p.cap_data.state.vaddr < start_page_vaddr_here)
-                    .take_while(|p| p.cap_data.state.vaddr < elf_vaddr_here + file_size)
-                {
-                    let page_vaddr_here = user_image_page.cap_data.state.vaddr;
-                    let page_offset = page_vaddr_here - start_page_vaddr_here;
-                    let child_vaddr = target_vaddr + page_offset;
-
-                    let copied_page_cap = user_image_page.copy(
-                        parent_cnode,
-                        // If this fails, it means that we weren't given enough
-                        // resources to map all the pages.This shouldn't happen, as
-                        // we've got it written down in a type that's extracted from
-                        // the binary itself.
-                        page_slots
-                            .alloc_strong::<U1>()
-                            .map_err(|_| VSpaceError::InsufficientResourcesForElf)?,
-                        CapRights::R,
-                    )?;
-
-                    let _ = vspace.map_page_at_addr_without_watermarking(
-                        copied_page_cap,
-                        child_vaddr,
-                        CapRights::R,
-                        vm_attrs,
-                    )?;
-                    vspace
-                        .available_address_range
-                        .observe_mapping(child_vaddr, arch::PageBits::U8)?;
-                }
+                    WeakCopyError::NotEnoughSlots => VSpaceError::InsufficientResourcesForElf,
+                    WeakCopyError::SeL4Error(e) => VSpaceError::SeL4Error(e),
+                })?;
+            for (index, page) in copied_pages.into_iter().enumerate() {
+                let child_vaddr = segment_vaddr + index * PageBytes::USIZE;
+                let _ = vspace.map_page_at_addr_without_watermarking(
+                    page,
+                    child_vaddr,
+                    CapRights::R,
+                    vm_attributes,
+                )?;
+                vspace
+                    .available_address_range
+                    .observe_mapping(child_vaddr, arch::PageBits::U8)?;
+                vspace
+                    .mappings
+                    .record(image_page_mapping(child_vaddr, CapRights::R));
             }
         }
-
-        let mut vspace = VSpace {
-            root: vspace.root,
-            asid: vspace.asid,
-            layers: vspace.layers,
-            untyped: vspace.untyped,
-            slots: vspace.slots,
-            available_address_range: vspace.available_address_range,
-            _state: PhantomData,
-        };
-
-        // allocate a padding page
-        vspace.skip_pages(1)?;
-
-        Ok(vspace)
+        Ok(())
     }
 
     pub fn new(
@@ -711,8 +968,37 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         };
         let mut vspace =
             VSpace::<vspace_state::Empty>::new(paging_root, asid, slots, paging_untyped)?;
+        if let Err(e) = Self::map_code_image(
+            &mut vspace,
+            code_slots,
+            code_image_config,
+            user_image,
+            parent_cnode,
+        ) {
+            return Err(vspace.abandon(parent_cnode, e));
+        }
+
+        Ok(VSpace {
+            root: vspace.root,
+            asid: vspace.asid,
+            layers: vspace.layers,
+            untyped: vspace.untyped,
+            slots: vspace.slots,
+            available_address_range: vspace.available_address_range,
+            guards: vspace.guards,
+            mappings: vspace.mappings,
+            _state: PhantomData,
+        })
+    }
 
-        // Map the code image into the process VSpace
+    /// Map the code image into the process VSpace
+    fn map_code_image(
+        vspace: &mut VSpace<vspace_state::Empty>,
+        code_slots: WCNodeSlots,
+        code_image_config: ProcessCodeImageConfig,
+        user_image: &UserImage<role::Local>,
+        parent_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<(), VSpaceError> {
         match code_image_config {
             ProcessCodeImageConfig::ReadOnly => {
                 for (user_image_page, slot) in
@@ -729,6 +1015,9 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                     vspace
                         .available_address_range
                         .observe_mapping(address, PageBits::U8)?;
+                    vspace
+                        .mappings
+                        .record(image_page_mapping(address, CapRights::R));
                 }
             }
             ProcessCodeImageConfig::ReadWritable {
@@ -775,19 +1064,13 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                     vspace
                         .available_address_range
                         .observe_mapping(address, PageBits::U8)?;
+                    vspace
+                        .mappings
+                        .record(image_page_mapping(address, CapRights::RW));
                 }
             }
         }
-
-        Ok(VSpace {
-            root: vspace.root,
-            asid: vspace.asid,
-            layers: vspace.layers,
-            untyped: vspace.untyped,
-            slots: vspace.slots,
-            available_address_range: vspace.available_address_range,
-            _state: PhantomData,
-        })
+        Ok(())
     }
 
     /// `bootstrap` is used to wrap the root thread's address space.
@@ -812,19 +1095,21 @@ impl VSpace<vspace_state::Imaged, role::Local> {
             untyped: ut_buddy::weak_ut_buddy(ut),
             slots: cslots,
             available_address_range,
+            guards: GuardTable::default(),
+            mappings: MappingTable::default(),
             asid: asid.cap_data.asid,
             _state: PhantomData,
         }
     }
 
-    pub fn map_region_at_addr<SizeBits: Unsigned, SS: SharedStatus>(
+    pub fn map_region_at_addr<SizeBits: Unsigned, SS: SharedStatus, A: MemoryAttributes>(
         &mut self,
         region: UnmappedMemoryRegion<SizeBits, SS>,
         vaddr: usize,
         rights: CapRights,
-        vm_attributes: arch::VMAttributes,
+        attributes: A,
     ) -> Result<
-        MappedMemoryRegion<SizeBits, SS>,
+        MappedMemoryRegion<SizeBits, SS, role::Local, A>,
         (VSpaceError, Option<UnmappedMemoryRegion<SizeBits, SS>>),
     >
     where
@@ -834,9 +1119,14 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         <SizeBits as Sub<PageBits>>::Output: _Pow,
         Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
     {
-        match self.weak_map_region_at_addr(region.weaken(), vaddr, rights, vm_attributes) {
-            Ok(r) => Ok(r.as_strong::<SizeBits>().map_err(|e| (e, None))?),
-            Err((e, r)) => Err((e, r.as_strong::<SizeBits>().ok())),
+        match self.weak_map_region_at_addr(
+            region.weaken(),
+            vaddr,
+            rights,
+            attributes.vm_attributes(),
+        ) {
+            Ok(r) => Ok(r.as_strong::<SizeBits, _, _>().map_err(|e| (e, None))?),
+            Err((e, r)) => Err((e, r.as_strong::<SizeBits, _, _>().ok())),
         }
     }
 
@@ -851,6 +1141,14 @@ impl VSpace<vspace_state::Imaged, role::Local> {
             return Err((VSpaceError::InvalidRegionSize, region));
         }
 
+        // Large frames can only be mapped at addresses aligned to their size.
+        if vaddr & ((1 << region.granule_bits()) - 1) != 0 {
+            return Err((
+                VSpaceError::MappingError(MappingError::AddrNotPageAligned),
+                region,
+            ));
+        }
+
         // Verify that we can fit this region into the address space.
         if vaddr.checked_add(region.size_bytes()) == None {
             return Err((VSpaceError::ExceededAddressableSpace, region));
@@ -859,6 +1157,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         let mut mapping_vaddr = vaddr;
         let cptr = region.caps.start_cptr;
         let size_bits = region.size_bits();
+        let granule_bits = region.granule_bits();
 
         // N.B. Currently expect a single continuous cap range of all pages.
         // Revisit this size if heterogenous granule types / ranges begin to back memory
@@ -867,11 +1166,12 @@ impl VSpace<vspace_state::Imaged, role::Local> {
 
         fn unmap_mapped_page_cptrs(
             mapped_pages: Option<WeakCapRange<Page<page_state::Mapped>, role::Local>>,
+            granule_bits: u8,
         ) -> Result<(), SeL4Error> {
             if let Some(mapped_pages) = mapped_pages {
                 mapped_pages
                     .into_iter()
-                    .try_for_each(|page| page.unmap().map(|_p| ()))
+                    .try_for_each(|page| page.unmap_frame(granule_bits).map(|_p| ()))
             } else {
                 Ok(())
             }
@@ -879,19 +1179,11 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         let kind = region.kind;
 
         for page in region.caps.into_iter() {
-            match self.layers.map_layer(
-                &page,
-                mapping_vaddr,
-                &mut self.root,
-                rights,
-                vm_attributes,
-                &mut self.untyped,
-                &mut self.slots,
-            ) {
+            match self.map_frame(&page, granule_bits, mapping_vaddr, rights, vm_attributes) {
                 Err(MappingError::PageMapFailure(e))
                 | Err(MappingError::IntermediateLayerFailure(e)) => {
                     // Rollback the pages we've mapped thus far.
-                    let _ = unmap_mapped_page_cptrs(mapped_pages);
+                    let _ = unmap_mapped_page_cptrs(mapped_pages, granule_bits);
                     return Err((
                         VSpaceError::SeL4Error(e),
                         WeakMemoryRegion::unchecked_new(
@@ -899,12 +1191,13 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                             page_state::Unmapped,
                             kind,
                             size_bits,
+                            granule_bits,
                         ),
                     ));
                 }
                 Err(e) => {
                     // Rollback the pages we've mapped thus far.
-                    let _ = unmap_mapped_page_cptrs(mapped_pages);
+                    let _ = unmap_mapped_page_cptrs(mapped_pages, granule_bits);
                     return Err((
                         VSpaceError::MappingError(e),
                         WeakMemoryRegion::unchecked_new(
@@ -912,6 +1205,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                             page_state::Unmapped,
                             kind,
                             size_bits,
+                            granule_bits,
                         ),
                     ));
                 }
@@ -941,7 +1235,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                     }
                 }
             };
-            mapping_vaddr += PageBytes::USIZE;
+            mapping_vaddr += 1 << granule_bits;
         }
 
         if let Err(e) = self
@@ -949,14 +1243,20 @@ impl VSpace<vspace_state::Imaged, role::Local> {
             .observe_mapping(vaddr, size_bits)
         {
             // Rollback the pages we've mapped thus far.
-            let _ = unmap_mapped_page_cptrs(mapped_pages);
+            let _ = unmap_mapped_page_cptrs(mapped_pages, granule_bits);
             return Err((
                 e,
-                WeakMemoryRegion::unchecked_new(cptr, page_state::Unmapped, kind, size_bits),
+                WeakMemoryRegion::unchecked_new(
+                    cptr,
+                    page_state::Unmapped,
+                    kind,
+                    size_bits,
+                    granule_bits,
+                ),
             ));
         }
 
-        Ok(WeakMappedMemoryRegion::unchecked_new(
+        let mapped_region = WeakMappedMemoryRegion::unchecked_new(
             cptr,
             page_state::Mapped {
                 vaddr,
@@ -965,16 +1265,19 @@ impl VSpace<vspace_state::Imaged, role::Local> {
             },
             kind,
             size_bits,
-        ))
+            granule_bits,
+        );
+        self.mappings.record(region_mapping(&mapped_region));
+        Ok(mapped_region)
     }
 
     /// Map a region of memory at some address, I don't care where.
-    pub fn map_region<SizeBits: Unsigned>(
+    pub fn map_region<SizeBits: Unsigned, A: MemoryAttributes>(
         &mut self,
         region: UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
         rights: CapRights,
-        vm_attributes: arch::VMAttributes,
-    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Exclusive>, VSpaceError>
+        attributes: A,
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Exclusive, role::Local, A>, VSpaceError>
     where
         SizeBits: IsGreaterOrEqual<PageBits>,
         SizeBits: Sub<PageBits>,
@@ -982,7 +1285,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         <SizeBits as Sub<PageBits>>::Output: _Pow,
         Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
     {
-        self.map_region_internal(region, rights, vm_attributes)
+        self.map_region_internal(region, rights, attributes)
     }
 
     /// Map a weak region of memory at some address, I don't care where.
@@ -997,14 +1300,14 @@ impl VSpace<vspace_state::Imaged, role::Local> {
 
     /// Map a region of memory at some address, then move it to a
     /// different cspace.
-    pub fn map_region_and_move<SizeBits: Unsigned, Role: CNodeRole>(
+    pub fn map_region_and_move<SizeBits: Unsigned, Role: CNodeRole, A: MemoryAttributes>(
         &mut self,
         region: UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
         rights: CapRights,
-        vm_attributes: arch::VMAttributes,
+        attributes: A,
         src_cnode: &LocalCap<LocalCNode>,
         dest_slots: CNodeSlots<NumPages<SizeBits>, Role>,
-    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Exclusive>, VSpaceError>
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Exclusive, role::Local, A>, VSpaceError>
     where
         SizeBits: IsGreaterOrEqual<PageBits>,
         SizeBits: Sub<PageBits>,
@@ -1015,11 +1318,11 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         self.weak_map_region_and_move(
             region.weaken(),
             rights,
-            vm_attributes,
+            attributes.vm_attributes(),
             src_cnode,
             &mut dest_slots.weaken(),
         )
-        .and_then(|r| r.as_strong::<SizeBits>())
+        .and_then(|r| r.as_strong::<SizeBits, _, _>())
     }
     /// Map a weak region of memory at some address, then move it to a
     /// different cspace.
@@ -1032,12 +1335,14 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         dest_slots: &mut LocalCap<WCNodeSlotsData<Role>>,
     ) -> Result<WeakMappedMemoryRegion<shared_status::Exclusive>, VSpaceError> {
         if dest_slots.size()
-            < num_pages(region.size_bits()).map_err(|_| VSpaceError::InvalidRegionSize)?
+            < num_frames(region.size_bits(), region.granule_bits())
+                .map_err(|_| VSpaceError::InvalidRegionSize)?
         {
             return Err(VSpaceError::InsufficientCNodeSlots);
         }
         let kind = region.kind;
         let size_bits = region.size_bits();
+        let granule_bits = region.granule_bits();
         let mapped_region: WeakMappedMemoryRegion<shared_status::Exclusive> =
             self.weak_map_region_internal(region, rights, vm_attributes)?;
         let vaddr = mapped_region.vaddr();
@@ -1060,6 +1365,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
             },
             kind,
             size_bits,
+            granule_bits,
         ))
     }
 
@@ -1069,14 +1375,14 @@ impl VSpace<vspace_state::Imaged, role::Local> {
     /// The incoming `UnmappedMemoryRegion` is only borrowed and one
     /// also gets back a new `MappedMemoryRegion` indexed with the
     /// status `Shared`.
-    pub fn map_shared_region<SizeBits: Unsigned>(
+    pub fn map_shared_region<SizeBits: Unsigned, A: MemoryAttributes>(
         &mut self,
         region: &UnmappedMemoryRegion<SizeBits, shared_status::Shared>,
         rights: CapRights,
-        vm_attributes: arch::VMAttributes,
+        attributes: A,
         slots: LocalCNodeSlots<NumPages<SizeBits>>,
         cnode: &LocalCap<LocalCNode>,
-    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Shared>, VSpaceError>
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Shared, role::Local, A>, VSpaceError>
     where
         SizeBits: IsGreaterOrEqual<PageBits>,
         SizeBits: Sub<PageBits>,
@@ -1086,7 +1392,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
     {
         let unmapped_sr: UnmappedMemoryRegion<_, shared_status::Shared> =
             UnmappedMemoryRegion::from_caps(region.caps.copy(cnode, slots, rights)?, region.kind);
-        self.map_region_internal(unmapped_sr, rights, vm_attributes)
+        self.map_region_internal(unmapped_sr, rights, attributes)
     }
     /// Map a _shared_ region of memory at some address, I don't care
     /// where. When `map_shared_region` is called, the caps making up
@@ -1110,8 +1416,13 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                 WeakCopyError::SeL4Error(e) => VSpaceError::SeL4Error(e),
             })?;
         let unmapped_sr: WeakUnmappedMemoryRegion<shared_status::Shared> =
-            WeakMemoryRegion::try_from_caps(caps_copy, region.kind, region.size_bits())
-                .map_err(|_| VSpaceError::InvalidRegionSize)?;
+            WeakMemoryRegion::try_from_caps(
+                caps_copy,
+                region.kind,
+                region.size_bits(),
+                region.granule_bits(),
+            )
+            .map_err(|_| VSpaceError::InvalidRegionSize)?;
         self.weak_map_region_internal(unmapped_sr, rights, vm_attributes)
     }
 
@@ -1120,12 +1431,30 @@ impl VSpace<vspace_state::Imaged, role::Local> {
     /// address space in which this region will be mapped—that
     /// unmapped region can be consumed and a mapped region is
     /// returned.
-    pub fn map_shared_region_and_consume<SizeBits: Unsigned>(
+    pub fn map_shared_region_and_consume<SizeBits: Unsigned, A: MemoryAttributes>(
         &mut self,
         region: UnmappedMemoryRegion<SizeBits, shared_status::Shared>,
         rights: CapRights,
-        vm_attributes: arch::VMAttributes,
-    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Shared>, VSpaceError>
+        attributes: A,
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Shared, role::Local, A>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        self.map_region_internal(region, rights, attributes)
+    }
+
+    /// Map a region without guards of its own, for callers which place
+    /// guards around it themselves, e.g. around a stack.
+    pub(crate) fn map_region_unguarded<SizeBits: Unsigned, SS: SharedStatus, A: MemoryAttributes>(
+        &mut self,
+        region: UnmappedMemoryRegion<SizeBits, SS>,
+        rights: CapRights,
+        attributes: A,
+    ) -> Result<MappedMemoryRegion<SizeBits, SS, role::Local, A>, VSpaceError>
     where
         SizeBits: IsGreaterOrEqual<PageBits>,
         SizeBits: Sub<PageBits>,
@@ -1133,15 +1462,43 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         <SizeBits as Sub<PageBits>>::Output: _Pow,
         Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
     {
-        self.map_region_internal(region, rights, vm_attributes)
+        self.weak_map_region_unguarded(region.weaken(), rights, attributes.vm_attributes())
+            .and_then(|r| r.as_strong::<SizeBits, _, _>())
     }
 
-    fn map_region_internal<SizeBits: Unsigned, SSIn: SharedStatus, SSOut: SharedStatus>(
+    /// As `map_shared_region`, but without guards of its own, like
+    /// `map_region_unguarded`.
+    pub(crate) fn map_shared_region_unguarded<SizeBits: Unsigned, A: MemoryAttributes>(
+        &mut self,
+        region: &UnmappedMemoryRegion<SizeBits, shared_status::Shared>,
+        rights: CapRights,
+        attributes: A,
+        slots: LocalCNodeSlots<NumPages<SizeBits>>,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Shared, role::Local, A>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let unmapped_sr: UnmappedMemoryRegion<_, shared_status::Shared> =
+            UnmappedMemoryRegion::from_caps(region.caps.copy(cnode, slots, rights)?, region.kind);
+        self.map_region_unguarded(unmapped_sr, rights, attributes)
+    }
+
+    fn map_region_internal<
+        SizeBits: Unsigned,
+        SSIn: SharedStatus,
+        SSOut: SharedStatus,
+        A: MemoryAttributes,
+    >(
         &mut self,
         region: UnmappedMemoryRegion<SizeBits, SSIn>,
         rights: CapRights,
-        vm_attributes: arch::VMAttributes,
-    ) -> Result<MappedMemoryRegion<SizeBits, SSOut>, VSpaceError>
+        attributes: A,
+    ) -> Result<MappedMemoryRegion<SizeBits, SSOut, role::Local, A>, VSpaceError>
     where
         SizeBits: IsGreaterOrEqual<PageBits>,
         SizeBits: Sub<PageBits>,
@@ -1149,8 +1506,8 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         <SizeBits as Sub<PageBits>>::Output: _Pow,
         Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
     {
-        self.weak_map_region_internal(region.weaken(), rights, vm_attributes)
-            .and_then(|r| r.as_strong::<SizeBits>())
+        self.weak_map_region_internal(region.weaken(), rights, attributes.vm_attributes())
+            .and_then(|r| r.as_strong::<SizeBits, _, _>())
     }
     fn weak_map_region_internal<SSIn: SharedStatus, SSOut: SharedStatus>(
         &mut self,
@@ -1158,9 +1515,35 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         rights: CapRights,
         vm_attributes: arch::VMAttributes,
     ) -> Result<WeakMappedMemoryRegion<SSOut>, VSpaceError> {
+        self.weak_map_region_guarded(region, rights, vm_attributes, GuardKind::Region)
+    }
+
+    fn weak_map_region_guarded<SSIn: SharedStatus, SSOut: SharedStatus>(
+        &mut self,
+        region: WeakUnmappedMemoryRegion<SSIn>,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+        kind: GuardKind,
+    ) -> Result<WeakMappedMemoryRegion<SSOut>, VSpaceError> {
+        let pending = self.place_guard_below(kind)?;
+        let mapped_region: WeakMappedMemoryRegion<SSOut> =
+            self.weak_map_region_unguarded(region, rights, vm_attributes)?;
+        self.place_guard_above(pending, mapped_region.vaddr(), mapped_region.size_bytes())?;
+        Ok(mapped_region)
+    }
+
+    /// Map a region at the bottom of the available address space, right
+    /// up against whatever was mapped before it.
+    fn weak_map_region_unguarded<SSIn: SharedStatus, SSOut: SharedStatus>(
+        &mut self,
+        region: WeakUnmappedMemoryRegion<SSIn>,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<WeakMappedMemoryRegion<SSOut>, VSpaceError> {
+        let granule_bits = region.granule_bits();
         let starting_address = self
             .available_address_range
-            .auto_propose_region_start(region.size_bits())
+            .auto_propose_region_start(region.size_bits(), granule_bits)
             .map_err(|_| VSpaceError::InsufficientAddressSpaceAvailableToMapRegion)?;
 
         // create the mapped region first because we need to pluck out
@@ -1175,19 +1558,12 @@ impl VSpace<vspace_state::Imaged, role::Local> {
             },
             region.kind,
             region.size_bits(),
+            granule_bits,
         );
 
         let mut vaddr = starting_address;
         for page_cap in region.caps.into_iter() {
-            match self.layers.map_layer(
-                &page_cap,
-                vaddr,
-                &mut self.root,
-                rights,
-                vm_attributes,
-                &mut self.untyped,
-                &mut self.slots,
-            ) {
+            match self.map_frame(&page_cap, granule_bits, vaddr, rights, vm_attributes) {
                 Err(MappingError::PageMapFailure(e))
                 | Err(MappingError::IntermediateLayerFailure(e)) => {
                     return Err(VSpaceError::SeL4Error(e))
@@ -1195,13 +1571,14 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                 Err(e) => return Err(VSpaceError::MappingError(e)),
                 Ok(_) => self
                     .available_address_range
-                    .observe_mapping(vaddr, PageBits::U8)?,
+                    .observe_mapping(vaddr, granule_bits)?,
             };
             // It's safe to do a direct addition as we've already
             // determined that this region will fit here.
-            vaddr += PageBytes::USIZE;
+            vaddr += 1 << granule_bits;
         }
 
+        self.mappings.record(region_mapping(&mapped_region));
         Ok(mapped_region)
     }
 
@@ -1209,7 +1586,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         for _ in 0..count {
             let starting_address = self
                 .available_address_range
-                .auto_propose_region_start(PageBits::U8)
+                .auto_propose_region_start(PageBits::U8, PageBits::U8)
                 .map_err(|_| VSpaceError::ExceededAddressableSpace)?;
             self.available_address_range
                 .observe_mapping(starting_address, PageBits::U8)?;
@@ -1217,6 +1594,159 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         Ok(())
     }
 
+    /// Map a region to be used as a thread's stack, read-write and with
+    /// guards of `GuardSpans::stack_pages` either side of it.
+    pub fn map_stack_region<SizeBits: Unsigned, A: MemoryAttributes>(
+        &mut self,
+        region: UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+        attributes: A,
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Exclusive, role::Local, A>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        self.weak_map_region_guarded(
+            region.weaken(),
+            CapRights::RW,
+            attributes.vm_attributes(),
+            GuardKind::Stack,
+        )
+        .and_then(|r| r.as_strong::<SizeBits, _, _>())
+    }
+
+    pub fn guard_spans(&self) -> GuardSpans {
+        self.guards.spans
+    }
+
+    /// Change how much guard is placed around stacks and regions mapped
+    /// from now on.
+    pub fn set_guard_spans(&mut self, spans: GuardSpans) {
+        self.guards.spans = spans;
+    }
+
+    /// The guards placed so far, in the order they were placed.
+    pub fn guards(&self) -> &[Guard] {
+        self.guards.as_slice()
+    }
+
+    /// How many guards were placed after `MAX_GUARDS` had been, which
+    /// are left unmapped all the same but not listed by `guards`.
+    pub fn untracked_guards(&self) -> usize {
+        self.guards.untracked()
+    }
+
+    /// The guard `address` falls in, if any.
+    pub fn guard_at(&self, address: usize) -> Option<&Guard> {
+        self.guards.as_slice().iter().find(|g| g.contains(address))
+    }
+
+    /// Name the stack or region starting at `vaddr` in the reports of
+    /// faults which land in its guards.
+    pub fn name_region(&mut self, vaddr: usize, name: &str) {
+        self.guards.name_region(vaddr, name)
+    }
+
+    /// Like `VMFault::explain`, but a fault in one of this VSpace's guards
+    /// is also attributed to the stack or region it protects.
+    pub fn explain_fault(&self, fault: &VMFault, regions: &[KnownRegion]) -> VMFaultReport {
+        let mut report = fault.explain(regions);
+        report.guard = self.guard_at(fault.address).copied();
+        report
+    }
+
+    /// The mappings made in this VSpace, in address order. Only the first
+    /// `MAX_TRACKED_MAPPINGS` are kept track of.
+    pub fn mappings(&self) -> &[Mapping] {
+        self.mappings.as_slice()
+    }
+
+    /// The mapping `address` falls in, if any.
+    pub fn mapping_at(&self, address: usize) -> Option<&Mapping> {
+        self.mappings
+            .as_slice()
+            .iter()
+            .find(|m| m.contains(address))
+    }
+
+    /// A snapshot of what is mapped where, which prints one line per
+    /// mapping or guard, e.g. when the address space unexpectedly runs
+    /// out of room for a region.
+    pub fn layout(&self) -> Layout {
+        Layout {
+            mappings: self.mappings.as_slice(),
+            guards: self.guards.as_slice(),
+            untracked: self.mappings.untracked(),
+            available: (
+                self.available_address_range.bottom,
+                self.available_address_range.top,
+            ),
+        }
+    }
+
+    /// Leave the guard below a stack or region of `kind` unmapped, ahead
+    /// of mapping the stack or region itself.
+    pub(crate) fn place_guard_below(
+        &mut self,
+        kind: GuardKind,
+    ) -> Result<PendingGuard, VSpaceError> {
+        let pages = self.guards.pages_for(kind);
+        self.place_guard_pages_below(kind, pages)
+    }
+
+    /// As `place_guard_below`, for callers which need a guard of `pages`
+    /// regardless of the configured `GuardSpans`.
+    pub(crate) fn place_guard_pages_below(
+        &mut self,
+        kind: GuardKind,
+        pages: usize,
+    ) -> Result<PendingGuard, VSpaceError> {
+        if pages == 0 {
+            return Ok(PendingGuard {
+                kind,
+                pages,
+                start: 0,
+            });
+        }
+        let start = self
+            .available_address_range
+            .auto_propose_region_start(PageBits::U8, PageBits::U8)
+            .map_err(|_| VSpaceError::ExceededAddressableSpace)?;
+        self.skip_pages(pages)?;
+        Ok(PendingGuard { kind, pages, start })
+    }
+
+    /// Leave the guard above the stack or region just mapped at `start`
+    /// unmapped, and remember the guards either side of it.
+    pub(crate) fn place_guard_above(
+        &mut self,
+        pending: PendingGuard,
+        start: usize,
+        size: usize,
+    ) -> Result<(), VSpaceError> {
+        if pending.pages == 0 {
+            return Ok(());
+        }
+        self.skip_pages(pending.pages)?;
+        self.guards.record(
+            pending.start,
+            start - pending.start,
+            pending.kind,
+            GuardSide::Below,
+            start,
+        );
+        self.guards.record(
+            start + size,
+            pending.pages * PageBytes::USIZE,
+            pending.kind,
+            GuardSide::Above,
+            start,
+        );
+        Ok(())
+    }
+
     pub fn reserve<PageCount: Unsigned>(
         &mut self,
         sacrificial_page: LocalCap<Page<page_state::Unmapped>>,
@@ -1258,12 +1788,18 @@ where
     ) -> Result<Self, VSpaceError> {
         let mut unmapped_region = sacrificial_page.to_region();
         let mut first_vaddr = None;
+        let pending = vspace.place_guard_below(GuardKind::Region)?;
         // Map (and then unmap) each page in the reserved range
         // in order to trigger the instantiation of the backing paging
         // structures.
         for i in 0..PageCount::USIZE {
-            let mapped_region =
-                vspace.map_region(unmapped_region, CapRights::RW, arch::vm_attributes::DEFAULT)?;
+            let mapped_region: MappedMemoryRegion<PageBits, shared_status::Exclusive> = vspace
+                .weak_map_region_unguarded(
+                    unmapped_region.weaken(),
+                    CapRights::RW,
+                    memory_attributes::Cached.vm_attributes(),
+                )?
+                .as_strong()?;
             match first_vaddr {
                 None => {
                     first_vaddr = Some(mapped_region.vaddr());
@@ -1273,14 +1809,16 @@ where
                     // (as above) will produce a continuous range of allocated address space
                     // and backing intermediate paging structures
                     assert_eq!(start_vaddr + i * PageBytes::USIZE, mapped_region.vaddr(),
-                        "Repeated calls to VSpace::map_region should produce a continuous range of addresses");
+                        "Repeated unguarded mappings should produce a continuous range of addresses");
                 }
             }
             unmapped_region = vspace.unmap_region(mapped_region)?;
         }
+        // Due to the type constraint that ensures PageCount >= 1, first_vaddr must be Some
+        let vaddr = first_vaddr.unwrap();
+        vspace.place_guard_above(pending, vaddr, PageCount::USIZE * PageBytes::USIZE)?;
         Ok(ReservedRegion {
-            // Due to the type constraint that ensures PageCount >= 1, first_vaddr must be Some
-            vaddr: first_vaddr.unwrap(),
+            vaddr,
             asid: vspace.asid(),
             _page_count: PhantomData,
         })
@@ -1418,12 +1956,23 @@ impl AvailableAddressRange {
         Ok(())
     }
 
-    fn auto_propose_region_start(&self, size_bits: u8) -> Result<usize, CouldNotAllocateRegion> {
+    /// Propose where to put a region of `size_bits`, starting at an
+    /// address aligned to `align_bits`.
+    fn auto_propose_region_start(
+        &self,
+        size_bits: u8,
+        align_bits: u8,
+    ) -> Result<usize, CouldNotAllocateRegion> {
         if self.bottom > self.top {
             return Err(CouldNotAllocateRegion);
         }
         let size_bytes = bytes_from_size_bits(size_bits);
-        let proposed_start = self.bottom;
+        let align_mask = bytes_from_size_bits(align_bits) - 1;
+        let proposed_start = self
+            .bottom
+            .checked_add(align_mask)
+            .ok_or(CouldNotAllocateRegion)?
+            & !align_mask;
         let proposed_end = proposed_start
             .checked_add(size_bytes)
             .ok_or(CouldNotAllocateRegion)?;
@@ -1436,6 +1985,30 @@ impl AvailableAddressRange {
 
 struct CouldNotAllocateRegion;
 
+/// Read-only image pages are the very frames of our own image.
+fn image_page_mapping(vaddr: usize, rights: CapRights) -> Mapping {
+    Mapping {
+        start: vaddr,
+        size: PageBytes::USIZE,
+        rights,
+        shared: !rights.is_writable(),
+        source: MappingSource::Image,
+    }
+}
+
+fn region_mapping<SS: SharedStatus>(region: &WeakMappedMemoryRegion<SS>) -> Mapping {
+    Mapping {
+        start: region.vaddr(),
+        size: region.size_bytes(),
+        rights: region.rights(),
+        shared: SS::IS_SHARED,
+        source: MappingSource::Region {
+            kind: region.kind,
+            start_cptr: region.caps.start_cptr,
+        },
+    }
+}
+
 fn bytes_from_size_bits(size_bits: u8) -> usize {
     2usize.pow(u32::from(size_bits))
 }
@@ -4,7 +4,8 @@ use core::ops::Sub;
 
 use typenum::*;
 
-use super::{KernelRetypeFanOutLimit, NumPages, VSpaceError};
+use super::{KernelRetypeFanOutLimit, NumFrames, NumPages, VSpaceError};
+use crate::arch::cap::LARGE_FRAME_BITS;
 use crate::arch::{self, PageBits, PageBytes};
 use crate::cap::{
     memory_kind, page_state, role, CNode, CNodeRole, CNodeSlots, Cap, CapRange, InternalASID,
@@ -16,23 +17,151 @@ use crate::error::SeL4Error;
 use crate::pow::{Pow, _Pow};
 use crate::userland::CapRights;
 
-pub trait SharedStatus: private::SealedSharedStatus {}
+pub trait SharedStatus: private::SealedSharedStatus {
+    /// Whether regions of this status may be mapped in more than one
+    /// place at once.
+    const IS_SHARED: bool;
+}
 
 pub mod shared_status {
     use super::SharedStatus;
 
     pub struct Shared;
-    impl SharedStatus for Shared {}
+    impl SharedStatus for Shared {
+        const IS_SHARED: bool = true;
+    }
 
     pub struct Exclusive;
-    impl SharedStatus for Exclusive {}
+    impl SharedStatus for Exclusive {
+        const IS_SHARED: bool = false;
+    }
+}
+
+/// How the memory behind a mapping is to be treated by the caches and
+/// the MMU, recorded in the type of a `MappedMemoryRegion`.
+pub trait MemoryAttributes: private::SealedMemoryAttributes + Default {
+    /// The seL4 VM attributes a region is mapped with.
+    fn vm_attributes(&self) -> arch::VMAttributes;
+}
+
+/// Attributes under which the caches may hold copies of a region's
+/// contents, so that cache maintenance is needed before sharing it with
+/// a device.
+pub trait Cacheable: MemoryAttributes {}
+
+/// The seL4 ARM VM attributes only distinguish cacheable from
+/// non-cacheable memory, so `Uncached`, `Device` and `WriteCombining`
+/// currently produce the same mapping. They are kept apart so that
+/// callers can state what they need, should the kernel grow finer
+/// distinctions.
+pub mod memory_attributes {
+    use super::{Cacheable, MemoryAttributes};
+    use crate::arch::{self, vm_attributes};
+
+    /// Normal, cacheable memory; the default.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct Cached;
+    impl MemoryAttributes for Cached {
+        fn vm_attributes(&self) -> arch::VMAttributes {
+            vm_attributes::DEFAULT
+        }
+    }
+    impl Cacheable for Cached {}
+
+    /// Normal memory which bypasses the caches, e.g. for DMA
+    /// descriptors.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct Uncached;
+    impl MemoryAttributes for Uncached {
+        fn vm_attributes(&self) -> arch::VMAttributes {
+            vm_attributes::DEFAULT & !vm_attributes::PAGE_CACHEABLE
+        }
+    }
+
+    /// Memory-mapped device registers.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct Device;
+    impl MemoryAttributes for Device {
+        fn vm_attributes(&self) -> arch::VMAttributes {
+            vm_attributes::DEFAULT & !vm_attributes::PAGE_CACHEABLE
+        }
+    }
+
+    /// Uncached memory whose writes may be buffered and merged, e.g. a
+    /// frame buffer.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct WriteCombining;
+    impl MemoryAttributes for WriteCombining {
+        fn vm_attributes(&self) -> arch::VMAttributes {
+            vm_attributes::DEFAULT & !vm_attributes::PAGE_CACHEABLE
+        }
+    }
+
+    /// `A`, but with instruction fetches from the region forbidden.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct ExecuteNever<A>(pub A);
+    impl<A: MemoryAttributes> MemoryAttributes for ExecuteNever<A> {
+        fn vm_attributes(&self) -> arch::VMAttributes {
+            self.0.vm_attributes() | vm_attributes::EXECUTE_NEVER
+        }
+    }
+    impl<A: Cacheable> Cacheable for ExecuteNever<A> {}
+}
+
+/// What the type of a mapped region says about the rights it is mapped
+/// with: either fixed by `VSpace::protect_region` to one of the rights
+/// markers (`mapped_rights::R`, `mapped_rights::RW`, ...), or
+/// `mapped_rights::Dynamic`.
+pub trait MappedRights: private::SealedMappedRights {}
+
+/// Rights under which a region may be written to through `as_mut_slice`.
+pub trait WritableRights: MappedRights {}
+
+pub mod mapped_rights {
+    use super::{MappedRights, WritableRights};
+    pub use crate::userland::rights::rights::{R, RW, RWG, W, WG};
+
+    /// The rights are only known at runtime, through `rights()`. Regions
+    /// are mapped this way until protected.
+    pub struct Dynamic;
+    impl MappedRights for Dynamic {}
+    impl WritableRights for Dynamic {}
+
+    impl MappedRights for R {}
+    impl MappedRights for W {}
+    impl MappedRights for RW {}
+    impl MappedRights for RWG {}
+    impl MappedRights for WG {}
+
+    impl WritableRights for W {}
+    impl WritableRights for RW {}
+    impl WritableRights for RWG {}
+    impl WritableRights for WG {}
 }
 
 mod private {
+    use super::mapped_rights::{Dynamic, R, RW, RWG, W, WG};
+    use super::memory_attributes::{Cached, Device, ExecuteNever, Uncached, WriteCombining};
     use super::shared_status::{Exclusive, Shared};
+    use super::MemoryAttributes;
     pub trait SealedSharedStatus {}
     impl SealedSharedStatus for Shared {}
     impl SealedSharedStatus for Exclusive {}
+
+    pub trait SealedMemoryAttributes {}
+    impl SealedMemoryAttributes for Cached {}
+    impl SealedMemoryAttributes for Uncached {}
+    impl SealedMemoryAttributes for Device {}
+    impl SealedMemoryAttributes for WriteCombining {}
+    impl<A: MemoryAttributes> SealedMemoryAttributes for ExecuteNever<A> {}
+
+    pub trait SealedMappedRights {}
+    impl SealedMappedRights for Dynamic {}
+    impl SealedMappedRights for R {}
+    impl SealedMappedRights for W {}
+    impl SealedMappedRights for RW {}
+    impl SealedMappedRights for RWG {}
+    impl SealedMappedRights for WG {}
 }
 /// A `1 << SizeBits` bytes region of unmapped memory. It can be
 /// shared or owned exclusively. The ramifications of its shared
@@ -43,10 +172,16 @@ pub type UnmappedMemoryRegion<SizeBits, ShStatus, CapRole: CNodeRole = role::Loc
     MemoryRegion<page_state::Unmapped, SizeBits, ShStatus, CapRole>;
 /// A memory region which is mapped into an address space, meaning it
 /// has a virtual address and an associated asid in which that virtual
-/// address is valid.
+/// address is valid. `Attrs` records how it was mapped, and `Rights`
+/// whether its rights are known to the type.
 #[allow(type_alias_bounds)]
-pub type MappedMemoryRegion<SizeBits, ShStatus, CapRole: CNodeRole = role::Local> =
-    MemoryRegion<page_state::Mapped, SizeBits, ShStatus, CapRole>;
+pub type MappedMemoryRegion<
+    SizeBits,
+    ShStatus,
+    CapRole: CNodeRole = role::Local,
+    Attrs: MemoryAttributes = memory_attributes::Cached,
+    Rights: MappedRights = mapped_rights::Dynamic,
+> = MemoryRegion<page_state::Mapped, SizeBits, ShStatus, CapRole, Attrs, Rights>;
 #[allow(type_alias_bounds)]
 pub type WeakUnmappedMemoryRegion<ShStatus, CapRole: CNodeRole = role::Local> =
     WeakMemoryRegion<page_state::Unmapped, ShStatus, CapRole>;
@@ -58,11 +193,17 @@ pub type WeakMappedMemoryRegion<ShStatus, CapRole: CNodeRole = role::Local> =
 /// shared or owned exclusively. The ramifications of its shared
 /// status are described more completely in the `mapped_shared_region`
 /// function description.
+///
+/// A region is always backed by pages, one per slot of its `CapRange`.
+/// Memory built from large frames is only available as a
+/// `WeakMemoryRegion`, which records the size of its frames.
 pub struct MemoryRegion<
     State: PageState,
     SizeBits: Unsigned,
     SS: SharedStatus,
     CapRole: CNodeRole = role::Local,
+    Attrs: MemoryAttributes = memory_attributes::Cached,
+    Rights: MappedRights = mapped_rights::Dynamic,
 > where
     // Forces regions to be page-aligned.
     SizeBits: IsGreaterOrEqual<PageBits>,
@@ -75,10 +216,18 @@ pub struct MemoryRegion<
     pub(super) kind: WeakMemoryKind,
     _size_bits: PhantomData<SizeBits>,
     _shared_status: PhantomData<SS>,
+    _attributes: PhantomData<Attrs>,
+    _rights: PhantomData<Rights>,
 }
 
-impl<State: PageState, SizeBits: Unsigned, SS: SharedStatus, CapRole: CNodeRole>
-    MemoryRegion<State, SizeBits, SS, CapRole>
+impl<
+        State: PageState,
+        SizeBits: Unsigned,
+        SS: SharedStatus,
+        CapRole: CNodeRole,
+        Attrs: MemoryAttributes,
+        Rights: MappedRights,
+    > MemoryRegion<State, SizeBits, SS, CapRole, Attrs, Rights>
 where
     SizeBits: IsGreaterOrEqual<PageBits>,
     SizeBits: Sub<PageBits>,
@@ -98,15 +247,27 @@ where
         Self::SIZE_BYTES
     }
 
+    /// The caps of the pages backing this region.
+    fn frames(&self) -> impl Iterator<Item = Cap<Page<State>, CapRole>> {
+        frame_caps(
+            self.caps.start_cptr,
+            self.caps.start_cap_data.state,
+            PageBits::U8,
+            self.caps.len(),
+        )
+    }
+
     pub(super) fn from_caps(
         caps: CapRange<Page<State>, CapRole, NumPages<SizeBits>>,
         kind: WeakMemoryKind,
-    ) -> MemoryRegion<State, SizeBits, SS, CapRole> {
+    ) -> MemoryRegion<State, SizeBits, SS, CapRole, Attrs, Rights> {
         MemoryRegion {
             caps,
             kind,
             _size_bits: PhantomData,
             _shared_status: PhantomData,
+            _attributes: PhantomData,
+            _rights: PhantomData,
         }
     }
 
@@ -120,10 +281,12 @@ where
             kind,
             _size_bits: PhantomData,
             _shared_status: PhantomData,
+            _attributes: PhantomData,
+            _rights: PhantomData,
         }
     }
     pub fn weaken(self) -> WeakMemoryRegion<State, SS, CapRole> {
-        WeakMemoryRegion::try_from_caps(self.caps.weaken(), self.kind, SizeBits::U8)
+        WeakMemoryRegion::try_from_caps(self.caps.weaken(), self.kind, SizeBits::U8, PageBits::U8)
             .expect("Cap page slots to memory region size invariant maintained by type signature")
     }
 
@@ -160,7 +323,7 @@ where
     ) -> Result<
         (
             MemoryRegion<page_state::Unmapped, SizeBits, shared_status::Shared, DestRole>,
-            MemoryRegion<State, SizeBits, shared_status::Shared, CapRole>,
+            MemoryRegion<State, SizeBits, shared_status::Shared, CapRole, Attrs, Rights>,
         ),
         VSpaceError,
     >
@@ -170,7 +333,7 @@ where
         let pages_offset = self.caps.start_cptr;
         let original_mapped_state = self.caps.start_cap_data.state;
         let slots_offset = slots.cap_data.offset;
-        for (slot, page) in slots.iter().zip(self.caps.into_iter()) {
+        for (slot, page) in slots.iter().zip(self.frames()) {
             let _ = page.copy(cnode, slot, rights)?;
         }
 
@@ -209,6 +372,12 @@ where
 {
     /// Retype the necessary number of granules into memory
     /// capabilities and return the unmapped region.
+    ///
+    /// The granule is always a page, even when the region is big enough
+    /// for large frames: a `MemoryRegion` takes its slots, and hands out
+    /// its caps, one per page, which `split` and `split_into` rely on.
+    /// `WeakMemoryRegion::new_with_large_frames` backs memory with the
+    /// largest frames that fit instead.
     pub fn new(
         ut: LocalCap<Untyped<SizeBits>>,
         slots: LocalCNodeSlots<NumPages<SizeBits>>,
@@ -243,7 +412,8 @@ where
     }
 }
 
-impl<SizeBits: Unsigned, SS: SharedStatus> MappedMemoryRegion<SizeBits, SS>
+impl<SizeBits: Unsigned, SS: SharedStatus, Attrs: MemoryAttributes, Rights: MappedRights>
+    MappedMemoryRegion<SizeBits, SS, role::Local, Attrs, Rights>
 where
     SizeBits: IsGreaterOrEqual<PageBits>,
     SizeBits: Sub<PageBits>,
@@ -267,37 +437,6 @@ where
         unsafe { core::slice::from_raw_parts(self.vaddr() as *const u8, self.size_bytes()) }
     }
 
-    pub fn as_mut_slice(&mut self) -> &mut [u8] {
-        unsafe { core::slice::from_raw_parts_mut(self.vaddr() as *mut u8, self.size_bytes()) }
-    }
-
-    pub fn flush(&self) -> Result<(), SeL4Error> {
-        self.caps.for_each::<SeL4Error, _>(|cap| {
-            unsafe {
-                arch::flush_page(cap.cptr)?;
-            }
-            Ok(())
-        })?;
-
-        Ok(())
-    }
-
-    pub fn flush_range(&self, vaddr: usize, size: usize) -> Result<(), SeL4Error> {
-        let bottom = vaddr & !0xFFF;
-        let top = vaddr + cmp::max(PageBytes::USIZE, size);
-        let range = bottom..top;
-        self.caps.for_each::<SeL4Error, _>(|cap| {
-            if range.contains(&cap.vaddr()) {
-                unsafe {
-                    arch::flush_page(cap.cptr)?;
-                }
-            }
-            Ok(())
-        })?;
-
-        Ok(())
-    }
-
     #[cfg(feature = "test_support")]
     /// Super dangerous copy-aliasing
     pub(crate) unsafe fn dangerous_internal_alias(&mut self) -> Self {
@@ -317,8 +456,8 @@ where
         self,
     ) -> Result<
         (
-            MappedMemoryRegion<op!(SizeBits - U1), SS>,
-            MappedMemoryRegion<op!(SizeBits - U1), SS>,
+            MappedMemoryRegion<op!(SizeBits - U1), SS, role::Local, Attrs, Rights>,
+            MappedMemoryRegion<op!(SizeBits - U1), SS, role::Local, Attrs, Rights>,
         ),
         VSpaceError,
     >
@@ -357,6 +496,8 @@ where
                 kind: self.kind,
                 _size_bits: PhantomData,
                 _shared_status: PhantomData,
+                _attributes: PhantomData,
+                _rights: PhantomData,
             },
             MappedMemoryRegion {
                 caps: CapRange::new(
@@ -372,6 +513,8 @@ where
                 kind: self.kind,
                 _size_bits: PhantomData,
                 _shared_status: PhantomData,
+                _attributes: PhantomData,
+                _rights: PhantomData,
             },
         ))
     }
@@ -394,8 +537,8 @@ where
         self,
     ) -> Result<
         (
-            MappedMemoryRegion<TargetSize, SS>,
-            MappedMemoryRegion<op!(SizeBits - U1), SS>,
+            MappedMemoryRegion<TargetSize, SS, role::Local, Attrs, Rights>,
+            MappedMemoryRegion<op!(SizeBits - U1), SS, role::Local, Attrs, Rights>,
         ),
         VSpaceError,
     >
@@ -431,16 +574,95 @@ where
                 kind: a.kind,
                 _size_bits: PhantomData,
                 _shared_status: PhantomData,
+                _attributes: PhantomData,
+                _rights: PhantomData,
             },
             b,
         ))
     }
 }
 
+impl<SizeBits: Unsigned, SS: SharedStatus, Attrs: MemoryAttributes, Rights: WritableRights>
+    MappedMemoryRegion<SizeBits, SS, role::Local, Attrs, Rights>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.vaddr() as *mut u8, self.size_bytes()) }
+    }
+}
+
+/// Only cacheable mappings can leave stale copies of their contents in
+/// the caches, so only they need flushing before a device looks at the
+/// memory.
+impl<SizeBits: Unsigned, SS: SharedStatus, Attrs: Cacheable, Rights: MappedRights>
+    MappedMemoryRegion<SizeBits, SS, role::Local, Attrs, Rights>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    pub fn flush(&self) -> Result<(), SeL4Error> {
+        for frame in self.frames() {
+            unsafe {
+                arch::flush_frame(frame.cptr, PageBits::U8)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn flush_range(&self, vaddr: usize, size: usize) -> Result<(), SeL4Error> {
+        let bottom = vaddr & !0xFFF;
+        let top = vaddr + cmp::max(PageBytes::USIZE, size);
+        let frame_bytes = PageBytes::USIZE;
+        for frame in self.frames() {
+            if frame.vaddr() < top && frame.vaddr() + frame_bytes > bottom {
+                unsafe {
+                    arch::flush_frame(frame.cptr, PageBits::U8)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply a cache maintenance `op` to the part of each frame which
+    /// overlaps bytes `offset..offset + len` of this region.
+    pub(super) fn maintain_range(
+        &self,
+        offset: usize,
+        len: usize,
+        op: unsafe fn(usize, usize, usize) -> Result<(), SeL4Error>,
+    ) -> Result<(), SeL4Error> {
+        let end = offset + len;
+        let frame_bytes = PageBytes::USIZE;
+        for (i, frame) in self.frames().enumerate() {
+            let frame_start = i * frame_bytes;
+            let start = cmp::max(offset, frame_start);
+            let stop = cmp::min(end, frame_start + frame_bytes);
+            if start < stop {
+                unsafe {
+                    op(frame.cptr, start - frame_start, stop - frame_start)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
 pub struct WeakMemoryRegion<State: PageState, SS: SharedStatus, CapRole: CNodeRole = role::Local> {
-    pub(super) caps: WeakCapRange<Page<State>, CapRole>,
+    pub(crate) caps: WeakCapRange<Page<State>, CapRole>,
     pub(super) kind: WeakMemoryKind,
     size_bits: u8,
+    granule_bits: u8,
     _shared_status: PhantomData<SS>,
 }
 
@@ -456,6 +678,62 @@ impl WeakMemoryRegion<page_state::Unmapped, shared_status::Exclusive> {
             caps,
             kind,
             size_bits,
+            granule_bits: PageBits::U8,
+            _shared_status: PhantomData,
+        })
+    }
+
+    /// Like `new`, but backs the region with the largest frames it is big
+    /// enough for, so that mapping it takes fewer entries (and fewer
+    /// paging structures) than mapping it page by page. Falls back to
+    /// pages for regions smaller than any large frame.
+    pub fn new_with_large_frames<MemKind: MemoryKind>(
+        untyped: LocalCap<WUntyped<MemKind>>,
+        slots: &mut WCNodeSlots,
+    ) -> Result<Self, RetypeError> {
+        let kind = untyped.cap_data.kind.weaken();
+        let size_bits = untyped.size_bits();
+        let granule_bits = LARGE_FRAME_BITS
+            .iter()
+            .copied()
+            .find(|&frame_bits| frame_bits <= size_bits)
+            .unwrap_or(PageBits::U8);
+        let caps = untyped.retype_frames(granule_bits, slots)?;
+        Ok(WeakMemoryRegion {
+            caps,
+            kind,
+            size_bits,
+            granule_bits,
+            _shared_status: PhantomData,
+        })
+    }
+
+    /// Retype into frames of `1 << FrameBits` bytes instead of pages, so
+    /// that the region is mapped with large pages or sections and needs
+    /// only one slot per frame. `FrameBits` must be one of the frame
+    /// sizes the architecture supports.
+    ///
+    /// The region is weak because a `MemoryRegion` holds a cap per page.
+    pub fn new_with_frames<SizeBits: Unsigned, FrameBits: Unsigned>(
+        ut: LocalCap<Untyped<SizeBits>>,
+        slots: LocalCNodeSlots<NumFrames<SizeBits, FrameBits>>,
+    ) -> Result<Self, RetypeError>
+    where
+        SizeBits: IsGreaterOrEqual<FrameBits, Output = True>,
+        SizeBits: Sub<FrameBits>,
+        <SizeBits as Sub<FrameBits>>::Output: _Pow,
+        NumFrames<SizeBits, FrameBits>: Unsigned,
+        NumFrames<SizeBits, FrameBits>: IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
+    {
+        let kind = ut.cap_data.kind.weaken();
+        let caps = ut
+            .weaken()
+            .retype_frames(FrameBits::U8, &mut slots.weaken())?;
+        Ok(WeakMemoryRegion {
+            caps,
+            kind,
+            size_bits: SizeBits::U8,
+            granule_bits: FrameBits::U8,
             _shared_status: PhantomData,
         })
     }
@@ -466,13 +744,15 @@ impl<State: PageState, SS: SharedStatus> WeakMemoryRegion<State, SS, role::Local
         state: State,
         kind: WeakMemoryKind,
         size_bits: u8,
+        granule_bits: u8,
     ) -> Self {
-        let num_pages = num_pages(size_bits)
-            .expect("Calling functions maintain the invariant that the size_bits is over the size of a page");
+        let num_frames = num_frames(size_bits, granule_bits)
+            .expect("Calling functions maintain the invariant that the size_bits is over the size of a frame");
         WeakMemoryRegion {
-            caps: WeakCapRange::new(local_page_caps_offset_cptr, Page { state }, num_pages),
+            caps: WeakCapRange::new(local_page_caps_offset_cptr, Page { state }, num_frames),
             kind,
             size_bits,
+            granule_bits,
             _shared_status: PhantomData,
         }
     }
@@ -487,25 +767,34 @@ impl<State: PageState, SS: SharedStatus, CapRole: CNodeRole> WeakMemoryRegion<St
     pub fn size_bytes(&self) -> usize {
         2usize.pow(u32::from(self.size_bits))
     }
+
+    /// The number of bits needed to address each of the frames backing
+    /// this region; `PageBits` unless it was built from large frames.
+    pub fn granule_bits(&self) -> u8 {
+        self.granule_bits
+    }
+
     pub(super) fn try_from_caps(
         caps: WeakCapRange<Page<State>, CapRole>,
         kind: WeakMemoryKind,
         size_bits: u8,
+        granule_bits: u8,
     ) -> Result<WeakMemoryRegion<State, SS, CapRole>, InvalidSizeBits> {
-        if num_pages(size_bits)? != caps.len() {
+        if num_frames(size_bits, granule_bits)? != caps.len() {
             return Err(InvalidSizeBits::SizeBitsMismatchPageCapCount);
         }
         Ok(WeakMemoryRegion {
             caps,
             kind,
             size_bits,
+            granule_bits,
             _shared_status: PhantomData,
         })
     }
 
-    pub(super) fn as_strong<SizeBits: Unsigned>(
+    pub(super) fn as_strong<SizeBits: Unsigned, Attrs: MemoryAttributes, Rights: MappedRights>(
         self,
-    ) -> Result<MemoryRegion<State, SizeBits, SS, CapRole>, VSpaceError>
+    ) -> Result<MemoryRegion<State, SizeBits, SS, CapRole, Attrs, Rights>, VSpaceError>
     where
         // Forces regions to be page-aligned.
         SizeBits: IsGreaterOrEqual<PageBits>,
@@ -514,7 +803,8 @@ impl<State: PageState, SS: SharedStatus, CapRole: CNodeRole> WeakMemoryRegion<St
         <SizeBits as Sub<PageBits>>::Output: _Pow,
         Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
     {
-        if self.size_bits != SizeBits::U8 {
+        // Strong regions hold one cap per page.
+        if self.size_bits != SizeBits::U8 || self.granule_bits != PageBits::U8 {
             return Err(VSpaceError::InvalidRegionSize);
         }
         Ok(MemoryRegion::from_caps(
@@ -528,6 +818,7 @@ impl<State: PageState, SS: SharedStatus, CapRole: CNodeRole> WeakMemoryRegion<St
             caps: self.caps,
             kind: self.kind,
             size_bits: self.size_bits,
+            granule_bits: self.granule_bits,
             _shared_status: PhantomData,
         }
     }
@@ -542,6 +833,10 @@ impl<SS: SharedStatus, CapRole: CNodeRole> WeakMappedMemoryRegion<SS, CapRole> {
         self.caps.start_cap_data.state.asid
     }
 
+    pub fn rights(&self) -> CapRights {
+        self.caps.start_cap_data.state.rights
+    }
+
     pub fn as_slice(&self) -> &[u8] {
         unsafe { core::slice::from_raw_parts(self.vaddr() as *const u8, self.size_bytes()) }
     }
@@ -558,11 +853,30 @@ pub(super) enum InvalidSizeBits {
     SizeBitsTooBig,
 }
 
-pub(super) fn num_pages(size_bits: u8) -> Result<usize, InvalidSizeBits> {
-    if size_bits < PageBits::U8 {
+pub(super) fn num_frames(size_bits: u8, frame_bits: u8) -> Result<usize, InvalidSizeBits> {
+    if size_bits < frame_bits {
         return Err(InvalidSizeBits::TooSmallToRepresentAPage);
     }
     2usize
-        .checked_pow(u32::from(size_bits) - PageBits::U32)
+        .checked_pow(u32::from(size_bits - frame_bits))
         .ok_or(InvalidSizeBits::SizeBitsTooBig)
 }
+
+/// Caps to `count` consecutive frames of `1 << frame_bits` bytes, the
+/// first of which has the given state.
+fn frame_caps<State: PageState, CapRole: CNodeRole>(
+    start_cptr: usize,
+    start_state: State,
+    frame_bits: u8,
+    count: usize,
+) -> impl Iterator<Item = Cap<Page<State>, CapRole>> {
+    (0..count).map(move |index| Cap {
+        cptr: start_cptr + index,
+        cap_data: Page {
+            state: start_state
+                .offset_by(index << frame_bits)
+                .expect("Earlier checks confirm the memory fits into available space"),
+        },
+        _role: PhantomData,
+    })
+}
@@ -0,0 +1,121 @@
+//! Read-only ELF segments shared between instances of one image, which
+//! map the frames of the root task's own image instead of copies.
+use arrayvec::ArrayVec;
+
+use super::{VSpaceError, PAGE_MASK};
+use crate::arch::{self, PageBytes};
+use crate::bootstrap::UserImage;
+use crate::cap::{page_state, role, Page, WeakCapRange};
+
+/// The most read-only loadable segments an image shared this way may have.
+pub const MAX_SHARED_ELF_SEGMENTS: usize = 8;
+
+struct ReadOnlySegment {
+    /// The page-aligned address the segment starts at in each instance.
+    vaddr: usize,
+    /// The root task's cap to the first frame backing the segment.
+    first_page_cptr: usize,
+    first_page: Page<page_state::Mapped>,
+    page_count: usize,
+    vm_attributes: arch::VMAttributes,
+}
+
+/// The read-only segments of an ELF image, resolved to the frames of the
+/// root task's image that hold them. Work these out once per image, then
+/// set up any number of instances with `VSpace::new_from_shared_elf`.
+pub struct SharedElfSegments<'a> {
+    elf_data: &'a [u8],
+    segments: ArrayVec<[ReadOnlySegment; MAX_SHARED_ELF_SEGMENTS]>,
+}
+
+impl<'a> SharedElfSegments<'a> {
+    /// `elf_data` must lie within the root task's `user_image`, as data
+    /// read from its archive does.
+    pub fn new(
+        elf_data: &'a [u8],
+        user_image: &UserImage<role::Local>,
+    ) -> Result<Self, VSpaceError> {
+        let elf = xmas_elf::ElfFile::new(elf_data).map_err(VSpaceError::ElfParseError)?;
+        let mut segments = ArrayVec::new();
+
+        for program_header in elf
+            .program_iter()
+            .filter(|h| h.get_type() == Ok(xmas_elf::program::Type::Load))
+            .filter(|h| !h.flags().is_write())
+        {
+            let target_vaddr = program_header.virtual_addr() as usize;
+            let file_size = program_header.file_size() as usize;
+
+            // The address of the elf data in the address space executing this code
+            let elf_vaddr_here =
+                (elf_data as *const [u8] as *const u8 as usize) + program_header.offset() as usize;
+            // Frames can only be shared if the segment sits at the same
+            // offset into a page here as it must in each instance.
+            if elf_vaddr_here & PAGE_MASK != target_vaddr & PAGE_MASK {
+                return Err(VSpaceError::ElfParseError(
+                    "read-only segment is not page-aligned with its place in the image",
+                ));
+            }
+            let start_page_vaddr_here = elf_vaddr_here & !PAGE_MASK;
+
+            let mut pages = user_image
+                .pages_iter()
+                .skip_while(|p| p.cap_data.state.vaddr < start_page_vaddr_here)
+                .take_while(|p| p.cap_data.state.vaddr < elf_vaddr_here + file_size);
+            let first_page = match pages.next() {
+                Some(page) => page,
+                None => continue,
+            };
+            let segment = ReadOnlySegment {
+                vaddr: target_vaddr & !PAGE_MASK,
+                first_page_cptr: first_page.cptr,
+                first_page: first_page.cap_data,
+                page_count: 1 + pages.count(),
+                vm_attributes: if program_header.flags().is_execute() {
+                    arch::vm_attributes::PROGRAM_CODE
+                } else {
+                    arch::vm_attributes::PROGRAM_DATA
+                },
+            };
+            segments.try_push(segment).map_err(|_| {
+                VSpaceError::ElfParseError("image has too many read-only segments to share")
+            })?;
+        }
+
+        Ok(SharedElfSegments { elf_data, segments })
+    }
+
+    pub fn elf_data(&self) -> &'a [u8] {
+        self.elf_data
+    }
+
+    /// How many frames are shared, and so how many cap slots each
+    /// instance needs for them.
+    pub fn page_count(&self) -> usize {
+        self.segments.iter().map(|s| s.page_count).sum()
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.page_count() * PageBytes::USIZE
+    }
+
+    /// Each segment's page-aligned address in an instance, its caps and
+    /// the VM attributes it is mapped with.
+    pub(super) fn segments(
+        &self,
+    ) -> impl Iterator<
+        Item = (
+            usize,
+            WeakCapRange<Page<page_state::Mapped>, role::Local>,
+            arch::VMAttributes,
+        ),
+    > + '_ {
+        self.segments.iter().map(|s| {
+            (
+                s.vaddr,
+                WeakCapRange::new(s.first_page_cptr, s.first_page.clone(), s.page_count),
+                s.vm_attributes,
+            )
+        })
+    }
+}